@@ -12,8 +12,7 @@ use crate::{
 };
 use anyhow::{bail, ensure, format_err, Context as AnyhowContext, Result};
 use aptos_api_types::{
-    AptosErrorCode, AsConverter, BcsBlock, GasEstimation, LedgerInfo, ResourceGroup,
-    TransactionOnChainData,
+    AptosErrorCode, AsConverter, BcsBlock, GasEstimation, LedgerInfo, TransactionOnChainData,
 };
 use aptos_config::config::{NodeConfig, RoleType};
 use aptos_crypto::HashValue;
@@ -45,7 +44,7 @@ use aptos_types::{
 };
 use aptos_vm::{
     data_cache::{IntoMoveResolver, StorageAdapter, StorageAdapterOwned},
-    move_vm_ext::MoveResolverExt,
+    move_vm_ext::{MoveResolverExt, ResourceGroupMap},
 };
 use futures::{channel::oneshot, SinkExt};
 use move_core_types::language_storage::{ModuleId, StructTag};
@@ -336,12 +335,8 @@ impl Context {
             .map(|(key, value)| {
                 if resolver.is_resource_group(&key) {
                     // An error here means a storage invariant has been violated
-                    bcs::from_bytes::<ResourceGroup>(&value)
-                        .map(|map| {
-                            map.into_iter()
-                                .map(|(key, value)| (key, value))
-                                .collect::<Vec<_>>()
-                        })
+                    ResourceGroupMap::from_bytes(&value)
+                        .map(|group| group.into_members().into_iter().collect::<Vec<_>>())
                         .map_err(|e| e.into())
                 } else {
                     Ok(vec![(key, value)])
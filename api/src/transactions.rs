@@ -1173,7 +1173,15 @@ impl TransactionsApi {
 
         // Simulate transaction
         let move_resolver = self.context.move_resolver_poem(&ledger_info)?;
-        let (_, output_ext) = AptosVM::simulate_signed_transaction(&txn, &move_resolver);
+        let (vm_status, output_ext) = AptosVM::simulate_signed_transaction(&txn, &move_resolver);
+        // Like a block, a simulation storage failed to serve reads for has no outcome to report.
+        if vm_status.status_code().is_retriable() {
+            return Err(SubmitTransactionError::internal_with_code(
+                format!("Failed to simulate transaction: {:?}", vm_status),
+                AptosErrorCode::InternalError,
+                &ledger_info,
+            ));
+        }
         let version = ledger_info.version();
 
         // Apply transaction outputs to build up a transaction
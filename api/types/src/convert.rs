@@ -12,9 +12,9 @@ use crate::{
     view::ViewRequest,
     Bytecode, DirectWriteSet, EntryFunctionId, EntryFunctionPayload, Event, HexEncodedBytes,
     MoveFunction, MoveModuleBytecode, MoveResource, MoveScriptBytecode, MoveType, MoveValue,
-    PendingTransaction, ResourceGroup, ScriptPayload, ScriptWriteSet, SubmitTransactionRequest,
-    Transaction, TransactionInfo, TransactionOnChainData, TransactionPayload,
-    UserTransactionRequest, VersionedEvent, WriteSet, WriteSetChange, WriteSetPayload,
+    PendingTransaction, ScriptPayload, ScriptWriteSet, SubmitTransactionRequest, Transaction,
+    TransactionInfo, TransactionOnChainData, TransactionPayload, UserTransactionRequest,
+    VersionedEvent, WriteSet, WriteSetChange, WriteSetPayload,
 };
 use anyhow::{bail, ensure, format_err, Context as AnyhowContext, Result};
 use aptos_crypto::{hash::CryptoHash, HashValue};
@@ -31,10 +31,10 @@ use aptos_types::{
         EntryFunction, ExecutionStatus, ModuleBundle, Multisig, RawTransaction, Script,
         SignedTransaction,
     },
-    vm_status::AbortLocation,
+    vm_status::{AbortLocation, StatusCode},
     write_set::WriteOp,
 };
-use aptos_vm::move_vm_ext::MoveResolverExt;
+use aptos_vm::move_vm_ext::{MoveResolverExt, ResourceGroupMap};
 use move_binary_format::file_format::FunctionHandleIndex;
 use move_core_types::{
     identifier::Identifier,
@@ -83,14 +83,8 @@ impl<'a, R: MoveResolverExt + ?Sized> MoveConverter<'a, R> {
         &self,
         bytes: &[u8],
     ) -> Result<Vec<MoveResource>> {
-        let resources_with_tag: Vec<(StructTag, Vec<u8>)> = bcs::from_bytes::<ResourceGroup>(bytes)
-            .map(|map| {
-                map.into_iter()
-                    .map(|(key, value)| (key, value))
-                    .collect::<Vec<_>>()
-            })?;
-
-        resources_with_tag
+        ResourceGroupMap::from_bytes(bytes)?
+            .members()
             .iter()
             .map(|(struct_tag, value_bytes)| self.try_into_resource(struct_tag, value_bytes))
             .collect::<Result<Vec<_>>>()
@@ -971,6 +965,18 @@ pub trait ExplainVMStatus {
                     func_name, code_offset
                 )
             }
+            ExecutionStatus::MiscellaneousError(Some(StatusCode::TOO_MANY_WRITE_OPS)) => {
+                "Transaction writes more state items than allowed in a single transaction".to_owned()
+            }
+            ExecutionStatus::MiscellaneousError(Some(StatusCode::STORAGE_QUOTA_EXCEEDED)) => {
+                "Transaction grows the state of an account beyond its storage quota".to_owned()
+            }
+            ExecutionStatus::MiscellaneousError(Some(StatusCode::RESERVED_RESOURCE_DELETION_DENIED)) => {
+                "Transaction deletes a resource at a framework reserved address".to_owned()
+            }
+            ExecutionStatus::MiscellaneousError(Some(StatusCode::FINISH_BUDGET_EXCEEDED)) => {
+                "Transaction changes more state than its gas limit allows to be processed".to_owned()
+            }
             ExecutionStatus::MiscellaneousError(code) => {
                 code.map_or(
                     "Move bytecode deserialization / verification failed, including entry function not found or invalid arguments".to_owned(),
@@ -524,17 +524,21 @@ pub struct Event {
     pub typ: MoveType,
     /// The JSON representation of the event
     pub data: serde_json::Value,
+    /// Set for module events, which are identified by their type rather than emitted to an event
+    /// handle: their `guid` and `sequence_number` are all zeros and identify nothing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub is_module_event: Option<bool>,
 }
 
 impl From<(&ContractEvent, serde_json::Value)> for Event {
     fn from((event, data): (&ContractEvent, serde_json::Value)) -> Self {
-        match event {
-            ContractEvent::V0(v0) => Self {
-                guid: (*v0.key()).into(),
-                sequence_number: v0.sequence_number().into(),
-                typ: v0.type_tag().clone().into(),
-                data,
-            },
+        Self {
+            guid: (*event.key()).into(),
+            sequence_number: event.sequence_number().into(),
+            typ: event.type_tag().clone().into(),
+            data,
+            is_module_event: event.is_module_event().then_some(true),
         }
     }
 }
@@ -552,18 +556,22 @@ pub struct VersionedEvent {
     pub typ: MoveType,
     /// The JSON representation of the event
     pub data: serde_json::Value,
+    /// Set for module events, which are identified by their type rather than emitted to an event
+    /// handle: their `guid` and `sequence_number` are all zeros and identify nothing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub is_module_event: Option<bool>,
 }
 
 impl From<(&EventWithVersion, serde_json::Value)> for VersionedEvent {
     fn from((event, data): (&EventWithVersion, serde_json::Value)) -> Self {
-        match &event.event {
-            ContractEvent::V0(v0) => Self {
-                version: event.transaction_version.into(),
-                guid: (*v0.key()).into(),
-                sequence_number: v0.sequence_number().into(),
-                typ: v0.type_tag().clone().into(),
-                data,
-            },
+        Self {
+            version: event.transaction_version.into(),
+            guid: (*event.event.key()).into(),
+            sequence_number: event.event.sequence_number().into(),
+            typ: event.event.type_tag().clone().into(),
+            data,
+            is_module_event: event.event.is_module_event().then_some(true),
         }
     }
 }
@@ -13,7 +13,13 @@ use aptos_types::{
     write_set::{WriteOp, WriteSetMut},
 };
 use move_binary_format::errors::{Location, PartialVMError, PartialVMResult};
-use std::collections::BTreeMap;
+use move_core_types::account_address::AccountAddress;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    fmt,
+    hash::{Hash, Hasher},
+};
 
 /// When `Addition` operation overflows the `limit`.
 const EADD_OVERFLOW: u64 = 0x02_0001;
@@ -22,7 +28,7 @@ const EADD_OVERFLOW: u64 = 0x02_0001;
 const ESUB_UNDERFLOW: u64 = 0x02_0002;
 
 /// Represents an update from aggregator's operation.
-#[derive(Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DeltaOp {
     /// Maximum positive delta seen during execution.
     max_positive: u128,
@@ -36,7 +42,7 @@ pub struct DeltaOp {
 }
 
 /// Different delta functions.
-#[derive(Copy, Clone, Debug, Hash, PartialOrd, Ord, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DeltaUpdate {
     Plus(u128),
     Minus(u128),
@@ -169,6 +175,17 @@ impl DeltaOp {
         state_view: &impl StateView,
         state_key: &StateKey,
     ) -> anyhow::Result<WriteOp, VMStatus> {
+        self.try_materialize_value(state_view, state_key)
+            .map(|result| WriteOp::Modification(serialize(&result)))
+    }
+
+    /// Like `try_into_write_op`, returning the value the delta results in instead of a write op,
+    /// e.g. to read an aggregator without writing it.
+    pub fn try_materialize_value(
+        &self,
+        state_view: &impl StateView,
+        state_key: &StateKey,
+    ) -> anyhow::Result<u128, VMStatus> {
         state_view
             .get_state_value_bytes(state_key)
             .map_err(|_| VMStatus::Error(StatusCode::STORAGE_ERROR, None))
@@ -176,15 +193,13 @@ impl DeltaOp {
                 match maybe_bytes {
                     Some(bytes) => {
                         let base = deserialize(&bytes);
-                        self.apply_to(base)
-                            .map_err(|partial_error| {
-                                // If delta application fails, transform partial VM
-                                // error into an appropriate VM status.
-                                partial_error
-                                    .finish(Location::Module(AGGREGATOR_MODULE.clone()))
-                                    .into_vm_status()
-                            })
-                            .map(|result| WriteOp::Modification(serialize(&result)))
+                        self.apply_to(base).map_err(|partial_error| {
+                            // If delta application fails, transform partial VM
+                            // error into an appropriate VM status.
+                            partial_error
+                                .finish(Location::Module(AGGREGATOR_MODULE.clone()))
+                                .into_vm_status()
+                        })
                     },
                     // Something is wrong, the value to which we apply delta should
                     // always exist. Guard anyway.
@@ -267,16 +282,54 @@ pub fn delta_add(v: u128, limit: u128) -> DeltaOp {
     DeltaOp::new(DeltaUpdate::Plus(v), limit, v, 0)
 }
 
+/// The aggregator a delta updates, kept to tell which one failed if the delta cannot be
+/// materialized.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct DeltaProvenance {
+    pub handle: AccountAddress,
+    pub key: AccountAddress,
+}
+
+impl fmt::Display for DeltaProvenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "aggregator {} of handle {}",
+            self.key.to_hex_literal(),
+            self.handle.to_hex_literal()
+        )
+    }
+}
+
 /// `DeltaChangeSet` contains all access paths that one transaction wants to update with deltas.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+///
+/// The provenance of the deltas only serves error messages, so it is left out of comparisons and
+/// hashes: two delta change sets are equal if they apply the same deltas to the same keys.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DeltaChangeSet {
     delta_change_set: BTreeMap<StateKey, DeltaOp>,
+    provenance: BTreeMap<StateKey, DeltaProvenance>,
+}
+
+impl PartialEq for DeltaChangeSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.delta_change_set == other.delta_change_set
+    }
+}
+
+impl Eq for DeltaChangeSet {}
+
+impl Hash for DeltaChangeSet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.delta_change_set.hash(state);
+    }
 }
 
 impl DeltaChangeSet {
     pub fn empty() -> Self {
         DeltaChangeSet {
             delta_change_set: BTreeMap::new(),
+            provenance: BTreeMap::new(),
         }
     }
 
@@ -287,6 +340,7 @@ impl DeltaChangeSet {
     pub fn new(delta_change_set: impl IntoIterator<Item = (StateKey, DeltaOp)>) -> Self {
         DeltaChangeSet {
             delta_change_set: delta_change_set.into_iter().collect(),
+            provenance: BTreeMap::new(),
         }
     }
 
@@ -294,7 +348,34 @@ impl DeltaChangeSet {
         self.delta_change_set.insert(delta.0, delta.1);
     }
 
+    /// Like `insert`, also recording which aggregator the delta updates.
+    pub fn insert_with_provenance(
+        &mut self,
+        delta: (StateKey, DeltaOp),
+        provenance: DeltaProvenance,
+    ) {
+        self.provenance.insert(delta.0.clone(), provenance);
+        self.insert(delta);
+    }
+
+    /// The aggregator the delta to `key` updates, if it was recorded.
+    pub fn provenance(&self, key: &StateKey) -> Option<&DeltaProvenance> {
+        self.provenance.get(key)
+    }
+
+    /// Records `provenance` for the delta to `key`, if there is one.
+    pub fn set_provenance(&mut self, key: &StateKey, provenance: DeltaProvenance) {
+        if self.delta_change_set.contains_key(key) {
+            self.provenance.insert(key.clone(), provenance);
+        }
+    }
+
+    pub fn get(&self, key: &StateKey) -> Option<&DeltaOp> {
+        self.delta_change_set.get(key)
+    }
+
     pub fn remove(&mut self, key: &StateKey) -> Option<DeltaOp> {
+        self.provenance.remove(key);
         self.delta_change_set.remove(key)
     }
 
@@ -312,6 +393,39 @@ impl DeltaChangeSet {
         &mut self.delta_change_set
     }
 
+    /// Merges this delta change set, e.g. of a later transaction, onto `earlier`. Deltas to the
+    /// same key are merged with `DeltaOp::merge_onto`, so the result fails to apply wherever
+    /// applying both in order would, and merging fails if the two cannot be composed at all.
+    /// Where both record which aggregator a key belongs to, the later provenance is kept.
+    pub fn merge_onto(self, earlier: DeltaChangeSet) -> PartialVMResult<DeltaChangeSet> {
+        let DeltaChangeSet {
+            delta_change_set: mut merged,
+            mut provenance,
+        } = earlier;
+        for (key, mut op) in self.delta_change_set {
+            match merged.entry(key) {
+                Entry::Occupied(mut entry) => {
+                    op.merge_onto(*entry.get())?;
+                    entry.insert(op);
+                },
+                Entry::Vacant(entry) => {
+                    entry.insert(op);
+                },
+            }
+        }
+        provenance.extend(self.provenance);
+
+        Ok(DeltaChangeSet {
+            delta_change_set: merged,
+            provenance,
+        })
+    }
+
+    /// The keys of all the deltas, e.g. to mark them as read by the transaction.
+    pub fn take_keys(self) -> BTreeSet<StateKey> {
+        self.delta_change_set.into_keys().collect()
+    }
+
     /// Consumes the delta change set and tries to materialize it. Returns a
     /// mutable write set if materialization succeeds (mutability since we want
     /// to merge these writes with transaction outputs).
@@ -328,6 +442,31 @@ impl DeltaChangeSet {
         // All deltas are applied successfully.
         Ok(WriteSetMut::new(materialized_write_set))
     }
+
+    /// Like `try_into_write_set_mut`, but a delta that cannot be applied fails with
+    /// `DELTA_MATERIALIZATION_FAILURE`, naming the aggregator it updates where that is known.
+    pub fn try_materialize(&self, state_view: &impl StateView) -> Result<WriteSetMut, VMStatus> {
+        let mut materialized_write_set = vec![];
+        for (state_key, delta_op) in &self.delta_change_set {
+            let write_op = delta_op
+                .try_into_write_op(state_view, state_key)
+                .map_err(|status| {
+                    let target = match self.provenance(state_key) {
+                        Some(provenance) => provenance.to_string(),
+                        None => format!("{:?}", state_key),
+                    };
+                    VMStatus::Error(
+                        StatusCode::DELTA_MATERIALIZATION_FAILURE,
+                        Some(format!(
+                            "delta {:?} to {} cannot be applied: {}",
+                            delta_op, target, status
+                        )),
+                    )
+                })?;
+            materialized_write_set.push((state_key.clone(), write_op));
+        }
+        Ok(WriteSetMut::new(materialized_write_set))
+    }
 }
 
 impl<'a> IntoIterator for &'a DeltaChangeSet {
@@ -352,7 +491,7 @@ impl ::std::iter::IntoIterator for DeltaChangeSet {
 mod test {
     use super::*;
     use aptos_language_e2e_tests::data_store::FakeDataStore;
-    use claims::{assert_err, assert_matches, assert_ok, assert_ok_eq};
+    use claims::{assert_err, assert_matches, assert_none, assert_ok, assert_ok_eq};
     use once_cell::sync::Lazy;
 
     fn delta_add_with_history(v: u128, limit: u128, max: u128, min: u128) -> DeltaOp {
@@ -576,4 +715,153 @@ mod test {
             Err(VMStatus::MoveAbort(_, ESUB_UNDERFLOW))
         );
     }
+
+    #[test]
+    fn test_failed_materialization_names_the_aggregator() {
+        let mut state_view = FakeDataStore::default();
+        state_view.set(KEY.clone(), serialize(&100));
+
+        let mut delta_change_set = DeltaChangeSet::empty();
+        delta_change_set.insert_with_provenance(
+            (KEY.clone(), delta_add(15, 100)),
+            DeltaProvenance {
+                handle: AccountAddress::from_hex_literal("0xcafe").unwrap(),
+                key: AccountAddress::from_hex_literal("0xbeef").unwrap(),
+            },
+        );
+        match delta_change_set.try_materialize(&state_view) {
+            Err(VMStatus::Error(StatusCode::DELTA_MATERIALIZATION_FAILURE, Some(message))) => {
+                assert!(
+                    message.contains("aggregator 0xbeef of handle 0xcafe"),
+                    "{}",
+                    message
+                );
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // Deltas removed from the change set take their provenance with them.
+        delta_change_set.remove(&KEY);
+        assert_none!(delta_change_set.provenance(&KEY));
+    }
+
+    fn key(i: u8) -> StateKey {
+        StateKey::raw(vec![i])
+    }
+
+    #[test]
+    fn test_delta_change_set_merge() {
+        use DeltaUpdate::*;
+
+        let earlier =
+            DeltaChangeSet::new([(key(1), delta_add(3, 100)), (key(2), delta_sub(7, 100))]);
+        let later = DeltaChangeSet::new([(key(2), delta_add(2, 100)), (key(3), delta_add(1, 100))]);
+        let merged = assert_ok!(later.merge_onto(earlier));
+
+        // Keys only one side touched are kept as they are, the others are composed.
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged.get(&key(1)), Some(&delta_add(3, 100)));
+        assert_eq!(merged.get(&key(2)).unwrap().get_update(), Minus(5));
+        assert_eq!(merged.get(&key(3)), Some(&delta_add(1, 100)));
+        assert_eq!(merged.take_keys(), BTreeSet::from([key(1), key(2), key(3)]));
+    }
+
+    #[test]
+    fn test_delta_change_set_merge_composes_bounds() {
+        use DeltaUpdate::*;
+        let merge = |earlier: DeltaOp, later: DeltaOp| {
+            DeltaChangeSet::new([(key(1), later)])
+                .merge_onto(DeltaChangeSet::new([(key(1), earlier)]))
+                .map(|merged| *merged.get(&key(1)).unwrap())
+        };
+
+        // The later peak of +60 happens on top of +3, tightening the upper bound to +63.
+        let merged = assert_ok!(merge(
+            delta_add_with_history(3, 100, 50, 0),
+            delta_add_with_history(5, 100, 60, 0),
+        ));
+        assert_eq!(merged, DeltaOp::new(Plus(8), 100, 63, 0));
+        assert_err!(merged.apply_to(38));
+        assert_ok_eq!(merged.apply_to(37), 45);
+
+        // The later dip to -5 happens on top of -10, tightening the lower bound to -15 even
+        // though the merged delta is only -6.
+        let merged = assert_ok!(merge(
+            delta_sub_with_history(10, 100, 0, 10),
+            delta_add_with_history(4, 100, 4, 5),
+        ));
+        assert_eq!(merged, DeltaOp::new(Minus(6), 100, 0, 15));
+        assert_err!(merged.apply_to(14));
+        assert_ok_eq!(merged.apply_to(15), 9);
+
+        // The later dip to -30 happens on top of +20, so the lower bound becomes -10, while the
+        // earlier peak of +20 stays the upper bound.
+        let merged = assert_ok!(merge(
+            delta_add_with_history(20, 100, 20, 0),
+            delta_sub_with_history(30, 100, 0, 30),
+        ));
+        assert_eq!(merged, DeltaOp::new(Minus(10), 100, 20, 10));
+
+        // Both deltas end up well within the limit, but the later peak of +98 on top of +3
+        // crosses it, so the two cannot be applied one after the other.
+        assert_err!(merge(
+            delta_add_with_history(3, 100, 3, 0),
+            delta_add_with_history(5, 100, 98, 0),
+        ));
+    }
+
+    #[test]
+    fn test_delta_change_set_merge_keeps_later_provenance() {
+        let provenance = |i: u64| DeltaProvenance {
+            handle: AccountAddress::from_hex_literal("0xcafe").unwrap(),
+            key: AccountAddress::from_hex_literal(&format!("0x{}", i)).unwrap(),
+        };
+        let mut earlier = DeltaChangeSet::empty();
+        earlier.insert_with_provenance((key(1), delta_add(1, 100)), provenance(1));
+        earlier.insert_with_provenance((key(2), delta_add(1, 100)), provenance(2));
+        let mut later = DeltaChangeSet::empty();
+        later.insert_with_provenance((key(2), delta_add(1, 100)), provenance(3));
+
+        let merged = assert_ok!(later.merge_onto(earlier));
+        assert_eq!(merged.provenance(&key(1)), Some(&provenance(1)));
+        assert_eq!(merged.provenance(&key(2)), Some(&provenance(3)));
+    }
+
+    #[test]
+    fn test_delta_change_set_bcs_round_trip() {
+        let mut delta_change_set = DeltaChangeSet::new([(key(1), delta_sub(4, 100))]);
+        delta_change_set.insert_with_provenance(
+            (key(2), delta_add_with_history(3, 1000, 50, 7)),
+            DeltaProvenance {
+                handle: AccountAddress::from_hex_literal("0xcafe").unwrap(),
+                key: AccountAddress::from_hex_literal("0xbeef").unwrap(),
+            },
+        );
+
+        let bytes = bcs::to_bytes(&delta_change_set).unwrap();
+        let decoded = bcs::from_bytes::<DeltaChangeSet>(&bytes).unwrap();
+        assert_eq!(decoded, delta_change_set);
+        assert_eq!(
+            decoded.provenance(&key(2)),
+            delta_change_set.provenance(&key(2))
+        );
+    }
+
+    #[test]
+    fn test_delta_change_set_equality_ignores_provenance() {
+        let mut with_provenance = DeltaChangeSet::empty();
+        with_provenance.insert_with_provenance((key(1), delta_add(1, 100)), DeltaProvenance {
+            handle: AccountAddress::from_hex_literal("0xcafe").unwrap(),
+            key: AccountAddress::from_hex_literal("0xbeef").unwrap(),
+        });
+        let without_provenance = DeltaChangeSet::new([(key(1), delta_add(1, 100))]);
+        assert_eq!(with_provenance, without_provenance);
+
+        let hash = |delta_change_set: &DeltaChangeSet| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            delta_change_set.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&with_provenance), hash(&without_provenance));
+    }
 }
@@ -0,0 +1,295 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! A rendering of `ChangeSetExt` meant for people reviewing write sets, e.g. of governance
+//! proposals, rather than for execution.
+
+use crate::{
+    delta_change_set::DeltaUpdate,
+    transaction::{ChangeSetExt, ExecutionMarker},
+};
+use aptos_types::{
+    access_path::Path,
+    state_store::state_key::{StateKey, StateKeyInner},
+    write_set::WriteOp,
+};
+use move_core_types::language_storage::StructTag;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// What a state key points to, with addresses and types spelled out.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HumanReadableKey {
+    Module { address: String, name: String },
+    Resource { address: String, resource: String },
+    ResourceGroup { address: String, group: String },
+    TableItem { handle: String, key: String },
+    Raw { key: String },
+}
+
+/// Turns state keys into `HumanReadableKey`s.
+pub trait StateKeyAnnotator {
+    fn annotate(&self, state_key: &StateKey) -> HumanReadableKey;
+}
+
+/// Annotates state keys by decoding them. Access paths carry the module id or struct tag they
+/// were built from, so nothing has to be remembered from the conversion.
+pub struct DecodingAnnotator;
+
+impl StateKeyAnnotator for DecodingAnnotator {
+    fn annotate(&self, state_key: &StateKey) -> HumanReadableKey {
+        match state_key.inner() {
+            StateKeyInner::AccessPath(access_path) => {
+                let address = access_path.address.to_hex_literal();
+                match bcs::from_bytes::<Path>(&access_path.path) {
+                    Ok(Path::Code(module_id)) => HumanReadableKey::Module {
+                        address,
+                        name: module_id.name().to_string(),
+                    },
+                    Ok(Path::Resource(struct_tag)) => HumanReadableKey::Resource {
+                        address,
+                        resource: struct_tag.to_string(),
+                    },
+                    Ok(Path::ResourceGroup(struct_tag)) => HumanReadableKey::ResourceGroup {
+                        address,
+                        group: struct_tag.to_string(),
+                    },
+                    Err(_) => HumanReadableKey::Raw {
+                        key: hex::encode(&access_path.path),
+                    },
+                }
+            },
+            StateKeyInner::TableItem { handle, key } => HumanReadableKey::TableItem {
+                handle: handle.0.to_hex_literal(),
+                key: hex::encode(key),
+            },
+            StateKeyInner::Raw(key) => HumanReadableKey::Raw {
+                key: hex::encode(key),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HumanReadableWriteKind {
+    Creation,
+    Modification,
+    Deletion,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct HumanReadableWrite {
+    pub key: HumanReadableKey,
+    pub kind: HumanReadableWriteKind,
+    /// The written bytes in hex, absent for deletions.
+    pub data: Option<String>,
+    /// For resource groups, the members of the written group by type, with their bytes in hex.
+    pub members: Option<BTreeMap<String, String>>,
+    /// What the write was converted from, if the change set recorded it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct HumanReadableDelta {
+    pub key: HumanReadableKey,
+    /// The delta applied, as `+n` or `-n`.
+    pub delta: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct HumanReadableChangeSet {
+    pub writes: Vec<HumanReadableWrite>,
+    pub deltas: Vec<HumanReadableDelta>,
+    /// The resource group each resource type written was classified into, `None` for standalone
+    /// resources, if the change set recorded it.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub group_classifications: BTreeMap<String, Option<String>>,
+    /// The execution that produced the change set, if it was tagged with one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<ExecutionMarker>,
+}
+
+impl ChangeSetExt {
+    /// Renders the writes and deltas in key order. Resource groups are expanded into their
+    /// members where the group blob can be decoded.
+    pub fn to_human_readable(&self, annotator: &impl StateKeyAnnotator) -> HumanReadableChangeSet {
+        let writes = self
+            .write_set()
+            .iter()
+            .map(|(state_key, op)| {
+                let key = annotator.annotate(state_key);
+                let kind = match op {
+                    WriteOp::Creation(_) | WriteOp::CreationWithMetadata { .. } => {
+                        HumanReadableWriteKind::Creation
+                    },
+                    WriteOp::Modification(_) | WriteOp::ModificationWithMetadata { .. } => {
+                        HumanReadableWriteKind::Modification
+                    },
+                    WriteOp::Deletion | WriteOp::DeletionWithMetadata { .. } => {
+                        HumanReadableWriteKind::Deletion
+                    },
+                };
+                let members = match (&key, op.bytes()) {
+                    (HumanReadableKey::ResourceGroup { .. }, Some(bytes)) => {
+                        bcs::from_bytes::<BTreeMap<StructTag, Vec<u8>>>(bytes)
+                            .ok()
+                            .map(|members| {
+                                members
+                                    .into_iter()
+                                    .map(|(tag, data)| (tag.to_string(), hex::encode(data)))
+                                    .collect()
+                            })
+                    },
+                    _ => None,
+                };
+                let source = self
+                    .write_op_sources()
+                    .and_then(|sources| sources.get(state_key))
+                    .map(ToString::to_string);
+                HumanReadableWrite {
+                    key,
+                    kind,
+                    data: op.bytes().map(hex::encode),
+                    members,
+                    source,
+                }
+            })
+            .collect();
+
+        let deltas = self
+            .delta_change_set()
+            .iter()
+            .map(|(state_key, delta_op)| HumanReadableDelta {
+                key: annotator.annotate(state_key),
+                delta: match delta_op.get_update() {
+                    DeltaUpdate::Plus(value) => format!("+{}", value),
+                    DeltaUpdate::Minus(value) => format!("-{}", value),
+                },
+            })
+            .collect();
+
+        let group_classifications = self
+            .group_classifications()
+            .iter()
+            .map(|(struct_tag, group)| {
+                (
+                    struct_tag.to_string(),
+                    group.as_ref().map(ToString::to_string),
+                )
+            })
+            .collect();
+
+        HumanReadableChangeSet {
+            writes,
+            deltas,
+            group_classifications,
+            marker: self.marker(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta_change_set::{delta_sub, DeltaChangeSet};
+    use aptos_types::{
+        access_path::AccessPath,
+        state_store::table::TableHandle,
+        transaction::{ChangeSet, NoOpChangeSetChecker},
+        write_set::WriteSetMut,
+    };
+    use move_core_types::{account_address::AccountAddress, identifier::Identifier};
+    use std::sync::Arc;
+
+    fn struct_tag(module: &str, name: &str) -> StructTag {
+        StructTag {
+            address: AccountAddress::ONE,
+            module: Identifier::new(module).unwrap(),
+            name: Identifier::new(name).unwrap(),
+            type_params: vec![],
+        }
+    }
+
+    #[test]
+    fn change_set_renders_keys_groups_and_deltas() {
+        let addr = AccountAddress::TWO;
+        let resource_key = StateKey::access_path(
+            AccessPath::resource_access_path(addr, struct_tag("account", "Account")).unwrap(),
+        );
+        let group_key = StateKey::access_path(AccessPath::resource_group_access_path(
+            addr,
+            struct_tag("object", "ObjectGroup"),
+        ));
+        let group = BTreeMap::from([(struct_tag("object", "ObjectCore"), vec![0xAB])]);
+        let table_key = StateKey::table_item(TableHandle(AccountAddress::ONE), vec![0x01, 0x02]);
+
+        let write_set = WriteSetMut::new(vec![
+            (resource_key, WriteOp::Deletion),
+            (group_key, WriteOp::Creation(bcs::to_bytes(&group).unwrap())),
+        ])
+        .freeze()
+        .unwrap();
+        let change_set_ext = ChangeSetExt::new(
+            DeltaChangeSet::new(vec![(table_key, delta_sub(5, 100))]),
+            ChangeSet::new(write_set, vec![], &NoOpChangeSetChecker).unwrap(),
+            Arc::new(NoOpChangeSetChecker),
+        );
+
+        let rendered = change_set_ext.to_human_readable(&DecodingAnnotator);
+        let resource = rendered
+            .writes
+            .iter()
+            .find(|write| write.kind == HumanReadableWriteKind::Deletion)
+            .unwrap();
+        assert_eq!(resource.key, HumanReadableKey::Resource {
+            address: "0x2".to_string(),
+            resource: "0x1::account::Account".to_string(),
+        });
+        let group = rendered
+            .writes
+            .iter()
+            .find(|write| write.kind == HumanReadableWriteKind::Creation)
+            .unwrap();
+        assert_eq!(
+            group.members,
+            Some(BTreeMap::from([(
+                "0x1::object::ObjectCore".to_string(),
+                "ab".to_string()
+            )]))
+        );
+        assert_eq!(rendered.deltas, vec![HumanReadableDelta {
+            key: HumanReadableKey::TableItem {
+                handle: "0x1".to_string(),
+                key: "0102".to_string(),
+            },
+            delta: "-5".to_string(),
+        }]);
+
+        let json = serde_json::to_value(&rendered).unwrap();
+        assert_eq!(json["deltas"][0]["key"]["type"], "table_item");
+        assert!(json.get("group_classifications").is_none());
+
+        let rendered = change_set_ext
+            .with_group_classifications(BTreeMap::from([
+                (struct_tag("account", "Account"), None),
+                (
+                    struct_tag("object", "ObjectCore"),
+                    Some(struct_tag("object", "ObjectGroup")),
+                ),
+            ]))
+            .to_human_readable(&DecodingAnnotator);
+        assert_eq!(
+            rendered.group_classifications,
+            BTreeMap::from([
+                ("0x1::account::Account".to_string(), None),
+                (
+                    "0x1::object::ObjectCore".to_string(),
+                    Some("0x1::object::ObjectGroup".to_string())
+                ),
+            ])
+        );
+    }
+}
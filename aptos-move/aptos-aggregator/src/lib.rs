@@ -3,5 +3,6 @@
 
 pub mod aggregator_extension;
 pub mod delta_change_set;
+pub mod human_readable;
 mod module;
 pub mod transaction;
@@ -1,14 +1,32 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::delta_change_set::{deserialize, DeltaChangeSet};
+use crate::delta_change_set::{deserialize, DeltaChangeSet, DeltaOp};
 use anyhow::bail;
 use aptos_state_view::StateView;
 use aptos_types::{
+    access_path::{AccessPath, Path},
+    contract_event::ContractEvent,
+    state_store::{
+        state_key::{StateKey, StateKeyInner},
+        state_value::StateValue,
+    },
     transaction::{ChangeSet, CheckChangeSet, TransactionOutput},
+    vm_status::{StatusCode, VMStatus},
     write_set::{TransactionWrite, WriteOp, WriteSet, WriteSetMut},
 };
-use std::{collections::btree_map, sync::Arc};
+use move_core_types::{
+    account_address::AccountAddress,
+    effects::Op,
+    gas_algebra::NumBytes,
+    language_storage::{ModuleId, StructTag},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{btree_map, BTreeMap},
+    fmt,
+    sync::Arc,
+};
 
 /// Helpful trait for e.g. extracting u128 value out of TransactionWrite that we know is
 /// for aggregator (i.e. if we have seen a DeltaOp for the same access path).
@@ -27,24 +45,589 @@ impl AggregatorValue {
     }
 }
 
+/// The kinds of writes a change set holds, so that e.g. executors can decide whether to flush
+/// their module caches without scanning the write set.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ChangeSetFlags {
+    pub module_writes: bool,
+    pub resource_group_writes: bool,
+    pub aggregator_deltas: bool,
+}
+
+impl ChangeSetFlags {
+    /// Derives the flags from the contents of a change set, for change sets that were not built
+    /// by converting a session's output.
+    pub fn scan(delta_change_set: &DeltaChangeSet, change_set: &ChangeSet) -> Self {
+        let mut flags = Self {
+            aggregator_deltas: !delta_change_set.is_empty(),
+            ..Self::default()
+        };
+        flags.add_writes(change_set.write_set());
+        flags
+    }
+
+    fn add_writes(&mut self, write_set: &WriteSet) {
+        for (state_key, _) in write_set.iter() {
+            match path_of(state_key) {
+                Some(Path::Code(_)) => self.module_writes = true,
+                Some(Path::ResourceGroup(_)) => self.resource_group_writes = true,
+                _ => (),
+            }
+        }
+    }
+}
+
+fn path_of(state_key: &StateKey) -> Option<Path> {
+    match state_key.inner() {
+        StateKeyInner::AccessPath(access_path) => Some(access_path.get_path()),
+        _ => None,
+    }
+}
+
+/// Which part of a session's output a write op was converted from, for diagnosing unexpected
+/// writes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WriteOpSource {
+    Resource,
+    ResourceGroup { group_tag: StructTag },
+    Module,
+    TableItem,
+    AggregatorWrite,
+    AggregatorDelete,
+}
+
+impl fmt::Display for WriteOpSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteOpSource::Resource => write!(f, "resource"),
+            WriteOpSource::ResourceGroup { group_tag } => write!(f, "resource group {}", group_tag),
+            WriteOpSource::Module => write!(f, "module"),
+            WriteOpSource::TableItem => write!(f, "table item"),
+            WriteOpSource::AggregatorWrite => write!(f, "aggregator write"),
+            WriteOpSource::AggregatorDelete => write!(f, "aggregator delete"),
+        }
+    }
+}
+
+/// The changes a session made to the members of a resource group, left to be merged into the
+/// group's blob once it is read, see `ChangeSetExt::materialize_group_patches`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GroupPatch {
+    pub addr: AccountAddress,
+    pub group_tag: StructTag,
+    pub member_ops: BTreeMap<StructTag, Op<Vec<u8>>>,
+    /// Whether the group is left out of the write set if it ends up as it is in storage, as
+    /// sessions stripping no-op modifications do.
+    pub strips_noop_modification: bool,
+}
+
+impl GroupPatch {
+    pub fn state_key(&self) -> StateKey {
+        StateKey::access_path(AccessPath::resource_group_access_path(
+            self.addr,
+            self.group_tag.clone(),
+        ))
+    }
+
+    /// The op writing the patched group, given its current blob. Like merging the group when the
+    /// session is finished, adding an existing member or changing a missing one is an invariant
+    /// violation, and whether the group is left empty is only decided once all member ops are
+    /// applied, so their order does not matter.
+    fn apply(&self, base: Option<&[u8]>) -> Result<WriteOp, VMStatus> {
+        let invariant_violation = |message: &str| {
+            VMStatus::Error(
+                StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR,
+                Some(format!("{} {} at {}", message, self.group_tag, self.addr)),
+            )
+        };
+
+        let mut members: BTreeMap<StructTag, Vec<u8>> = match base {
+            Some(base) => bcs::from_bytes(base)
+                .map_err(|_| invariant_violation("Failed to decode resource group"))?,
+            None => BTreeMap::new(),
+        };
+        for (struct_tag, op) in &self.member_ops {
+            let consistent = match op {
+                Op::New(data) => members.insert(struct_tag.clone(), data.clone()).is_none(),
+                Op::Modify(data) => members.insert(struct_tag.clone(), data.clone()).is_some(),
+                Op::Delete => members.remove(struct_tag).is_some(),
+            };
+            if !consistent {
+                return Err(invariant_violation(
+                    "Inconsistent member ops for resource group",
+                ));
+            }
+        }
+
+        if members.is_empty() {
+            return Ok(WriteOp::Deletion);
+        }
+        let data = bcs::to_bytes(&members)
+            .map_err(|_| invariant_violation("Failed to serialize resource group"))?;
+        Ok(if base.is_some() {
+            WriteOp::Modification(data)
+        } else {
+            WriteOp::Creation(data)
+        })
+    }
+}
+
+/// The `WriteSet` version tag.
+const WRITE_SET_VERSION_BYTES: u64 = 1;
+/// The variant tag of a `StateKey` or a `WriteOp`.
+const VARIANT_TAG_BYTES: u64 = 1;
+/// The account address of an access path or the handle of a table.
+const ADDRESS_BYTES: u64 = AccountAddress::LENGTH as u64;
+/// `StateValueMetadata::V0`: its tag, payer, deposit and creation time.
+const STATE_VALUE_METADATA_BYTES: u64 = VARIANT_TAG_BYTES + ADDRESS_BYTES + 8 + 8;
+
+/// The BCS-serialized size of a `WriteSet`, kept up to date as write ops are added and removed
+/// so that it is known without serializing the write set.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WriteSetSize {
+    num_ops: u64,
+    op_bytes: u64,
+}
+
+impl WriteSetSize {
+    /// Sums up the sizes of the write ops of a write set that was not tracked while built.
+    pub fn of(write_set: &WriteSet) -> Self {
+        let mut size = Self::default();
+        for (state_key, op) in write_set.iter() {
+            size.add(state_key, op);
+        }
+        size
+    }
+
+    pub fn add(&mut self, state_key: &StateKey, op: &WriteOp) {
+        self.num_ops += 1;
+        self.op_bytes += Self::entry_bytes(state_key, op);
+    }
+
+    pub fn remove(&mut self, state_key: &StateKey, op: &WriteOp) {
+        self.num_ops -= 1;
+        self.op_bytes -= Self::entry_bytes(state_key, op);
+    }
+
+    pub fn num_ops(&self) -> u64 {
+        self.num_ops
+    }
+
+    pub fn serialized_size(&self) -> u64 {
+        WRITE_SET_VERSION_BYTES + uleb128_bytes(self.num_ops) + self.op_bytes
+    }
+
+    fn entry_bytes(state_key: &StateKey, op: &WriteOp) -> u64 {
+        use WriteOp::*;
+
+        let key_bytes = match state_key.inner() {
+            StateKeyInner::AccessPath(access_path) => {
+                ADDRESS_BYTES + byte_vector_bytes(access_path.path.len())
+            },
+            StateKeyInner::TableItem { key, .. } => ADDRESS_BYTES + byte_vector_bytes(key.len()),
+            StateKeyInner::Raw(bytes) => byte_vector_bytes(bytes.len()),
+        };
+        let op_bytes = match op {
+            Creation(data) | Modification(data) => byte_vector_bytes(data.len()),
+            Deletion => 0,
+            CreationWithMetadata { data, .. } | ModificationWithMetadata { data, .. } => {
+                byte_vector_bytes(data.len()) + STATE_VALUE_METADATA_BYTES
+            },
+            DeletionWithMetadata { .. } => STATE_VALUE_METADATA_BYTES,
+        };
+        2 * VARIANT_TAG_BYTES + key_bytes + op_bytes
+    }
+}
+
+/// A byte vector is serialized as its ULEB128-encoded length followed by its bytes.
+fn event_sizes(events: &[ContractEvent]) -> Vec<u64> {
+    events.iter().map(|event| event.size() as u64).collect()
+}
+
+fn byte_vector_bytes(len: usize) -> u64 {
+    uleb128_bytes(len as u64) + len as u64
+}
+
+fn uleb128_bytes(mut value: u64) -> u64 {
+    let mut bytes = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        bytes += 1;
+    }
+    bytes
+}
+
+/// By how many bytes a blob of size `old` grows when replaced by one of size `new`, `None` being
+/// no blob at all.
+fn size_delta(old: Option<&[u8]>, new: Option<&[u8]>) -> i64 {
+    new.map_or(0, <[u8]>::len) as i64 - old.map_or(0, <[u8]>::len) as i64
+}
+
+/// Adds `delta` to the group size delta of `state_key`, see `ChangeSetExt::group_size_deltas`.
+fn add_group_size_delta(deltas: &mut Vec<(StateKey, i64)>, state_key: &StateKey, delta: i64) {
+    match deltas.iter_mut().find(|(key, _)| key == state_key) {
+        Some((_, total)) => *total += delta,
+        None => deltas.push((state_key.clone(), delta)),
+    }
+}
+
+/// Identifies the execution that produced a change set, e.g. the incarnation of a transaction
+/// re-executed by a parallel executor, so that logs of re-executions can be told apart. It is
+/// never part of the committed change set.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub struct ExecutionMarker {
+    pub epoch: u64,
+    pub incarnation: u64,
+}
+
+impl fmt::Display for ExecutionMarker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "epoch {}, incarnation {}", self.epoch, self.incarnation)
+    }
+}
+
 /// Extension of `ChangeSet` that also holds deltas.
 pub struct ChangeSetExt {
     pub delta_change_set: DeltaChangeSet,
     pub change_set: ChangeSet,
     checker: Arc<dyn CheckChangeSet>,
+    modules_changed: Vec<ModuleId>,
+    flags: ChangeSetFlags,
+    write_set_size: WriteSetSize,
+    marker: Option<ExecutionMarker>,
+    write_op_sources: Option<BTreeMap<StateKey, WriteOpSource>>,
+    group_classifications: BTreeMap<StructTag, Option<StructTag>>,
+    group_patches: Vec<GroupPatch>,
+    group_size_deltas: Vec<(StateKey, i64)>,
+    event_sizes: Vec<u64>,
 }
 
 impl ChangeSetExt {
+    /// Creates a change set from its parts, scanning them for its `ChangeSetFlags` and the size
+    /// of its write set.
     pub fn new(
         delta_change_set: DeltaChangeSet,
         change_set: ChangeSet,
         checker: Arc<dyn CheckChangeSet>,
     ) -> Self {
+        let flags = ChangeSetFlags::scan(&delta_change_set, &change_set);
+        let write_set_size = WriteSetSize::of(change_set.write_set());
+        Self::new_with_flags(delta_change_set, change_set, checker, flags, write_set_size)
+    }
+
+    /// Like `new`, but takes the flags and write set size the caller recorded while building
+    /// the change set.
+    pub fn new_with_flags(
+        delta_change_set: DeltaChangeSet,
+        change_set: ChangeSet,
+        checker: Arc<dyn CheckChangeSet>,
+        flags: ChangeSetFlags,
+        write_set_size: WriteSetSize,
+    ) -> Self {
+        let event_sizes = event_sizes(change_set.events());
         ChangeSetExt {
             delta_change_set,
             change_set,
             checker,
+            modules_changed: vec![],
+            flags,
+            write_set_size,
+            marker: None,
+            write_op_sources: None,
+            group_classifications: BTreeMap::new(),
+            group_patches: vec![],
+            group_size_deltas: vec![],
+            event_sizes,
+        }
+    }
+
+    /// Tags the change set with the execution that produced it.
+    pub fn with_marker(mut self, marker: ExecutionMarker) -> Self {
+        self.marker = Some(marker);
+        self
+    }
+
+    pub fn marker(&self) -> Option<ExecutionMarker> {
+        self.marker
+    }
+
+    /// Records where each write op was converted from, see `write_op_sources`.
+    pub fn with_write_op_sources(
+        mut self,
+        write_op_sources: BTreeMap<StateKey, WriteOpSource>,
+    ) -> Self {
+        self.write_op_sources = Some(write_op_sources);
+        self
+    }
+
+    /// Where each write op was converted from, if recorded. Like the marker, this is never part
+    /// of the committed change set.
+    pub fn write_op_sources(&self) -> Option<&BTreeMap<StateKey, WriteOpSource>> {
+        self.write_op_sources.as_ref()
+    }
+
+    /// Records the resource group each resource the change set was built from was classified
+    /// into, see `group_classifications`.
+    pub fn with_group_classifications(
+        mut self,
+        group_classifications: BTreeMap<StructTag, Option<StructTag>>,
+    ) -> Self {
+        self.group_classifications.extend(group_classifications);
+        self
+    }
+
+    /// For every resource type written while building the change set, the resource group it was
+    /// found to be a member of, or `None` if it is a standalone resource. Meant for checking other
+    /// classifiers, e.g. the indexer's, against the VM's; never part of the committed change set.
+    pub fn group_classifications(&self) -> &BTreeMap<StructTag, Option<StructTag>> {
+        &self.group_classifications
+    }
+
+    /// Adds resource group changes whose groups were not read yet, see `group_patches`.
+    pub fn with_group_patches(mut self, group_patches: Vec<GroupPatch>) -> Self {
+        self.group_patches.extend(group_patches);
+        if !self.group_patches.is_empty() {
+            self.flags.resource_group_writes = true;
+        }
+        self
+    }
+
+    /// The resource group changes left to be merged into their groups, in the order they were
+    /// made. The change set must not be committed before `materialize_group_patches`.
+    pub fn group_patches(&self) -> &[GroupPatch] {
+        &self.group_patches
+    }
+
+    /// Reads the groups of the pending `group_patches` from the write set or else from
+    /// `state_view`, and writes the patched groups, so that the result is the same as if the
+    /// groups had been read when the session was finished. Meant to be run by the executor
+    /// where its reads are tracked. The change set is checked again once the groups are written.
+    pub fn materialize_group_patches(self, state_view: &impl StateView) -> Result<Self, VMStatus> {
+        if self.group_patches.is_empty() {
+            return Ok(self);
+        }
+        let ChangeSetExt {
+            delta_change_set,
+            change_set,
+            checker,
+            modules_changed,
+            flags,
+            mut write_set_size,
+            marker,
+            mut write_op_sources,
+            group_classifications,
+            group_patches,
+            mut group_size_deltas,
+            event_sizes,
+        } = self;
+        let (write_set, events) = change_set.into_inner();
+        let mut write_set = write_set.into_mut();
+        let write_ops = write_set.as_inner_mut();
+
+        for patch in group_patches {
+            let state_key = patch.state_key();
+            match write_ops.entry(state_key) {
+                btree_map::Entry::Occupied(mut entry) => {
+                    // Written by an earlier session, whose write the patch goes on top of.
+                    let op = patch.apply(entry.get().bytes())?;
+                    add_group_size_delta(
+                        &mut group_size_deltas,
+                        entry.key(),
+                        size_delta(entry.get().bytes(), op.bytes()),
+                    );
+                    write_set_size.remove(entry.key(), entry.get());
+                    let keep = WriteOp::squash(entry.get_mut(), op).map_err(|err| {
+                        VMStatus::Error(
+                            StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR,
+                            Some(err.to_string()),
+                        )
+                    })?;
+                    if keep {
+                        write_set_size.add(entry.key(), entry.get());
+                    } else {
+                        entry.remove();
+                    }
+                },
+                btree_map::Entry::Vacant(entry) => {
+                    let existing = state_view
+                        .get_state_value(entry.key())
+                        .map_err(|_| VMStatus::Error(StatusCode::STORAGE_ERROR, None))?;
+                    let op = patch.apply(existing.as_ref().map(StateValue::bytes))?;
+                    add_group_size_delta(
+                        &mut group_size_deltas,
+                        entry.key(),
+                        size_delta(existing.as_ref().map(StateValue::bytes), op.bytes()),
+                    );
+                    let noop = match &op {
+                        WriteOp::Modification(data) => {
+                            patch.strips_noop_modification
+                                && existing == Some(StateValue::new_legacy(data.clone()))
+                        },
+                        _ => false,
+                    };
+                    if !noop {
+                        if let Some(sources) = &mut write_op_sources {
+                            sources.insert(entry.key().clone(), WriteOpSource::ResourceGroup {
+                                group_tag: patch.group_tag.clone(),
+                            });
+                        }
+                        write_set_size.add(entry.key(), &op);
+                        entry.insert(op);
+                    }
+                },
+            }
+        }
+        if let Some(sources) = &mut write_op_sources {
+            sources.retain(|state_key, _| write_ops.contains_key(state_key));
+        }
+
+        let write_set = write_set
+            .freeze()
+            .map_err(|_| VMStatus::Error(StatusCode::DATA_FORMAT_ERROR, None))?;
+        Ok(Self {
+            delta_change_set,
+            change_set: ChangeSet::new(write_set, events, checker.as_ref())?,
+            checker,
+            modules_changed,
+            flags,
+            write_set_size,
+            marker,
+            write_op_sources,
+            group_classifications,
+            group_patches: vec![],
+            group_size_deltas,
+            event_sizes,
+        })
+    }
+
+    /// Records how much the resource groups written by the change set grow, see
+    /// `group_size_deltas`.
+    pub fn with_group_size_deltas(mut self, group_size_deltas: Vec<(StateKey, i64)>) -> Self {
+        for (state_key, delta) in group_size_deltas {
+            add_group_size_delta(&mut self.group_size_deltas, &state_key, delta);
+        }
+        self
+    }
+
+    /// For every resource group the change set writes, by how many bytes its blob grows: the
+    /// size of the merged blob minus the size of the blob it replaces, so that a created group
+    /// counts its full size and a deleted one credits the size it had. Meant for charging storage
+    /// for the growth of groups rather than for rewriting them; never part of the committed change
+    /// set. Groups of `group_patches` are only counted once `materialize_group_patches` merged
+    /// them. Squashing change sets adds up the deltas of the same group.
+    pub fn group_size_deltas(&self) -> &[(StateKey, i64)] {
+        &self.group_size_deltas
+    }
+
+    /// The size of every event, in the order of `events`, as storage fees are charged for it:
+    /// its key, sequence number, type tag and data. Computed once when the change set is built.
+    pub fn event_sizes(&self) -> &[u64] {
+        &self.event_sizes
+    }
+
+    /// The events along with their sizes, see `event_sizes`.
+    pub fn events_with_sizes(&self) -> impl Iterator<Item = (&ContractEvent, NumBytes)> {
+        self.change_set
+            .events()
+            .iter()
+            .zip(self.event_sizes.iter().map(|size| NumBytes::new(*size)))
+    }
+
+    /// Whether the change set writes any resource group.
+    pub fn has_resource_group_writes(&self) -> bool {
+        self.flags.resource_group_writes
+    }
+
+    /// Whether the change set holds deltas left to be materialized.
+    pub fn has_aggregator_deltas(&self) -> bool {
+        self.flags.aggregator_deltas
+    }
+
+    pub fn flags(&self) -> ChangeSetFlags {
+        self.flags
+    }
+
+    /// The size of `bcs::to_bytes(write_set)`, e.g. for chunking outputs, known without
+    /// serializing the write set.
+    pub fn serialized_size_estimate(&self) -> u64 {
+        self.write_set_size.serialized_size()
+    }
+
+    /// Records the modules the change set writes, see `modules_changed`.
+    pub fn with_modules_changed(mut self, modules_changed: Vec<ModuleId>) -> Self {
+        self.modules_changed = modules_changed;
+        self
+    }
+
+    /// The modules published, upgraded or deleted by the change set.
+    ///
+    /// The executor does not use them to invalidate cached code yet: it still flushes the whole
+    /// loader cache after a failed publish, and falls back to sequential execution when module
+    /// writes may race with reads.
+    pub fn modules_changed(&self) -> &[ModuleId] {
+        &self.modules_changed
+    }
+
+    /// Rewrites the write set op by op, e.g. to adjust a release write set built by publishing
+    /// the framework in a session. `f` is called with every write op in key order and returns the
+    /// op to write instead, or `None` to drop it. The rewritten change set is checked again, and
+    /// keeps the deltas and events. Pending `group_patches` must be materialized first.
+    pub fn map_write_ops(
+        self,
+        mut f: impl FnMut(&StateKey, WriteOp) -> Option<WriteOp>,
+    ) -> Result<Self, VMStatus> {
+        if !self.group_patches.is_empty() {
+            return Err(VMStatus::Error(
+                StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR,
+                Some("Resource group patches must be materialized before rewriting".to_string()),
+            ));
+        }
+        let ChangeSetExt {
+            delta_change_set,
+            change_set,
+            checker,
+            mut modules_changed,
+            marker,
+            mut write_op_sources,
+            group_classifications,
+            mut group_size_deltas,
+            ..
+        } = self;
+        let (write_set, events) = change_set.into_inner();
+        let write_set = WriteSetMut::new(
+            write_set
+                .into_iter()
+                .filter_map(|(state_key, op)| f(&state_key, op).map(|op| (state_key, op))),
+        )
+        .freeze()
+        .map_err(|_| VMStatus::Error(StatusCode::DATA_FORMAT_ERROR, None))?;
+
+        modules_changed.retain(|module_id| {
+            write_set
+                .get(&StateKey::access_path(AccessPath::from(module_id)))
+                .is_some()
+        });
+        if let Some(sources) = &mut write_op_sources {
+            sources.retain(|state_key, _| write_set.get(state_key).is_some());
         }
+        group_size_deltas.retain(|(state_key, _)| write_set.get(state_key).is_some());
+        let change_set = ChangeSet::new(write_set, events, checker.as_ref())?;
+        Ok(Self {
+            modules_changed,
+            marker,
+            write_op_sources,
+            group_classifications,
+            group_size_deltas,
+            ..Self::new(delta_change_set, change_set, checker)
+        })
+    }
+
+    /// Drops the write ops to the keys `predicate` rejects, see `map_write_ops`.
+    pub fn retain_keys(
+        self,
+        mut predicate: impl FnMut(&StateKey) -> bool,
+    ) -> Result<Self, VMStatus> {
+        self.map_write_ops(|state_key, op| predicate(state_key).then_some(op))
     }
 
     pub fn change_set(&self) -> &ChangeSet {
@@ -59,96 +642,398 @@ impl ChangeSetExt {
         self.change_set.write_set()
     }
 
+    /// The parts to commit. Bookkeeping such as the execution marker is dropped.
     pub fn into_inner(self) -> (DeltaChangeSet, ChangeSet) {
+        debug_assert!(
+            self.group_patches.is_empty(),
+            "Resource group patches must be materialized first"
+        );
         (self.delta_change_set, self.change_set)
     }
 
-    pub fn squash_delta_change_set(self, other: DeltaChangeSet) -> anyhow::Result<Self> {
-        use btree_map::Entry::*;
+    /// Applies the deltas to the values in `state_view`, see `DeltaChangeSet::try_materialize`.
+    pub fn try_materialize(&self, state_view: &impl StateView) -> Result<WriteSetMut, VMStatus> {
+        self.delta_change_set.try_materialize(state_view)
+    }
+
+    pub fn squash_delta_change_set(mut self, mut other: DeltaChangeSet) -> anyhow::Result<Self> {
         use WriteOp::*;
 
         let checker = self.checker.clone();
-        let (mut delta_set, change_set) = self.into_inner();
+        let modules_changed = std::mem::take(&mut self.modules_changed);
+        let mut flags = self.flags;
+        let mut write_set_size = self.write_set_size;
+        let marker = self.marker;
+        let write_op_sources = self.write_op_sources.take();
+        let group_classifications = std::mem::take(&mut self.group_classifications);
+        let group_patches = std::mem::take(&mut self.group_patches);
+        let group_size_deltas = std::mem::take(&mut self.group_size_deltas);
+        let event_sizes = std::mem::take(&mut self.event_sizes);
+        let (delta_set, change_set) = self.into_inner();
         let (write_set, events) = change_set.into_inner();
         let mut write_set = write_set.into_mut();
-
-        let delta_ops = delta_set.as_inner_mut();
         let write_ops = write_set.as_inner_mut();
 
-        for (key, mut op) in other.into_iter() {
-            if let Some(r) = write_ops.get_mut(&key) {
-                match r {
-                    Creation(data)
-                    | Modification(data)
-                    | CreationWithMetadata { data, .. }
-                    | ModificationWithMetadata { data, .. } => {
-                        let val: u128 = bcs::from_bytes(data)?;
-                        *data = bcs::to_bytes(&op.apply_to(val)?)?;
-                    },
-                    Deletion | DeletionWithMetadata { .. } => {
-                        bail!("Failed to apply Aggregator delta -- value already deleted");
-                    },
-                }
-            } else {
-                match delta_ops.entry(key) {
-                    Occupied(entry) => {
-                        // In this case, we need to merge the new incoming `op` to the existing
-                        // delta, ensuring the strict ordering.
-                        op.merge_onto(*entry.get())?;
-                        *entry.into_mut() = op;
-                    },
-                    Vacant(entry) => {
-                        entry.insert(op);
-                    },
-                }
+        // Deltas to values this change set writes are applied to the written values, the others
+        // are merged onto its deltas, ensuring the strict ordering.
+        for (key, r) in write_ops.iter_mut() {
+            let op = match other.remove(key) {
+                Some(op) => op,
+                None => continue,
+            };
+            write_set_size.remove(key, r);
+            match r {
+                Creation(data)
+                | Modification(data)
+                | CreationWithMetadata { data, .. }
+                | ModificationWithMetadata { data, .. } => {
+                    let val: u128 = bcs::from_bytes(data)?;
+                    *data = bcs::to_bytes(&op.apply_to(val)?)?;
+                },
+                Deletion | DeletionWithMetadata { .. } => {
+                    bail!("Failed to apply Aggregator delta -- value already deleted");
+                },
             }
+            write_set_size.add(key, r);
         }
+        let delta_set = other.merge_onto(delta_set)?;
+        flags.aggregator_deltas = !delta_set.is_empty();
 
         Ok(Self {
             delta_change_set: delta_set,
             change_set: ChangeSet::new(write_set.freeze()?, events, checker.as_ref())?,
             checker,
+            modules_changed,
+            flags,
+            write_set_size,
+            marker,
+            write_op_sources,
+            group_classifications,
+            group_patches,
+            group_size_deltas,
+            event_sizes,
         })
     }
 
-    pub fn squash_change_set(self, other: ChangeSet) -> anyhow::Result<Self> {
+    pub fn squash_change_set(mut self, other: ChangeSet) -> anyhow::Result<Self> {
+        // Where the writes of a bare change set come from is unknown.
+        if let Some(sources) = &mut self.write_op_sources {
+            for (state_key, _) in other.write_set().iter() {
+                sources.remove(state_key);
+            }
+        }
+        let mut other_flags = ChangeSetFlags::default();
+        other_flags.add_writes(other.write_set());
+        let other_event_sizes = event_sizes(other.events());
+        self.squash_change_set_with_flags(other, other_flags, other_event_sizes)
+    }
+
+    fn squash_change_set_with_flags(
+        mut self,
+        other: ChangeSet,
+        other_flags: ChangeSetFlags,
+        other_event_sizes: Vec<u64>,
+    ) -> anyhow::Result<Self> {
         use btree_map::Entry::*;
 
         let checker = self.checker.clone();
+        let modules_changed = std::mem::take(&mut self.modules_changed);
+        let mut flags = self.flags;
+        let mut write_set_size = self.write_set_size;
+        let marker = self.marker;
+        let mut write_op_sources = self.write_op_sources.take();
+        let group_classifications = std::mem::take(&mut self.group_classifications);
+        let group_patches = std::mem::take(&mut self.group_patches);
+        let group_size_deltas = std::mem::take(&mut self.group_size_deltas);
+        let mut event_sizes = std::mem::take(&mut self.event_sizes);
         let (mut delta, change_set) = self.into_inner();
         let (write_set, mut events) = change_set.into_inner();
         let mut write_set = write_set.into_mut();
         let write_ops = write_set.as_inner_mut();
 
+        flags.module_writes |= other_flags.module_writes;
+        flags.resource_group_writes |= other_flags.resource_group_writes;
         let (other_write_set, other_events) = other.into_inner();
 
         for (key, op) in other_write_set.into_iter() {
+            // The patch would have to be merged under the write, which needs the group read.
+            if group_patches.iter().any(|patch| patch.state_key() == key) {
+                bail!("Cannot squash a write to a resource group with pending patches");
+            }
             match write_ops.entry(key) {
                 Occupied(mut entry) => {
-                    if !WriteOp::squash(entry.get_mut(), op)? {
+                    write_set_size.remove(entry.key(), entry.get());
+                    if WriteOp::squash(entry.get_mut(), op)? {
+                        write_set_size.add(entry.key(), entry.get());
+                    } else {
                         entry.remove();
                     }
                 },
                 Vacant(entry) => {
                     delta.remove(entry.key());
+                    write_set_size.add(entry.key(), &op);
                     entry.insert(op);
                 },
             }
         }
 
         events.extend(other_events);
+        event_sizes.extend(other_event_sizes);
+        flags.aggregator_deltas = !delta.is_empty();
+        // Keys whose writes cancelled out no longer have a source.
+        if let Some(sources) = &mut write_op_sources {
+            sources.retain(|state_key, _| write_ops.contains_key(state_key));
+        }
 
         Ok(Self {
             delta_change_set: delta,
             change_set: ChangeSet::new(write_set.freeze()?, events, checker.as_ref())?,
             checker,
+            modules_changed,
+            flags,
+            write_set_size,
+            marker,
+            write_op_sources,
+            group_classifications,
+            group_patches,
+            group_size_deltas,
+            event_sizes,
         })
     }
 
-    pub fn squash(self, other: Self) -> anyhow::Result<Self> {
+    pub fn squash(mut self, mut other: Self) -> anyhow::Result<Self> {
+        for module_id in &other.modules_changed {
+            if !self.modules_changed.contains(module_id) {
+                self.modules_changed.push(module_id.clone());
+            }
+        }
+        self.marker = other.marker.or(self.marker);
+        // Writes of `other` replace the ones to the same keys.
+        let other_sources = other.write_op_sources.clone();
+        self.write_op_sources = match (self.write_op_sources.take(), other_sources) {
+            (Some(mut sources), Some(other_sources)) => {
+                sources.extend(other_sources);
+                Some(sources)
+            },
+            (sources, other_sources) => sources.or(other_sources),
+        };
+        self.group_classifications
+            .extend(std::mem::take(&mut other.group_classifications));
+        // The flags of `other` are already known, so its writes need not be scanned.
+        let other_flags = other.flags;
+        let other_patches = std::mem::take(&mut other.group_patches);
+        let other_group_size_deltas = std::mem::take(&mut other.group_size_deltas);
+        let other_event_sizes = std::mem::take(&mut other.event_sizes);
         let (delta_change_set, change_set) = other.into_inner();
-        self.squash_change_set(change_set)?
-            .squash_delta_change_set(delta_change_set)
+        Ok(self
+            .squash_change_set_with_flags(change_set, other_flags, other_event_sizes)?
+            .squash_delta_change_set(delta_change_set)?
+            .with_group_patches(other_patches)
+            .with_group_size_deltas(other_group_size_deltas))
+    }
+}
+
+impl fmt::Debug for ChangeSetExt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChangeSetExt")
+            .field("delta_change_set", &self.delta_change_set)
+            .field("change_set", &self.change_set)
+            .field("modules_changed", &self.modules_changed)
+            .field("has_module_writes", &self.flags.module_writes)
+            .field(
+                "has_resource_group_writes",
+                &self.flags.resource_group_writes,
+            )
+            .field("has_aggregator_deltas", &self.flags.aggregator_deltas)
+            .field("marker", &self.marker)
+            .field("write_op_sources", &self.write_op_sources)
+            .field("group_classifications", &self.group_classifications)
+            .field("group_patches", &self.group_patches)
+            .field("group_size_deltas", &self.group_size_deltas)
+            .finish()
+    }
+}
+
+/// The parts of a `ChangeSetExt` that are encoded by `ChangeSetExt::to_bytes`.
+#[derive(Deserialize, Serialize)]
+struct ChangeSetExtEnvelope<D, C> {
+    delta_change_set: D,
+    change_set: C,
+}
+
+impl ChangeSetExt {
+    /// The version byte `to_bytes` starts with.
+    pub const ENCODING_VERSION: u8 = 0;
+
+    /// Encodes the change set for tools outside of the VM, e.g. replay tools written in other
+    /// languages. The layout is a version byte, currently `ENCODING_VERSION`, followed by the BCS
+    /// encoding of:
+    ///
+    /// ```text
+    /// struct Envelope {
+    ///     delta_change_set: struct {
+    ///         delta_change_set: map<StateKey, DeltaOp>,
+    ///         provenance: map<StateKey, struct { handle: address, key: address }>,
+    ///     },
+    ///     change_set: ChangeSet,
+    /// }
+    /// struct DeltaOp { max_positive: u128, min_negative: u128, limit: u128, update: DeltaUpdate }
+    /// enum DeltaUpdate { Plus(u128), Minus(u128) }
+    /// ```
+    ///
+    /// `StateKey` and `ChangeSet` are encoded as they are everywhere else, so the write set is in
+    /// its canonical, committed form. Bookkeeping such as the execution marker is not encoded,
+    /// and pending `group_patches` must be materialized first.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        if !self.group_patches.is_empty() {
+            bail!("Resource group patches must be materialized before encoding");
+        }
+        let mut bytes = vec![Self::ENCODING_VERSION];
+        bcs::serialize_into(&mut bytes, &ChangeSetExtEnvelope {
+            delta_change_set: &self.delta_change_set,
+            change_set: &self.change_set,
+        })?;
+        Ok(bytes)
+    }
+
+    /// Decodes a change set encoded by `to_bytes`, checking it with `checker`.
+    pub fn from_bytes(bytes: &[u8], checker: Arc<dyn CheckChangeSet>) -> anyhow::Result<Self> {
+        let (version, envelope) = match bytes.split_first() {
+            Some(parts) => parts,
+            None => bail!("Encoded change set is empty"),
+        };
+        if *version != Self::ENCODING_VERSION {
+            bail!("Unsupported change set encoding version {}", version);
+        }
+        let envelope: ChangeSetExtEnvelope<DeltaChangeSet, ChangeSet> = bcs::from_bytes(envelope)?;
+        let (write_set, events) = envelope.change_set.into_inner();
+        let change_set = ChangeSet::new(write_set, events, checker.as_ref())?;
+        Ok(Self::new(envelope.delta_change_set, change_set, checker))
+    }
+}
+
+/// What a block of change sets does to a state item, once all of them are squashed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FinalOpKind {
+    Creation,
+    Modification,
+    Deletion,
+}
+
+/// The combined effects of a sequence of change sets, e.g. the outputs of a block, borrowing
+/// the keys from the change sets rather than copying them. Built by `ChangeSetExt::aggregate`.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct BlockChangeSummary<'a> {
+    /// The kind of the squashed write to every key written. A key created and deleted again
+    /// within the block is not written at all and does not appear.
+    pub final_ops: BTreeMap<&'a StateKey, FinalOpKind>,
+    /// The deltas applied to every aggregator after its last write in the block, or over the
+    /// whole block if it was not written, merged into one.
+    pub deltas: BTreeMap<&'a StateKey, DeltaOp>,
+    /// The size of the keys and values of the squashed creations and modifications.
+    pub total_bytes: u64,
+    /// The number of slots created by the block, by the payer of the transaction that created
+    /// them. Slots deleted again within the block are not counted.
+    pub slots_created: BTreeMap<AccountAddress, u64>,
+}
+
+/// The squashed write to a key while a block is aggregated.
+struct AggregatedWrite {
+    kind: FinalOpKind,
+    value_len: usize,
+    creator: Option<AccountAddress>,
+}
+
+impl ChangeSetExt {
+    /// Summarizes the effects of `change_sets`, in the order they are given, each with the payer
+    /// of its transaction, as change sets do not record who pays for them.
+    ///
+    /// Writes are squashed by kind as `WriteOp::squash` would squash them, without copying their
+    /// values, e.g. a slot created by one transaction and modified by a later one remains a
+    /// creation attributed to the first payer, and one deleted by a later transaction is dropped.
+    /// Fails on writes that could not follow each other, e.g. modifying a deleted slot, and on
+    /// deltas that overflow when merged.
+    pub fn aggregate<'a>(
+        change_sets: impl IntoIterator<Item = (AccountAddress, &'a ChangeSetExt)>,
+    ) -> anyhow::Result<BlockChangeSummary<'a>> {
+        use FinalOpKind::*;
+
+        let mut writes: BTreeMap<&'a StateKey, AggregatedWrite> = BTreeMap::new();
+        let mut deltas: BTreeMap<&'a StateKey, DeltaOp> = BTreeMap::new();
+        for (payer, change_set) in change_sets {
+            for (key, op) in change_set.write_set() {
+                let kind = if op.is_creation() {
+                    Creation
+                } else if op.is_modification() {
+                    Modification
+                } else {
+                    Deletion
+                };
+                let value_len = op.bytes().map_or(0, |bytes| bytes.len());
+                // A write replaces whatever the deltas before it did.
+                deltas.remove(key);
+                match writes.entry(key) {
+                    btree_map::Entry::Vacant(entry) => {
+                        entry.insert(AggregatedWrite {
+                            kind,
+                            value_len,
+                            creator: (kind == Creation).then_some(payer),
+                        });
+                    },
+                    btree_map::Entry::Occupied(mut entry) => {
+                        let previous = entry.get_mut();
+                        match (previous.kind, kind) {
+                            (Creation, Modification) => previous.value_len = value_len,
+                            (Creation, Deletion) => {
+                                entry.remove();
+                            },
+                            (Modification, Modification) => previous.value_len = value_len,
+                            (Modification, Deletion) => {
+                                previous.kind = Deletion;
+                                previous.value_len = 0;
+                            },
+                            (Deletion, Creation) => {
+                                previous.kind = Modification;
+                                previous.value_len = value_len;
+                            },
+                            (previous_kind, kind) => bail!(
+                                "The {:?} of {:?} cannot follow its {:?}",
+                                kind,
+                                key,
+                                previous_kind
+                            ),
+                        }
+                    },
+                }
+            }
+            for (key, delta) in change_set.delta_change_set() {
+                match deltas.entry(key) {
+                    btree_map::Entry::Vacant(entry) => {
+                        entry.insert(*delta);
+                    },
+                    btree_map::Entry::Occupied(mut entry) => {
+                        let mut merged = *delta;
+                        merged.merge_onto(*entry.get())?;
+                        entry.insert(merged);
+                    },
+                }
+            }
+        }
+
+        let mut summary = BlockChangeSummary {
+            deltas,
+            ..Default::default()
+        };
+        for (key, write) in writes {
+            if write.kind != Deletion {
+                summary.total_bytes += (key.size() + write.value_len) as u64;
+            }
+            if let Some(creator) = write.creator {
+                *summary.slots_created.entry(creator).or_insert(0) += 1;
+            }
+            summary.final_ops.insert(key, write.kind);
+        }
+        Ok(summary)
     }
 }
 
@@ -203,7 +1088,7 @@ impl TransactionOutputExt {
         // rather ugly and has a lot of legacy code. This makes proper error
         // handling quite challenging.
         delta_change_set
-            .try_into_write_set_mut(state_view)
+            .try_materialize(state_view)
             .map(|materialized_deltas| Self::merge_delta_writes(txn_output, materialized_deltas))
             .expect("Failed to apply aggregator delta outputs")
     }
@@ -247,3 +1132,303 @@ impl From<TransactionOutput> for TransactionOutputExt {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta_change_set::{DeltaProvenance, DeltaUpdate};
+    use aptos_types::{
+        event::EventKey,
+        state_store::{state_value::StateValueMetadata, table::TableHandle},
+        transaction::NoOpChangeSetChecker,
+    };
+    use move_core_types::language_storage::TypeTag;
+    use proptest::{collection::vec, prelude::*};
+
+    fn arb_delta_op() -> impl Strategy<Value = DeltaOp> {
+        (
+            any::<bool>(),
+            any::<u128>(),
+            any::<u128>(),
+            any::<u128>(),
+            any::<u128>(),
+        )
+            .prop_map(|(plus, value, limit, max_positive, min_negative)| {
+                let update = if plus {
+                    DeltaUpdate::Plus(value)
+                } else {
+                    DeltaUpdate::Minus(value)
+                };
+                DeltaOp::new(update, limit, max_positive, min_negative)
+            })
+    }
+
+    fn arb_delta_change_set() -> impl Strategy<Value = DeltaChangeSet> {
+        vec(
+            (
+                any::<AccountAddress>(),
+                any::<AccountAddress>(),
+                arb_delta_op(),
+                any::<bool>(),
+            ),
+            0..8,
+        )
+        .prop_map(|deltas| {
+            let mut delta_change_set = DeltaChangeSet::empty();
+            for (handle, key, delta_op, with_provenance) in deltas {
+                let state_key = StateKey::table_item(TableHandle(handle), key.to_vec());
+                if with_provenance {
+                    delta_change_set.insert_with_provenance(
+                        (state_key, delta_op),
+                        DeltaProvenance { handle, key },
+                    );
+                } else {
+                    delta_change_set.insert((state_key, delta_op));
+                }
+            }
+            delta_change_set
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn change_sets_round_trip_through_their_encoding(
+            delta_change_set in arb_delta_change_set(),
+            write_set in any::<WriteSet>(),
+            events in vec(any::<ContractEvent>(), 0..4),
+        ) {
+            let change_set = ChangeSet::new(write_set, events, &NoOpChangeSetChecker).unwrap();
+            let change_set_ext =
+                ChangeSetExt::new(delta_change_set, change_set, Arc::new(NoOpChangeSetChecker));
+
+            let bytes = change_set_ext.to_bytes().unwrap();
+            prop_assert_eq!(bytes[0], ChangeSetExt::ENCODING_VERSION);
+            let decoded = ChangeSetExt::from_bytes(&bytes, Arc::new(NoOpChangeSetChecker)).unwrap();
+            prop_assert_eq!(decoded.delta_change_set(), change_set_ext.delta_change_set());
+            prop_assert_eq!(decoded.change_set(), change_set_ext.change_set());
+            prop_assert_eq!(decoded.flags(), change_set_ext.flags());
+
+            // The write set is embedded in its canonical encoding, after the deltas.
+            let delta_bytes = bcs::to_bytes(change_set_ext.delta_change_set()).unwrap();
+            let change_set_bytes = bcs::to_bytes(change_set_ext.change_set()).unwrap();
+            prop_assert_eq!(&bytes[1..1 + delta_bytes.len()], &delta_bytes[..]);
+            prop_assert_eq!(&bytes[1 + delta_bytes.len()..], &change_set_bytes[..]);
+        }
+    }
+
+    #[test]
+    fn unknown_encoding_versions_are_rejected() {
+        let change_set_ext = ChangeSetExt::new(
+            DeltaChangeSet::empty(),
+            ChangeSet::new(WriteSet::default(), vec![], &NoOpChangeSetChecker).unwrap(),
+            Arc::new(NoOpChangeSetChecker),
+        );
+        let mut bytes = change_set_ext.to_bytes().unwrap();
+        bytes[0] = ChangeSetExt::ENCODING_VERSION + 1;
+        assert!(ChangeSetExt::from_bytes(&bytes, Arc::new(NoOpChangeSetChecker)).is_err());
+        assert!(ChangeSetExt::from_bytes(&[], Arc::new(NoOpChangeSetChecker)).is_err());
+    }
+
+    /// Rejects write ops with more bytes than the limit it holds.
+    struct MaxWriteOpSize(usize);
+
+    impl CheckChangeSet for MaxWriteOpSize {
+        fn check_change_set(&self, change_set: &ChangeSet) -> Result<(), VMStatus> {
+            for (_, op) in change_set.write_set() {
+                if op.bytes().map_or(0, |bytes| bytes.len()) > self.0 {
+                    return Err(VMStatus::Error(
+                        StatusCode::STORAGE_WRITE_LIMIT_REACHED,
+                        None,
+                    ));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn state_key(key: u8) -> StateKey {
+        StateKey::table_item(TableHandle(AccountAddress::ONE), vec![key])
+    }
+
+    fn change_set_to_rewrite() -> ChangeSetExt {
+        let mut delta_change_set = DeltaChangeSet::empty();
+        delta_change_set.insert((state_key(0), DeltaOp::new(DeltaUpdate::Plus(1), 100, 1, 0)));
+        let write_set = WriteSetMut::new(vec![
+            (state_key(1), WriteOp::Creation(vec![1])),
+            (state_key(2), WriteOp::Modification(vec![2])),
+        ])
+        .freeze()
+        .unwrap();
+        let events = vec![ContractEvent::new(
+            EventKey::new(0, AccountAddress::ONE),
+            0,
+            TypeTag::U64,
+            vec![],
+        )];
+        let checker = Arc::new(MaxWriteOpSize(4));
+        let change_set = ChangeSet::new(write_set, events, checker.as_ref()).unwrap();
+        ChangeSetExt::new(delta_change_set, change_set, checker)
+    }
+
+    #[test]
+    fn retained_keys_keep_deltas_and_events() {
+        let change_set_ext = change_set_to_rewrite();
+        let events = change_set_ext.change_set().events().to_vec();
+
+        let rewritten = change_set_ext
+            .retain_keys(|key| *key != state_key(2))
+            .unwrap();
+        assert!(rewritten.write_set().get(&state_key(1)).is_some());
+        assert!(rewritten.write_set().get(&state_key(2)).is_none());
+        assert!(rewritten.delta_change_set().get(&state_key(0)).is_some());
+        assert_eq!(rewritten.change_set().events(), &events[..]);
+    }
+
+    #[test]
+    fn write_ops_can_be_replaced() {
+        let metadata = StateValueMetadata::V0 {
+            payer: AccountAddress::ONE,
+            deposit: 100,
+            creation_time_usecs: 1,
+        };
+        let rewritten = change_set_to_rewrite()
+            .map_write_ops(|_, op| match op {
+                WriteOp::Creation(data) => Some(WriteOp::CreationWithMetadata {
+                    data,
+                    metadata: metadata.clone(),
+                }),
+                op => Some(op),
+            })
+            .unwrap();
+        assert_eq!(
+            rewritten.write_set().get(&state_key(1)),
+            Some(&WriteOp::CreationWithMetadata {
+                data: vec![1],
+                metadata,
+            })
+        );
+        assert_eq!(
+            rewritten.write_set().get(&state_key(2)),
+            Some(&WriteOp::Modification(vec![2]))
+        );
+    }
+
+    #[test]
+    fn rewritten_change_sets_are_checked_again() {
+        let status = change_set_to_rewrite()
+            .map_write_ops(|_, op| match op {
+                WriteOp::Modification(_) => Some(WriteOp::Modification(vec![0; 5])),
+                op => Some(op),
+            })
+            .unwrap_err();
+        assert_eq!(
+            status.status_code(),
+            StatusCode::STORAGE_WRITE_LIMIT_REACHED
+        );
+    }
+
+    fn block_change_set(
+        writes: Vec<(StateKey, WriteOp)>,
+        deltas: Vec<(StateKey, DeltaOp)>,
+    ) -> ChangeSetExt {
+        let write_set = WriteSetMut::new(writes).freeze().unwrap();
+        let change_set = ChangeSet::new(write_set, vec![], &NoOpChangeSetChecker).unwrap();
+        ChangeSetExt::new(
+            DeltaChangeSet::new(deltas),
+            change_set,
+            Arc::new(NoOpChangeSetChecker),
+        )
+    }
+
+    #[test]
+    fn aggregate_drops_slots_deleted_later_in_the_block() {
+        let payer = AccountAddress::from_hex_literal("0xa").unwrap();
+        let other_payer = AccountAddress::from_hex_literal("0xb").unwrap();
+        let first = block_change_set(
+            vec![
+                (state_key(1), WriteOp::Creation(vec![1, 1])),
+                (state_key(2), WriteOp::Creation(vec![2])),
+            ],
+            vec![],
+        );
+        let second = block_change_set(
+            vec![
+                (state_key(1), WriteOp::Deletion),
+                (state_key(2), WriteOp::Modification(vec![2, 2, 2])),
+                (state_key(3), WriteOp::Deletion),
+            ],
+            vec![],
+        );
+
+        let summary =
+            ChangeSetExt::aggregate(vec![(payer, &first), (other_payer, &second)]).unwrap();
+        assert_eq!(
+            summary.final_ops,
+            BTreeMap::from([
+                (&state_key(2), FinalOpKind::Creation),
+                (&state_key(3), FinalOpKind::Deletion),
+            ])
+        );
+        // The slot created and modified is still the first payer's, and the deleted one is
+        // not counted.
+        assert_eq!(summary.slots_created, BTreeMap::from([(payer, 1)]));
+        assert_eq!(summary.total_bytes, (state_key(2).size() + 3) as u64);
+    }
+
+    #[test]
+    fn aggregate_recreating_a_deleted_slot_modifies_it() {
+        let payer = AccountAddress::from_hex_literal("0xa").unwrap();
+        let first = block_change_set(vec![(state_key(1), WriteOp::Deletion)], vec![]);
+        let second = block_change_set(vec![(state_key(1), WriteOp::Creation(vec![1]))], vec![]);
+
+        let summary = ChangeSetExt::aggregate(vec![(payer, &first), (payer, &second)]).unwrap();
+        assert_eq!(
+            summary.final_ops,
+            BTreeMap::from([(&state_key(1), FinalOpKind::Modification)])
+        );
+        assert!(summary.slots_created.is_empty());
+        assert_eq!(summary.total_bytes, (state_key(1).size() + 1) as u64);
+    }
+
+    #[test]
+    fn aggregate_rejects_writes_to_deleted_slots() {
+        let payer = AccountAddress::ONE;
+        let first = block_change_set(vec![(state_key(1), WriteOp::Deletion)], vec![]);
+        let second = block_change_set(vec![(state_key(1), WriteOp::Modification(vec![1]))], vec![]);
+        assert!(ChangeSetExt::aggregate(vec![(payer, &first), (payer, &second)]).is_err());
+    }
+
+    #[test]
+    fn aggregate_merges_deltas_after_the_last_write() {
+        let payer = AccountAddress::ONE;
+        let first = block_change_set(vec![], vec![
+            (state_key(0), DeltaOp::new(DeltaUpdate::Plus(5), 100, 5, 0)),
+            (state_key(1), DeltaOp::new(DeltaUpdate::Plus(5), 100, 5, 0)),
+        ]);
+        let second = block_change_set(
+            vec![(state_key(1), WriteOp::Modification(vec![0; 16]))],
+            vec![(state_key(0), DeltaOp::new(DeltaUpdate::Minus(2), 100, 0, 2))],
+        );
+        let third = block_change_set(vec![], vec![(
+            state_key(1),
+            DeltaOp::new(DeltaUpdate::Plus(1), 100, 1, 0),
+        )]);
+
+        let summary =
+            ChangeSetExt::aggregate(vec![(payer, &first), (payer, &second), (payer, &third)])
+                .unwrap();
+        assert_eq!(
+            summary.deltas.get(&state_key(0)).unwrap().get_update(),
+            DeltaUpdate::Plus(3)
+        );
+        // The write to the second aggregator replaces the delta before it.
+        assert_eq!(
+            summary.deltas.get(&state_key(1)).unwrap().get_update(),
+            DeltaUpdate::Plus(1)
+        );
+        assert_eq!(
+            summary.final_ops,
+            BTreeMap::from([(&state_key(1), FinalOpKind::Modification)])
+        );
+    }
+}
@@ -239,7 +239,9 @@ impl AptosDebugger {
         let change_set_ext = session
             .finish(
                 &mut (),
-                &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+                &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                    LATEST_GAS_FEATURE_VERSION,
+                )),
             )
             .map_err(|err| format_err!("Unexpected VM Error: {:?}", err))?;
         let (_delta_change_set, change_set) = change_set_ext.into_inner();
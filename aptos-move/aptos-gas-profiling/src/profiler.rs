@@ -471,11 +471,15 @@ where
 
         fn storage_fee_per_write(&self, key: &StateKey, op: &WriteOp) -> Fee;
 
-        fn storage_fee_per_event(&self, event: &ContractEvent) -> Fee;
+        fn storage_fee_per_event(&self, event_size: NumBytes) -> Fee;
 
         fn storage_discount_for_events(&self, total_cost: Fee) -> Fee;
 
         fn storage_fee_for_transaction_storage(&self, txn_size: NumBytes) -> Fee;
+
+        fn group_member_tag_gas(&self, tag_bytes: NumBytes) -> InternalGas;
+
+        fn group_deserialization_gas(&self, group_bytes: NumBytes) -> InternalGas;
     }
 
     delegate_mut! {
@@ -510,7 +514,7 @@ where
     fn charge_storage_fee_for_all<'a>(
         &mut self,
         write_ops: impl IntoIterator<Item = (&'a StateKey, &'a WriteOp)>,
-        events: impl IntoIterator<Item = &'a ContractEvent>,
+        events: impl IntoIterator<Item = (&'a ContractEvent, NumBytes)>,
         txn_size: NumBytes,
         gas_unit_price: aptos_gas::FeePerGasUnit,
     ) -> VMResult<()> {
@@ -542,8 +546,8 @@ where
         // Events
         let mut event_fee = Fee::new(0);
         let mut event_fees = vec![];
-        for event in events {
-            let fee = self.storage_fee_per_event(event);
+        for (event, event_size) in events {
+            let fee = self.storage_fee_per_event(event_size);
             event_fees.push(EventStorage {
                 ty: event.type_tag().clone(),
                 cost: fee,
@@ -134,6 +134,13 @@ crate::natives::define_gas_parameters_for_natives!(GasParameters, "aptos_framewo
     [.ristretto255.scalar_sub, "ristretto255.scalar_sub", 1_060 * MUL],
     [.ristretto255.scalar_parse_arg, "ristretto255.scalar_parse_arg", 150 * MUL],
 
+    [.ristretto255.fiat_shamir_base, { 9.. => "ristretto255.fiat_shamir_base" }, 4_000 * MUL],
+    [.ristretto255.fiat_shamir_per_byte, { 9.. => "ristretto255.fiat_shamir_per_byte" }, 45 * MUL],
+    [.ristretto255.pedersen_open_check_base, { 9.. => "ristretto255.pedersen_open_check_base" }, 100_000 * MUL],
+    [.ristretto255.pedersen_open_check_per_commitment, { 9.. => "ristretto255.pedersen_open_check_per_commitment" }, 150_000 * MUL],
+    [.ristretto255.ct_eq_base, { 9.. => "ristretto255.ct_eq_base" }, 500 * MUL],
+    [.ristretto255.ct_eq_per_byte, { 9.. => "ristretto255.ct_eq_per_byte" }, 3 * MUL],
+
     [.hash.sip_hash.base, "hash.sip_hash.base", 1000 * MUL],
     [.hash.sip_hash.per_byte, "hash.sip_hash.per_byte", 20 * MUL],
 
@@ -164,6 +171,9 @@ crate::natives::define_gas_parameters_for_natives!(GasParameters, "aptos_framewo
     [.util.from_bytes.per_byte, "util.from_bytes.per_byte", 5 * MUL],
 
     [.transaction_context.get_script_hash.base, "transaction_context.get_script_hash.base", 200 * MUL],
+    [.transaction_context.unique_session_value.base, { 9.. => "transaction_context.unique_session_value.base" }, 1000 * MUL],
+    [.transaction_context.remaining_write_budget.base, { 9.. => "transaction_context.remaining_write_budget.base" }, 1000 * MUL],
+    [.transaction_context.remaining_write_budget.per_byte, { 9.. => "transaction_context.remaining_write_budget.per_byte" }, 10 * MUL],
 
     [.code.request_publish.base, "code.request_publish.base", 500 * MUL],
     [.code.request_publish.per_byte, "code.request_publish.per_byte", 2 * MUL],
@@ -172,9 +182,13 @@ crate::natives::define_gas_parameters_for_natives!(GasParameters, "aptos_framewo
     [.event.write_to_event_store.base, "event.write_to_event_store.base", 300_000],
     // TODO(Gas): the on-chain name is wrong...
     [.event.write_to_event_store.per_abstract_value_unit, "event.write_to_event_store.per_abstract_memory_unit", 5_000],
+    [.event.write_module_event_to_store.base, { 9.. => "event.write_module_event_to_store.base" }, 300_000],
+    [.event.write_module_event_to_store.per_abstract_value_unit, { 9.. => "event.write_module_event_to_store.per_abstract_value_unit" }, 5_000],
 
     [.state_storage.get_usage.base_cost, "state_storage.get_usage.base", 500 * MUL],
 
+    [.session_counter.next.base, { 9.. => "session_counter.next.base" }, 200 * MUL],
+
     [.aggregator.add.base, "aggregator.add.base", 300 * MUL],
     [.aggregator.read.base, "aggregator.read.base", 300 * MUL],
     [.aggregator.sub.base, "aggregator.sub.base", 300 * MUL],
@@ -185,6 +199,8 @@ crate::natives::define_gas_parameters_for_natives!(GasParameters, "aptos_framewo
     // These are dummy value, they copied from storage gas in aptos-core/aptos-vm/src/aptos_vm_impl.rs
     [.object.exists_at.per_byte_loaded, { 7.. => "object.exists_at.per_byte_loaded" }, 1000],
     [.object.exists_at.per_item_loaded, { 7.. => "object.exists_at.per_item_loaded" }, 8000],
+    [.object.is_resource_group.base, { 9.. => "object.is_resource_group.base" }, 250 * MUL],
+    [.object.is_resource_group.per_module_metadata_loaded, { 9.. => "object.is_resource_group.per_module_metadata_loaded" }, 8000],
     [.string_utils.base, {8.. => "string_utils.format.base"}, 300 * MUL],
     [.string_utils.per_byte, {8.. =>"string_utils.format.per_byte"}, MUL],
 ]);
@@ -33,6 +33,18 @@ use move_vm_types::{
 use std::collections::BTreeMap;
 
 // Change log:
+// - V9
+//   - Added the Ristretto255 Fiat-Shamir challenge native.
+//   - Added the session counter native.
+//   - Limits on, and gas for, the struct tags of resource group members.
+//   - Gas for decoding existing resource groups when merging changes into them.
+//   - Limit on the number of write ops per transaction.
+//   - Limits on the table and aggregator changes of a session.
+//   - Added the module event native.
+//   - Added the unique session value native.
+//   - Added the batch Pedersen opening check native.
+//   - Added the constant-time byte equality native.
+//   - Added the remaining write budget native.
 // - V8
 //   - Added BLS12-381 operations.
 // - V7
@@ -59,7 +71,7 @@ use std::collections::BTreeMap;
 //       global operations.
 // - V1
 //   - TBA
-pub const LATEST_GAS_FEATURE_VERSION: u64 = 8;
+pub const LATEST_GAS_FEATURE_VERSION: u64 = 9;
 
 pub(crate) const EXECUTION_GAS_MULTIPLIER: u64 = 20;
 
@@ -276,8 +288,8 @@ pub trait AptosGasMeter: MoveGasMeter {
     /// Calculates the storage fee for a write operation.
     fn storage_fee_per_write(&self, key: &StateKey, op: &WriteOp) -> Fee;
 
-    /// Calculates the storage fee for an event.
-    fn storage_fee_per_event(&self, event: &ContractEvent) -> Fee;
+    /// Calculates the storage fee for an event of `event_size` bytes, see `ContractEvent::size`.
+    fn storage_fee_per_event(&self, event_size: NumBytes) -> Fee;
 
     /// Calculates the discount applied to the event storage fees, based on a free quota.
     fn storage_discount_for_events(&self, total_cost: Fee) -> Fee;
@@ -285,8 +297,30 @@ pub trait AptosGasMeter: MoveGasMeter {
     /// Calculates the storage fee for the transaction.
     fn storage_fee_for_transaction_storage(&self, txn_size: NumBytes) -> Fee;
 
+    /// Calculates the gas for classifying resource group members whose struct tags serialize to
+    /// `tag_bytes` in total.
+    fn group_member_tag_gas(&self, tag_bytes: NumBytes) -> InternalGas;
+
+    /// Charges execution gas for classifying resource group members whose struct tags serialize
+    /// to `tag_bytes` in total, see `fn group_member_tag_gas`.
+    fn charge_group_member_tags(&mut self, tag_bytes: NumBytes) -> VMResult<()> {
+        self.charge_execution(self.group_member_tag_gas(tag_bytes))
+            .map_err(|e| e.finish(Location::Undefined))
+    }
+
+    /// Calculates the gas for decoding existing resource groups whose blobs are `group_bytes` long
+    /// in total.
+    fn group_deserialization_gas(&self, group_bytes: NumBytes) -> InternalGas;
+
+    /// Charges execution gas for decoding existing resource groups whose blobs are `group_bytes`
+    /// long in total, see `fn group_deserialization_gas`.
+    fn charge_group_deserialization(&mut self, group_bytes: NumBytes) -> VMResult<()> {
+        self.charge_execution(self.group_deserialization_gas(group_bytes))
+            .map_err(|e| e.finish(Location::Undefined))
+    }
+
     /// Charges the storage fees for writes, events & txn storage in a lump sum, minimizing the
-    /// loss of precision.
+    /// loss of precision. Events come with their sizes, see `ChangeSetExt::events_with_sizes`.
     ///
     /// The contract requires that this function behaviors in a way that is consistent to
     /// the ones defining the costs.
@@ -295,7 +329,7 @@ pub trait AptosGasMeter: MoveGasMeter {
     fn charge_storage_fee_for_all<'a>(
         &mut self,
         write_ops: impl IntoIterator<Item = (&'a StateKey, &'a WriteOp)>,
-        events: impl IntoIterator<Item = &'a ContractEvent>,
+        events: impl IntoIterator<Item = (&'a ContractEvent, NumBytes)>,
         txn_size: NumBytes,
         gas_unit_price: FeePerGasUnit,
     ) -> VMResult<()> {
@@ -315,9 +349,11 @@ pub trait AptosGasMeter: MoveGasMeter {
         let write_fee = write_ops.into_iter().fold(Fee::new(0), |acc, (key, op)| {
             acc + self.storage_fee_per_write(key, op)
         });
-        let event_fee = events.into_iter().fold(Fee::new(0), |acc, event| {
-            acc + self.storage_fee_per_event(event)
-        });
+        let event_fee = events
+            .into_iter()
+            .fold(Fee::new(0), |acc, (_, event_size)| {
+                acc + self.storage_fee_per_event(event_size)
+            });
         let event_discount = self.storage_discount_for_events(event_fee);
         let event_net_fee = event_fee
             .checked_sub(event_discount)
@@ -1009,8 +1045,8 @@ impl AptosGasMeter for StandardGasMeter {
         self.gas_params.txn.storage_fee_per_write(key, op)
     }
 
-    fn storage_fee_per_event(&self, event: &ContractEvent) -> Fee {
-        self.gas_params.txn.storage_fee_per_event(event)
+    fn storage_fee_per_event(&self, event_size: NumBytes) -> Fee {
+        self.gas_params.txn.storage_fee_per_event(event_size)
     }
 
     fn storage_discount_for_events(&self, total_cost: Fee) -> Fee {
@@ -1023,6 +1059,14 @@ impl AptosGasMeter for StandardGasMeter {
             .storage_fee_for_transaction_storage(txn_size)
     }
 
+    fn group_member_tag_gas(&self, tag_bytes: NumBytes) -> InternalGas {
+        self.gas_params.txn.group_member_tag_per_byte * tag_bytes
+    }
+
+    fn group_deserialization_gas(&self, group_bytes: NumBytes) -> InternalGas {
+        self.gas_params.txn.per_byte_group_deserialize * group_bytes
+    }
+
     fn charge_intrinsic_gas_for_transaction(&mut self, txn_size: NumBytes) -> VMResult<()> {
         let cost = self.gas_params.txn.calculate_intrinsic_gas(txn_size);
         self.charge_execution(cost)
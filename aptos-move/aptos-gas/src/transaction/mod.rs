@@ -8,9 +8,7 @@ use crate::algebra::{
     AbstractValueSize, Fee, FeePerByte, FeePerGasUnit, FeePerSlot, Gas, GasScalingFactor, GasUnit,
     NumSlots,
 };
-use aptos_types::{
-    contract_event::ContractEvent, state_store::state_key::StateKey, write_set::WriteOp,
-};
+use aptos_types::{state_store::state_key::StateKey, write_set::WriteOp};
 use move_core_types::gas_algebra::{
     InternalGas, InternalGasPerArg, InternalGasPerByte, InternalGasUnit, NumBytes,
     ToUnitFractionalWithParams, ToUnitWithParams,
@@ -128,6 +126,11 @@ crate::params::define_gas_parameters!(
             { 5.. => "max_bytes_all_write_ops_per_transaction" },
             10 << 20, // all write ops from a single transaction are 10MB max
         ],
+        [
+            max_write_ops_per_transaction: NumSlots,
+            { 9.. => "max_write_ops_per_transaction" },
+            8192,
+        ],
         [
             max_bytes_per_event: NumBytes,
             { 5.. => "max_bytes_per_event" },
@@ -172,6 +175,16 @@ crate::params::define_gas_parameters!(
             max_storage_fee: Fee,
             { 7.. => "max_storage_fee" },
             2_0000_0000, // 2 APT
+        ],
+        [
+            group_member_tag_per_byte: InternalGasPerByte,
+            { 9.. => "group_member_tag_per_byte" },
+            100,
+        ],
+        [
+            per_byte_group_deserialize: InternalGasPerByte,
+            { 9.. => "per_byte_group_deserialize" },
+            10,
         ]
     ]
 );
@@ -208,8 +221,9 @@ impl TransactionGasParameters {
     }
 
     /// New formula to charge storage fee for an event, measured in APT.
-    pub fn storage_fee_per_event(&self, event: &ContractEvent) -> Fee {
-        NumBytes::new(event.size() as u64) * self.storage_fee_per_event_byte
+    /// The storage fee for an event of `event_size` bytes, see `ContractEvent::size`.
+    pub fn storage_fee_per_event(&self, event_size: NumBytes) -> Fee {
+        event_size * self.storage_fee_per_event_byte
     }
 
     pub fn storage_discount_for_events(&self, total_cost: Fee) -> Fee {
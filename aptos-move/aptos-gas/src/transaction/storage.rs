@@ -3,16 +3,19 @@
 
 use crate::{AptosGasParameters, LATEST_GAS_FEATURE_VERSION};
 use aptos_types::{
+    contract_event::ContractEvent,
     on_chain_config::StorageGasSchedule,
     state_store::state_key::StateKey,
     transaction::{ChangeSet, CheckChangeSet},
-    write_set::WriteOp,
+    write_set::{WriteOp, WriteSet},
 };
+use move_binary_format::errors::{PartialVMError, PartialVMResult};
 use move_core_types::{
     gas_algebra::{InternalGas, InternalGasPerArg, InternalGasPerByte, NumArgs, NumBytes},
+    language_storage::{StructTag, TypeTag},
     vm_status::{StatusCode, VMStatus},
 };
-use std::fmt::Debug;
+use std::{collections::BTreeSet, fmt::Debug, sync::Arc};
 
 #[derive(Clone, Debug)]
 pub struct StoragePricingV1 {
@@ -203,15 +206,43 @@ pub struct ChangeSetConfigs {
     max_bytes_all_write_ops_per_transaction: u64,
     max_bytes_per_event: u64,
     max_bytes_all_events_per_transaction: u64,
+    max_group_member_tag_depth: usize,
+    max_group_member_tag_bytes: u64,
+    max_write_ops_per_transaction: u64,
+    max_table_change_entries: u64,
+    max_aggregator_changes: u64,
+    protect_reserved_deletions: bool,
+    deletable_reserved_resources: BTreeSet<StructTag>,
 }
 
 impl ChangeSetConfigs {
+    pub const MAX_AGGREGATOR_CHANGES: u64 = 8192;
+    pub const MAX_GROUP_MEMBER_TAG_BYTES: u64 = 1024;
+    /// Limits on the struct tags of resource group members applied from gas feature version 9.
+    pub const MAX_GROUP_MEMBER_TAG_DEPTH: usize = 16;
+    /// Limits on the changes the table and aggregator extensions may accumulate in a session,
+    /// applied from gas feature version 9.
+    pub const MAX_TABLE_CHANGE_ENTRIES: u64 = 8192;
+
     pub fn unlimited_at_gas_feature_version(gas_feature_version: u64) -> Self {
         Self::new_impl(gas_feature_version, u64::MAX, u64::MAX, u64::MAX, u64::MAX)
     }
 
     pub fn new(feature_version: u64, gas_params: &AptosGasParameters) -> Self {
-        if feature_version >= 5 {
+        if feature_version >= 9 {
+            Self::from_gas_params(feature_version, gas_params)
+                .with_group_member_tag_limits(
+                    Self::MAX_GROUP_MEMBER_TAG_DEPTH,
+                    Self::MAX_GROUP_MEMBER_TAG_BYTES,
+                )
+                .with_max_write_ops_per_transaction(
+                    gas_params.txn.max_write_ops_per_transaction.into(),
+                )
+                .with_extension_change_limits(
+                    Self::MAX_TABLE_CHANGE_ENTRIES,
+                    Self::MAX_AGGREGATOR_CHANGES,
+                )
+        } else if feature_version >= 5 {
             Self::from_gas_params(feature_version, gas_params)
         } else if feature_version >= 3 {
             Self::for_feature_version_3()
@@ -233,6 +264,13 @@ impl ChangeSetConfigs {
             max_bytes_all_write_ops_per_transaction,
             max_bytes_per_event,
             max_bytes_all_events_per_transaction,
+            max_group_member_tag_depth: usize::MAX,
+            max_group_member_tag_bytes: u64::MAX,
+            max_write_ops_per_transaction: u64::MAX,
+            max_table_change_entries: u64::MAX,
+            max_aggregator_changes: u64::MAX,
+            protect_reserved_deletions: false,
+            deletable_reserved_resources: BTreeSet::new(),
         }
     }
 
@@ -245,32 +283,149 @@ impl ChangeSetConfigs {
         self.gas_feature_version < 3
     }
 
-    fn for_feature_version_3() -> Self {
-        const MB: u64 = 1 << 20;
+    /// Overrides the limits on the nesting depth and serialized size of the struct tags of
+    /// resource group members, see `check_group_member_tag`.
+    pub fn with_group_member_tag_limits(mut self, max_depth: usize, max_bytes: u64) -> Self {
+        self.max_group_member_tag_depth = max_depth;
+        self.max_group_member_tag_bytes = max_bytes;
+        self
+    }
 
-        Self::new_impl(3, MB, u64::MAX, MB, 10 * MB)
+    /// Checks the struct tag of a resource group member against the configured limits, returning
+    /// its serialized size. A tag without type arguments has depth 1, and every struct or vector
+    /// type argument wrapping another adds one.
+    pub fn check_group_member_tag(&self, struct_tag: &StructTag) -> PartialVMResult<NumBytes> {
+        let too_complex = |message: String| {
+            PartialVMError::new(StatusCode::RESOURCE_GROUP_MEMBER_TAG_TOO_COMPLEX)
+                .with_message(message)
+        };
+
+        let depth = struct_tag_depth(struct_tag);
+        if depth > self.max_group_member_tag_depth {
+            return Err(too_complex(format!(
+                "group member {} is nested {} levels deep, above the limit of {}",
+                struct_tag, depth, self.max_group_member_tag_depth
+            )));
+        }
+
+        let size = bcs::serialized_size(struct_tag).map_err(|_| {
+            PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+                .with_message(format!("failed to size struct tag {}", struct_tag))
+        })? as u64;
+        if size > self.max_group_member_tag_bytes {
+            return Err(too_complex(format!(
+                "group member {} serializes to {} bytes, above the limit of {}",
+                struct_tag, size, self.max_group_member_tag_bytes
+            )));
+        }
+        Ok(NumBytes::new(size))
     }
 
-    fn from_gas_params(gas_feature_version: u64, gas_params: &AptosGasParameters) -> Self {
-        Self::new_impl(
-            gas_feature_version,
-            gas_params.txn.max_bytes_per_write_op.into(),
-            gas_params
-                .txn
-                .max_bytes_all_write_ops_per_transaction
-                .into(),
-            gas_params.txn.max_bytes_per_event.into(),
-            gas_params.txn.max_bytes_all_events_per_transaction.into(),
-        )
+    /// Overrides the limit on the number of write ops of a transaction, see
+    /// `max_write_ops_per_transaction`.
+    pub fn with_max_write_ops_per_transaction(mut self, max_write_ops: u64) -> Self {
+        self.max_write_ops_per_transaction = max_write_ops;
+        self
     }
-}
 
-impl CheckChangeSet for ChangeSetConfigs {
-    fn check_change_set(&self, change_set: &ChangeSet) -> Result<(), VMStatus> {
+    /// How many write ops a transaction may produce, regardless of their size, as each one is a
+    /// leaf update of the state tree. Deltas are not counted: they only become write ops once
+    /// materialized, and how many aggregators a session touches is bounded by
+    /// `max_aggregator_changes`.
+    pub fn max_write_ops_per_transaction(&self) -> u64 {
+        self.max_write_ops_per_transaction
+    }
+
+    /// Overrides the limits on the changes the table and aggregator extensions of a session may
+    /// accumulate, see `max_table_change_entries` and `max_aggregator_changes`.
+    pub fn with_extension_change_limits(
+        mut self,
+        max_table_change_entries: u64,
+        max_aggregator_changes: u64,
+    ) -> Self {
+        self.max_table_change_entries = max_table_change_entries;
+        self.max_aggregator_changes = max_aggregator_changes;
+        self
+    }
+
+    /// How many table items, across all tables, a session may change. Checked when the session
+    /// is finished, before its changes are converted to write ops.
+    pub fn max_table_change_entries(&self) -> u64 {
+        self.max_table_change_entries
+    }
+
+    /// How many aggregators a session may change, whether by writing, deleting or adding deltas
+    /// to them. Checked along with `max_table_change_entries`.
+    pub fn max_aggregator_changes(&self) -> u64 {
+        self.max_aggregator_changes
+    }
+
+    /// Only lets genesis and governance sessions delete resources, resource groups included, at
+    /// the framework reserved addresses 0x0 to 0xa, apart from the ones in `deletable`. Off unless
+    /// set.
+    pub fn with_reserved_deletion_protection(
+        mut self,
+        deletable: impl IntoIterator<Item = StructTag>,
+    ) -> Self {
+        self.protect_reserved_deletions = true;
+        self.deletable_reserved_resources = deletable.into_iter().collect();
+        self
+    }
+
+    /// Whether a session not allowed to delete reserved resources may still delete the resource
+    /// (or resource group) `struct_tag` at a framework reserved address, see
+    /// `with_reserved_deletion_protection`.
+    pub fn may_delete_reserved_resource(&self, struct_tag: &StructTag) -> bool {
+        !self.protect_reserved_deletions || self.deletable_reserved_resources.contains(struct_tag)
+    }
+
+    pub fn max_bytes_per_write_op(&self) -> u64 {
+        self.max_bytes_per_write_op
+    }
+
+    pub fn max_bytes_all_write_ops_per_transaction(&self) -> u64 {
+        self.max_bytes_all_write_ops_per_transaction
+    }
+
+    pub fn max_bytes_per_event(&self) -> u64 {
+        self.max_bytes_per_event
+    }
+
+    pub fn max_bytes_all_events_per_transaction(&self) -> u64 {
+        self.max_bytes_all_events_per_transaction
+    }
+
+    /// Whether the event limits are also enforced while a transaction executes, aborting it on the
+    /// first event that crosses them instead of failing the whole transaction when it finishes.
+    pub fn enforces_event_limits_during_execution(&self) -> bool {
+        self.gas_feature_version >= 9
+    }
+
+    /// Whether writes of the same state key by different parts of a change set are checked against
+    /// each other. Before gas feature version 9, a later write silently replaced an earlier one
+    /// and aggregator deltas were kept apart from the write set, so replays must do the same.
+    pub fn checks_conflicting_writes(&self) -> bool {
+        self.gas_feature_version >= 9
+    }
+
+    /// Checks a write set and the events emitted along with it against the limits, e.g. to
+    /// re-validate the output of a simulated transaction after the limits changed. This is the
+    /// check `ChangeSet::new` runs, and fails with the same status codes.
+    pub fn validate_write_set(
+        &self,
+        write_set: &WriteSet,
+        events: &[ContractEvent],
+    ) -> Result<(), VMStatus> {
         const ERR: StatusCode = StatusCode::STORAGE_WRITE_LIMIT_REACHED;
 
+        // Conversion already stops at the first write op over the limit; this catches change sets
+        // built otherwise, e.g. by squashing the ones of several sessions.
+        if write_set.iter().count() as u64 > self.max_write_ops_per_transaction {
+            return Err(VMStatus::Error(StatusCode::TOO_MANY_WRITE_OPS, None));
+        }
+
         let mut write_set_size = 0;
-        for (key, op) in change_set.write_set() {
+        for (key, op) in write_set {
             if let Some(bytes) = op.bytes() {
                 let write_op_size = (bytes.len() + key.size()) as u64;
                 if write_op_size > self.max_bytes_per_write_op {
@@ -284,7 +439,7 @@ impl CheckChangeSet for ChangeSetConfigs {
         }
 
         let mut total_event_size = 0;
-        for event in change_set.events() {
+        for event in events {
             let size = event.event_data().len() as u64;
             if size > self.max_bytes_per_event {
                 return Err(VMStatus::Error(ERR, None));
@@ -297,12 +452,54 @@ impl CheckChangeSet for ChangeSetConfigs {
 
         Ok(())
     }
+
+    fn for_feature_version_3() -> Self {
+        const MB: u64 = 1 << 20;
+
+        Self::new_impl(3, MB, u64::MAX, MB, 10 * MB)
+    }
+
+    fn from_gas_params(gas_feature_version: u64, gas_params: &AptosGasParameters) -> Self {
+        Self::new_impl(
+            gas_feature_version,
+            gas_params.txn.max_bytes_per_write_op.into(),
+            gas_params
+                .txn
+                .max_bytes_all_write_ops_per_transaction
+                .into(),
+            gas_params.txn.max_bytes_per_event.into(),
+            gas_params.txn.max_bytes_all_events_per_transaction.into(),
+        )
+    }
+}
+
+fn struct_tag_depth(struct_tag: &StructTag) -> usize {
+    1 + struct_tag
+        .type_params
+        .iter()
+        .map(type_tag_depth)
+        .max()
+        .unwrap_or(0)
+}
+
+fn type_tag_depth(type_tag: &TypeTag) -> usize {
+    match type_tag {
+        TypeTag::Struct(struct_tag) => struct_tag_depth(struct_tag),
+        TypeTag::Vector(element) => 1 + type_tag_depth(element),
+        _ => 0,
+    }
+}
+
+impl CheckChangeSet for ChangeSetConfigs {
+    fn check_change_set(&self, change_set: &ChangeSet) -> Result<(), VMStatus> {
+        self.validate_write_set(change_set.write_set(), change_set.events())
+    }
 }
 
 #[derive(Clone)]
 pub struct StorageGasParameters {
     pub pricing: StoragePricing,
-    pub change_set_configs: ChangeSetConfigs,
+    pub change_set_configs: Arc<ChangeSetConfigs>,
 }
 
 impl StorageGasParameters {
@@ -323,7 +520,7 @@ impl StorageGasParameters {
             None => StoragePricing::V1(StoragePricingV1::new(gas_params)),
         };
 
-        let change_set_configs = ChangeSetConfigs::new(feature_version, gas_params);
+        let change_set_configs = Arc::new(ChangeSetConfigs::new(feature_version, gas_params));
 
         Some(Self {
             pricing,
@@ -334,9 +531,9 @@ impl StorageGasParameters {
     pub fn free_and_unlimited() -> Self {
         Self {
             pricing: StoragePricing::V2(StoragePricingV2::zeros()),
-            change_set_configs: ChangeSetConfigs::unlimited_at_gas_feature_version(
+            change_set_configs: Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
                 LATEST_GAS_FEATURE_VERSION,
-            ),
+            )),
         }
     }
 }
@@ -34,6 +34,12 @@ pub enum FeatureFlag {
     StructConstructors,
     PeriodicalRewardRateReduction,
     PartialGovernanceVoting,
+    ResourceGroupContainerValidation,
+    StripNoopModifications,
+    AggregatorHandleValidation,
+    StorageQuotas,
+    ModuleEvent,
+    EventKeyValidation,
 }
 
 fn generate_features_blob(writer: &CodeWriter, data: &[u64]) {
@@ -146,6 +152,16 @@ impl From<FeatureFlag> for AptosFeatureFlag {
                 AptosFeatureFlag::PERIODICAL_REWARD_RATE_DECREASE
             },
             FeatureFlag::PartialGovernanceVoting => AptosFeatureFlag::PARTIAL_GOVERNANCE_VOTING,
+            FeatureFlag::ResourceGroupContainerValidation => {
+                AptosFeatureFlag::RESOURCE_GROUP_CONTAINER_VALIDATION
+            },
+            FeatureFlag::StripNoopModifications => AptosFeatureFlag::STRIP_NOOP_MODIFICATIONS,
+            FeatureFlag::AggregatorHandleValidation => {
+                AptosFeatureFlag::AGGREGATOR_HANDLE_VALIDATION
+            },
+            FeatureFlag::StorageQuotas => AptosFeatureFlag::STORAGE_QUOTAS,
+            FeatureFlag::ModuleEvent => AptosFeatureFlag::MODULE_EVENT,
+            FeatureFlag::EventKeyValidation => AptosFeatureFlag::EVENT_KEY_VALIDATION,
         }
     }
 }
@@ -183,6 +199,16 @@ impl From<AptosFeatureFlag> for FeatureFlag {
                 FeatureFlag::PeriodicalRewardRateReduction
             },
             AptosFeatureFlag::PARTIAL_GOVERNANCE_VOTING => FeatureFlag::PartialGovernanceVoting,
+            AptosFeatureFlag::RESOURCE_GROUP_CONTAINER_VALIDATION => {
+                FeatureFlag::ResourceGroupContainerValidation
+            },
+            AptosFeatureFlag::STRIP_NOOP_MODIFICATIONS => FeatureFlag::StripNoopModifications,
+            AptosFeatureFlag::AGGREGATOR_HANDLE_VALIDATION => {
+                FeatureFlag::AggregatorHandleValidation
+            },
+            AptosFeatureFlag::STORAGE_QUOTAS => FeatureFlag::StorageQuotas,
+            AptosFeatureFlag::MODULE_EVENT => FeatureFlag::ModuleEvent,
+            AptosFeatureFlag::EVENT_KEY_VALIDATION => FeatureFlag::EventKeyValidation,
         }
     }
 }
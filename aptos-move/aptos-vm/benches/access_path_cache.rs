@@ -0,0 +1,54 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_crypto::hash::CryptoHash;
+use aptos_vm::access_path_cache::{AccessPathCache, BTreeAccessPathCache};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use move_core_types::{
+    account_address::AccountAddress, identifier::Identifier, language_storage::StructTag,
+};
+
+const NUM_OBJECTS: usize = 100;
+const NUM_TRANSACTIONS: usize = 10;
+
+/// Every transaction writes the object group of the same 100 objects, and the keys are hashed
+/// as they are when the write set is committed.
+fn write_hot_objects<C: AccessPathCache>(
+    cache: &mut C,
+    objects: &[AccountAddress],
+    group: &StructTag,
+) {
+    for _ in 0..NUM_TRANSACTIONS {
+        for addr in objects {
+            cache
+                .get_resource_group_state_key(*addr, group.clone())
+                .hash();
+        }
+    }
+}
+
+fn resource_group_state_keys(c: &mut Criterion) {
+    let objects: Vec<_> = (0..NUM_OBJECTS).map(|_| AccountAddress::random()).collect();
+    let group = StructTag {
+        address: AccountAddress::ONE,
+        module: Identifier::new("object").unwrap(),
+        name: Identifier::new("ObjectGroup").unwrap(),
+        type_params: vec![],
+    };
+
+    let mut group_bench = c.benchmark_group("resource_group_state_keys");
+    group_bench.bench_function("uncached", |b| {
+        b.iter(|| write_hot_objects(&mut (), &objects, &group))
+    });
+    group_bench.bench_function("btree_cache", |b| {
+        b.iter_batched(
+            BTreeAccessPathCache::new,
+            |mut cache| write_hot_objects(&mut cache, &objects, &group),
+            BatchSize::SmallInput,
+        )
+    });
+    group_bench.finish();
+}
+
+criterion_group!(benches, resource_group_state_keys);
+criterion_main!(benches);
@@ -2,7 +2,8 @@
 // Parts of the project are originally copyright © Meta Platforms, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use aptos_types::access_path::AccessPath;
+use aptos_crypto::hash::CryptoHash;
+use aptos_types::{access_path::AccessPath, state_store::state_key::StateKey};
 use move_core_types::{
     account_address::AccountAddress,
     language_storage::{ModuleId, StructTag},
@@ -17,6 +18,16 @@ pub trait AccessPathCache {
         address: AccountAddress,
         struct_tag: StructTag,
     ) -> AccessPath;
+
+    /// The state key of a resource group. Caches may keep the final key, including its hash, as
+    /// the same groups are typically written over and over again.
+    fn get_resource_group_state_key(
+        &mut self,
+        address: AccountAddress,
+        struct_tag: StructTag,
+    ) -> StateKey {
+        StateKey::access_path(self.get_resource_group_path(address, struct_tag))
+    }
 }
 
 impl AccessPathCache for () {
@@ -38,10 +49,11 @@ impl AccessPathCache for () {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct BTreeAccessPathCache {
     modules: BTreeMap<ModuleId, Vec<u8>>,
     resources: BTreeMap<StructTag, Vec<u8>>,
+    resource_group_keys: BTreeMap<(AccountAddress, StructTag), StateKey>,
 }
 
 impl AccessPathCache for BTreeAccessPathCache {
@@ -86,6 +98,23 @@ impl AccessPathCache for BTreeAccessPathCache {
         };
         AccessPath::new(address, access_vec)
     }
+
+    fn get_resource_group_state_key(
+        &mut self,
+        address: AccountAddress,
+        struct_tag: StructTag,
+    ) -> StateKey {
+        let key = (address, struct_tag);
+        if let Some(state_key) = self.resource_group_keys.get(&key) {
+            return state_key.clone();
+        }
+
+        let state_key = StateKey::access_path(self.get_resource_group_path(key.0, key.1.clone()));
+        // Hash once here so that every clone handed out carries the hash along.
+        state_key.hash();
+        self.resource_group_keys.insert(key, state_key.clone());
+        state_key
+    }
 }
 
 impl BTreeAccessPathCache {
@@ -94,6 +123,34 @@ impl BTreeAccessPathCache {
         Self {
             modules: BTreeMap::new(),
             resources: BTreeMap::new(),
+            resource_group_keys: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use move_core_types::identifier::Identifier;
+
+    #[test]
+    fn cached_resource_group_keys_match_uncached_ones() {
+        let mut cache = BTreeAccessPathCache::new();
+        let group = StructTag {
+            address: AccountAddress::ONE,
+            module: Identifier::new("object").unwrap(),
+            name: Identifier::new("ObjectGroup").unwrap(),
+            type_params: vec![],
+        };
+
+        for _ in 0..2 {
+            for addr in [AccountAddress::ONE, AccountAddress::TWO] {
+                let expected = ().get_resource_group_state_key(addr, group.clone());
+                let state_key = cache.get_resource_group_state_key(addr, group.clone());
+                assert_eq!(state_key, expected);
+                assert_eq!(state_key.hash(), expected.hash());
+            }
         }
+        assert_eq!(cache.resource_group_keys.len(), 2);
     }
 }
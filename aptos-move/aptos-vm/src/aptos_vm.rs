@@ -10,9 +10,8 @@ use crate::{
     block_executor::BlockAptosVM,
     counters::*,
     data_cache::{AsMoveResolver, IntoMoveResolver, StorageAdapter},
-    delta_state_view::DeltaStateView,
     errors::expect_only_successful_execution,
-    move_vm_ext::{MoveResolverExt, SessionExt, SessionId},
+    move_vm_ext::{EffectiveStateView, MoveResolverExt, SessionExt, SessionId},
     system_module_names::*,
     transaction_metadata::TransactionMetadata,
     verifier, VMExecutor, VMValidator,
@@ -208,7 +207,7 @@ impl AptosVM {
         txn_data: &TransactionMetadata,
         storage: &S,
         log_context: &AdapterLogSchema,
-        change_set_configs: &ChangeSetConfigs,
+        change_set_configs: &Arc<ChangeSetConfigs>,
     ) -> TransactionOutputExt {
         self.failed_transaction_cleanup_and_keep_vm_status(
             error_code,
@@ -228,7 +227,7 @@ impl AptosVM {
         txn_data: &TransactionMetadata,
         storage: &S,
         log_context: &AdapterLogSchema,
-        change_set_configs: &ChangeSetConfigs,
+        change_set_configs: &Arc<ChangeSetConfigs>,
     ) -> (VMStatus, TransactionOutputExt) {
         let resolver = self.0.new_move_resolver(storage);
         let mut session = self.0.new_session(&resolver, SessionId::txn_meta(txn_data));
@@ -290,31 +289,14 @@ impl AptosVM {
         gas_meter: &mut impl AptosGasMeter,
         txn_data: &TransactionMetadata,
         log_context: &AdapterLogSchema,
-        change_set_configs: &ChangeSetConfigs,
+        change_set_configs: &Arc<ChangeSetConfigs>,
     ) -> Result<(VMStatus, TransactionOutputExt), VMStatus> {
+        // The epilogue sees the writes of the transaction, its deltas applied as they are read.
         let storage_with_changes =
-            DeltaStateView::new(storage, user_txn_change_set_ext.write_set());
-        // TODO: at this point we know that delta application failed
-        // (and it should have occurred in user transaction in general).
-        // We need to rerun the epilogue and charge gas. Currently, the use
-        // case of an aggregator is for gas fees (which are computed in
-        // the epilogue), and therefore this should never happen.
-        // Also, it is worth mentioning that current VM error handling is
-        // rather ugly and has a lot of legacy code. This makes proper error
-        // handling quite challenging.
-        let delta_write_set_mut = user_txn_change_set_ext
-            .delta_change_set()
-            .clone()
-            .try_into_write_set_mut(storage)
-            .expect("something terrible happened when applying aggregator deltas");
-        let delta_write_set = delta_write_set_mut
-            .freeze()
-            .map_err(|_err| VMStatus::Error(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR, None))?;
-        let storage_with_changes =
-            DeltaStateView::new(&storage_with_changes, &delta_write_set).into_move_resolver();
-
+            EffectiveStateView::new(storage, Some(&user_txn_change_set_ext)).into_move_resolver();
         let resolver = self.0.new_move_resolver(&storage_with_changes);
         let mut session = self.0.new_session(&resolver, SessionId::txn_meta(txn_data));
+        session.continue_native_events(&user_txn_change_set_ext);
 
         self.0
             .run_success_epilogue(&mut session, gas_meter.balance(), txn_data, log_context)?;
@@ -390,7 +372,7 @@ impl AptosVM {
         payload: &TransactionPayload,
         log_context: &AdapterLogSchema,
         new_published_modules_loaded: &mut bool,
-        change_set_configs: &ChangeSetConfigs,
+        change_set_configs: &Arc<ChangeSetConfigs>,
     ) -> Result<(VMStatus, TransactionOutputExt), VMStatus> {
         fail_point!("move_adapter::execute_script_or_entry_function", |_| {
             Err(VMStatus::Error(
@@ -451,12 +433,12 @@ impl AptosVM {
             )?;
 
             let change_set_ext = session
-                .finish(&mut (), change_set_configs)
+                .finish_with_gas(&mut (), change_set_configs, gas_meter)
                 .map_err(|e| e.into_vm_status())?;
             gas_meter.charge_io_gas_for_write_set(change_set_ext.write_set().iter())?;
             gas_meter.charge_storage_fee_for_all(
                 change_set_ext.write_set().iter(),
-                change_set_ext.change_set().events(),
+                change_set_ext.events_with_sizes(),
                 txn_data.transaction_size,
                 txn_data.gas_unit_price,
             )?;
@@ -489,7 +471,7 @@ impl AptosVM {
         txn_payload: &Multisig,
         log_context: &AdapterLogSchema,
         new_published_modules_loaded: &mut bool,
-        change_set_configs: &ChangeSetConfigs,
+        change_set_configs: &Arc<ChangeSetConfigs>,
     ) -> Result<(VMStatus, TransactionOutputExt), VMStatus> {
         fail_point!("move_adapter::execute_multisig_transaction", |_| {
             Err(VMStatus::Error(
@@ -640,36 +622,28 @@ impl AptosVM {
         gas_meter: &mut impl AptosGasMeter,
         txn_data: &TransactionMetadata,
         cleanup_args: Vec<Vec<u8>>,
-        change_set_configs: &ChangeSetConfigs,
+        change_set_configs: &Arc<ChangeSetConfigs>,
     ) -> Result<ChangeSetExt, VMStatus> {
         // Charge gas for writeset before we do cleanup. This ensures we don't charge gas for
         // cleanup writeset changes, which is consistent with outer-level success cleanup
         // flow. We also wouldn't need to worry that we run out of gas when doing cleanup.
         let inner_function_change_set_ext = session
-            .finish(&mut (), change_set_configs)
+            .finish_with_gas(&mut (), change_set_configs, gas_meter)
             .map_err(|e| e.into_vm_status())?;
         gas_meter.charge_io_gas_for_write_set(inner_function_change_set_ext.write_set().iter())?;
         gas_meter.charge_storage_fee_for_all(
             inner_function_change_set_ext.write_set().iter(),
-            inner_function_change_set_ext.change_set().events(),
+            inner_function_change_set_ext.events_with_sizes(),
             txn_data.transaction_size,
             txn_data.gas_unit_price,
         )?;
 
         let storage_with_changes =
-            DeltaStateView::new(storage, inner_function_change_set_ext.write_set());
-        let delta_write_set_mut = inner_function_change_set_ext
-            .delta_change_set()
-            .clone()
-            .try_into_write_set_mut(storage)
-            .expect("something terrible happened when applying aggregator deltas");
-        let delta_write_set = delta_write_set_mut
-            .freeze()
-            .map_err(|_err| VMStatus::Error(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR, None))?;
-        let storage_with_changes =
-            DeltaStateView::new(&storage_with_changes, &delta_write_set).into_move_resolver();
+            EffectiveStateView::new(storage, Some(&inner_function_change_set_ext))
+                .into_move_resolver();
         let resolver = self.0.new_move_resolver(&storage_with_changes);
         let mut cleanup_session = self.0.new_session(&resolver, SessionId::txn_meta(txn_data));
+        cleanup_session.continue_native_events(&inner_function_change_set_ext);
         cleanup_session.execute_function_bypass_visibility(
             &MULTISIG_ACCOUNT_MODULE,
             SUCCESSFUL_TRANSACTION_EXECUTION_CLEANUP,
@@ -692,7 +666,7 @@ impl AptosVM {
         execution_error: VMStatus,
         txn_data: &TransactionMetadata,
         mut cleanup_args: Vec<Vec<u8>>,
-        change_set_configs: &ChangeSetConfigs,
+        change_set_configs: &Arc<ChangeSetConfigs>,
     ) -> Result<ChangeSetExt, VMStatus> {
         // Start a fresh session for running cleanup that does not contain any changes from
         // the inner function call earlier (since it failed).
@@ -826,7 +800,7 @@ impl AptosVM {
         modules: &ModuleBundle,
         log_context: &AdapterLogSchema,
         new_published_modules_loaded: &mut bool,
-        change_set_configs: &ChangeSetConfigs,
+        change_set_configs: &Arc<ChangeSetConfigs>,
     ) -> Result<(VMStatus, TransactionOutputExt), VMStatus> {
         if MODULE_BUNDLE_DISALLOWED.load(Ordering::Relaxed) {
             return Err(VMStatus::Error(StatusCode::FEATURE_UNDER_GATING, None));
@@ -870,12 +844,12 @@ impl AptosVM {
         )?;
 
         let change_set_ext = session
-            .finish(&mut (), change_set_configs)
+            .finish_with_gas(&mut (), change_set_configs, gas_meter)
             .map_err(|e| e.into_vm_status())?;
         gas_meter.charge_io_gas_for_write_set(change_set_ext.write_set().iter())?;
         gas_meter.charge_storage_fee_for_all(
             change_set_ext.write_set().iter(),
-            change_set_ext.change_set().events(),
+            change_set_ext.events_with_sizes(),
             txn_data.transaction_size,
             txn_data.gas_unit_price,
         )?;
@@ -1053,6 +1027,7 @@ impl AptosVM {
         }
 
         let storage_gas_params = unwrap_or_discard!(self.0.get_storage_gas_parameters(log_context));
+        session.set_event_limits(&storage_gas_params.change_set_configs);
         let txn_data = TransactionMetadata::new(txn);
 
         // We keep track of whether any newly published modules are loaded into the Vm's loader
@@ -1184,18 +1159,21 @@ impl AptosVM {
         session_id: SessionId,
     ) -> Result<ChangeSetExt, Result<(VMStatus, TransactionOutputExt), VMStatus>> {
         let mut gas_meter = UnmeteredGasMeter;
-        let change_set_configs =
-            ChangeSetConfigs::unlimited_at_gas_feature_version(self.0.get_gas_feature_version());
+        let change_set_configs = Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+            self.0.get_gas_feature_version(),
+        ));
 
         Ok(match writeset_payload {
             WriteSetPayload::Direct(change_set) => ChangeSetExt::new(
                 DeltaChangeSet::empty(),
                 change_set.clone(),
-                Arc::new(change_set_configs),
+                change_set_configs,
             ),
             WriteSetPayload::Script { script, execute_as } => {
                 let resolver = self.0.new_move_resolver(storage);
                 let mut tmp_session = self.0.new_session(&resolver, session_id);
+                // Write set scripts are applied by genesis or governance.
+                tmp_session.set_allow_reserved_deletions(true);
                 let senders = match txn_sender {
                     None => vec![*execute_as],
                     Some(sender) => vec![sender, *execute_as],
@@ -1781,14 +1759,18 @@ impl AptosSimulationVM {
                                     // which is in the middle. Introducing a boolean would make the code
                                     // messier.
                                     let change_set_ext = session
-                                        .finish(&mut (), &storage_gas_params.change_set_configs)
+                                        .finish_with_gas(
+                                            &mut (),
+                                            &storage_gas_params.change_set_configs,
+                                            &mut gas_meter,
+                                        )
                                         .map_err(|e| e.into_vm_status())?;
                                     gas_meter.charge_io_gas_for_write_set(
                                         change_set_ext.write_set().iter(),
                                     )?;
                                     gas_meter.charge_storage_fee_for_all(
                                         change_set_ext.write_set().iter(),
-                                        change_set_ext.change_set().events(),
+                                        change_set_ext.events_with_sizes(),
                                         txn_data.transaction_size,
                                         txn_data.gas_unit_price,
                                     )?;
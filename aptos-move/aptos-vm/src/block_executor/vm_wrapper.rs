@@ -69,6 +69,15 @@ impl<'a, S: 'a + StateView + Sync> ExecutorTask for AptosExecutorTask<'a, S> {
             .vm
             .execute_single_transaction(txn, &view.as_move_resolver(), &log_context)
         {
+            // Discarding the transaction would commit an outcome that nodes whose storage is
+            // available do not reach, so the transaction is executed again instead.
+            Ok((vm_status, _, _)) if vm_status.status_code().is_retriable() => {
+                speculative_error!(
+                    &log_context,
+                    format!("Storage unavailable, error: {:?}", vm_status),
+                );
+                ExecutionStatus::Retry(vm_status)
+            },
             Ok((vm_status, mut output_ext, sender)) => {
                 if materialize_deltas {
                     // Keep TransactionOutputExt type for wrapper.
@@ -105,3 +105,46 @@ pub static TXN_VALIDATION_SECONDS: Lazy<Histogram> = Lazy::new(|| {
 pub static TXN_GAS_USAGE: Lazy<Histogram> = Lazy::new(|| {
     register_histogram!("aptos_vm_txn_gas_usage", "Gas used per transaction").unwrap()
 });
+
+/// Count the number of modifications dropped from write sets because they wrote back the value
+/// already in storage.
+pub static NOOP_MODIFICATIONS_STRIPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_vm_noop_modifications_stripped",
+        "Number of no-op modifications dropped from write sets",
+    )
+    .unwrap()
+});
+
+/// Count the resource creations written as modifications because of
+/// `ChangeSetConfigs::legacy_resource_creation_as_modification`, with an "address" label telling
+/// whether the resource is under the core code address.
+pub static LEGACY_CREATIONS_AS_MODIFICATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "aptos_vm_legacy_creations_as_modifications",
+        "Number of resource creations written as modifications for legacy gas feature versions",
+        &["address"]
+    )
+    .unwrap()
+});
+
+/// Count the account and resource group change sets dropped before finishing a session because
+/// no ops were left in them.
+pub static EMPTY_ACCOUNT_CHANGE_SETS_PRUNED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_vm_empty_account_change_sets_pruned",
+        "Number of empty account and resource group change sets pruned before conversion",
+    )
+    .unwrap()
+});
+
+/// Count the reads `SessionExt::finish` adds on top of execution, by finish phase and kind of
+/// read.
+pub static FINISH_RESOLVER_READS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "aptos_vm_finish_resolver_reads",
+        "Number of reads and bytes fetched while finishing sessions",
+        &["phase", "kind"]
+    )
+    .unwrap()
+});
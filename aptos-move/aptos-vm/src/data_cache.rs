@@ -6,12 +6,17 @@
 use crate::move_vm_ext::MoveResolverExt;
 #[allow(unused_imports)]
 use anyhow::Error;
-use aptos_framework::{natives::state_storage::StateStorageUsageResolver, RuntimeModuleMetadataV1};
-use aptos_state_view::StateView;
+use aptos_framework::{
+    natives::{object::ModuleMetadataResolver, state_storage::StateStorageUsageResolver},
+    RuntimeModuleMetadataV1,
+};
+use aptos_state_view::{StateView, StateViewId, TStateView};
 use aptos_types::{
     access_path::AccessPath,
     on_chain_config::ConfigStorage,
-    state_store::{state_key::StateKey, state_storage_usage::StateStorageUsage},
+    state_store::{
+        state_key::StateKey, state_storage_usage::StateStorageUsage, state_value::StateValue,
+    },
 };
 use move_binary_format::{errors::*, CompiledModule};
 use move_core_types::{
@@ -22,11 +27,143 @@ use move_core_types::{
 };
 use move_table_extension::{TableHandle, TableResolver};
 use move_vm_runtime::move_vm::MoveVM;
-use std::ops::{Deref, DerefMut};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+};
+
+/// The part of `SessionExt::finish` a read was issued by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FinishPhase {
+    /// Merging the session's resource group changes into the stored groups.
+    GroupMerge,
+    /// Converting the merged changes into write ops.
+    WriteOpConversion,
+}
+
+/// Reads that went past the resolver's own caches.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ResolverReads {
+    pub state_value_reads: u64,
+    pub group_data_reads: u64,
+    pub group_metadata_lookups: u64,
+    pub bytes_fetched: u64,
+}
+
+/// The reads `SessionExt::finish` added on top of execution, by phase.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FinishReadStats {
+    pub group_merge: ResolverReads,
+    pub write_op_conversion: ResolverReads,
+}
+
+/// A bound on the work `SessionExt::finish` does on top of execution, which no gas is charged for
+/// as it is done.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FinishBudget {
+    /// Reads that go past the resolver's own caches.
+    pub max_reads: u64,
+    /// The bytes fetched by those reads, plus the bytes of the resource groups decoded and encoded
+    /// to merge changes into them, and of the write set and events produced.
+    pub max_bytes: u64,
+}
+
+/// The error finishing a session fails with once it exceeds its `FinishBudget`.
+pub(crate) fn finish_budget_exceeded() -> PartialVMError {
+    PartialVMError::new(StatusCode::FINISH_BUDGET_EXCEEDED)
+        .with_message("finishing the session exceeded its budget".to_string())
+}
+
+/// Counts reads while a finish phase is set. Compiled out with the `no-metrics` feature, in which
+/// case all stats are zero. Charging a `FinishBudget` is not, as it decides the outcome of
+/// transactions.
+#[derive(Default)]
+pub(crate) struct ReadRecorder {
+    #[cfg(not(feature = "no-metrics"))]
+    phase: Cell<Option<FinishPhase>>,
+    #[cfg(not(feature = "no-metrics"))]
+    stats: Cell<FinishReadStats>,
+    // What is left of the budget, if any is charged.
+    budget: Cell<Option<FinishBudget>>,
+    budget_exceeded: Cell<bool>,
+}
+
+impl ReadRecorder {
+    fn set_phase(&self, _phase: Option<FinishPhase>) {
+        #[cfg(not(feature = "no-metrics"))]
+        self.phase.set(_phase);
+    }
+
+    fn record(&self, _record: impl FnOnce(&mut ResolverReads)) {
+        #[cfg(not(feature = "no-metrics"))]
+        if let Some(phase) = self.phase.get() {
+            let mut stats = self.stats.get();
+            _record(match phase {
+                FinishPhase::GroupMerge => &mut stats.group_merge,
+                FinishPhase::WriteOpConversion => &mut stats.write_op_conversion,
+            });
+            self.stats.set(stats);
+        }
+    }
+
+    /// Counts a read of `bytes`, returning whether the budget, if any, still covers it.
+    fn record_fetch(
+        &self,
+        bytes: Option<&[u8]>,
+        counter: fn(&mut ResolverReads) -> &mut u64,
+    ) -> bool {
+        let bytes = bytes.map_or(0, |bytes| bytes.len() as u64);
+        self.record(|reads| {
+            *counter(reads) += 1;
+            reads.bytes_fetched += bytes;
+        });
+        self.charge(1, bytes)
+    }
+
+    fn set_budget(&self, budget: Option<FinishBudget>) {
+        self.budget.set(budget);
+    }
+
+    /// Takes `reads` and `bytes` off the budget, returning whether it covered them. Once it did
+    /// not, it stays exceeded.
+    fn charge(&self, reads: u64, bytes: u64) -> bool {
+        if let Some(budget) = self.budget.get() {
+            match (
+                budget.max_reads.checked_sub(reads),
+                budget.max_bytes.checked_sub(bytes),
+            ) {
+                (Some(max_reads), Some(max_bytes)) => self.budget.set(Some(FinishBudget {
+                    max_reads,
+                    max_bytes,
+                })),
+                _ => self.budget_exceeded.set(true),
+            }
+        }
+        !self.budget_exceeded.get()
+    }
+
+    fn budget_exceeded(&self) -> bool {
+        self.budget_exceeded.get()
+    }
+
+    #[cfg(not(feature = "no-metrics"))]
+    fn stats(&self) -> FinishReadStats {
+        self.stats.get()
+    }
+
+    #[cfg(feature = "no-metrics")]
+    fn stats(&self) -> FinishReadStats {
+        FinishReadStats::default()
+    }
+}
 
 pub struct MoveResolverWithVMMetadata<'a, 'm, S> {
     move_resolver: &'a S,
     move_vm: &'m MoveVM,
+    // Resource group blobs as they are in storage, i.e., before the session's own writes.
+    resource_group_cache: RefCell<HashMap<(AccountAddress, StructTag), Option<Vec<u8>>>>,
+    read_recorder: ReadRecorder,
 }
 
 impl<'a, 'm, S: MoveResolverExt> MoveResolverWithVMMetadata<'a, 'm, S> {
@@ -34,12 +171,61 @@ impl<'a, 'm, S: MoveResolverExt> MoveResolverWithVMMetadata<'a, 'm, S> {
         Self {
             move_resolver,
             move_vm,
+            resource_group_cache: RefCell::new(HashMap::new()),
+            read_recorder: ReadRecorder::default(),
+        }
+    }
+
+    /// Attributes the reads that follow to `phase`, or stops counting with `None`.
+    pub fn set_finish_phase(&self, phase: Option<FinishPhase>) {
+        self.read_recorder.set_phase(phase)
+    }
+
+    /// The reads counted so far.
+    pub fn finish_read_stats(&self) -> FinishReadStats {
+        self.read_recorder.stats()
+    }
+
+    /// Charges the reads that follow, and the bytes passed to `charge_finish_bytes`, to `budget`,
+    /// or stops charging with `None`. Reads the budget does not cover fail with
+    /// `FINISH_BUDGET_EXCEEDED`.
+    pub fn set_finish_budget(&self, budget: Option<FinishBudget>) {
+        self.read_recorder.set_budget(budget)
+    }
+
+    /// Charges `bytes` handled by finishing to the budget set with `set_finish_budget`, if any.
+    pub fn charge_finish_bytes(&self, bytes: u64) -> PartialVMResult<()> {
+        if self.read_recorder.charge(0, bytes) {
+            Ok(())
+        } else {
+            Err(finish_budget_exceeded())
+        }
+    }
+
+    /// Replaces `err` with `FINISH_BUDGET_EXCEEDED` if the budget was exceeded, as reads failing
+    /// for lack of budget may have been reported as some other error on the way, e.g. a storage
+    /// error.
+    pub fn blame_finish_budget(&self, err: VMError) -> VMError {
+        if self.read_recorder.budget_exceeded() {
+            finish_budget_exceeded().finish(Location::Undefined)
+        } else {
+            err
+        }
+    }
+
+    /// The underlying state view, with the reads made through it counted like the resolver's own.
+    pub(crate) fn recording_state_view(&self) -> RecordingStateView<'_> {
+        RecordingStateView {
+            state_view: self.move_resolver,
+            read_recorder: &self.read_recorder,
         }
     }
 }
 
 impl<'a, 'm, S: MoveResolverExt> MoveResolverExt for MoveResolverWithVMMetadata<'a, 'm, S> {
     fn get_module_metadata(&self, module_id: ModuleId) -> Option<RuntimeModuleMetadataV1> {
+        self.read_recorder
+            .record(|reads| reads.group_metadata_lookups += 1);
         aptos_framework::get_vm_metadata(self.move_vm, module_id)
     }
 
@@ -48,8 +234,24 @@ impl<'a, 'm, S: MoveResolverExt> MoveResolverExt for MoveResolverWithVMMetadata<
         address: &AccountAddress,
         resource_group: &StructTag,
     ) -> Result<Option<Vec<u8>>, VMError> {
-        self.move_resolver
-            .get_resource_group_data(address, resource_group)
+        let key = (*address, resource_group.clone());
+        if let Some(data) = self.resource_group_cache.borrow().get(&key) {
+            return Ok(data.clone());
+        }
+
+        let data = self
+            .move_resolver
+            .get_resource_group_data(address, resource_group)?;
+        let within_budget = self
+            .read_recorder
+            .record_fetch(data.as_deref(), |reads| &mut reads.group_data_reads);
+        self.resource_group_cache
+            .borrow_mut()
+            .insert(key, data.clone());
+        if !within_budget {
+            return Err(finish_budget_exceeded().finish(Location::Undefined));
+        }
+        Ok(data)
     }
 
     fn get_standard_resource(
@@ -57,8 +259,16 @@ impl<'a, 'm, S: MoveResolverExt> MoveResolverExt for MoveResolverWithVMMetadata<
         address: &AccountAddress,
         struct_tag: &StructTag,
     ) -> Result<Option<Vec<u8>>, VMError> {
-        self.move_resolver
-            .get_standard_resource(address, struct_tag)
+        let data = self
+            .move_resolver
+            .get_standard_resource(address, struct_tag)?;
+        if !self
+            .read_recorder
+            .record_fetch(data.as_deref(), |reads| &mut reads.state_value_reads)
+        {
+            return Err(finish_budget_exceeded().finish(Location::Undefined));
+        }
+        Ok(data)
     }
 }
 
@@ -106,6 +316,12 @@ impl<'a, 'm, S: MoveResolverExt> StateStorageUsageResolver
     }
 }
 
+impl<'a, 'm, S: MoveResolverExt> ModuleMetadataResolver for MoveResolverWithVMMetadata<'a, 'm, S> {
+    fn resolve_module_metadata(&self, module_id: &ModuleId) -> Option<RuntimeModuleMetadataV1> {
+        self.get_module_metadata(module_id.clone())
+    }
+}
+
 impl<'a, 'm, S: MoveResolverExt> Deref for MoveResolverWithVMMetadata<'a, 'm, S> {
     type Target = S;
 
@@ -114,6 +330,42 @@ impl<'a, 'm, S: MoveResolverExt> Deref for MoveResolverWithVMMetadata<'a, 'm, S>
     }
 }
 
+/// A state view counting the reads made through it, see
+/// `MoveResolverWithVMMetadata::recording_state_view`.
+pub(crate) struct RecordingStateView<'a> {
+    state_view: &'a dyn StateView,
+    read_recorder: &'a ReadRecorder,
+}
+
+impl<'a> TStateView for RecordingStateView<'a> {
+    type Key = StateKey;
+
+    fn id(&self) -> StateViewId {
+        self.state_view.id()
+    }
+
+    fn get_state_value(&self, state_key: &StateKey) -> anyhow::Result<Option<StateValue>> {
+        let value = self.state_view.get_state_value(state_key)?;
+        if !self
+            .read_recorder
+            .record_fetch(value.as_ref().map(|value| value.bytes()), |reads| {
+                &mut reads.state_value_reads
+            })
+        {
+            anyhow::bail!("finishing the session exceeded its budget");
+        }
+        Ok(value)
+    }
+
+    fn is_genesis(&self) -> bool {
+        self.state_view.is_genesis()
+    }
+
+    fn get_usage(&self) -> anyhow::Result<StateStorageUsage> {
+        self.state_view.get_usage()
+    }
+}
+
 /// Adapter to convert a `StateView` into a `MoveResolverExt`.
 pub struct StorageAdapter<'a, S>(&'a S);
 
@@ -201,6 +453,12 @@ impl<'a, S: StateView> StateStorageUsageResolver for StorageAdapter<'a, S> {
     }
 }
 
+impl<'a, S: StateView> ModuleMetadataResolver for StorageAdapter<'a, S> {
+    fn resolve_module_metadata(&self, module_id: &ModuleId) -> Option<RuntimeModuleMetadataV1> {
+        self.get_module_metadata(module_id.clone())
+    }
+}
+
 impl<'a, S> Deref for StorageAdapter<'a, S> {
     type Target = S;
 
@@ -304,6 +562,12 @@ impl<S: StateView> StateStorageUsageResolver for StorageAdapterOwned<S> {
     }
 }
 
+impl<S: StateView> ModuleMetadataResolver for StorageAdapterOwned<S> {
+    fn resolve_module_metadata(&self, module_id: &ModuleId) -> Option<RuntimeModuleMetadataV1> {
+        self.get_module_metadata(module_id.clone())
+    }
+}
+
 pub trait IntoMoveResolver<S> {
     fn into_move_resolver(self) -> StorageAdapterOwned<S>;
 }
@@ -0,0 +1,399 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fuzzing of the conversion of a session's output into a `ChangeSetExt`, without running any
+//! Move code: the parts a session would have produced are generated directly, in shapes a session
+//! may not produce, and run through resource group merging and `convert_change_set` against an
+//! in-memory state. The same conversion backs the change set goldens in `tests`.
+
+use crate::{
+    data_cache::{IntoMoveResolver, StorageAdapterOwned},
+    move_vm_ext::{
+        AllowAllWrites, DefaultStateKeyCodec, InMemoryStateView, NoGroupGas, SessionExt,
+        SplitChangeSet,
+    },
+};
+use aptos_aggregator::{
+    aggregator_extension::{AggregatorHandle, AggregatorID},
+    delta_change_set::{delta_add, delta_sub},
+    transaction::ChangeSetExt,
+};
+use aptos_framework::{
+    natives::aggregator_natives::{AggregatorChange, AggregatorChangeSet},
+    KnownAttribute, RuntimeModuleMetadataV1, APTOS_METADATA_KEY_V1,
+};
+use aptos_gas::{ChangeSetConfigs, LATEST_GAS_FEATURE_VERSION};
+use aptos_state_view::StateView;
+use aptos_types::{
+    access_path::AccessPath,
+    event::EventKey,
+    state_store::{state_key::StateKey, table::TableHandle},
+};
+use move_binary_format::{access::ModuleAccess, file_format::empty_module};
+use move_core_types::{
+    account_address::AccountAddress,
+    effects::{ChangeSet as MoveChangeSet, Op},
+    identifier::Identifier,
+    language_storage::{ModuleId, StructTag, TypeTag},
+    metadata::Metadata,
+    vm_status::{StatusCode, VMStatus},
+};
+use move_table_extension::{TableChange, TableChangeSet};
+use proptest::{collection::vec, prelude::*};
+use std::{collections::BTreeMap, sync::Arc};
+
+type FuzzSession<'r> = SessionExt<'r, 'r, StorageAdapterOwned<InMemoryStateView>>;
+
+/// The module at `0x1` declaring the resource group the generated resources may belong to.
+pub const GROUP_MODULE: &str = "fuzz_group";
+pub const GROUP: &str = "Group";
+pub const GROUP_MEMBERS: [&str; 2] = ["Member", "OtherMember"];
+pub const PLAIN_RESOURCE: &str = "Plain";
+
+/// The status codes converting a session's output may fail with. Any other error, or a panic, is
+/// a bug.
+const EXPECTED_ERRORS: &[StatusCode] = &[
+    StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR,
+    StatusCode::STORAGE_ERROR,
+    StatusCode::DATA_FORMAT_ERROR,
+    StatusCode::DUPLICATE_WRITE_SET_KEY,
+    StatusCode::DELTA_ON_DELETED_STATE_KEY,
+    StatusCode::EVENT_KEY_MISMATCH,
+    StatusCode::RESOURCE_GROUP_ADDRESS_NOT_INITIALIZED,
+    StatusCode::RESOURCE_GROUP_MEMBER_TAG_TOO_COMPLEX,
+];
+
+/// The output of a session, in the parts `SessionExt::finish` converts.
+#[derive(Clone, Debug)]
+pub struct ChangeSetParts {
+    pub resources: Vec<(AccountAddress, StructTag, Op<Vec<u8>>)>,
+    pub modules: Vec<(AccountAddress, Identifier, Op<Vec<u8>>)>,
+    /// Table items, by the address of their table handle.
+    pub table_items: Vec<(AccountAddress, Vec<u8>, Op<Vec<u8>>)>,
+    /// Aggregator changes, by the addresses of the aggregator's table handle and key.
+    pub aggregators: Vec<(AccountAddress, AccountAddress, AggregatorChange)>,
+    /// Events as Move emits them, i.e. with their key still serialized.
+    pub events: Vec<(Vec<u8>, u64, TypeTag, Vec<u8>)>,
+    pub validate_resource_group_containers: bool,
+    pub defer_resource_group_reads: bool,
+    pub strip_noop_modifications: bool,
+}
+
+/// The state the parts are converted against, on top of the module declaring the resource group.
+#[derive(Clone, Debug)]
+pub struct BaseState {
+    /// Resource groups, by the address they are stored at.
+    pub groups: Vec<(AccountAddress, BTreeMap<StructTag, Vec<u8>>)>,
+    /// Other values, e.g. aggregators or blobs that do not decode.
+    pub values: Vec<(StateKey, Vec<u8>)>,
+}
+
+/// Converts `parts` against `base_state`, panicking if conversion fails with an error it is not
+/// expected to fail with, or if the resulting change set is inconsistent.
+pub fn fuzz_convert(parts: ChangeSetParts, base_state: BaseState) {
+    let configs = Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+        LATEST_GAS_FEATURE_VERSION,
+    ));
+    match convert_parts(parts, base_state, configs) {
+        Ok(change_set) => {
+            let serialized = bcs::to_bytes(change_set.write_set()).unwrap();
+            assert_eq!(
+                change_set.serialized_size_estimate(),
+                serialized.len() as u64
+            );
+            assert_eq!(
+                change_set.event_sizes().len(),
+                change_set.change_set().events().len()
+            );
+        },
+        Err(status) => assert!(
+            EXPECTED_ERRORS.contains(&status.status_code()),
+            "unexpected error converting a change set: {:?}",
+            status
+        ),
+    }
+}
+
+/// Converts `parts` against `base_state` as finishing a session would, with the resource groups
+/// merged and any deferred group reads materialized.
+pub fn convert_parts(
+    parts: ChangeSetParts,
+    base_state: BaseState,
+    configs: Arc<ChangeSetConfigs>,
+) -> Result<ChangeSetExt, VMStatus> {
+    let resolver = base_state.into_state_view().into_move_resolver();
+
+    let mut change_set = MoveChangeSet::new();
+    for (addr, struct_tag, op) in parts.resources {
+        // A session never writes a resource twice, skip duplicates instead of failing.
+        let _ = change_set.add_resource_op(addr, struct_tag, op);
+    }
+    for (addr, name, op) in parts.modules {
+        let _ = change_set.add_module_op(ModuleId::new(addr, name), op);
+    }
+
+    let mut table_change_set = TableChangeSet::default();
+    for (handle, key, op) in parts.table_items {
+        table_change_set
+            .changes
+            .entry(TableHandle(handle))
+            .or_insert_with(|| TableChange {
+                entries: BTreeMap::new(),
+            })
+            .entries
+            .insert(key, op);
+    }
+    let aggregator_change_set = AggregatorChangeSet {
+        changes: parts
+            .aggregators
+            .into_iter()
+            .map(|(handle, key, change)| {
+                (
+                    AggregatorID::new(TableHandle(handle), AggregatorHandle(key)),
+                    change,
+                )
+            })
+            .collect(),
+    };
+
+    FuzzSession::split_and_merge_resource_groups(
+        &resolver,
+        change_set,
+        &configs,
+        &DefaultStateKeyCodec,
+        &AllowAllWrites,
+        &mut NoGroupGas,
+        parts.validate_resource_group_containers,
+        parts.defer_resource_group_reads,
+    )
+    .map_err(|err| err.into_vm_status())
+    .and_then(
+        |SplitChangeSet {
+             change_set,
+             resource_group_change_set,
+             mut group_patches,
+             ..
+         }| {
+            for patch in &mut group_patches {
+                patch.strips_noop_modification = parts.strip_noop_modifications;
+            }
+            FuzzSession::convert_change_set(
+                change_set,
+                resource_group_change_set,
+                parts.events,
+                vec![],
+                table_change_set,
+                aggregator_change_set,
+                &mut (),
+                &DefaultStateKeyCodec,
+                &configs,
+                &AllowAllWrites,
+                false,
+                None,
+                parts
+                    .strip_noop_modifications
+                    .then_some(&*resolver as &dyn StateView),
+                None,
+                None,
+            )?
+            .with_group_patches(group_patches)
+            .materialize_group_patches(&*resolver)
+        },
+    )
+}
+
+impl BaseState {
+    fn into_state_view(self) -> InMemoryStateView {
+        let mut state_view = InMemoryStateView::default();
+        state_view.insert(
+            StateKey::access_path(AccessPath::from(&group_module_id())),
+            group_module_bytes(),
+        );
+        for (addr, group) in self.groups {
+            state_view.insert(
+                StateKey::access_path(AccessPath::resource_group_access_path(
+                    addr,
+                    struct_tag(GROUP),
+                )),
+                bcs::to_bytes(&group).unwrap(),
+            );
+        }
+        for (state_key, bytes) in self.values {
+            state_view.insert(state_key, bytes);
+        }
+        state_view
+    }
+}
+
+fn group_module_id() -> ModuleId {
+    ModuleId::new(AccountAddress::ONE, Identifier::new(GROUP_MODULE).unwrap())
+}
+
+/// A module declaring `Group` and its members, with no code: converting a change set only reads
+/// the metadata.
+fn group_module_bytes() -> Vec<u8> {
+    let member = KnownAttribute::resource_group_member(format!("0x1::{}::{}", GROUP_MODULE, GROUP));
+    let metadata = RuntimeModuleMetadataV1 {
+        error_map: BTreeMap::new(),
+        struct_attributes: GROUP_MEMBERS
+            .iter()
+            .map(|name| (name.to_string(), vec![member.clone()]))
+            .collect(),
+        fun_attributes: BTreeMap::new(),
+    };
+
+    let mut module = empty_module();
+    module.identifiers = vec![Identifier::new(GROUP_MODULE).unwrap()];
+    module.address_identifiers = vec![AccountAddress::ONE];
+    module.metadata.push(Metadata {
+        key: APTOS_METADATA_KEY_V1.clone(),
+        value: bcs::to_bytes(&metadata).unwrap(),
+    });
+    debug_assert_eq!(module.self_id(), group_module_id());
+    let mut bytes = vec![];
+    module.serialize(&mut bytes).unwrap();
+    bytes
+}
+
+/// The struct `name` declared by the group module, e.g. `GROUP` or `PLAIN_RESOURCE`.
+pub fn struct_tag(name: &str) -> StructTag {
+    StructTag {
+        address: AccountAddress::ONE,
+        module: Identifier::new(GROUP_MODULE).unwrap(),
+        name: Identifier::new(name).unwrap(),
+        type_params: vec![],
+    }
+}
+
+/// A few addresses, so that the generated parts collide with each other and the base state.
+fn arb_address() -> impl Strategy<Value = AccountAddress> {
+    (1u8..5).prop_map(|i| {
+        let mut bytes = [0u8; AccountAddress::LENGTH];
+        bytes[AccountAddress::LENGTH - 1] = i;
+        AccountAddress::new(bytes)
+    })
+}
+
+/// Group members, the group itself, or a resource outside any group.
+fn arb_struct_tag() -> impl Strategy<Value = StructTag> {
+    prop_oneof![
+        Just(struct_tag(GROUP_MEMBERS[0])),
+        Just(struct_tag(GROUP_MEMBERS[1])),
+        Just(struct_tag(GROUP)),
+        Just(struct_tag(PLAIN_RESOURCE)),
+    ]
+}
+
+fn arb_bytes() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 0..16)
+}
+
+fn arb_op() -> impl Strategy<Value = Op<Vec<u8>>> {
+    prop_oneof![
+        arb_bytes().prop_map(Op::New),
+        arb_bytes().prop_map(Op::Modify),
+        Just(Op::Delete),
+    ]
+}
+
+fn arb_aggregator_change() -> impl Strategy<Value = AggregatorChange> {
+    prop_oneof![
+        any::<u128>().prop_map(AggregatorChange::Write),
+        (any::<u128>(), any::<u128>())
+            .prop_map(|(value, limit)| AggregatorChange::Merge(delta_add(value, limit))),
+        (any::<u128>(), any::<u128>())
+            .prop_map(|(value, limit)| AggregatorChange::Merge(delta_sub(value, limit))),
+        Just(AggregatorChange::Delete),
+    ]
+}
+
+/// A serialized event key, or bytes that may not decode as one.
+fn arb_event_key_bytes() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        (any::<u64>(), arb_address())
+            .prop_map(|(salt, addr)| bcs::to_bytes(&EventKey::new(salt, addr)).unwrap()),
+        arb_bytes(),
+    ]
+}
+
+fn arb_aggregator_key() -> impl Strategy<Value = StateKey> {
+    (arb_address(), arb_address())
+        .prop_map(|(handle, key)| StateKey::table_item(TableHandle(handle), key.to_vec()))
+}
+
+prop_compose! {
+    pub fn arb_change_set_parts()(
+        resources in vec((arb_address(), arb_struct_tag(), arb_op()), 0..8),
+        modules in vec(
+            (arb_address(), Just(Identifier::new(GROUP_MODULE).unwrap()), arb_op()),
+            0..2,
+        ),
+        table_items in vec((arb_address(), arb_bytes(), arb_op()), 0..4),
+        aggregators in vec((arb_address(), arb_address(), arb_aggregator_change()), 0..4),
+        events in vec(
+            (arb_event_key_bytes(), any::<u64>(), Just(TypeTag::U64), arb_bytes()),
+            0..4,
+        ),
+        validate_resource_group_containers in any::<bool>(),
+        defer_resource_group_reads in any::<bool>(),
+        strip_noop_modifications in any::<bool>(),
+    ) -> ChangeSetParts {
+        ChangeSetParts {
+            resources,
+            modules,
+            table_items,
+            aggregators,
+            events,
+            validate_resource_group_containers,
+            defer_resource_group_reads,
+            strip_noop_modifications,
+        }
+    }
+}
+
+prop_compose! {
+    pub fn arb_base_state()(
+        groups in vec(
+            (
+                arb_address(),
+                proptest::collection::btree_map(arb_struct_tag(), arb_bytes(), 0..3),
+            ),
+            0..3,
+        ),
+        aggregators in vec(
+            (arb_aggregator_key(), any::<u128>().prop_map(|v| bcs::to_bytes(&v).unwrap())),
+            0..3,
+        ),
+        blobs in vec(
+            (
+                arb_address().prop_map(|addr| StateKey::access_path(
+                    AccessPath::resource_group_access_path(addr, struct_tag(GROUP)),
+                )),
+                arb_bytes(),
+            ),
+            0..2,
+        ),
+    ) -> BaseState {
+        BaseState {
+            groups,
+            values: aggregators.into_iter().chain(blobs).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        #[test]
+        fn converting_change_sets_does_not_panic(
+            parts in arb_change_set_parts(),
+            base_state in arb_base_state(),
+        ) {
+            fuzz_convert(parts, base_state);
+        }
+    }
+}
@@ -101,7 +101,7 @@
 //!             +-----------------------------+
 //! ```
 
-mod access_path_cache;
+pub mod access_path_cache;
 #[macro_use]
 mod counters;
 pub mod data_cache;
@@ -113,8 +113,9 @@ mod adapter_common;
 pub mod aptos_vm;
 mod aptos_vm_impl;
 pub mod block_executor;
-mod delta_state_view;
 mod errors;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
 pub mod move_vm_ext;
 pub mod natives;
 pub mod read_write_set_analysis;
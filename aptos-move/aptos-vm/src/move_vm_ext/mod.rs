@@ -4,11 +4,31 @@
 ///! MoveVM and Session wrapped, to make sure Aptos natives and extensions are always installed and
 ///! taken care of after session finish.
 mod resolver;
+mod resource_group_gas;
+mod resource_group_view;
 mod session;
+mod session_chain;
+mod state_footprint;
+mod state_key_codec;
+#[cfg(any(test, feature = "testing", feature = "fuzzing"))]
+mod test_harness;
 mod vm;
+mod write_policy;
 
+#[cfg(any(test, feature = "fuzzing"))]
+pub(crate) use crate::move_vm_ext::session::SplitChangeSet;
+#[cfg(any(test, feature = "testing", feature = "fuzzing"))]
+pub use crate::move_vm_ext::test_harness::{
+    HarnessSession, InMemoryStateView, InspectedChangeSet, SessionTestEnv, SessionTestHarness,
+};
 pub use crate::move_vm_ext::{
-    resolver::MoveResolverExt,
+    resolver::{MoveResolverExt, ResourceGroupMap},
+    resource_group_gas::{NoGroupGas, ResourceGroupGasHook},
+    resource_group_view::ResourceGroupView,
     session::{SessionExt, SessionId},
+    session_chain::{ChainedResolver, EffectiveStateView, SessionChain},
+    state_footprint::{BlockMetaFootprint, StateFootprint},
+    state_key_codec::{DefaultStateKeyCodec, StateKeyCodec},
     vm::{verifier_config, MoveVmExt},
+    write_policy::{AllowAllWrites, WriteDecision, WritePolicy},
 };
@@ -1,12 +1,17 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use aptos_framework::{natives::state_storage::StateStorageUsageResolver, RuntimeModuleMetadataV1};
+use crate::move_vm_ext::StateKeyCodec;
+use aptos_framework::{
+    natives::{object::ModuleMetadataResolver, state_storage::StateStorageUsageResolver},
+    RuntimeModuleMetadataV1,
+};
 use aptos_state_view::StateView;
-use aptos_types::on_chain_config::ConfigStorage;
+use aptos_types::{access_path::AccessPath, on_chain_config::ConfigStorage};
 use move_binary_format::errors::{Location, PartialVMError, VMError};
 use move_core_types::{
     account_address::AccountAddress,
+    effects::Op,
     language_storage::{ModuleId, StructTag},
     resolver::MoveResolver,
     vm_status::StatusCode,
@@ -14,8 +19,121 @@ use move_core_types::{
 use move_table_extension::TableResolver;
 use std::collections::BTreeMap;
 
+/// The members of a resource group keyed by their struct tags, as stored in the group's blob.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceGroupMap(BTreeMap<StructTag, Vec<u8>>);
+
+impl ResourceGroupMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the blob of a resource group.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VMError> {
+        bcs::from_bytes(bytes)
+            .map(Self)
+            .map_err(|_| invariant_violation("Failed to decode resource group"))
+    }
+
+    pub fn get_member(&self, struct_tag: &StructTag) -> Option<&[u8]> {
+        self.0.get(struct_tag).map(Vec::as_slice)
+    }
+
+    /// Adds or replaces a member, returning the blob it replaced.
+    pub fn insert_member(&mut self, struct_tag: StructTag, blob: Vec<u8>) -> Option<Vec<u8>> {
+        self.0.insert(struct_tag, blob)
+    }
+
+    pub fn remove_member(&mut self, struct_tag: &StructTag) -> Option<Vec<u8>> {
+        self.0.remove(struct_tag)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn members(&self) -> &BTreeMap<StructTag, Vec<u8>> {
+        &self.0
+    }
+
+    pub fn into_members(self) -> BTreeMap<StructTag, Vec<u8>> {
+        self.0
+    }
+
+    /// Applies the op of a single member, returning whether it was consistent with the group: a
+    /// created member must not exist yet, and a modified or deleted one must.
+    pub fn apply_member_op(&mut self, struct_tag: StructTag, op: Op<Vec<u8>>) -> bool {
+        match op {
+            Op::New(blob) => self.0.insert(struct_tag, blob).is_none(),
+            Op::Modify(blob) => self.0.insert(struct_tag, blob).is_some(),
+            Op::Delete => self.0.remove(&struct_tag).is_some(),
+        }
+    }
+
+    /// Applies the ops of a change set to the members of the group, returning the first member
+    /// whose op is inconsistent with the group, see `apply_member_op`.
+    ///
+    /// The result does not depend on the order the ops are applied in: there is at most one op
+    /// per member, every op only reads and writes its own member, and whether the group is left
+    /// empty, and so deleted, is only decided by the caller once all of them are applied. A
+    /// member being deleted thus never hides the modification of another one, and whether any of
+    /// the ops is inconsistent does not depend on the order either.
+    pub fn apply_member_ops(
+        &mut self,
+        member_ops: BTreeMap<StructTag, Op<Vec<u8>>>,
+    ) -> Result<(), StructTag> {
+        for (struct_tag, op) in member_ops {
+            if !self.apply_member_op(struct_tag.clone(), op) {
+                return Err(struct_tag);
+            }
+        }
+        Ok(())
+    }
+
+    /// The size of the blob `to_bytes` produces, computed without building it.
+    pub fn serialized_size(&self) -> Result<usize, VMError> {
+        bcs::serialized_size(&self.0)
+            .map_err(|_| invariant_violation("Failed to size resource group"))
+    }
+
+    /// The canonical blob of the group, allocated exactly once.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, VMError> {
+        let mut bytes = Vec::with_capacity(self.serialized_size()?);
+        bcs::serialize_into(&mut bytes, &self.0)
+            .map_err(|_| invariant_violation("Failed to serialize resource group"))?;
+        Ok(bytes)
+    }
+}
+
+impl From<BTreeMap<StructTag, Vec<u8>>> for ResourceGroupMap {
+    fn from(members: BTreeMap<StructTag, Vec<u8>>) -> Self {
+        Self(members)
+    }
+}
+
+impl FromIterator<(StructTag, Vec<u8>)> for ResourceGroupMap {
+    fn from_iter<I: IntoIterator<Item = (StructTag, Vec<u8>)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+fn invariant_violation(message: &str) -> VMError {
+    PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+        .with_message(message.to_string())
+        .finish(Location::Undefined)
+}
+
 pub trait MoveResolverExt:
-    MoveResolver<Err = VMError> + TableResolver + StateStorageUsageResolver + ConfigStorage + StateView
+    MoveResolver<Err = VMError>
+    + TableResolver
+    + StateStorageUsageResolver
+    + ModuleMetadataResolver
+    + ConfigStorage
+    + StateView
 {
     fn get_module_metadata(&self, module_id: ModuleId) -> Option<RuntimeModuleMetadataV1>;
 
@@ -25,6 +143,36 @@ pub trait MoveResolverExt:
         struct_tag: &StructTag,
     ) -> Result<Option<Vec<u8>>, VMError>;
 
+    /// Like `get_resource_group_data`, but reads the group from the key `key_codec` builds for it.
+    /// Groups are read by the resolver itself, caches included, with the default codec.
+    fn get_resource_group_data_with_codec(
+        &self,
+        address: &AccountAddress,
+        struct_tag: &StructTag,
+        key_codec: &dyn StateKeyCodec,
+    ) -> Result<Option<Vec<u8>>, VMError> {
+        if key_codec.is_default() {
+            return self.get_resource_group_data(address, struct_tag);
+        }
+        let state_key = key_codec.access_path_key(AccessPath::resource_group_access_path(
+            *address,
+            struct_tag.clone(),
+        ));
+        self.get_state_value_bytes(&state_key)
+            .map_err(|_| PartialVMError::new(StatusCode::STORAGE_ERROR).finish(Location::Undefined))
+    }
+
+    /// Like `get_resource_group_data`, but decodes the group.
+    fn get_resource_group_map(
+        &self,
+        address: &AccountAddress,
+        struct_tag: &StructTag,
+    ) -> Result<Option<ResourceGroupMap>, VMError> {
+        self.get_resource_group_data(address, struct_tag)?
+            .map(|data| ResourceGroupMap::from_bytes(&data))
+            .transpose()
+    }
+
     fn get_standard_resource(
         &self,
         address: &AccountAddress,
@@ -37,7 +185,7 @@ pub trait MoveResolverExt:
         struct_tag: &StructTag,
     ) -> Result<Option<Vec<u8>>, VMError> {
         let metadata = self.get_module_metadata(struct_tag.module_id());
-        let resource_group = Self::get_resource_group_from_metadata(struct_tag, metadata);
+        let resource_group = Self::get_resource_group_from_metadata(struct_tag, metadata.as_ref());
         if let Some(resource_group) = resource_group {
             self.get_resource_from_group(address, struct_tag, &resource_group)
         } else {
@@ -51,27 +199,22 @@ pub trait MoveResolverExt:
         struct_tag: &StructTag,
         resource_group: &StructTag,
     ) -> Result<Option<Vec<u8>>, VMError> {
-        let group_data = self.get_resource_group_data(address, resource_group)?;
-        if let Some(group_data) = group_data {
-            let mut group_data: BTreeMap<StructTag, Vec<u8>> = bcs::from_bytes(&group_data)
-                .map_err(|_| {
-                    PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
-                        .finish(Location::Undefined)
-                })?;
-            Ok(group_data.remove(struct_tag))
-        } else {
-            Ok(None)
-        }
+        Ok(self
+            .get_resource_group_map(address, resource_group)?
+            .and_then(|mut group| group.remove_member(struct_tag)))
     }
 
     fn get_resource_group(&self, struct_tag: &StructTag) -> Result<Option<StructTag>, VMError> {
         let metadata = self.get_module_metadata(struct_tag.module_id());
-        Ok(Self::get_resource_group_from_metadata(struct_tag, metadata))
+        Ok(Self::get_resource_group_from_metadata(
+            struct_tag,
+            metadata.as_ref(),
+        ))
     }
 
     fn get_resource_group_from_metadata(
         struct_tag: &StructTag,
-        metadata: Option<aptos_framework::RuntimeModuleMetadataV1>,
+        metadata: Option<&RuntimeModuleMetadataV1>,
     ) -> Option<StructTag> {
         metadata.and_then(|metadata| {
             metadata
@@ -0,0 +1,41 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_gas::AptosGasMeter;
+use move_binary_format::errors::VMResult;
+use move_core_types::gas_algebra::NumBytes;
+
+/// Charges for merging a session's changes to resource groups into the groups in storage, which
+/// is done when the session is finished. Each piece of work is charged for right before it is
+/// done, so that a transaction that cannot pay for decoding a large group fails with
+/// `OUT_OF_GAS` without decoding it.
+pub trait ResourceGroupGasHook {
+    /// Charges for classifying a group member whose struct tag serializes to `tag_bytes`.
+    fn charge_member_tag(&mut self, tag_bytes: NumBytes) -> VMResult<()>;
+
+    /// Charges for decoding an existing group whose blob is `group_bytes` long.
+    fn charge_group_decoding(&mut self, group_bytes: NumBytes) -> VMResult<()>;
+}
+
+impl<G: AptosGasMeter> ResourceGroupGasHook for G {
+    fn charge_member_tag(&mut self, tag_bytes: NumBytes) -> VMResult<()> {
+        AptosGasMeter::charge_group_member_tags(self, tag_bytes)
+    }
+
+    fn charge_group_decoding(&mut self, group_bytes: NumBytes) -> VMResult<()> {
+        AptosGasMeter::charge_group_deserialization(self, group_bytes)
+    }
+}
+
+/// Charges nothing, for sessions whose output is not paid for, e.g. genesis or block prologues.
+pub struct NoGroupGas;
+
+impl ResourceGroupGasHook for NoGroupGas {
+    fn charge_member_tag(&mut self, _tag_bytes: NumBytes) -> VMResult<()> {
+        Ok(())
+    }
+
+    fn charge_group_decoding(&mut self, _group_bytes: NumBytes) -> VMResult<()> {
+        Ok(())
+    }
+}
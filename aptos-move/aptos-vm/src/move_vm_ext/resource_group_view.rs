@@ -0,0 +1,170 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::move_vm_ext::{MoveResolverExt, ResourceGroupMap};
+use move_binary_format::errors::VMError;
+use move_core_types::{account_address::AccountAddress, language_storage::StructTag};
+
+/// Read-only access to the resource groups stored under an account, decoded the way the VM
+/// decodes them, for the API, the indexer and other readers outside of sessions.
+///
+/// Members are always listed in their canonical order, i.e. the order of their struct tags in
+/// the group's blob, so a page given by an offset and a limit stays stable across requests as
+/// long as the group does not change.
+pub struct ResourceGroupView<'a, R: ?Sized> {
+    resolver: &'a R,
+}
+
+impl<'a, R: MoveResolverExt + ?Sized> ResourceGroupView<'a, R> {
+    pub fn new(resolver: &'a R) -> Self {
+        Self { resolver }
+    }
+
+    /// The struct tags of all members of the group, or none if the group does not exist.
+    pub fn list_members(
+        &self,
+        address: &AccountAddress,
+        group: &StructTag,
+    ) -> Result<Vec<StructTag>, VMError> {
+        self.list_members_page(address, group, 0, usize::MAX)
+    }
+
+    /// The struct tags of at most `limit` members of the group, skipping the first `offset` ones.
+    pub fn list_members_page(
+        &self,
+        address: &AccountAddress,
+        group: &StructTag,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<StructTag>, VMError> {
+        Ok(self
+            .get_members_page(address, group, offset, limit)?
+            .into_iter()
+            .map(|(struct_tag, _)| struct_tag)
+            .collect())
+    }
+
+    /// Like `list_members_page`, but with the blobs of the members.
+    pub fn get_members_page(
+        &self,
+        address: &AccountAddress,
+        group: &StructTag,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<(StructTag, Vec<u8>)>, VMError> {
+        Ok(
+            match self.resolver.get_resource_group_map(address, group)? {
+                Some(group) => group
+                    .into_members()
+                    .into_iter()
+                    .skip(offset)
+                    .take(limit)
+                    .collect(),
+                None => vec![],
+            },
+        )
+    }
+
+    /// The blob of a single member, or none if either the group or the member does not exist.
+    pub fn get_member(
+        &self,
+        address: &AccountAddress,
+        group: &StructTag,
+        struct_tag: &StructTag,
+    ) -> Result<Option<Vec<u8>>, VMError> {
+        self.resolver
+            .get_resource_from_group(address, struct_tag, group)
+    }
+
+    /// The size of the group's blob in storage, or none if the group does not exist.
+    pub fn group_size_bytes(
+        &self,
+        address: &AccountAddress,
+        group: &StructTag,
+    ) -> Result<Option<u64>, VMError> {
+        Ok(self
+            .resolver
+            .get_resource_group_data(address, group)?
+            .map(|data| data.len() as u64))
+    }
+
+    /// The decoded group, or none if it does not exist.
+    pub fn get_group(
+        &self,
+        address: &AccountAddress,
+        group: &StructTag,
+    ) -> Result<Option<ResourceGroupMap>, VMError> {
+        self.resolver.get_resource_group_map(address, group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data_cache::IntoMoveResolver, move_vm_ext::InMemoryStateView};
+    use aptos_types::{access_path::AccessPath, state_store::state_key::StateKey};
+    use move_core_types::identifier::Identifier;
+
+    fn struct_tag(name: &str) -> StructTag {
+        StructTag {
+            address: AccountAddress::ONE,
+            module: Identifier::new("m").unwrap(),
+            name: Identifier::new(name).unwrap(),
+            type_params: vec![],
+        }
+    }
+
+    #[test]
+    fn members_are_listed_in_canonical_order() {
+        let address = AccountAddress::from_hex_literal("0xcafe").unwrap();
+        let group_tag = struct_tag("Group");
+        let group: ResourceGroupMap = ["C", "A", "B"]
+            .into_iter()
+            .map(|name| (struct_tag(name), name.as_bytes().to_vec()))
+            .collect();
+        let bytes = group.to_bytes().unwrap();
+
+        let mut state_view = InMemoryStateView::default();
+        state_view.insert(
+            StateKey::access_path(AccessPath::resource_group_access_path(
+                address,
+                group_tag.clone(),
+            )),
+            bytes.clone(),
+        );
+        let resolver = state_view.into_move_resolver();
+        let view = ResourceGroupView::new(&resolver);
+
+        assert_eq!(view.list_members(&address, &group_tag).unwrap(), vec![
+            struct_tag("A"),
+            struct_tag("B"),
+            struct_tag("C")
+        ]);
+        assert_eq!(
+            view.list_members_page(&address, &group_tag, 1, 1).unwrap(),
+            vec![struct_tag("B")]
+        );
+        assert!(view
+            .list_members_page(&address, &group_tag, 3, 10)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            view.get_member(&address, &group_tag, &struct_tag("C"))
+                .unwrap(),
+            Some(b"C".to_vec())
+        );
+        assert_eq!(
+            view.get_member(&address, &group_tag, &struct_tag("D"))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            view.group_size_bytes(&address, &group_tag).unwrap(),
+            Some(bytes.len() as u64)
+        );
+
+        let missing = struct_tag("Missing");
+        assert!(view.list_members(&address, &missing).unwrap().is_empty());
+        assert_eq!(view.group_size_bytes(&address, &missing).unwrap(), None);
+    }
+}
@@ -2,27 +2,52 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    access_path_cache::AccessPathCache, data_cache::MoveResolverWithVMMetadata,
-    move_vm_ext::MoveResolverExt, transaction_metadata::TransactionMetadata,
+    access_path_cache::AccessPathCache,
+    counters::{
+        EMPTY_ACCOUNT_CHANGE_SETS_PRUNED, LEGACY_CREATIONS_AS_MODIFICATIONS,
+        NOOP_MODIFICATIONS_STRIPPED,
+    },
+    data_cache::{FinishBudget, FinishPhase, FinishReadStats, MoveResolverWithVMMetadata},
+    move_vm_ext::{
+        AllowAllWrites, DefaultStateKeyCodec, MoveResolverExt, NoGroupGas, ResourceGroupGasHook,
+        ResourceGroupMap, StateFootprint, StateKeyCodec, WriteDecision, WritePolicy,
+    },
+    transaction_metadata::TransactionMetadata,
 };
 use aptos_aggregator::{
     aggregator_extension::AggregatorID,
-    delta_change_set::{serialize, DeltaChangeSet},
-    transaction::ChangeSetExt,
+    delta_change_set::{serialize, DeltaChangeSet, DeltaProvenance},
+    transaction::{ChangeSetExt, ChangeSetFlags, GroupPatch, WriteOpSource, WriteSetSize},
 };
 use aptos_crypto::{hash::CryptoHash, HashValue};
 use aptos_crypto_derive::{BCSCryptoHash, CryptoHasher};
-use aptos_framework::natives::{
-    aggregator_natives::{AggregatorChange, AggregatorChangeSet, NativeAggregatorContext},
-    code::{NativeCodeContext, PublishRequest},
+#[cfg(feature = "feature-usage")]
+use aptos_framework::natives::feature_usage::NativeFeatureUsageContext;
+use aptos_framework::{
+    natives::{
+        aggregator_natives::{AggregatorChange, AggregatorChangeSet, NativeAggregatorContext},
+        code::{NativeCodeContext, PublishRequest},
+        event::{EmittedEvent, EventLimits, NativeEventContext},
+        transaction_context::NativeWriteBudgetContext,
+    },
+    RuntimeModuleMetadataV1,
 };
-use aptos_gas::ChangeSetConfigs;
+use aptos_gas::{ChangeSetConfigs, LATEST_GAS_FEATURE_VERSION};
+use aptos_logger::debug;
+use aptos_state_view::StateView;
 use aptos_types::{
+    access_path::AccessPath,
+    account_config::{AccountResource, ObjectGroupResource, StorageQuotaResource},
     block_metadata::BlockMetadata,
     contract_event::ContractEvent,
-    state_store::{state_key::StateKey, table::TableHandle},
+    event::EventKey,
+    state_store::{
+        state_key::{StateKey, StateKeyInner},
+        state_value::StateValue,
+        table::TableHandle,
+    },
     transaction::{ChangeSet, SignatureCheckedTransaction},
-    write_set::{WriteOp, WriteSetMut},
+    write_set::{WriteOp, WriteSet, WriteSetMut},
 };
 use move_binary_format::errors::{Location, PartialVMError, VMResult};
 use move_core_types::{
@@ -30,16 +55,22 @@ use move_core_types::{
     effects::{
         AccountChangeSet, ChangeSet as MoveChangeSet, Event as MoveEvent, Op as MoveStorageOp,
     },
-    language_storage::{ModuleId, StructTag},
+    gas_algebra::NumBytes,
+    ident_str,
+    language_storage::{ModuleId, StructTag, CORE_CODE_ADDRESS},
+    move_resource::MoveStructType,
     vm_status::{StatusCode, VMStatus},
 };
 use move_table_extension::{NativeTableContext, TableChangeSet};
 use move_vm_runtime::{move_vm::MoveVM, session::Session};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     ops::{Deref, DerefMut},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 #[derive(BCSCryptoHash, CryptoHasher, Deserialize, Serialize)]
@@ -91,51 +122,429 @@ impl SessionId {
     pub fn as_uuid(&self) -> HashValue {
         self.hash()
     }
+
+    /// The sender of the transaction this session executes, if any.
+    pub fn sender(&self) -> Option<AccountAddress> {
+        match self {
+            Self::Txn { sender, .. } => Some(*sender),
+            Self::BlockMeta { .. } | Self::Genesis { .. } | Self::Void => None,
+        }
+    }
+}
+
+/// What `SessionExt::split_and_merge_resource_groups` splits a Move change set into.
+pub(crate) struct SplitChangeSet {
+    /// The modules and the resources that are not resource group members.
+    pub change_set: MoveChangeSet,
+    /// One op per resource group, with the changes to its members merged in.
+    pub resource_group_change_set: MoveChangeSet,
+    /// The changes to groups left unread, see `GroupPatch`.
+    pub group_patches: Vec<GroupPatch>,
+    /// The total size of the existing group blobs decoded to merge the changes into.
+    pub group_bytes_deserialized: NumBytes,
+    /// The group every resource was classified into, or `None` for standalone resources.
+    pub group_classifications: BTreeMap<StructTag, Option<StructTag>>,
+    /// How much every merged group grows, see `ChangeSetExt::group_size_deltas`.
+    pub group_size_deltas: Vec<(StateKey, i64)>,
 }
 
 pub struct SessionExt<'r, 'l, S> {
     inner: Session<'r, 'l, S>,
     remote: MoveResolverWithVMMetadata<'r, 'l, S>,
+    state_key_codec: Arc<dyn StateKeyCodec>,
+    sender: Option<AccountAddress>,
+    validate_event_keys: bool,
+    validate_resource_group_containers: bool,
+    strip_noop_modifications: bool,
+    is_block_meta: bool,
+    block_meta_footprint: Option<Arc<dyn StateFootprint>>,
+    validate_aggregator_handles: bool,
+    defer_resource_group_reads: bool,
+    enforce_storage_quotas: bool,
+    allow_reserved_deletions: bool,
+    finish_budget: Option<FinishBudget>,
+    is_void: bool,
+    native_event_key: EventKey,
+    first_native_event_seq_num: u64,
 }
 
 impl<'r, 'l, S> SessionExt<'r, 'l, S>
 where
     S: MoveResolverExt + 'r,
 {
-    pub fn new(inner: Session<'r, 'l, S>, move_vm: &'l MoveVM, remote: &'r S) -> Self {
+    pub fn new(
+        inner: Session<'r, 'l, S>,
+        move_vm: &'l MoveVM,
+        remote: &'r S,
+        session_id: &SessionId,
+    ) -> Self {
         Self {
             inner,
             remote: MoveResolverWithVMMetadata::new(remote, move_vm),
+            state_key_codec: Arc::new(DefaultStateKeyCodec),
+            sender: session_id.sender(),
+            validate_event_keys: false,
+            validate_resource_group_containers: false,
+            strip_noop_modifications: false,
+            is_block_meta: matches!(session_id, SessionId::BlockMeta { .. }),
+            block_meta_footprint: None,
+            validate_aggregator_handles: false,
+            defer_resource_group_reads: false,
+            enforce_storage_quotas: false,
+            allow_reserved_deletions: matches!(session_id, SessionId::Genesis { .. }),
+            finish_budget: None,
+            is_void: matches!(session_id, SessionId::Void),
+            native_event_key: native_event_key(session_id),
+            first_native_event_seq_num: 0,
+        }
+    }
+
+    /// Replaces the codec building the state keys of the session's writes, and of the resource
+    /// groups read to merge into them, when the session is finished. By default keys are built as
+    /// everywhere else.
+    pub fn set_state_key_codec(&mut self, state_key_codec: Arc<dyn StateKeyCodec>) {
+        self.state_key_codec = state_key_codec;
+    }
+
+    /// Requires the events of transaction sessions to be keyed to the sender, an account the
+    /// session writes or a reserved address (see `convert_change_set`). Off by default.
+    pub fn set_validate_event_keys(&mut self, validate_event_keys: bool) {
+        self.validate_event_keys = validate_event_keys;
+    }
+
+    /// Requires every newly created resource group to live at an address holding an account or an
+    /// object (see `check_resource_group_container`). Off by default.
+    pub fn set_validate_resource_group_containers(&mut self, validate: bool) {
+        self.validate_resource_group_containers = validate;
+    }
+
+    /// Drops modifications that write back the value already in storage from the write set when
+    /// the session is finished (see `convert_change_set`). Off by default.
+    pub fn set_strip_noop_modifications(&mut self, strip: bool) {
+        self.strip_noop_modifications = strip;
+    }
+
+    /// Restricts what a block metadata session may write to `footprint` (see
+    /// `convert_change_set`). Has no effect on other sessions; by default nothing is enforced.
+    pub fn set_block_meta_footprint(&mut self, footprint: Arc<dyn StateFootprint>) {
+        self.block_meta_footprint = Some(footprint);
+    }
+
+    /// Requires every aggregator change to be to the table of the `0x1::aggregator_factory`, see
+    /// `aggregator_factory_handle`. Off by default.
+    pub fn set_validate_aggregator_handles(&mut self, validate: bool) {
+        self.validate_aggregator_handles = validate;
+    }
+
+    /// Leaves resource groups unread when the session is finished: the changes to their members
+    /// are returned as `GroupPatch`es instead, for the executor to merge with
+    /// `ChangeSetExt::materialize_group_patches` where it tracks reads. The groups end up the
+    /// same either way. Has no effect while containers are validated or storage quotas enforced,
+    /// or on block metadata sessions, whose checks need the groups, nor with a state key codec
+    /// other than the default one, as patches are materialized under default keys. Off by default.
+    pub fn set_defer_resource_group_reads(&mut self, defer: bool) {
+        self.defer_resource_group_reads = defer;
+    }
+
+    /// Rejects the session's writes if they grow the state under an account beyond the quota in
+    /// its `StorageQuotaResource`, see `check_storage_quotas`. Off by default.
+    ///
+    /// Only available to tests: nothing keeps `used_bytes` of the quotas up to date, so the check
+    /// cannot be enabled on chain, not even through `FeatureFlag::STORAGE_QUOTAS`.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn set_enforce_storage_quotas(&mut self, enforce: bool) {
+        self.enforce_storage_quotas = enforce;
+    }
+
+    /// Lets the session delete resources at framework reserved addresses even if the
+    /// `ChangeSetConfigs` it is finished with protect them, as governance write set sessions do.
+    /// Allowed by default for genesis sessions only.
+    pub fn set_allow_reserved_deletions(&mut self, allow: bool) {
+        self.allow_reserved_deletions = allow;
+    }
+
+    /// Numbers the events emitted by natives after the ones in `previous`, the output of an earlier
+    /// session of the same transaction that this session's output is squashed into. The sessions
+    /// of a transaction share the key of those events, see `native_event_key`.
+    pub fn continue_native_events(&mut self, previous: &ChangeSetExt) {
+        self.first_native_event_seq_num = previous
+            .change_set()
+            .events()
+            .iter()
+            .filter(|event| event.key() == &self.native_event_key)
+            .count() as u64;
+    }
+
+    /// Bounds the reads, and the bytes handled, when the session is finished, where no gas is
+    /// charged as the work is done. Finishing fails with `FINISH_BUDGET_EXCEEDED` once `budget`
+    /// is used up. Unbounded by default.
+    pub fn set_finish_budget(&mut self, budget: FinishBudget) {
+        self.finish_budget = Some(budget);
+    }
+
+    /// Enforces the event limits of `configs` as events are emitted, if the gas feature version
+    /// calls for it. The same limits are checked again when the session is finished. Along with
+    /// them, `0x1::transaction_context::remaining_write_budget` counts down from the limits of
+    /// `configs` rather than being unlimited.
+    pub fn set_event_limits(&mut self, configs: &ChangeSetConfigs) {
+        if configs.enforces_event_limits_during_execution() {
+            let extensions = self.inner.get_native_extensions();
+            extensions
+                .get_mut::<NativeEventContext>()
+                .set_limits(EventLimits {
+                    max_bytes_per_event: configs.max_bytes_per_event(),
+                    max_bytes_all_events: configs.max_bytes_all_events_per_transaction(),
+                });
+            extensions
+                .get_mut::<NativeWriteBudgetContext>()
+                .set_max_bytes_all_write_ops(configs.max_bytes_all_write_ops_per_transaction());
         }
     }
 
     pub fn finish<C: AccessPathCache>(
         self,
         ap_cache: &mut C,
-        configs: &ChangeSetConfigs,
+        configs: &Arc<ChangeSetConfigs>,
+    ) -> VMResult<ChangeSetExt> {
+        self.finish_with_read_stats(ap_cache, configs, &mut NoGroupGas)
+            .map(|(change_set, _)| change_set)
+    }
+
+    /// Like `finish`, charging `gas_hook` for merging the changes to resource groups as the work
+    /// is done, see `ResourceGroupGasHook`.
+    pub fn finish_with_gas<C: AccessPathCache>(
+        self,
+        ap_cache: &mut C,
+        configs: &Arc<ChangeSetConfigs>,
+        gas_hook: &mut dyn ResourceGroupGasHook,
     ) -> VMResult<ChangeSetExt> {
+        self.finish_with_read_stats(ap_cache, configs, gas_hook)
+            .map(|(change_set, _)| change_set)
+    }
+
+    /// Like `finish_with_gas`, also returning the reads finishing did on top of execution. The
+    /// same stats are added to the `aptos_vm_finish_resolver_reads` counters.
+    pub fn finish_with_read_stats<C: AccessPathCache>(
+        self,
+        ap_cache: &mut C,
+        configs: &Arc<ChangeSetConfigs>,
+        gas_hook: &mut dyn ResourceGroupGasHook,
+    ) -> VMResult<(ChangeSetExt, FinishReadStats)> {
         let (change_set, events, mut extensions) = self.inner.finish_with_extensions()?;
-        let (change_set, resource_group_change_set) =
-            Self::split_and_merge_resource_groups(&self.remote, change_set)?;
+        self.remote.set_finish_phase(Some(FinishPhase::GroupMerge));
+        self.remote.set_finish_budget(self.finish_budget);
+        let defer_group_reads = self.defer_resource_group_reads
+            && !self.validate_resource_group_containers
+            && !self.enforce_storage_quotas
+            && !self.is_block_meta
+            && self.state_key_codec.is_default();
+        let SplitChangeSet {
+            change_set,
+            resource_group_change_set,
+            mut group_patches,
+            group_bytes_deserialized,
+            group_classifications,
+            group_size_deltas,
+        } = Self::split_and_merge_resource_groups(
+            &self.remote,
+            change_set,
+            configs,
+            self.state_key_codec.as_ref(),
+            &AllowAllWrites,
+            gas_hook,
+            self.validate_resource_group_containers,
+            defer_group_reads,
+        )
+        .map_err(|err| self.remote.blame_finish_budget(err))?;
+        let group_bytes_serialized: u64 = resource_group_change_set
+            .resources()
+            .filter_map(|(_, _, op)| op.ok())
+            .map(|blob| blob.len() as u64)
+            .sum();
+        self.remote
+            .charge_finish_bytes(u64::from(group_bytes_deserialized) + group_bytes_serialized)
+            .map_err(|err| err.finish(Location::Undefined))?;
+        for patch in &mut group_patches {
+            patch.strips_noop_modification = self.strip_noop_modifications;
+        }
+
+        // Sessions of lightweight tools may come without some of the extensions, in which case
+        // there is nothing they could have changed.
+        let table_change_set = match extensions.try_remove::<NativeTableContext>() {
+            Some(table_context) => table_context
+                .into_change_set()
+                .map_err(|e| e.finish(Location::Undefined))?,
+            None => {
+                debug!("No table extension registered, finishing without table changes");
+                TableChangeSet::default()
+            },
+        };
 
-        let table_context: NativeTableContext = extensions.remove();
-        let table_change_set = table_context
-            .into_change_set()
-            .map_err(|e| e.finish(Location::Undefined))?;
+        let aggregator_change_set = match extensions.try_remove::<NativeAggregatorContext>() {
+            Some(aggregator_context) => aggregator_context.into_change_set(),
+            None => {
+                debug!("No aggregator extension registered, finishing without aggregator changes");
+                AggregatorChangeSet {
+                    changes: BTreeMap::new(),
+                }
+            },
+        };
+        Self::check_extension_change_limits(&table_change_set, &aggregator_change_set, configs)?;
+        let aggregator_handles =
+            if self.validate_aggregator_handles && !aggregator_change_set.changes.is_empty() {
+                let handle = Self::aggregator_factory_handle(&self.remote, &change_set)?;
+                Some(handle.into_iter().collect::<BTreeSet<_>>())
+            } else {
+                None
+            };
 
-        let aggregator_context: NativeAggregatorContext = extensions.remove();
-        let aggregator_change_set = aggregator_context.into_change_set();
+        let emitted_events = match extensions.try_remove::<NativeEventContext>() {
+            Some(event_context) => event_context.into_events(),
+            None => {
+                debug!("No event extension registered, finishing with handle events only");
+                vec![]
+            },
+        };
+        let mut native_seq_num = self.first_native_event_seq_num;
+        let emitted_events = emitted_events
+            .into_iter()
+            .map(|event| match event {
+                EmittedEvent::Handle => None,
+                EmittedEvent::Native(type_tag, data) => {
+                    let event =
+                        ContractEvent::new(self.native_event_key, native_seq_num, type_tag, data);
+                    native_seq_num += 1;
+                    Some(event)
+                },
+                EmittedEvent::Module(type_tag, data) => {
+                    Some(ContractEvent::new_module_event(type_tag, data))
+                },
+            })
+            .collect();
 
-        Self::convert_change_set(
+        let event_key_owners = self
+            .sender
+            .filter(|_| self.validate_event_keys)
+            .map(|sender| Self::event_key_owners(sender, &group_patches));
+        self.remote
+            .set_finish_phase(Some(FinishPhase::WriteOpConversion));
+        let recording_state_view = self.remote.recording_state_view();
+        let existing_state = if self.strip_noop_modifications {
+            Some(&recording_state_view as &dyn StateView)
+        } else {
+            None
+        };
+        let footprint = self
+            .block_meta_footprint
+            .as_deref()
+            .filter(|_| self.is_block_meta);
+        let change_set = Self::convert_change_set(
             change_set,
             resource_group_change_set,
             events,
+            emitted_events,
             table_change_set,
             aggregator_change_set,
             ap_cache,
+            self.state_key_codec.as_ref(),
             configs,
+            &AllowAllWrites,
+            self.allow_reserved_deletions,
+            event_key_owners,
+            existing_state,
+            footprint,
+            aggregator_handles.as_ref(),
         )
-        .map_err(|status| PartialVMError::new(status.status_code()).finish(Location::Undefined))
+        .and_then(|change_set| {
+            if self.enforce_storage_quotas {
+                Self::check_storage_quotas(change_set.write_set(), &recording_state_view)?;
+            }
+            Ok(change_set)
+        })
+        .map_err(|status| {
+            let err = PartialVMError::new(status.status_code());
+            let err = match status {
+                VMStatus::Error(_, Some(message)) => err.with_message(message),
+                _ => err,
+            }
+            .finish(Location::Undefined);
+            self.remote.blame_finish_budget(err)
+        })?
+        .with_group_classifications(group_classifications)
+        .with_group_size_deltas(group_size_deltas)
+        .with_group_patches(group_patches);
+        self.remote
+            .charge_finish_bytes(
+                change_set.serialized_size_estimate()
+                    + change_set.event_sizes().iter().sum::<u64>(),
+            )
+            .map_err(|err| err.finish(Location::Undefined))?;
+
+        self.remote.set_finish_phase(None);
+        self.remote.set_finish_budget(None);
+        let read_stats = self.remote.finish_read_stats();
+        #[cfg(not(feature = "no-metrics"))]
+        record_finish_reads(&read_stats);
+        Ok((change_set, read_stats))
+    }
+
+    /// Finishes a `Void` session run for a view function, returning its events and the values its
+    /// aggregator deltas result in on top of `view`. Nothing is written: the deltas are resolved
+    /// against `view` without producing write ops, and a session that changed anything else, e.g.
+    /// created a resource or wrote an aggregator, is rejected with `RESOURCE_WRITE_DENIED`.
+    pub fn finish_view(
+        self,
+        view: &impl StateView,
+    ) -> VMResult<(Vec<ContractEvent>, Vec<(StateKey, u128)>)> {
+        if !self.is_void {
+            return Err(
+                PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+                    .with_message("only void sessions can be finished as views".to_string())
+                    .finish(Location::Undefined),
+            );
+        }
+        let configs = Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+            LATEST_GAS_FEATURE_VERSION,
+        ));
+        let change_set = self.finish(&mut (), &configs)?;
+        if !change_set.write_set().is_empty() || !change_set.group_patches().is_empty() {
+            return Err(PartialVMError::new(StatusCode::RESOURCE_WRITE_DENIED)
+                .with_message("view functions cannot write to storage".to_string())
+                .finish(Location::Undefined));
+        }
+
+        let values = change_set
+            .delta_change_set()
+            .iter()
+            .map(|(state_key, delta_op)| {
+                delta_op
+                    .try_materialize_value(view, state_key)
+                    .map(|value| (state_key.clone(), value))
+                    .map_err(|status| {
+                        PartialVMError::new(status.status_code())
+                            .with_message(format!(
+                                "failed to resolve aggregator delta for {:?}",
+                                state_key
+                            ))
+                            .finish(Location::Undefined)
+                    })
+            })
+            .collect::<VMResult<Vec<_>>>()?;
+        let (_, change_set) = change_set.into_inner();
+        let (_, events) = change_set.into_inner();
+        Ok((events, values))
+    }
+
+    /// The feature flags the natives of the session checked so far, in ascending order, e.g. for
+    /// a replay to warn about a features snapshot that differs in one of them. The flags deciding
+    /// which checks run when the session is finished are read once per session rather than by
+    /// execution, and are not among them. Only compiled with the `feature-usage` feature.
+    #[cfg(feature = "feature-usage")]
+    pub fn feature_flags_read(&mut self) -> Vec<u64> {
+        self.inner
+            .get_native_extensions()
+            .try_get::<NativeFeatureUsageContext>()
+            .map_or_else(Vec::new, NativeFeatureUsageContext::used_flags)
     }
 
     pub fn extract_publish_request(&mut self) -> Option<PublishRequest> {
@@ -161,26 +570,104 @@ where
     ///   * If group or data does't exist, Unreachable
     ///   * If elements remain, Modify
     ///   * Otherwise delete
-    fn split_and_merge_resource_groups(
-        remote: &MoveResolverWithVMMetadata<S>,
+    ///
+    /// Whether elements remain is only decided once the ops of all members are applied, and the
+    /// outcome does not depend on the order they are applied in (see
+    /// `ResourceGroupMap::apply_member_ops`): a group is never deleted while one of its members is
+    /// modified or created.
+    ///
+    /// Module metadata used to classify resources is looked up at most once per module for the
+    /// whole change set, so many resources declared by the same module cost a single read.
+    ///
+    /// Merged groups whose serialized size already exceeds the per-write-op limit are rejected
+    /// before their output buffer is allocated.
+    ///
+    /// With `validate_containers` set, creating a group fails unless its address holds an account
+    /// or an object, either already or as part of this change set.
+    ///
+    /// The struct tag of every group member must be within the limits of `configs` (see
+    /// `ChangeSetConfigs::check_group_member_tag`). Classifying each member is charged to
+    /// `gas_hook`, and so is decoding each existing group, before the group is decoded, so that
+    /// merging into a group too large to pay for fails with `OUT_OF_GAS` without decoding it. The
+    /// totals charged are returned along with the change sets.
+    ///
+    /// Every group member written is checked against `write_policy` before it is merged or
+    /// deferred, and so is the group of every `GroupPatch`. The groups merged are checked when the
+    /// change set is converted, see `convert_change_set`.
+    ///
+    /// Accounts and groups left without any ops are pruned before anything is read for them.
+    ///
+    /// With `defer_group_reads` set, groups are not read at all: the ops on their members are
+    /// returned as `GroupPatch`es instead of being merged into the resource group change set.
+    ///
+    /// The group every resource was classified into, or `None` for standalone resources, is
+    /// returned too, see `ChangeSetExt::group_classifications`. So is how much every merged group
+    /// grows, see `ChangeSetExt::group_size_deltas`.
+    ///
+    /// Reading a group fails with `STORAGE_UNAVAILABLE` if storage could not serve the read, so
+    /// that the transaction can be executed again rather than discarded, and with an
+    /// invariant violation otherwise.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn split_and_merge_resource_groups<R: MoveResolverExt>(
+        remote: &R,
         change_set: MoveChangeSet,
-    ) -> VMResult<(MoveChangeSet, MoveChangeSet)> {
+        configs: &ChangeSetConfigs,
+        key_codec: &dyn StateKeyCodec,
+        write_policy: &dyn WritePolicy,
+        gas_hook: &mut dyn ResourceGroupGasHook,
+        validate_containers: bool,
+        defer_group_reads: bool,
+    ) -> VMResult<SplitChangeSet> {
         // The use of this implies that we could theoretically call unwrap with no consequences,
         // but using unwrap means the code panics if someone can come up with an attack.
         let common_error = PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
             .finish(Location::Undefined);
+        let check_write = |addr: &AccountAddress, struct_tag: &StructTag| match write_policy
+            .check_resource_write(addr, struct_tag)
+        {
+            WriteDecision::Allow => Ok(()),
+            WriteDecision::Deny => Err(PartialVMError::new(StatusCode::RESOURCE_WRITE_DENIED)
+                .with_message(format!("write to {} under {} denied", struct_tag, addr))
+                .finish(Location::Undefined)),
+        };
         let mut change_set_filtered = MoveChangeSet::new();
         let mut resource_group_change_set = MoveChangeSet::new();
+        let mut module_metadata: BTreeMap<ModuleId, Option<RuntimeModuleMetadataV1>> =
+            BTreeMap::new();
+        let mut group_bytes_deserialized = NumBytes::zero();
+        let mut group_patches = vec![];
+        let mut group_classifications = BTreeMap::new();
+        let mut group_member_tags = BTreeMap::new();
+        let mut group_size_deltas = vec![];
 
-        for (addr, account_changeset) in change_set.into_inner() {
+        let mut num_pruned = 0;
+        let accounts: Vec<_> = change_set
+            .into_inner()
+            .into_iter()
+            .filter(|(_, account_changeset)| {
+                let empty = account_changeset.is_empty();
+                num_pruned += empty as u64;
+                !empty
+            })
+            .collect();
+
+        for (addr, account_changeset) in accounts {
             let mut resource_groups: BTreeMap<StructTag, AccountChangeSet> = BTreeMap::new();
             let (modules, resources) = account_changeset.into_inner();
 
             for (struct_tag, blob_op) in resources {
-                let resource_group = remote
-                    .get_resource_group(&struct_tag)
-                    .map_err(|_| common_error.clone())?;
+                let metadata = module_metadata
+                    .entry(struct_tag.module_id())
+                    .or_insert_with_key(|module_id| remote.get_module_metadata(module_id.clone()));
+                let resource_group =
+                    R::get_resource_group_from_metadata(&struct_tag, metadata.as_ref());
+                group_classifications.insert(struct_tag.clone(), resource_group.clone());
                 if let Some(resource_group) = resource_group {
+                    check_write(&addr, &struct_tag)?;
+                    let tag_bytes = configs
+                        .check_group_member_tag(&struct_tag)
+                        .map_err(|err| err.finish(Location::Undefined))?;
+                    gas_hook.charge_member_tag(tag_bytes)?;
                     resource_groups
                         .entry(resource_group)
                         .or_insert_with(AccountChangeSet::new)
@@ -199,146 +686,890 @@ where
                     .map_err(|_| common_error.clone())?;
             }
 
+            let num_groups = resource_groups.len();
+            resource_groups.retain(|_, resources| !resources.is_empty());
+            num_pruned += (num_groups - resource_groups.len()) as u64;
+            for (group_tag, resources) in &resource_groups {
+                group_member_tags.insert(
+                    (addr, group_tag.clone()),
+                    resources.resources().keys().cloned().collect(),
+                );
+            }
+
+            let creates_object = resource_groups.values().any(|resources| {
+                matches!(
+                    resources.resources().get(&object_core_tag()),
+                    Some(MoveStorageOp::New(_))
+                )
+            });
+
+            if defer_group_reads {
+                for group_tag in resource_groups.keys() {
+                    check_write(&addr, group_tag)?;
+                }
+                group_patches.extend(resource_groups.into_iter().map(|(group_tag, resources)| {
+                    GroupPatch {
+                        addr,
+                        group_tag,
+                        member_ops: resources.into_resources(),
+                        // Up to the session, see `finish_with_read_stats`.
+                        strips_noop_modification: false,
+                    }
+                }));
+                continue;
+            }
+
             for (resource_tag, resources) in resource_groups {
                 let source_data = remote
-                    .get_resource_group_data(&addr, &resource_tag)
-                    .map_err(|_| common_error.clone())?;
-                let (mut source_data, create) = if let Some(source_data) = source_data {
-                    let source_data =
-                        bcs::from_bytes(&source_data).map_err(|_| common_error.clone())?;
-                    (source_data, false)
-                } else {
-                    (BTreeMap::new(), true)
+                    .get_resource_group_data_with_codec(&addr, &resource_tag, key_codec)
+                    .map_err(|err| {
+                        if err.major_status() == StatusCode::STORAGE_ERROR {
+                            PartialVMError::new(StatusCode::STORAGE_UNAVAILABLE)
+                                .finish(Location::Undefined)
+                        } else {
+                            common_error.clone()
+                        }
+                    })?;
+                let old_len = source_data.as_ref().map_or(0, Vec::len) as i64;
+                let (mut source_data, create) = match source_data {
+                    Some(source_data) => {
+                        // Charged before decoding, so that the work is paid for however large the
+                        // group is.
+                        let group_bytes = NumBytes::new(source_data.len() as u64);
+                        gas_hook.charge_group_decoding(group_bytes)?;
+                        group_bytes_deserialized += group_bytes;
+                        let source_data = ResourceGroupMap::from_bytes(&source_data)
+                            .map_err(|_| common_error.clone())?;
+                        (source_data, false)
+                    },
+                    None => (ResourceGroupMap::new(), true),
                 };
 
-                for (struct_tag, current_op) in resources.into_resources() {
-                    match current_op {
-                        MoveStorageOp::Delete => {
-                            source_data
-                                .remove(&struct_tag)
-                                .ok_or_else(|| common_error.clone())?;
-                        },
-                        MoveStorageOp::Modify(new_data) => {
-                            let data = source_data
-                                .get_mut(&struct_tag)
-                                .ok_or_else(|| common_error.clone())?;
-                            *data = new_data;
-                        },
-                        MoveStorageOp::New(data) => {
-                            let data = source_data.insert(struct_tag, data);
-                            if data.is_some() {
-                                return Err(common_error);
-                            }
-                        },
-                    }
+                // Order independent, see `ResourceGroupMap::apply_member_ops`.
+                source_data
+                    .apply_member_ops(resources.into_resources())
+                    .map_err(|_| common_error.clone())?;
+
+                if create && validate_containers && !creates_object {
+                    Self::check_resource_group_container(
+                        remote,
+                        &addr,
+                        &resource_tag,
+                        &change_set_filtered,
+                    )?;
                 }
 
-                let op = if source_data.is_empty() {
-                    MoveStorageOp::Delete
-                } else if create {
-                    MoveStorageOp::New(
-                        bcs::to_bytes(&source_data).map_err(|_| common_error.clone())?,
-                    )
-                } else {
-                    MoveStorageOp::Modify(
-                        bcs::to_bytes(&source_data).map_err(|_| common_error.clone())?,
-                    )
-                };
+                let op = Self::resource_group_op(&source_data, create, configs)?;
+                let new_len = op.as_ref().ok().map_or(0, |data| data.len()) as i64;
+                group_size_deltas.push((
+                    key_codec.access_path_key(AccessPath::resource_group_access_path(
+                        addr,
+                        resource_tag.clone(),
+                    )),
+                    new_len - old_len,
+                ));
                 resource_group_change_set
                     .add_resource_op(addr, resource_tag, op)
                     .map_err(|_| common_error.clone())?;
             }
         }
 
-        Ok((change_set_filtered, resource_group_change_set))
+        Self::check_group_member_conflicts(&change_set_filtered, &group_member_tags)?;
+
+        EMPTY_ACCOUNT_CHANGE_SETS_PRUNED.inc_by(num_pruned);
+        Ok(SplitChangeSet {
+            change_set: change_set_filtered,
+            resource_group_change_set,
+            group_patches,
+            group_bytes_deserialized,
+            group_classifications,
+            group_size_deltas,
+        })
+    }
+
+    /// The handle of the table holding the aggregators created by `0x1::aggregator_factory`. At
+    /// genesis the factory is created by `change_set` itself, so it is read from there first.
+    fn aggregator_factory_handle<R: MoveResolverExt>(
+        remote: &R,
+        change_set: &MoveChangeSet,
+    ) -> VMResult<Option<AccountAddress>> {
+        let factory_tag = aggregator_factory_tag();
+        let written = change_set
+            .accounts()
+            .get(&AccountAddress::ONE)
+            .and_then(|account| account.resources().get(&factory_tag));
+        let factory = match written {
+            Some(MoveStorageOp::New(data)) | Some(MoveStorageOp::Modify(data)) => {
+                Some(data.clone())
+            },
+            Some(MoveStorageOp::Delete) => None,
+            None => remote.get_standard_resource(&AccountAddress::ONE, &factory_tag)?,
+        };
+        // The factory only holds its table, which in turn only holds its handle.
+        factory
+            .map(|data| {
+                bcs::from_bytes::<AccountAddress>(&data).map_err(|_| {
+                    PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+                        .with_message("Failed to decode the aggregator factory".to_string())
+                        .finish(Location::Undefined)
+                })
+            })
+            .transpose()
+    }
+
+    /// Checks the changes drained from the table and aggregator extensions against the limits of
+    /// `configs`, before any of them is converted. Exceeding them fails the transaction with
+    /// `TOO_MANY_WRITE_OPS`, as every change would end up as a write op or a delta.
+    fn check_extension_change_limits(
+        table_change_set: &TableChangeSet,
+        aggregator_change_set: &AggregatorChangeSet,
+        configs: &ChangeSetConfigs,
+    ) -> VMResult<()> {
+        let too_many = |message: String| {
+            PartialVMError::new(StatusCode::TOO_MANY_WRITE_OPS)
+                .with_message(message)
+                .finish(Location::Undefined)
+        };
+
+        let table_entries: u64 = table_change_set
+            .changes
+            .values()
+            .map(|change| change.entries.len() as u64)
+            .sum();
+        if table_entries > configs.max_table_change_entries() {
+            return Err(too_many(format!(
+                "session changes {} table items, above the limit of {}",
+                table_entries,
+                configs.max_table_change_entries()
+            )));
+        }
+
+        let aggregator_changes = aggregator_change_set.changes.len() as u64;
+        if aggregator_changes > configs.max_aggregator_changes() {
+            return Err(too_many(format!(
+                "session changes {} aggregators, above the limit of {}",
+                aggregator_changes,
+                configs.max_aggregator_changes()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that no resource is written both as a standalone resource and as a member of a group
+    /// at the same address, which would commit two diverging copies of it. Resources are
+    /// classified consistently within a change set, so this only fails if the classification
+    /// changed between the writes, e.g. with metadata gone stale across a module upgrade.
+    /// `group_member_tags` holds the member tags of every group written, by address and group.
+    fn check_group_member_conflicts(
+        standalone: &MoveChangeSet,
+        group_member_tags: &BTreeMap<(AccountAddress, StructTag), BTreeSet<StructTag>>,
+    ) -> VMResult<()> {
+        for ((addr, group_tag), member_tags) in group_member_tags {
+            let standalone_resources = match standalone.accounts().get(addr) {
+                Some(account) => account.resources(),
+                None => continue,
+            };
+            if let Some(member_tag) = member_tags
+                .iter()
+                .find(|member_tag| standalone_resources.contains_key(*member_tag))
+            {
+                return Err(
+                    PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+                        .with_message(format!(
+                            "{} under {} is written both as a standalone resource and as a member \
+                             of resource group {}",
+                            member_tag, addr, group_tag
+                        ))
+                        .finish(Location::Undefined),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that a resource group about to be created at `addr` has a container: an account that
+    /// exists or is written by this change set, or an existing object. Objects created by the same
+    /// change set are accounted for by the caller.
+    fn check_resource_group_container<R: MoveResolverExt>(
+        remote: &R,
+        addr: &AccountAddress,
+        resource_group: &StructTag,
+        change_set_filtered: &MoveChangeSet,
+    ) -> VMResult<()> {
+        let account_tag = AccountResource::struct_tag();
+        let account_written = change_set_filtered
+            .accounts()
+            .get(addr)
+            .and_then(|account| account.resources().get(&account_tag))
+            .map(|op| !matches!(op, MoveStorageOp::Delete));
+        let has_container = match account_written {
+            Some(written) => written,
+            None => {
+                remote.get_standard_resource(addr, &account_tag)?.is_some()
+                    || remote
+                        .get_resource_from_group(
+                            addr,
+                            &object_core_tag(),
+                            &ObjectGroupResource::struct_tag(),
+                        )?
+                        .is_some()
+            },
+        };
+
+        if has_container {
+            Ok(())
+        } else {
+            Err(
+                PartialVMError::new(StatusCode::RESOURCE_GROUP_ADDRESS_NOT_INITIALIZED)
+                    .with_message(format!(
+                        "Cannot create resource group {} at {}: the address holds neither an account nor an object",
+                        resource_group, addr
+                    ))
+                    .finish(Location::Undefined),
+            )
+        }
+    }
+
+    /// The op writing back a merged resource group. A group left without members is deleted.
+    fn resource_group_op(
+        source_data: &ResourceGroupMap,
+        create: bool,
+        configs: &ChangeSetConfigs,
+    ) -> VMResult<MoveStorageOp<Vec<u8>>> {
+        if source_data.is_empty() {
+            return Ok(MoveStorageOp::Delete);
+        }
+
+        let data = Self::serialize_resource_group(source_data, configs.max_bytes_per_write_op())?;
+        Ok(if create {
+            MoveStorageOp::New(data)
+        } else {
+            MoveStorageOp::Modify(data)
+        })
+    }
+
+    /// Serializes a merged resource group. The size is computed up front so that over-limit groups
+    /// are rejected without building their blob, and the output is allocated exactly once.
+    fn serialize_resource_group(
+        source_data: &ResourceGroupMap,
+        max_bytes: u64,
+    ) -> VMResult<Vec<u8>> {
+        if source_data.serialized_size()? as u64 > max_bytes {
+            return Err(PartialVMError::new(StatusCode::STORAGE_WRITE_LIMIT_REACHED)
+                .finish(Location::Undefined));
+        }
+        source_data.to_bytes()
+    }
+
+    /// The accounts, besides the ones whose resources are in the converted change sets, that the
+    /// events of a session of `sender` may be keyed to: the sender, and the accounts of the
+    /// groups whose changes are deferred to `group_patches` rather than merged into the change
+    /// sets.
+    fn event_key_owners(
+        sender: AccountAddress,
+        group_patches: &[GroupPatch],
+    ) -> BTreeSet<AccountAddress> {
+        let mut owners = BTreeSet::from([sender]);
+        owners.extend(group_patches.iter().map(|patch| patch.addr));
+        owners
     }
 
+    /// Converts the session output into a `ChangeSetExt`.
+    ///
+    /// If `event_key_owners` is set, every emitted event must be keyed to either one of them, an
+    /// account whose resources this session writes (emitting bumps the counter of the handle, so
+    /// its owner is always written), or a reserved system address.
+    ///
+    /// `emitted_events` lists all events of the session in the order they were emitted: the ones
+    /// emitted by natives, already keyed by the session, and module events, with a `None` in place
+    /// of each of the Move `events`, which come in the same order. Move events without a place,
+    /// e.g. when `emitted_events` is empty, come last. Module events have no key, so
+    /// `event_key_owners` does not apply to them; they are limited in size like the others.
+    ///
+    /// If `existing_state` is set, modifications writing back exactly the value it holds, metadata
+    /// included, are dropped from the write set.
+    ///
+    /// Unless `allow_reserved_deletions` is set, deleting a resource or resource group at a
+    /// reserved system address fails with `RESERVED_RESOURCE_DELETION_DENIED` if `configs` do not
+    /// let it be deleted, see `ChangeSetConfigs::with_reserved_deletion_protection`.
+    ///
+    /// If `footprint` is set, every written state key, deltas included, must be allowed by it.
+    /// Otherwise the conversion fails with `BLOCK_META_WRITE_OUTSIDE_FOOTPRINT`, listing all the
+    /// keys outside of it.
+    ///
+    /// If `aggregator_handles` is set, every aggregator change must be to a table among them, or
+    /// the conversion fails with `UNKNOWN_AGGREGATOR_HANDLE`.
+    ///
+    /// Every state key is built by `key_codec`. Resource group keys come from `ap_cache` as they
+    /// are only with the default codec.
+    #[allow(clippy::too_many_arguments)]
     pub fn convert_change_set<C: AccessPathCache>(
         change_set: MoveChangeSet,
         resource_group_change_set: MoveChangeSet,
         events: Vec<MoveEvent>,
+        emitted_events: Vec<Option<ContractEvent>>,
         table_change_set: TableChangeSet,
         aggregator_change_set: AggregatorChangeSet,
         ap_cache: &mut C,
-        configs: &ChangeSetConfigs,
+        key_codec: &dyn StateKeyCodec,
+        configs: &Arc<ChangeSetConfigs>,
+        write_policy: &dyn WritePolicy,
+        allow_reserved_deletions: bool,
+        event_key_owners: Option<BTreeSet<AccountAddress>>,
+        existing_state: Option<&dyn StateView>,
+        footprint: Option<&dyn StateFootprint>,
+        aggregator_handles: Option<&BTreeSet<AccountAddress>>,
     ) -> Result<ChangeSetExt, VMStatus> {
+        let event_key_owners = event_key_owners.map(|mut owners| {
+            owners.extend(
+                change_set
+                    .accounts()
+                    .iter()
+                    .chain(resource_group_change_set.accounts())
+                    .filter(|(_, account)| !account.resources().is_empty())
+                    .map(|(addr, _)| *addr),
+            );
+            owners
+        });
+
+        let check_write = |addr: &AccountAddress, struct_tag: &StructTag| match write_policy
+            .check_resource_write(addr, struct_tag)
+        {
+            WriteDecision::Allow => Ok(()),
+            WriteDecision::Deny => Err(VMStatus::Error(
+                StatusCode::RESOURCE_WRITE_DENIED,
+                Some(format!("write to {} under {} denied", struct_tag, addr)),
+            )),
+        };
+        let deletion_denied = |addr: &AccountAddress, struct_tag: &StructTag, is_deletion: bool| {
+            is_deletion
+                && !allow_reserved_deletions
+                && is_reserved_system_address(addr)
+                && !configs.may_delete_reserved_resource(struct_tag)
+        };
+
         let mut write_set_mut = WriteSetMut::new(Vec::new());
         let mut delta_change_set = DeltaChangeSet::empty();
+        let mut modules_changed = vec![];
+        let mut flags = ChangeSetFlags::default();
+        let mut write_set_size = WriteSetSize::default();
+        let max_write_ops = configs.max_write_ops_per_transaction();
+        let checks_conflicting_writes = configs.checks_conflicting_writes();
+        // Only recorded by testing builds, for diagnosing unexpected writes.
+        let mut write_op_sources = cfg!(any(test, feature = "testing")).then(BTreeMap::new);
 
         for (addr, account_changeset) in change_set.into_inner() {
             let (modules, resources) = account_changeset.into_inner();
             for (struct_tag, blob_op) in resources {
-                let state_key = StateKey::access_path(ap_cache.get_resource_path(addr, struct_tag));
-                let op = Self::convert_write_op(
-                    blob_op,
-                    configs.legacy_resource_creation_as_modification(),
-                );
-                write_set_mut.insert((state_key, op))
+                check_write(&addr, &struct_tag)?;
+                if deletion_denied(&addr, &struct_tag, matches!(blob_op, MoveStorageOp::Delete)) {
+                    let access_path = ap_cache.get_resource_path(addr, struct_tag.clone());
+                    return Err(Self::reserved_deletion_denied(&struct_tag, access_path));
+                }
+                let state_key =
+                    key_codec.access_path_key(ap_cache.get_resource_path(addr, struct_tag));
+                Self::record_source(&mut write_op_sources, &state_key, || {
+                    WriteOpSource::Resource
+                });
+                let creation_as_modification = configs.legacy_resource_creation_as_modification();
+                if creation_as_modification && matches!(blob_op, MoveStorageOp::New(_)) {
+                    let address = if addr == CORE_CODE_ADDRESS {
+                        "core"
+                    } else {
+                        "other"
+                    };
+                    LEGACY_CREATIONS_AS_MODIFICATIONS
+                        .with_label_values(&[address])
+                        .inc();
+                }
+                let op = Self::convert_write_op(blob_op, creation_as_modification);
+                Self::insert_write_op(
+                    &mut write_set_mut,
+                    &mut write_set_size,
+                    max_write_ops,
+                    checks_conflicting_writes,
+                    state_key,
+                    op,
+                )?;
             }
 
             for (name, blob_op) in modules {
+                let module_id = ModuleId::new(addr, name);
                 let state_key =
-                    StateKey::access_path(ap_cache.get_module_path(ModuleId::new(addr, name)));
+                    key_codec.access_path_key(ap_cache.get_module_path(module_id.clone()));
+                modules_changed.push(module_id);
+                flags.module_writes = true;
+                Self::record_source(&mut write_op_sources, &state_key, || WriteOpSource::Module);
                 let op = Self::convert_write_op(blob_op, false);
-                write_set_mut.insert((state_key, op))
+                Self::insert_write_op(
+                    &mut write_set_mut,
+                    &mut write_set_size,
+                    max_write_ops,
+                    checks_conflicting_writes,
+                    state_key,
+                    op,
+                )?;
             }
         }
 
         for (addr, account_changeset) in resource_group_change_set.into_inner() {
             let (_, resources) = account_changeset.into_inner();
             for (struct_tag, blob_op) in resources {
-                let state_key =
-                    StateKey::access_path(ap_cache.get_resource_group_path(addr, struct_tag));
+                check_write(&addr, &struct_tag)?;
+                if deletion_denied(&addr, &struct_tag, matches!(blob_op, MoveStorageOp::Delete)) {
+                    let access_path = ap_cache.get_resource_group_path(addr, struct_tag.clone());
+                    return Err(Self::reserved_deletion_denied(&struct_tag, access_path));
+                }
+                let group_tag = write_op_sources.is_some().then(|| struct_tag.clone());
+                let state_key = if key_codec.is_default() {
+                    ap_cache.get_resource_group_state_key(addr, struct_tag)
+                } else {
+                    key_codec.access_path_key(ap_cache.get_resource_group_path(addr, struct_tag))
+                };
+                if let (Some(sources), Some(group_tag)) = (&mut write_op_sources, group_tag) {
+                    sources.insert(state_key.clone(), WriteOpSource::ResourceGroup {
+                        group_tag,
+                    });
+                }
+                flags.resource_group_writes = true;
                 let op = Self::convert_write_op(blob_op, false);
-                write_set_mut.insert((state_key, op))
+                Self::insert_write_op(
+                    &mut write_set_mut,
+                    &mut write_set_size,
+                    max_write_ops,
+                    checks_conflicting_writes,
+                    state_key,
+                    op,
+                )?;
             }
         }
 
         for (handle, change) in table_change_set.changes {
             for (key, value_op) in change.entries {
-                let state_key = StateKey::table_item(handle.into(), key);
+                let state_key = key_codec.table_item_key(handle.into(), key);
+                Self::record_source(&mut write_op_sources, &state_key, || {
+                    WriteOpSource::TableItem
+                });
                 let op = Self::convert_write_op(value_op, false);
-                write_set_mut.insert((state_key, op))
+                Self::insert_write_op(
+                    &mut write_set_mut,
+                    &mut write_set_size,
+                    max_write_ops,
+                    checks_conflicting_writes,
+                    state_key,
+                    op,
+                )?;
             }
         }
 
         for (id, change) in aggregator_change_set.changes {
             let AggregatorID { handle, key } = id;
+            if let Some(handles) = aggregator_handles {
+                if !handles.contains(&handle.0) {
+                    return Err(VMStatus::Error(
+                        StatusCode::UNKNOWN_AGGREGATOR_HANDLE,
+                        Some(format!(
+                            "aggregator {} is in table {}, which does not belong to the aggregator factory",
+                            key.0, handle.0
+                        )),
+                    ));
+                }
+            }
             let key_bytes = key.0.to_vec();
-            let state_key = StateKey::table_item(TableHandle::from(handle), key_bytes);
+            let state_key = key_codec.table_item_key(TableHandle::from(handle), key_bytes);
 
             match change {
                 AggregatorChange::Write(value) => {
+                    Self::record_source(&mut write_op_sources, &state_key, || {
+                        WriteOpSource::AggregatorWrite
+                    });
                     let write_op = WriteOp::Modification(serialize(&value));
-                    write_set_mut.insert((state_key, write_op));
+                    Self::insert_aggregator_write_op(
+                        &mut write_set_mut,
+                        &mut write_set_size,
+                        max_write_ops,
+                        checks_conflicting_writes,
+                        state_key,
+                        write_op,
+                    )?;
+                },
+                AggregatorChange::Merge(delta_op) => {
+                    let prior = if checks_conflicting_writes {
+                        write_set_mut.as_inner_mut().get_mut(&state_key)
+                    } else {
+                        None
+                    };
+                    match prior {
+                        // The value is written by the same change set, so the delta is applied to it.
+                        // Aggregator values are always 16 bytes, so the size of the write is unchanged.
+                        Some(
+                            WriteOp::Creation(data)
+                            | WriteOp::Modification(data)
+                            | WriteOp::CreationWithMetadata { data, .. }
+                            | WriteOp::ModificationWithMetadata { data, .. },
+                        ) => {
+                            let value: u128 = bcs::from_bytes(data).map_err(|_| {
+                                VMStatus::Error(
+                                    StatusCode::DATA_FORMAT_ERROR,
+                                    Some(format!(
+                                        "aggregator value at state key {:?} cannot be decoded",
+                                        state_key
+                                    )),
+                                )
+                            })?;
+                            let value = delta_op
+                                .apply_to(value)
+                                .map_err(|err| err.finish(Location::Undefined).into_vm_status())?;
+                            *data = serialize(&value);
+                        },
+                        // There is nothing left to apply a delta to once the slot is deleted, so the
+                        // change set could never be materialized.
+                        Some(WriteOp::Deletion | WriteOp::DeletionWithMetadata { .. }) => {
+                            return Err(VMStatus::Error(
+                                StatusCode::DELTA_ON_DELETED_STATE_KEY,
+                                Some(format!(
+                                    "aggregator delta targets deleted state key {:?}",
+                                    state_key
+                                )),
+                            ));
+                        },
+                        None => {
+                            let provenance = DeltaProvenance {
+                                handle: handle.0,
+                                key: key.0,
+                            };
+                            delta_change_set
+                                .insert_with_provenance((state_key, delta_op), provenance);
+                            flags.aggregator_deltas = true;
+                        },
+                    }
                 },
-                AggregatorChange::Merge(delta_op) => delta_change_set.insert((state_key, delta_op)),
                 AggregatorChange::Delete => {
+                    Self::record_source(&mut write_op_sources, &state_key, || {
+                        WriteOpSource::AggregatorDelete
+                    });
                     let write_op = WriteOp::Deletion;
-                    write_set_mut.insert((state_key, write_op));
+                    let written = Self::insert_aggregator_write_op(
+                        &mut write_set_mut,
+                        &mut write_set_size,
+                        max_write_ops,
+                        checks_conflicting_writes,
+                        state_key.clone(),
+                        write_op,
+                    )?;
+                    // Deleting a slot created by the same change set leaves nothing to write.
+                    if !written {
+                        if let Some(sources) = &mut write_op_sources {
+                            sources.remove(&state_key);
+                        }
+                    }
                 },
             }
         }
 
+        if let Some(existing_state) = existing_state {
+            let num_stripped = Self::strip_noop_modifications(
+                &mut write_set_mut,
+                &mut write_set_size,
+                existing_state,
+            )?;
+            NOOP_MODIFICATIONS_STRIPPED.inc_by(num_stripped as u64);
+        }
+
         let write_set = write_set_mut
             .freeze()
-            .map_err(|_| VMStatus::Error(StatusCode::DATA_FORMAT_ERROR, None))?;
+            .map_err(|err| VMStatus::Error(StatusCode::DATA_FORMAT_ERROR, Some(err.to_string())))?;
+        if let Some(footprint) = footprint {
+            Self::check_footprint(&write_set, &delta_change_set, footprint)?;
+        }
 
-        let events = events
+        let mut move_events = events
             .into_iter()
             .map(|(guid, seq_num, ty_tag, blob)| {
-                let key = bcs::from_bytes(guid.as_slice())
+                let key: EventKey = bcs::from_bytes(guid.as_slice())
                     .map_err(|_| VMStatus::Error(StatusCode::EVENT_KEY_MISMATCH, None))?;
+                if let Some(owners) = &event_key_owners {
+                    let creator = key.get_creator_address();
+                    if !owners.contains(&creator) && !is_reserved_system_address(&creator) {
+                        return Err(VMStatus::Error(
+                            StatusCode::EVENT_KEY_MISMATCH,
+                            Some(format!(
+                                "event {} of type {} is keyed to an account the session does not write",
+                                key, ty_tag
+                            )),
+                        ));
+                    }
+                }
                 Ok(ContractEvent::new(key, seq_num, ty_tag, blob))
             })
-            .collect::<Result<Vec<_>, VMStatus>>()?;
+            .collect::<Result<Vec<_>, VMStatus>>()?
+            .into_iter();
+        let mut events: Vec<_> = emitted_events
+            .into_iter()
+            .filter_map(|event| event.or_else(|| move_events.next()))
+            .collect();
+        events.extend(move_events);
 
         let change_set = ChangeSet::new(write_set, events, configs)?;
-        Ok(ChangeSetExt::new(
+        let change_set_ext = ChangeSetExt::new_with_flags(
             delta_change_set,
             change_set,
-            Arc::new(configs.clone()),
-        ))
+            configs.clone(),
+            flags,
+            write_set_size,
+        )
+        .with_modules_changed(modules_changed);
+        Ok(match write_op_sources {
+            Some(mut sources) => {
+                // Stripped no-op modifications are not written after all.
+                let write_set = change_set_ext.write_set();
+                sources.retain(|state_key, _| write_set.get(state_key).is_some());
+                change_set_ext.with_write_op_sources(sources)
+            },
+            None => change_set_ext,
+        })
+    }
+
+    fn reserved_deletion_denied(struct_tag: &StructTag, access_path: AccessPath) -> VMStatus {
+        VMStatus::Error(
+            StatusCode::RESERVED_RESOURCE_DELETION_DENIED,
+            Some(format!(
+                "deletion of {} under framework reserved address {} denied: {}",
+                struct_tag,
+                access_path.address.to_hex_literal(),
+                access_path
+            )),
+        )
+    }
+
+    fn record_source(
+        write_op_sources: &mut Option<BTreeMap<StateKey, WriteOpSource>>,
+        state_key: &StateKey,
+        source: impl FnOnce() -> WriteOpSource,
+    ) {
+        if let Some(sources) = write_op_sources {
+            sources.insert(state_key.clone(), source());
+        }
+    }
+
+    /// Adds `op` to the write set, failing if the change set already wrote `state_key`, e.g.
+    /// because a table item and an aggregator both modify it, or if the write set would have more
+    /// than `max_write_ops` entries. Unless `checks_conflicting_writes`, `op` replaces an earlier
+    /// write of `state_key` instead.
+    fn insert_write_op(
+        write_set_mut: &mut WriteSetMut,
+        write_set_size: &mut WriteSetSize,
+        max_write_ops: u64,
+        checks_conflicting_writes: bool,
+        state_key: StateKey,
+        op: WriteOp,
+    ) -> Result<(), VMStatus> {
+        if !checks_conflicting_writes {
+            if let Some(replaced) = write_set_mut.as_inner_mut().remove(&state_key) {
+                write_set_size.remove(&state_key, &replaced);
+            }
+        }
+        // Either failure fails the whole conversion, so the size need not be rolled back.
+        write_set_size.add(&state_key, &op);
+        if write_set_size.num_ops() > max_write_ops {
+            return Err(VMStatus::Error(
+                StatusCode::TOO_MANY_WRITE_OPS,
+                Some(format!(
+                    "transaction writes more than {} state items",
+                    max_write_ops
+                )),
+            ));
+        }
+        write_set_mut.try_insert((state_key, op)).map_err(|err| {
+            VMStatus::Error(StatusCode::DUPLICATE_WRITE_SET_KEY, Some(err.to_string()))
+        })
+    }
+
+    /// Adds an aggregator's write to the write set, composing it with what the table changes of
+    /// the change set wrote to the same slot as `WriteOp::squash` would. A slot created and then
+    /// written by the aggregator is still created, and one created and then deleted is not written
+    /// at all. A slot deleted and then written, i.e. an aggregator destroyed and created again,
+    /// is modified. Any other write following one to the same slot is a duplicate, as for
+    /// `insert_write_op`. Returns whether the slot is still written. Unless
+    /// `checks_conflicting_writes`, nothing is composed and `op` replaces the earlier write.
+    fn insert_aggregator_write_op(
+        write_set_mut: &mut WriteSetMut,
+        write_set_size: &mut WriteSetSize,
+        max_write_ops: u64,
+        checks_conflicting_writes: bool,
+        state_key: StateKey,
+        op: WriteOp,
+    ) -> Result<bool, VMStatus> {
+        let composes = match write_set_mut.get(&state_key) {
+            Some(prior) if checks_conflicting_writes => {
+                prior.is_creation() || (prior.is_deletion() && op.is_modification())
+            },
+            _ => false,
+        };
+        if !composes {
+            Self::insert_write_op(
+                write_set_mut,
+                write_set_size,
+                max_write_ops,
+                checks_conflicting_writes,
+                state_key,
+                op,
+            )?;
+            return Ok(true);
+        }
+
+        let mut composed = write_set_mut
+            .as_inner_mut()
+            .remove(&state_key)
+            .expect("Composed write op must exist");
+        write_set_size.remove(&state_key, &composed);
+        if composed.is_deletion() {
+            // The slot existed before it was deleted, so writing it again modifies it.
+            composed = op;
+        } else if !WriteOp::squash(&mut composed, op).map_err(|err| {
+            VMStatus::Error(StatusCode::DUPLICATE_WRITE_SET_KEY, Some(err.to_string()))
+        })? {
+            return Ok(false);
+        }
+        Self::insert_write_op(
+            write_set_mut,
+            write_set_size,
+            max_write_ops,
+            checks_conflicting_writes,
+            state_key,
+            composed,
+        )?;
+        Ok(true)
+    }
+
+    /// Fails if any key written or updated by a delta is not allowed by `footprint`.
+    fn check_footprint(
+        write_set: &WriteSet,
+        delta_change_set: &DeltaChangeSet,
+        footprint: &dyn StateFootprint,
+    ) -> Result<(), VMStatus> {
+        let offending_keys = write_set
+            .iter()
+            .map(|(state_key, _)| state_key)
+            .chain(delta_change_set.iter().map(|(state_key, _)| state_key))
+            .filter(|state_key| !footprint.allows(state_key))
+            .map(|state_key| format!("{:?}", state_key))
+            .collect::<Vec<_>>();
+        if offending_keys.is_empty() {
+            Ok(())
+        } else {
+            Err(VMStatus::Error(
+                StatusCode::BLOCK_META_WRITE_OUTSIDE_FOOTPRINT,
+                Some(format!(
+                    "block metadata session writes outside of its footprint: {}",
+                    offending_keys.join(", ")
+                )),
+            ))
+        }
+    }
+
+    /// Checks that the writes do not grow the state under any account beyond the quota in its
+    /// `StorageQuotaResource`, if it has one. The growth of an account is the size of the items
+    /// it creates, keys included, plus how much its modifications grow the values in
+    /// `existing_state`, minus what it deletes; accounts that do not grow are not checked. Table
+    /// items are not under any account and are not counted. The check does not update
+    /// `used_bytes`, see `StorageQuotaResource`.
+    fn check_storage_quotas(
+        write_set: &WriteSet,
+        existing_state: &dyn StateView,
+    ) -> Result<(), VMStatus> {
+        let existing_size = |state_key: &StateKey| -> Result<i128, VMStatus> {
+            let existing = existing_state
+                .get_state_value(state_key)
+                .map_err(|_| VMStatus::Error(StatusCode::STORAGE_ERROR, None))?;
+            Ok(existing.map_or(0, |value| (state_key.size() + value.size()) as i128))
+        };
+
+        let mut growth: BTreeMap<AccountAddress, i128> = BTreeMap::new();
+        for (state_key, op) in write_set {
+            let address = match state_key.inner() {
+                StateKeyInner::AccessPath(access_path) => access_path.address,
+                _ => continue,
+            };
+            let written = op
+                .bytes()
+                .map_or(0, |bytes| (state_key.size() + bytes.len()) as i128);
+            let existing = match op {
+                WriteOp::Creation(_) | WriteOp::CreationWithMetadata { .. } => 0,
+                _ => existing_size(state_key)?,
+            };
+            *growth.entry(address).or_default() += written - existing;
+        }
+
+        for (address, growth) in growth {
+            if growth <= 0 {
+                continue;
+            }
+            let quota_path =
+                AccessPath::resource_access_path(address, StorageQuotaResource::struct_tag())
+                    .map_err(|_| {
+                        VMStatus::Error(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR, None)
+                    })?;
+            let quota_key = StateKey::access_path(quota_path);
+            let quota = match existing_state
+                .get_state_value_bytes(&quota_key)
+                .map_err(|_| VMStatus::Error(StatusCode::STORAGE_ERROR, None))?
+            {
+                Some(bytes) => bcs::from_bytes::<StorageQuotaResource>(&bytes).map_err(|_| {
+                    VMStatus::Error(
+                        StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR,
+                        Some(format!("malformed storage quota at {}", address)),
+                    )
+                })?,
+                None => continue,
+            };
+            if quota.used_bytes() as i128 + growth > quota.limit_bytes() as i128 {
+                return Err(VMStatus::Error(
+                    StatusCode::STORAGE_QUOTA_EXCEEDED,
+                    Some(format!(
+                        "account {} uses {} of its {} bytes and cannot grow by {} more",
+                        address,
+                        quota.used_bytes(),
+                        quota.limit_bytes(),
+                        growth
+                    )),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the modifications whose bytes and metadata match the value in `existing_state`,
+    /// returning how many were removed.
+    fn strip_noop_modifications(
+        write_set_mut: &mut WriteSetMut,
+        write_set_size: &mut WriteSetSize,
+        existing_state: &dyn StateView,
+    ) -> Result<usize, VMStatus> {
+        let mut noop_keys = vec![];
+        for (state_key, op) in write_set_mut.as_inner_mut().iter() {
+            let written = match op {
+                WriteOp::Modification(data) => StateValue::new_legacy(data.clone()),
+                WriteOp::ModificationWithMetadata { data, metadata } => {
+                    StateValue::new_with_metadata(data.clone(), metadata.clone())
+                },
+                _ => continue,
+            };
+            let existing = existing_state
+                .get_state_value(state_key)
+                .map_err(|_| VMStatus::Error(StatusCode::STORAGE_ERROR, None))?;
+            if existing.as_ref() == Some(&written) {
+                noop_keys.push(state_key.clone());
+            }
+        }
+
+        let write_ops = write_set_mut.as_inner_mut();
+        for state_key in &noop_keys {
+            if let Some(op) = write_ops.remove(state_key) {
+                write_set_size.remove(state_key, &op);
+            }
+        }
+        Ok(noop_keys.len())
     }
 
     fn convert_write_op(
@@ -362,6 +1593,73 @@ where
     }
 }
 
+#[cfg(not(feature = "no-metrics"))]
+fn record_finish_reads(read_stats: &FinishReadStats) {
+    use crate::counters::FINISH_RESOLVER_READS;
+
+    for (phase, reads) in [
+        ("group_merge", &read_stats.group_merge),
+        ("write_op_conversion", &read_stats.write_op_conversion),
+    ] {
+        for (kind, count) in [
+            ("state_value_reads", reads.state_value_reads),
+            ("group_data_reads", reads.group_data_reads),
+            ("group_metadata_lookups", reads.group_metadata_lookups),
+            ("bytes_fetched", reads.bytes_fetched),
+        ] {
+            FINISH_RESOLVER_READS
+                .with_label_values(&[phase, kind])
+                .inc_by(count);
+        }
+    }
+}
+
+/// How many void sessions were created, see `native_event_key`.
+static VOID_SESSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// The key of events emitted by natives. It is derived from the session id, so native events of
+/// different transactions never share a key. The sessions of a transaction do, and number their
+/// events one after the other, see `SessionExt::continue_native_events`.
+///
+/// Void sessions all share the same id and belong to no transaction, so each of them is told
+/// apart by a creation number of its own. Their output is never committed, so the number need
+/// not be the same on every node.
+fn native_event_key(session_id: &SessionId) -> EventKey {
+    let creation_number = match session_id {
+        SessionId::Void => VOID_SESSIONS.fetch_add(1, Ordering::Relaxed),
+        _ => 0,
+    };
+    EventKey::new(creation_number, AccountAddress::new(*session_id.as_uuid()))
+}
+
+/// Tag of `0x1::aggregator_factory::AggregatorFactory`, the resource holding the table every
+/// aggregator lives in.
+fn aggregator_factory_tag() -> StructTag {
+    StructTag {
+        address: AccountAddress::ONE,
+        module: ident_str!("aggregator_factory").to_owned(),
+        name: ident_str!("AggregatorFactory").to_owned(),
+        type_params: vec![],
+    }
+}
+
+/// Tag of `0x1::object::ObjectCore`, the resource every object holds in its `ObjectGroup`.
+fn object_core_tag() -> StructTag {
+    StructTag {
+        address: AccountAddress::ONE,
+        module: ident_str!("object").to_owned(),
+        name: ident_str!("ObjectCore").to_owned(),
+        type_params: vec![],
+    }
+}
+
+/// The VM reserved address 0x0 and the framework reserved addresses 0x1 to 0xa.
+pub(crate) fn is_reserved_system_address(addr: &AccountAddress) -> bool {
+    let bytes = addr.as_ref();
+    let (prefix, last) = bytes.split_at(AccountAddress::LENGTH - 1);
+    prefix.iter().all(|b| *b == 0) && last[0] <= 0xA
+}
+
 impl<'r, 'l, S> Deref for SessionExt<'r, 'l, S> {
     type Target = Session<'r, 'l, S>;
 
@@ -375,3 +1673,4296 @@ impl<'r, 'l, S> DerefMut for SessionExt<'r, 'l, S> {
         &mut self.inner
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data_cache::{ResolverReads, StorageAdapter},
+        move_vm_ext::{
+            BlockMetaFootprint, ChainedResolver, EffectiveStateView, InMemoryStateView,
+            SessionTestEnv, SessionTestHarness,
+        },
+    };
+    use aptos_aggregator::{
+        aggregator_extension::{aggregator_id_for_test, AggregatorHandle},
+        delta_change_set::delta_add,
+        human_readable::DecodingAnnotator,
+        transaction::ExecutionMarker,
+    };
+    use aptos_framework::{
+        natives::transaction_context::NativeTransactionContext, KnownAttribute,
+        APTOS_METADATA_KEY_V1,
+    };
+    use aptos_gas::{
+        AptosGasMeter, AptosGasParameters, InitialGasSchedule, StandardGasMeter,
+        StorageGasParameters,
+    };
+    use aptos_state_view::TStateView;
+    use aptos_types::{
+        on_chain_config::FeatureFlag,
+        state_store::{state_storage_usage::StateStorageUsage, state_value::StateValueMetadata},
+        transaction::NoOpChangeSetChecker,
+    };
+    use move_binary_format::{
+        access::ModuleAccess,
+        errors::PartialVMResult,
+        file_format::{
+            empty_module, Ability, AbilitySet, Bytecode, CodeUnit, FieldDefinition, FieldHandle,
+            FieldHandleIndex, FieldInstantiation, FieldInstantiationIndex, FunctionDefinition,
+            FunctionHandle, FunctionHandleIndex, FunctionInstantiation, FunctionInstantiationIndex,
+            IdentifierIndex, ModuleHandleIndex, Signature, SignatureIndex, SignatureToken,
+            StructDefInstantiation, StructDefInstantiationIndex, StructDefinition,
+            StructDefinitionIndex, StructFieldInformation, StructHandle, StructHandleIndex,
+            StructTypeParameter, TypeSignature, Visibility,
+        },
+        CompiledModule,
+    };
+    use move_core_types::{
+        gas_algebra::InternalGas,
+        identifier::{IdentStr, Identifier},
+        language_storage::TypeTag,
+        metadata::Metadata,
+        value::MoveValue,
+    };
+    use move_table_extension::TableChange;
+    use move_vm_runtime::{
+        native_extensions::NativeContextExtensions,
+        native_functions::{NativeContext, NativeFunction},
+    };
+    use move_vm_types::{
+        gas::{GasMeter, UnmeteredGasMeter},
+        loaded_data::runtime_types::Type,
+        natives::function::NativeResult,
+        values::Value,
+    };
+    use proptest::{
+        collection::{btree_map, vec},
+        prelude::*,
+    };
+    use smallvec::smallvec;
+    use std::{
+        cell::RefCell,
+        collections::{HashMap, HashSet, VecDeque},
+    };
+
+    /// In-memory state view that counts how many times each key was read. The first read of a key
+    /// in `unavailable_once` fails as if storage was unavailable.
+    #[derive(Default)]
+    struct CountingStateView {
+        data: HashMap<StateKey, StateValue>,
+        reads: RefCell<HashMap<StateKey, usize>>,
+        unavailable_once: RefCell<HashSet<StateKey>>,
+    }
+
+    impl CountingStateView {
+        fn reads_of(&self, key: &StateKey) -> usize {
+            self.reads.borrow().get(key).copied().unwrap_or(0)
+        }
+
+        fn total_reads(&self) -> usize {
+            self.reads.borrow().values().sum()
+        }
+    }
+
+    impl TStateView for CountingStateView {
+        type Key = StateKey;
+
+        fn get_state_value(&self, state_key: &StateKey) -> anyhow::Result<Option<StateValue>> {
+            *self
+                .reads
+                .borrow_mut()
+                .entry(state_key.clone())
+                .or_default() += 1;
+            if self.unavailable_once.borrow_mut().remove(state_key) {
+                anyhow::bail!("storage unavailable");
+            }
+            Ok(self.data.get(state_key).cloned())
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+
+        fn get_usage(&self) -> anyhow::Result<StateStorageUsage> {
+            Ok(StateStorageUsage::new_untracked())
+        }
+    }
+
+    fn struct_tag(module: &str, name: &str) -> StructTag {
+        StructTag {
+            address: AccountAddress::ONE,
+            module: Identifier::new(module).unwrap(),
+            name: Identifier::new(name).unwrap(),
+            type_params: vec![],
+        }
+    }
+
+    #[test]
+    fn module_metadata_is_read_once_per_module() {
+        let state_view = CountingStateView::default();
+        let resolver = StorageAdapter::new(&state_view);
+
+        let num_ops = 8;
+        let mut change_set = MoveChangeSet::new();
+        for i in 0..num_ops {
+            change_set
+                .add_resource_op(
+                    AccountAddress::random(),
+                    struct_tag("coin", &format!("Resource{}", i)),
+                    MoveStorageOp::New(vec![i as u8]),
+                )
+                .unwrap();
+        }
+
+        let SplitChangeSet {
+            change_set: filtered,
+            resource_group_change_set: groups,
+            ..
+        } = SessionExt::<StorageAdapter<CountingStateView>>::split_and_merge_resource_groups(
+            &resolver,
+            change_set,
+            &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+            &DefaultStateKeyCodec,
+            &AllowAllWrites,
+            &mut NoGroupGas,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let module_key = StateKey::access_path(AccessPath::from(&ModuleId::new(
+            AccountAddress::ONE,
+            Identifier::new("coin").unwrap(),
+        )));
+        assert_eq!(state_view.reads_of(&module_key), 1);
+        assert!(state_view.total_reads() <= num_ops);
+        assert_eq!(filtered.into_inner().len(), num_ops);
+        assert!(groups.into_inner().is_empty());
+    }
+
+    type TestSession<'r, 'l> = SessionExt<'r, 'l, StorageAdapter<'r, CountingStateView>>;
+
+    fn resource_group(num_resources: usize, resource_size: usize) -> ResourceGroupMap {
+        (0..num_resources)
+            .map(|i| {
+                (
+                    struct_tag("group", &format!("Resource{}", i)),
+                    vec![i as u8; resource_size],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resource_group_serialization_matches_bcs() {
+        for (num_resources, resource_size) in [(1, 0), (1, 1), (3, 100), (200, 1000)] {
+            let group = resource_group(num_resources, resource_size);
+            let expected = bcs::to_bytes(group.members()).unwrap();
+
+            let data = TestSession::serialize_resource_group(&group, u64::MAX).unwrap();
+            assert_eq!(data, expected);
+            assert_eq!(data.capacity(), expected.len());
+
+            // A limit of exactly the serialized size is still accepted.
+            let data =
+                TestSession::serialize_resource_group(&group, expected.len() as u64).unwrap();
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[test]
+    fn oversized_resource_group_is_rejected() {
+        let group = resource_group(4, 256);
+        let size = group.serialized_size().unwrap() as u64;
+
+        let err = TestSession::serialize_resource_group(&group, size - 1).unwrap_err();
+        assert_eq!(err.major_status(), StatusCode::STORAGE_WRITE_LIMIT_REACHED);
+    }
+
+    #[test]
+    fn resource_group_map_round_trips_through_its_blob() {
+        let mut group = resource_group(2, 4);
+        let member = struct_tag("group", "Resource0");
+        assert_eq!(group.get_member(&member), Some(&[0u8; 4][..]));
+        assert_eq!(
+            group.insert_member(member.clone(), vec![7]),
+            Some(vec![0; 4])
+        );
+        assert!(group
+            .remove_member(&struct_tag("group", "Resource1"))
+            .is_some());
+        assert_eq!(group.len(), 1);
+
+        let bytes = group.to_bytes().unwrap();
+        assert_eq!(bytes.len(), group.serialized_size().unwrap());
+        assert_eq!(ResourceGroupMap::from_bytes(&bytes).unwrap(), group);
+
+        let err = ResourceGroupMap::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(
+            err.major_status(),
+            StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR
+        );
+    }
+
+    fn convert_parts(
+        change_set: MoveChangeSet,
+        resource_group_change_set: MoveChangeSet,
+        aggregator_change_set: AggregatorChangeSet,
+    ) -> ChangeSetExt {
+        TestSession::convert_change_set(
+            change_set,
+            resource_group_change_set,
+            vec![],
+            vec![],
+            TableChangeSet::default(),
+            aggregator_change_set,
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn conversion_records_where_writes_come_from() {
+        let addr = AccountAddress::random();
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(
+                addr,
+                struct_tag("coin", "CoinStore"),
+                MoveStorageOp::Modify(vec![1]),
+            )
+            .unwrap();
+        let module_id = ModuleId::new(addr, Identifier::new("m").unwrap());
+        change_set
+            .add_module_op(module_id.clone(), MoveStorageOp::New(vec![1]))
+            .unwrap();
+        let mut groups = MoveChangeSet::new();
+        groups
+            .add_resource_op(
+                addr,
+                struct_tag("group", "Group"),
+                MoveStorageOp::New(vec![0]),
+            )
+            .unwrap();
+        let written = aggregator_id_for_test(1);
+        let deleted = aggregator_id_for_test(2);
+        let aggregator_change_set = AggregatorChangeSet {
+            changes: BTreeMap::from([
+                (written, AggregatorChange::Write(5)),
+                (deleted, AggregatorChange::Delete),
+            ]),
+        };
+
+        let change_set_ext = convert_parts(change_set, groups, aggregator_change_set);
+        let aggregator_key = |id: &AggregatorID| {
+            StateKey::table_item(TableHandle::from(id.handle), id.key.0.to_vec())
+        };
+        assert_eq!(
+            change_set_ext.write_op_sources().unwrap(),
+            &BTreeMap::from([
+                (
+                    resource_key(addr, struct_tag("coin", "CoinStore")),
+                    WriteOpSource::Resource
+                ),
+                (
+                    StateKey::access_path(AccessPath::code_access_path(module_id)),
+                    WriteOpSource::Module
+                ),
+                (
+                    StateKey::access_path(AccessPath::resource_group_access_path(
+                        addr,
+                        struct_tag("group", "Group")
+                    )),
+                    WriteOpSource::ResourceGroup {
+                        group_tag: struct_tag("group", "Group")
+                    }
+                ),
+                (aggregator_key(&written), WriteOpSource::AggregatorWrite),
+                (aggregator_key(&deleted), WriteOpSource::AggregatorDelete),
+            ])
+        );
+
+        let rendered = change_set_ext.to_human_readable(&DecodingAnnotator);
+        assert!(rendered
+            .writes
+            .iter()
+            .any(|write| write.source.as_deref() == Some("resource group 0x1::group::Group")));
+        assert!(rendered.writes.iter().all(|write| write.source.is_some()));
+    }
+
+    #[test]
+    fn conversion_flags_the_kinds_of_writes() {
+        let addr = AccountAddress::random();
+        let no_aggregators = || AggregatorChangeSet {
+            changes: BTreeMap::new(),
+        };
+        let check = |change_set_ext: ChangeSetExt, expected: ChangeSetFlags| {
+            assert_eq!(change_set_ext.flags(), expected);
+            // The flags recorded while converting agree with the ones found by scanning.
+            assert_eq!(
+                ChangeSetFlags::scan(
+                    change_set_ext.delta_change_set(),
+                    change_set_ext.change_set()
+                ),
+                expected
+            );
+        };
+
+        let mut resources = MoveChangeSet::new();
+        resources
+            .add_resource_op(
+                addr,
+                struct_tag("coin", "CoinStore"),
+                MoveStorageOp::Modify(vec![1]),
+            )
+            .unwrap();
+        check(
+            convert_parts(resources, MoveChangeSet::new(), no_aggregators()),
+            ChangeSetFlags::default(),
+        );
+
+        let mut modules = MoveChangeSet::new();
+        modules
+            .add_module_op(
+                ModuleId::new(addr, Identifier::new("m").unwrap()),
+                MoveStorageOp::New(vec![1]),
+            )
+            .unwrap();
+        let change_set_ext = convert_parts(modules, MoveChangeSet::new(), no_aggregators());
+        assert!(format!("{:?}", change_set_ext).contains("has_module_writes: true"));
+        check(change_set_ext, ChangeSetFlags {
+            module_writes: true,
+            ..ChangeSetFlags::default()
+        });
+
+        let mut groups = MoveChangeSet::new();
+        groups
+            .add_resource_op(
+                addr,
+                struct_tag("group", "Group"),
+                MoveStorageOp::New(vec![0]),
+            )
+            .unwrap();
+        check(
+            convert_parts(MoveChangeSet::new(), groups, no_aggregators()),
+            ChangeSetFlags {
+                resource_group_writes: true,
+                ..ChangeSetFlags::default()
+            },
+        );
+
+        let written = AggregatorChangeSet {
+            changes: BTreeMap::from([(aggregator_id_for_test(1), AggregatorChange::Write(5))]),
+        };
+        check(
+            convert_parts(MoveChangeSet::new(), MoveChangeSet::new(), written),
+            ChangeSetFlags::default(),
+        );
+
+        let merged = AggregatorChangeSet {
+            changes: BTreeMap::from([(
+                aggregator_id_for_test(2),
+                AggregatorChange::Merge(delta_add(1, 100)),
+            )]),
+        };
+        check(
+            convert_parts(MoveChangeSet::new(), MoveChangeSet::new(), merged),
+            ChangeSetFlags {
+                aggregator_deltas: true,
+                ..ChangeSetFlags::default()
+            },
+        );
+    }
+
+    /// Denies writes to `0x1::account::Account` anywhere but under the sender.
+    struct SenderOnlyAccountWrites {
+        sender: AccountAddress,
+    }
+
+    impl WritePolicy for SenderOnlyAccountWrites {
+        fn check_resource_write(&self, addr: &AccountAddress, tag: &StructTag) -> WriteDecision {
+            if *addr != self.sender && *tag == struct_tag("account", "Account") {
+                WriteDecision::Deny
+            } else {
+                WriteDecision::Allow
+            }
+        }
+    }
+
+    #[test]
+    fn aggregator_delta_on_deleted_table_item_is_rejected() {
+        let id = aggregator_id_for_test(7);
+        let key_bytes = id.key.0.to_vec();
+
+        let mut table_change_set = TableChangeSet::default();
+        table_change_set.changes.insert(id.handle, TableChange {
+            entries: BTreeMap::from([(key_bytes, MoveStorageOp::Delete)]),
+        });
+        let aggregator_change_set = AggregatorChangeSet {
+            changes: BTreeMap::from([(id, AggregatorChange::Merge(delta_add(1, 100)))]),
+        };
+
+        let status = TestSession::convert_change_set(
+            MoveChangeSet::new(),
+            MoveChangeSet::new(),
+            vec![],
+            vec![],
+            table_change_set,
+            aggregator_change_set,
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(status.status_code(), StatusCode::DELTA_ON_DELETED_STATE_KEY);
+    }
+
+    fn convert_aggregator_over_table_op(
+        prior: Option<MoveStorageOp<Vec<u8>>>,
+        change: AggregatorChange,
+        gas_feature_version: u64,
+    ) -> (StateKey, Result<ChangeSetExt, VMStatus>) {
+        let id = aggregator_id_for_test(7);
+        let key_bytes = id.key.0.to_vec();
+        let state_key = StateKey::table_item(TableHandle::from(id.handle), key_bytes.clone());
+
+        let mut table_change_set = TableChangeSet::default();
+        if let Some(prior) = prior {
+            table_change_set.changes.insert(id.handle, TableChange {
+                entries: BTreeMap::from([(key_bytes, prior)]),
+            });
+        }
+        let aggregator_change_set = AggregatorChangeSet {
+            changes: BTreeMap::from([(id, change)]),
+        };
+
+        let result = TestSession::convert_change_set(
+            MoveChangeSet::new(),
+            MoveChangeSet::new(),
+            vec![],
+            vec![],
+            table_change_set,
+            aggregator_change_set,
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                gas_feature_version,
+            )),
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        );
+        (state_key, result)
+    }
+
+    #[test]
+    fn aggregator_changes_compose_with_earlier_writes_to_their_slot() {
+        use AggregatorChange::{Delete, Merge, Write};
+        use MoveStorageOp::{Delete as TableDelete, Modify, New};
+
+        let write = Write(5);
+        let merge = Merge(delta_add(1, 100));
+        // For every earlier write of the slot by its table, and every change of the aggregator:
+        // the resulting write of the slot, or none, and whether a delta is left, or the error.
+        let cases: Vec<(
+            Option<MoveStorageOp<Vec<u8>>>,
+            AggregatorChange,
+            Result<(Option<WriteOp>, bool), StatusCode>,
+        )> = vec![
+            (
+                None,
+                write,
+                Ok((Some(WriteOp::Modification(serialize(&5))), false)),
+            ),
+            (None, merge, Ok((None, true))),
+            (None, Delete, Ok((Some(WriteOp::Deletion), false))),
+            // A slot created by the same change set stays created, or is not written at all.
+            (
+                Some(New(serialize(&3))),
+                write,
+                Ok((Some(WriteOp::Creation(serialize(&5))), false)),
+            ),
+            (
+                Some(New(serialize(&3))),
+                merge,
+                Ok((Some(WriteOp::Creation(serialize(&4))), false)),
+            ),
+            (Some(New(serialize(&3))), Delete, Ok((None, false))),
+            // The table and the aggregator both overwrite the value.
+            (
+                Some(Modify(serialize(&3))),
+                write,
+                Err(StatusCode::DUPLICATE_WRITE_SET_KEY),
+            ),
+            (
+                Some(Modify(serialize(&3))),
+                merge,
+                Ok((Some(WriteOp::Modification(serialize(&4))), false)),
+            ),
+            (
+                Some(Modify(serialize(&3))),
+                Delete,
+                Err(StatusCode::DUPLICATE_WRITE_SET_KEY),
+            ),
+            // An aggregator destroyed and created again modifies the original slot.
+            (
+                Some(TableDelete),
+                write,
+                Ok((Some(WriteOp::Modification(serialize(&5))), false)),
+            ),
+            (
+                Some(TableDelete),
+                merge,
+                Err(StatusCode::DELTA_ON_DELETED_STATE_KEY),
+            ),
+            (
+                Some(TableDelete),
+                Delete,
+                Err(StatusCode::DUPLICATE_WRITE_SET_KEY),
+            ),
+        ];
+
+        for (prior, change, expected) in cases {
+            let case = format!("{:?} followed by {:?}", prior, change);
+            let (state_key, result) =
+                convert_aggregator_over_table_op(prior, change, LATEST_GAS_FEATURE_VERSION);
+            match (result, expected) {
+                (Ok(change_set), Ok((write_op, has_delta))) => {
+                    assert_eq!(
+                        change_set.write_set().get(&state_key),
+                        write_op.as_ref(),
+                        "{}",
+                        case
+                    );
+                    assert_eq!(
+                        change_set.delta_change_set().get(&state_key).is_some(),
+                        has_delta,
+                        "{}",
+                        case
+                    );
+                    assert_eq!(
+                        change_set
+                            .write_op_sources()
+                            .unwrap()
+                            .contains_key(&state_key),
+                        write_op.is_some(),
+                        "{}",
+                        case
+                    );
+                    assert_eq!(
+                        change_set.serialized_size_estimate(),
+                        bcs::serialized_size(change_set.write_set()).unwrap() as u64,
+                        "{}",
+                        case
+                    );
+                },
+                (Err(status), Err(status_code)) => {
+                    assert_eq!(status.status_code(), status_code, "{}", case)
+                },
+                (result, expected) => panic!("{}: got {:?}, expected {:?}", case, result, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn aggregator_delta_is_kept_apart_before_gas_feature_version_9() {
+        let (state_key, result) = convert_aggregator_over_table_op(
+            Some(MoveStorageOp::Delete),
+            AggregatorChange::Merge(delta_add(1, 100)),
+            8,
+        );
+        let change_set = result.unwrap();
+        assert_eq!(
+            change_set.write_set().get(&state_key),
+            Some(&WriteOp::Deletion)
+        );
+        assert!(change_set.delta_change_set().get(&state_key).is_some());
+    }
+
+    #[test]
+    fn later_writes_replace_earlier_ones_before_gas_feature_version_9() {
+        use AggregatorChange::{Delete, Merge, Write};
+        use MoveStorageOp::{Modify, New};
+
+        let cases = vec![
+            (
+                Modify(serialize(&3)),
+                Write(5),
+                WriteOp::Modification(serialize(&5)),
+                false,
+            ),
+            (New(serialize(&3)), Delete, WriteOp::Deletion, false),
+            (
+                New(serialize(&3)),
+                Merge(delta_add(1, 100)),
+                WriteOp::Creation(serialize(&3)),
+                true,
+            ),
+        ];
+        for (prior, change, write_op, has_delta) in cases {
+            let case = format!("{:?} followed by {:?}", prior, change);
+            let (state_key, result) = convert_aggregator_over_table_op(Some(prior), change, 8);
+            let change_set = result.unwrap();
+            assert_eq!(
+                change_set.write_set().get(&state_key),
+                Some(&write_op),
+                "{}",
+                case
+            );
+            assert_eq!(
+                change_set.delta_change_set().get(&state_key).is_some(),
+                has_delta,
+                "{}",
+                case
+            );
+            assert_eq!(
+                change_set.serialized_size_estimate(),
+                bcs::serialized_size(change_set.write_set()).unwrap() as u64,
+                "{}",
+                case
+            );
+        }
+    }
+
+    fn convert_with_write_op_limit(
+        num_resources: usize,
+        with_delta: bool,
+        max_write_ops: u64,
+    ) -> Result<ChangeSetExt, VMStatus> {
+        let addr = AccountAddress::random();
+        let mut change_set = MoveChangeSet::new();
+        for i in 0..num_resources {
+            change_set
+                .add_resource_op(
+                    addr,
+                    struct_tag("m", &format!("R{}", i)),
+                    MoveStorageOp::New(vec![1]),
+                )
+                .unwrap();
+        }
+        let mut aggregator_change_set = AggregatorChangeSet {
+            changes: BTreeMap::new(),
+        };
+        if with_delta {
+            aggregator_change_set.changes.insert(
+                aggregator_id_for_test(1),
+                AggregatorChange::Merge(delta_add(1, 100)),
+            );
+        }
+
+        TestSession::convert_change_set(
+            change_set,
+            MoveChangeSet::new(),
+            vec![],
+            vec![],
+            TableChangeSet::default(),
+            aggregator_change_set,
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(
+                ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION)
+                    .with_max_write_ops_per_transaction(max_write_ops),
+            ),
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn write_ops_up_to_the_limit_are_allowed() {
+        let change_set_ext = convert_with_write_op_limit(4, false, 4).unwrap();
+        assert_eq!(change_set_ext.write_set().iter().count(), 4);
+    }
+
+    #[test]
+    fn write_ops_over_the_limit_are_rejected() {
+        let status = convert_with_write_op_limit(5, false, 4).unwrap_err();
+        assert_eq!(status.status_code(), StatusCode::TOO_MANY_WRITE_OPS);
+    }
+
+    #[test]
+    fn deltas_do_not_count_towards_the_write_op_limit() {
+        let change_set_ext = convert_with_write_op_limit(4, true, 4).unwrap();
+        assert_eq!(change_set_ext.write_set().iter().count(), 4);
+        assert_eq!(change_set_ext.delta_change_set().len(), 1);
+    }
+
+    #[test]
+    fn validating_a_write_set_matches_change_set_construction() {
+        let change_set_ext = convert_with_write_op_limit(5, false, 5).unwrap();
+        let write_set = change_set_ext.write_set().clone();
+        let loose = ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION);
+        let tight = ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION)
+            .with_max_write_ops_per_transaction(4);
+
+        assert!(loose.validate_write_set(&write_set, &[]).is_ok());
+        assert!(ChangeSet::new(write_set.clone(), vec![], &loose).is_ok());
+
+        let validated = tight.validate_write_set(&write_set, &[]).unwrap_err();
+        let constructed = ChangeSet::new(write_set, vec![], &tight).unwrap_err();
+        assert_eq!(validated.status_code(), StatusCode::TOO_MANY_WRITE_OPS);
+        assert_eq!(constructed.status_code(), validated.status_code());
+    }
+
+    #[test]
+    fn aggregator_overflow_names_the_aggregator() {
+        let handle = AccountAddress::from_hex_literal("0xcafe").unwrap();
+        let key = AccountAddress::from_hex_literal("0xbeef").unwrap();
+        let id = AggregatorID::new(
+            move_table_extension::TableHandle(handle),
+            AggregatorHandle(key),
+        );
+        let aggregator_change_set = AggregatorChangeSet {
+            changes: BTreeMap::from([(id, AggregatorChange::Merge(delta_add(10, 100)))]),
+        };
+
+        let change_set_ext = TestSession::convert_change_set(
+            MoveChangeSet::new(),
+            MoveChangeSet::new(),
+            vec![],
+            vec![],
+            TableChangeSet::default(),
+            aggregator_change_set,
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let state_key = StateKey::table_item(TableHandle(handle), key.to_vec());
+        let mut state_view = CountingStateView::default();
+        state_view
+            .data
+            .insert(state_key, StateValue::new_legacy(serialize(&95)));
+        match change_set_ext.try_materialize(&state_view).unwrap_err() {
+            VMStatus::Error(StatusCode::DELTA_MATERIALIZATION_FAILURE, Some(message)) => {
+                assert!(message.contains("0xcafe"), "{}", message);
+                assert!(message.contains("0xbeef"), "{}", message);
+            },
+            status => panic!("unexpected status {:?}", status),
+        }
+    }
+
+    #[test]
+    fn effective_state_view_applies_the_change_set_on_read() {
+        let addr = AccountAddress::random();
+        let group_tag = struct_tag("object", "ObjectGroup");
+        let group_key = StateKey::access_path(AccessPath::resource_group_access_path(
+            addr,
+            group_tag.clone(),
+        ));
+        let aggregator_key = |key: u8| StateKey::table_item(TableHandle(addr), vec![key]);
+
+        let mut state_view = CountingStateView::default();
+        state_view
+            .data
+            .insert(group_key.clone(), StateValue::new_legacy(vec![1]));
+        for key in [1, 2] {
+            state_view
+                .data
+                .insert(aggregator_key(key), StateValue::new_legacy(serialize(&95)));
+        }
+
+        let write_set = WriteSetMut::new(vec![(group_key, WriteOp::Modification(vec![2]))])
+            .freeze()
+            .unwrap();
+        let change_set_ext = ChangeSetExt::new(
+            DeltaChangeSet::new(vec![
+                (aggregator_key(1), delta_add(3, 100)),
+                (aggregator_key(2), delta_add(10, 100)),
+            ]),
+            ChangeSet::new(write_set, vec![], &NoOpChangeSetChecker).unwrap(),
+            Arc::new(NoOpChangeSetChecker),
+        );
+        let view = EffectiveStateView::new(&state_view, Some(&change_set_ext));
+
+        assert_eq!(
+            view.get_resource_group_data(&addr, &group_tag).unwrap(),
+            Some(vec![2])
+        );
+        assert_eq!(
+            view.get_state_value_bytes(&aggregator_key(1)).unwrap(),
+            Some(serialize(&98))
+        );
+        let err = view.get_state_value(&aggregator_key(2)).unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<VMStatus>(),
+                Some(VMStatus::MoveAbort(_, 0x02_0001))
+            ),
+            "{:?}",
+            err
+        );
+        // Without a change set, the base values are read as they are.
+        assert_eq!(
+            EffectiveStateView::new(&state_view, None)
+                .get_resource_group_data(&addr, &group_tag)
+                .unwrap(),
+            Some(vec![1])
+        );
+    }
+
+    #[test]
+    fn aggregator_changes_outside_the_factory_table_are_rejected() {
+        let factory_handle = AccountAddress::from_hex_literal("0xfac").unwrap();
+        let convert = |handle: AccountAddress| {
+            let id = AggregatorID::new(
+                move_table_extension::TableHandle(handle),
+                AggregatorHandle(AccountAddress::random()),
+            );
+            TestSession::convert_change_set(
+                MoveChangeSet::new(),
+                MoveChangeSet::new(),
+                vec![],
+                vec![],
+                TableChangeSet::default(),
+                AggregatorChangeSet {
+                    changes: BTreeMap::from([(id, AggregatorChange::Merge(delta_add(1, 100)))]),
+                },
+                &mut (),
+                &DefaultStateKeyCodec,
+                &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                    LATEST_GAS_FEATURE_VERSION,
+                )),
+                &AllowAllWrites,
+                false,
+                None,
+                None,
+                None,
+                Some(&BTreeSet::from([factory_handle])),
+            )
+        };
+
+        assert!(convert(factory_handle).is_ok());
+        match convert(AccountAddress::from_hex_literal("0xbad").unwrap()) {
+            Err(VMStatus::Error(StatusCode::UNKNOWN_AGGREGATOR_HANDLE, Some(message))) => {
+                assert!(message.contains("0xbad"), "{}", message);
+            },
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn aggregator_factory_handle_is_read_from_the_change_set_first() {
+        let stored = AccountAddress::from_hex_literal("0xfac").unwrap();
+        let created = AccountAddress::from_hex_literal("0x9e4").unwrap();
+        let factory_handle = |state_view: &CountingStateView, change_set: &MoveChangeSet| {
+            TestSession::aggregator_factory_handle(&StorageAdapter::new(state_view), change_set)
+                .unwrap()
+        };
+
+        let mut state_view = CountingStateView::default();
+        assert_eq!(factory_handle(&state_view, &MoveChangeSet::new()), None);
+
+        // At genesis the factory is created by the change set being converted.
+        let mut genesis = MoveChangeSet::new();
+        genesis
+            .add_resource_op(
+                AccountAddress::ONE,
+                aggregator_factory_tag(),
+                MoveStorageOp::New(bcs::to_bytes(&created).unwrap()),
+            )
+            .unwrap();
+        assert_eq!(factory_handle(&state_view, &genesis), Some(created));
+
+        state_view.data.insert(
+            resource_key(AccountAddress::ONE, aggregator_factory_tag()),
+            StateValue::new_legacy(bcs::to_bytes(&stored).unwrap()),
+        );
+        assert_eq!(
+            factory_handle(&state_view, &MoveChangeSet::new()),
+            Some(stored)
+        );
+    }
+
+    /// Resources, modules and table items of a handful of accounts and tables, with payloads
+    /// long enough for their length prefixes to take more than a byte.
+    fn random_session_output() -> impl Strategy<Value = (MoveChangeSet, TableChangeSet)> {
+        let op = (0..3u8, vec(any::<u8>(), 0..300)).prop_map(|(kind, data)| match kind {
+            0 => MoveStorageOp::New(data),
+            1 => MoveStorageOp::Modify(data),
+            _ => MoveStorageOp::Delete,
+        });
+        (
+            btree_map((0..4u8, 0..4u8), op.clone(), 0..16),
+            btree_map((0..4u8, 0..4u8), op.clone(), 0..4),
+            btree_map((0..4u8, vec(any::<u8>(), 0..200)), op, 0..16),
+        )
+            .prop_map(|(resources, modules, table_items)| {
+                let address =
+                    |i: u8| AccountAddress::from_hex_literal(&format!("0xa{}", i)).unwrap();
+                let mut change_set = MoveChangeSet::new();
+                for ((addr, name), op) in resources {
+                    change_set
+                        .add_resource_op(address(addr), struct_tag("m", &format!("R{}", name)), op)
+                        .unwrap();
+                }
+                for ((addr, name), op) in modules {
+                    let name = Identifier::new(format!("m{}", name)).unwrap();
+                    change_set
+                        .add_module_op(ModuleId::new(address(addr), name), op)
+                        .unwrap();
+                }
+                let mut table_change_set = TableChangeSet::default();
+                for ((handle, key), op) in table_items {
+                    table_change_set
+                        .changes
+                        .entry(move_table_extension::TableHandle(address(handle)))
+                        .or_insert_with(|| TableChange {
+                            entries: BTreeMap::new(),
+                        })
+                        .entries
+                        .insert(key, op);
+                }
+                (change_set, table_change_set)
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn serialized_size_estimate_matches_the_write_set(
+            (change_set, table_change_set) in random_session_output(),
+            (other_change_set, other_table_change_set) in random_session_output(),
+        ) {
+            let convert = |change_set, table_change_set| {
+                TestSession::convert_change_set(
+                    change_set,
+                    MoveChangeSet::new(),
+                    vec![],
+                    vec![],
+                    table_change_set,
+                    AggregatorChangeSet {
+                        changes: BTreeMap::new(),
+                    },
+                    &mut (),
+                    &DefaultStateKeyCodec,
+                    &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                        LATEST_GAS_FEATURE_VERSION,
+                    )),
+                    &AllowAllWrites,
+                    false,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap()
+            };
+            let serialized_size = |change_set_ext: &ChangeSetExt| {
+                bcs::to_bytes(change_set_ext.write_set()).unwrap().len() as u64
+            };
+
+            let first = convert(change_set, table_change_set);
+            prop_assert_eq!(first.serialized_size_estimate(), serialized_size(&first));
+
+            // Squashing updates the size of the ops it merges or drops instead of rescanning.
+            if let Ok(squashed) = first.squash(convert(other_change_set, other_table_change_set)) {
+                prop_assert_eq!(squashed.serialized_size_estimate(), serialized_size(&squashed));
+            }
+        }
+    }
+
+    #[test]
+    fn state_key_written_twice_is_reported() {
+        let id = aggregator_id_for_test(7);
+        let key_bytes = id.key.0.to_vec();
+        let handle = id.handle.0.to_hex_literal();
+
+        let mut table_change_set = TableChangeSet::default();
+        table_change_set.changes.insert(id.handle, TableChange {
+            entries: BTreeMap::from([(key_bytes, MoveStorageOp::Modify(vec![1]))]),
+        });
+        let aggregator_change_set = AggregatorChangeSet {
+            changes: BTreeMap::from([(id, AggregatorChange::Write(5))]),
+        };
+
+        let status = TestSession::convert_change_set(
+            MoveChangeSet::new(),
+            MoveChangeSet::new(),
+            vec![],
+            vec![],
+            table_change_set,
+            aggregator_change_set,
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        match status {
+            VMStatus::Error(StatusCode::DUPLICATE_WRITE_SET_KEY, Some(message)) => {
+                assert!(message.contains(&handle), "{}", message);
+            },
+            _ => panic!("expected a duplicate key error, got {:?}", status),
+        }
+    }
+
+    fn convert_with_policy(
+        change_set: MoveChangeSet,
+        write_policy: &dyn WritePolicy,
+    ) -> Result<ChangeSetExt, VMStatus> {
+        TestSession::convert_change_set(
+            change_set,
+            MoveChangeSet::new(),
+            vec![],
+            vec![],
+            TableChangeSet::default(),
+            AggregatorChangeSet {
+                changes: BTreeMap::new(),
+            },
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            write_policy,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn write_policy_denies_account_writes_for_non_sender() {
+        let sender = AccountAddress::random();
+        let other = AccountAddress::random();
+        let policy = SenderOnlyAccountWrites { sender };
+
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(
+                sender,
+                struct_tag("account", "Account"),
+                MoveStorageOp::Modify(vec![0]),
+            )
+            .unwrap();
+        change_set
+            .add_resource_op(
+                other,
+                struct_tag("coin", "CoinStore"),
+                MoveStorageOp::Modify(vec![1]),
+            )
+            .unwrap();
+        let change_set_ext = convert_with_policy(change_set, &policy).unwrap();
+        assert_eq!(change_set_ext.change_set().write_set().iter().count(), 2);
+
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(
+                other,
+                struct_tag("account", "Account"),
+                MoveStorageOp::Modify(vec![0]),
+            )
+            .unwrap();
+        match convert_with_policy(change_set, &policy) {
+            Err(VMStatus::Error(StatusCode::RESOURCE_WRITE_DENIED, Some(message))) => {
+                assert!(message.contains("0x1::account::Account"), "{}", message);
+            },
+            _ => panic!("expected the write to be denied"),
+        }
+    }
+
+    #[test]
+    fn default_write_policy_allows_everything() {
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(
+                AccountAddress::random(),
+                struct_tag("account", "Account"),
+                MoveStorageOp::New(vec![0]),
+            )
+            .unwrap();
+        assert!(convert_with_policy(change_set, &AllowAllWrites).is_ok());
+    }
+
+    fn convert_with_reserved_deletions(
+        change_set: MoveChangeSet,
+        resource_group_change_set: MoveChangeSet,
+        configs: ChangeSetConfigs,
+        allow_reserved_deletions: bool,
+    ) -> Result<ChangeSetExt, VMStatus> {
+        TestSession::convert_change_set(
+            change_set,
+            resource_group_change_set,
+            vec![],
+            vec![],
+            TableChangeSet::default(),
+            AggregatorChangeSet {
+                changes: BTreeMap::new(),
+            },
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(configs),
+            &AllowAllWrites,
+            allow_reserved_deletions,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn deletion(addr: AccountAddress, struct_tag: StructTag) -> MoveChangeSet {
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(addr, struct_tag, MoveStorageOp::Delete)
+            .unwrap();
+        change_set
+    }
+
+    #[test]
+    fn reserved_resource_deletions_need_genesis_or_governance() {
+        let protected = || {
+            ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION)
+                .with_reserved_deletion_protection([struct_tag("account", "Account")])
+        };
+        let chain_id = || deletion(AccountAddress::ONE, struct_tag("chain_id", "ChainId"));
+        let group = || deletion(AccountAddress::ONE, struct_tag("object", "ObjectGroup"));
+
+        match convert_with_reserved_deletions(chain_id(), MoveChangeSet::new(), protected(), false)
+        {
+            Err(VMStatus::Error(StatusCode::RESERVED_RESOURCE_DELETION_DENIED, Some(message))) => {
+                assert!(message.contains("0x1::chain_id::ChainId"), "{}", message);
+                assert!(message.contains("AccessPath"), "{}", message);
+            },
+            result => panic!("expected the deletion to be denied, got {:?}", result.err()),
+        }
+        let status =
+            convert_with_reserved_deletions(MoveChangeSet::new(), group(), protected(), false)
+                .unwrap_err();
+        assert_eq!(
+            status.status_code(),
+            StatusCode::RESERVED_RESOURCE_DELETION_DENIED
+        );
+
+        // Genesis and governance sessions may delete them.
+        assert!(convert_with_reserved_deletions(
+            chain_id(),
+            MoveChangeSet::new(),
+            protected(),
+            true
+        )
+        .is_ok());
+        assert!(
+            convert_with_reserved_deletions(MoveChangeSet::new(), group(), protected(), true)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn allowed_reserved_deletions_and_other_writes_are_not_restricted() {
+        let protected = || {
+            ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION)
+                .with_reserved_deletion_protection([struct_tag("account", "Account")])
+        };
+        let account = AccountAddress::from_hex_literal("0xa").unwrap();
+
+        // Allowlisted resources, deletions at other addresses and other writes at reserved
+        // addresses all go through.
+        let allowlisted = deletion(account, struct_tag("account", "Account"));
+        assert!(convert_with_reserved_deletions(
+            allowlisted,
+            MoveChangeSet::new(),
+            protected(),
+            false
+        )
+        .is_ok());
+        let user = deletion(
+            AccountAddress::from_hex_literal("0xb").unwrap(),
+            struct_tag("chain_id", "ChainId"),
+        );
+        assert!(
+            convert_with_reserved_deletions(user, MoveChangeSet::new(), protected(), false).is_ok()
+        );
+        let mut modification = MoveChangeSet::new();
+        modification
+            .add_resource_op(
+                AccountAddress::ONE,
+                struct_tag("chain_id", "ChainId"),
+                MoveStorageOp::Modify(vec![1]),
+            )
+            .unwrap();
+        assert!(convert_with_reserved_deletions(
+            modification,
+            MoveChangeSet::new(),
+            protected(),
+            false
+        )
+        .is_ok());
+
+        // Without the protection, nothing is restricted.
+        let unprotected =
+            ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION);
+        let chain_id = deletion(AccountAddress::ONE, struct_tag("chain_id", "ChainId"));
+        assert!(convert_with_reserved_deletions(
+            chain_id,
+            MoveChangeSet::new(),
+            unprotected,
+            false
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn only_genesis_sessions_may_delete_reserved_resources_by_default() {
+        let state_view = CountingStateView::default();
+        let resolver = StorageAdapter::new(&state_view);
+        let vm = MoveVM::new(vec![]).unwrap();
+        let new_session = |session_id: SessionId| {
+            SessionExt::new(vm.new_session(&resolver), &vm, &resolver, &session_id)
+        };
+
+        assert!(new_session(SessionId::genesis(HashValue::zero())).allow_reserved_deletions);
+        let mut session = new_session(SessionId::void());
+        assert!(!session.allow_reserved_deletions);
+        session.set_allow_reserved_deletions(true);
+        assert!(session.allow_reserved_deletions);
+    }
+
+    /// Prefixes every key with a shard derived from the last byte of the address it is under.
+    struct ShardedKeys;
+
+    impl ShardedKeys {
+        fn shard(addr: AccountAddress, state_key: StateKey) -> StateKey {
+            let mut raw = vec![addr.to_vec()[AccountAddress::LENGTH - 1] % 4];
+            raw.extend(state_key.inner().encode().unwrap());
+            StateKey::raw(raw)
+        }
+    }
+
+    impl StateKeyCodec for ShardedKeys {
+        fn access_path_key(&self, access_path: AccessPath) -> StateKey {
+            Self::shard(access_path.address, StateKey::access_path(access_path))
+        }
+
+        fn table_item_key(&self, handle: TableHandle, key: Vec<u8>) -> StateKey {
+            Self::shard(handle.0, StateKey::table_item(handle, key))
+        }
+    }
+
+    fn convert_with_codec(
+        state_view: &CountingStateView,
+        change_set: MoveChangeSet,
+        table_change_set: TableChangeSet,
+        key_codec: &dyn StateKeyCodec,
+    ) -> ChangeSetExt {
+        let resolver = StorageAdapter::new(state_view);
+        let configs = Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+            LATEST_GAS_FEATURE_VERSION,
+        ));
+        let split = TestSession::split_and_merge_resource_groups(
+            &resolver,
+            change_set,
+            &configs,
+            key_codec,
+            &AllowAllWrites,
+            &mut NoGroupGas,
+            false,
+            false,
+        )
+        .unwrap();
+        TestSession::convert_change_set(
+            split.change_set,
+            split.resource_group_change_set,
+            vec![],
+            vec![],
+            table_change_set,
+            AggregatorChangeSet {
+                changes: BTreeMap::new(),
+            },
+            &mut (),
+            key_codec,
+            &configs,
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn table_change_set(handle: AccountAddress, key: Vec<u8>) -> TableChangeSet {
+        let mut table_change_set = TableChangeSet::default();
+        table_change_set
+            .changes
+            .insert(TableHandle(handle), TableChange {
+                entries: BTreeMap::from([(key, MoveStorageOp::New(vec![0]))]),
+            });
+        table_change_set
+    }
+
+    #[test]
+    fn default_state_key_codec_matches_golden_keys() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(
+                AccountAddress::ONE,
+                struct_tag("account", "Account"),
+                MoveStorageOp::New(vec![0]),
+            )
+            .unwrap();
+        change_set
+            .add_module_op(
+                ModuleId::new(AccountAddress::ONE, Identifier::new("coin").unwrap()),
+                MoveStorageOp::New(vec![0]),
+            )
+            .unwrap();
+        change_set
+            .add_resource_op(
+                AccountAddress::TWO,
+                object_core_tag(),
+                MoveStorageOp::New(vec![0]),
+            )
+            .unwrap();
+        let table_handle = AccountAddress::from_hex_literal("0x3").unwrap();
+
+        let change_set_ext = convert_with_codec(
+            &state_view,
+            change_set,
+            table_change_set(table_handle, vec![1, 2, 3]),
+            &DefaultStateKeyCodec,
+        );
+        let keys: BTreeSet<_> = change_set_ext
+            .write_set()
+            .iter()
+            .map(|(state_key, _)| hex::encode(state_key.inner().encode().unwrap()))
+            .collect();
+        // Resource, module and resource group access paths, then a table item. Changing any of
+        // these changes where existing state is stored.
+        let golden = BTreeSet::from([
+            "00000000000000000000000000000000000000000000000000000000000000000132010000000000000000000000000000000000000000000000000000000000000001076163636f756e74074163636f756e7400".to_string(),
+            "0000000000000000000000000000000000000000000000000000000000000000012600000000000000000000000000000000000000000000000000000000000000000104636f696e".to_string(),
+            "00000000000000000000000000000000000000000000000000000000000000000235020000000000000000000000000000000000000000000000000000000000000001066f626a6563740b4f626a65637447726f757000".to_string(),
+            "010000000000000000000000000000000000000000000000000000000000000003010203".to_string(),
+        ]);
+        assert_eq!(keys, golden);
+    }
+
+    #[test]
+    fn state_key_codec_builds_written_and_read_group_keys() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let addr = AccountAddress::random();
+        let group_key = ShardedKeys.access_path_key(AccessPath::resource_group_access_path(
+            addr,
+            ObjectGroupResource::struct_tag(),
+        ));
+        state_view.data.insert(
+            group_key.clone(),
+            StateValue::new_legacy(
+                bcs::to_bytes(&BTreeMap::from([(object_core_tag(), vec![0])])).unwrap(),
+            ),
+        );
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(
+                addr,
+                struct_tag("object", "Tag"),
+                MoveStorageOp::New(vec![1]),
+            )
+            .unwrap();
+        change_set
+            .add_resource_op(
+                addr,
+                struct_tag("account", "Account"),
+                MoveStorageOp::New(vec![2]),
+            )
+            .unwrap();
+        let table_handle = AccountAddress::random();
+
+        let change_set_ext = convert_with_codec(
+            &state_view,
+            change_set,
+            table_change_set(table_handle, vec![3]),
+            &ShardedKeys,
+        );
+        assert_eq!(state_view.reads_of(&group_key), 1);
+        let write_set = change_set_ext.write_set();
+        assert_eq!(write_set.iter().count(), 3);
+        // The group was read from its sharded key, so the existing member is kept.
+        let group: BTreeMap<StructTag, Vec<u8>> =
+            bcs::from_bytes(write_set.get(&group_key).unwrap().bytes().unwrap()).unwrap();
+        assert_eq!(
+            group,
+            BTreeMap::from([
+                (object_core_tag(), vec![0]),
+                (struct_tag("object", "Tag"), vec![1]),
+            ])
+        );
+        assert!(write_set
+            .get(&ShardedKeys.access_path_key(
+                AccessPath::resource_access_path(addr, struct_tag("account", "Account")).unwrap()
+            ))
+            .is_some());
+        assert!(write_set
+            .get(&ShardedKeys.table_item_key(TableHandle(table_handle), vec![3]))
+            .is_some());
+    }
+
+    fn convert_within(
+        change_set: MoveChangeSet,
+        footprint: &dyn StateFootprint,
+    ) -> Result<ChangeSetExt, VMStatus> {
+        TestSession::convert_change_set(
+            change_set,
+            MoveChangeSet::new(),
+            vec![],
+            vec![],
+            TableChangeSet::default(),
+            AggregatorChangeSet {
+                changes: BTreeMap::new(),
+            },
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            Some(footprint),
+            None,
+        )
+    }
+
+    #[test]
+    fn block_meta_footprint_lists_writes_outside_of_it() {
+        let proposer = AccountAddress::from_hex_literal("0xbeef").unwrap();
+        let user = AccountAddress::from_hex_literal("0xcafe").unwrap();
+        let validator = AccountAddress::from_hex_literal("0xfeed").unwrap();
+        let change_set = |addrs: &[AccountAddress]| {
+            let mut change_set = MoveChangeSet::new();
+            for addr in addrs {
+                change_set
+                    .add_resource_op(
+                        *addr,
+                        struct_tag("coin", "CoinStore"),
+                        MoveStorageOp::Modify(vec![1]),
+                    )
+                    .unwrap();
+            }
+            change_set
+        };
+
+        let footprint = BlockMetaFootprint::new(proposer);
+        convert_within(change_set(&[AccountAddress::ONE, proposer]), &footprint).unwrap();
+
+        let status =
+            convert_within(change_set(&[proposer, user, validator]), &footprint).unwrap_err();
+        assert_eq!(
+            status.status_code(),
+            StatusCode::BLOCK_META_WRITE_OUTSIDE_FOOTPRINT
+        );
+        let message = match status {
+            VMStatus::Error(_, Some(message)) => message,
+            _ => panic!("expected an error listing the offending keys"),
+        };
+        assert!(message.contains(&user.short_str_lossless()));
+        assert!(message.contains(&validator.short_str_lossless()));
+        assert!(!message.contains(&proposer.short_str_lossless()));
+
+        let footprint = footprint.with_address(user).with_address(validator);
+        convert_within(change_set(&[proposer, user, validator]), &footprint).unwrap();
+    }
+
+    fn resource_key(addr: AccountAddress, struct_tag: StructTag) -> StateKey {
+        StateKey::access_path(AccessPath::resource_access_path(addr, struct_tag).unwrap())
+    }
+
+    fn convert_against(
+        change_set: MoveChangeSet,
+        existing_state: Option<&dyn StateView>,
+    ) -> ChangeSetExt {
+        TestSession::convert_change_set(
+            change_set,
+            MoveChangeSet::new(),
+            vec![],
+            vec![],
+            TableChangeSet::default(),
+            AggregatorChangeSet {
+                changes: BTreeMap::new(),
+            },
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            &AllowAllWrites,
+            false,
+            None,
+            existing_state,
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn noop_modifications_are_stripped_only_when_asked() {
+        let addr = AccountAddress::random();
+        let unchanged = struct_tag("coin", "CoinStore");
+        let changed = struct_tag("account", "Account");
+        let created = struct_tag("object", "ObjectCore");
+
+        let mut state_view = CountingStateView::default();
+        state_view.data.insert(
+            resource_key(addr, unchanged.clone()),
+            StateValue::new_legacy(vec![1]),
+        );
+        state_view.data.insert(
+            resource_key(addr, changed.clone()),
+            StateValue::new_legacy(vec![2]),
+        );
+        let change_set = || {
+            let mut change_set = MoveChangeSet::new();
+            change_set
+                .add_resource_op(addr, unchanged.clone(), MoveStorageOp::Modify(vec![1]))
+                .unwrap();
+            change_set
+                .add_resource_op(addr, changed.clone(), MoveStorageOp::Modify(vec![3]))
+                .unwrap();
+            change_set
+                .add_resource_op(addr, created.clone(), MoveStorageOp::New(vec![4]))
+                .unwrap();
+            change_set
+        };
+
+        // Without the state to compare against, e.g. when replaying transactions from before the
+        // feature, the write set is left as is.
+        let kept = convert_against(change_set(), None);
+        assert_eq!(kept.write_set().iter().count(), 3);
+        assert_eq!(state_view.total_reads(), 0);
+
+        let stripped = convert_against(change_set(), Some(&state_view));
+        let keys: Vec<_> = stripped
+            .write_set()
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect();
+        assert_eq!(keys.len(), 2);
+        assert!(!keys.contains(&resource_key(addr, unchanged.clone())));
+        assert!(keys.contains(&resource_key(addr, changed)));
+        assert!(keys.contains(&resource_key(addr, created.clone())));
+        // Only modifications are compared against storage.
+        assert_eq!(state_view.reads_of(&resource_key(addr, created)), 0);
+    }
+
+    #[test]
+    fn noop_modifications_with_metadata_need_matching_metadata() {
+        let metadata = |deposit| StateValueMetadata::V0 {
+            payer: AccountAddress::ONE,
+            deposit,
+            creation_time_usecs: 0,
+        };
+        let same_key = StateKey::raw(vec![1]);
+        let other_metadata_key = StateKey::raw(vec![2]);
+        let legacy_key = StateKey::raw(vec![3]);
+
+        let mut state_view = CountingStateView::default();
+        for key in [&same_key, &other_metadata_key, &legacy_key] {
+            state_view.data.insert(
+                key.clone(),
+                StateValue::new_with_metadata(vec![7], metadata(10)),
+            );
+        }
+
+        let mut write_set_mut = WriteSetMut::new(vec![
+            (same_key.clone(), WriteOp::ModificationWithMetadata {
+                data: vec![7],
+                metadata: metadata(10),
+            }),
+            (
+                other_metadata_key.clone(),
+                WriteOp::ModificationWithMetadata {
+                    data: vec![7],
+                    metadata: metadata(20),
+                },
+            ),
+            (legacy_key.clone(), WriteOp::Modification(vec![7])),
+        ]);
+        let mut write_set_size = WriteSetSize::of(&write_set_mut.clone().freeze().unwrap());
+        let num_stripped = TestSession::strip_noop_modifications(
+            &mut write_set_mut,
+            &mut write_set_size,
+            &state_view,
+        )
+        .unwrap();
+        assert_eq!(num_stripped, 1);
+        assert_eq!(
+            write_set_size,
+            WriteSetSize::of(&write_set_mut.clone().freeze().unwrap())
+        );
+        let write_ops = write_set_mut.as_inner_mut();
+        assert!(!write_ops.contains_key(&same_key));
+        assert!(write_ops.contains_key(&other_metadata_key));
+        assert!(write_ops.contains_key(&legacy_key));
+    }
+
+    /// Checks `write_ops` by an account holding `quota`, if any, and a resource of `existing_size`
+    /// bytes.
+    fn check_quota(
+        quota: Option<StorageQuotaResource>,
+        existing_size: usize,
+        write_ops: Vec<(StructTag, WriteOp)>,
+    ) -> Result<(), VMStatus> {
+        let addr = AccountAddress::random();
+        let mut state_view = CountingStateView::default();
+        state_view.data.insert(
+            resource_key(addr, struct_tag("m", "Existing")),
+            StateValue::new_legacy(vec![0; existing_size]),
+        );
+        if let Some(quota) = quota {
+            state_view.data.insert(
+                resource_key(addr, StorageQuotaResource::struct_tag()),
+                StateValue::new_legacy(bcs::to_bytes(&quota).unwrap()),
+            );
+        }
+        let write_set = WriteSetMut::new(
+            write_ops
+                .into_iter()
+                .map(|(struct_tag, op)| (resource_key(addr, struct_tag), op)),
+        )
+        .freeze()
+        .unwrap();
+        TestSession::check_storage_quotas(&write_set, &state_view)
+    }
+
+    #[test]
+    fn growth_beyond_the_storage_quota_is_rejected() {
+        let created = struct_tag("m", "Created");
+        let created_size = (resource_key(AccountAddress::ONE, created.clone()).size() + 10) as u64;
+
+        let fits = StorageQuotaResource::new(1000 + created_size, 1000);
+        assert!(check_quota(Some(fits), 0, vec![(
+            created.clone(),
+            WriteOp::Creation(vec![0; 10])
+        )])
+        .is_ok());
+
+        let too_small = StorageQuotaResource::new(1000 + created_size - 1, 1000);
+        let status = check_quota(Some(too_small), 0, vec![(
+            created,
+            WriteOp::Creation(vec![0; 10]),
+        )])
+        .unwrap_err();
+        assert_eq!(status.status_code(), StatusCode::STORAGE_QUOTA_EXCEEDED);
+    }
+
+    #[test]
+    fn shrinking_accounts_are_not_checked_against_their_quota() {
+        // Already over its quota, the account may still create an item while shrinking overall.
+        let over_quota = StorageQuotaResource::new(100, 200);
+        assert!(check_quota(Some(over_quota), 100, vec![
+            (
+                struct_tag("m", "Existing"),
+                WriteOp::Modification(vec![0; 10])
+            ),
+            (struct_tag("m", "Created"), WriteOp::Creation(vec![0; 10])),
+        ])
+        .is_ok());
+
+        let over_quota = StorageQuotaResource::new(100, 200);
+        let status = check_quota(Some(over_quota), 100, vec![(
+            struct_tag("m", "Existing"),
+            WriteOp::Modification(vec![0; 101]),
+        )])
+        .unwrap_err();
+        assert_eq!(status.status_code(), StatusCode::STORAGE_QUOTA_EXCEEDED);
+    }
+
+    #[test]
+    fn accounts_without_a_storage_quota_are_not_limited() {
+        assert!(check_quota(None, 0, vec![(
+            struct_tag("m", "Created"),
+            WriteOp::Creation(vec![0; 1 << 20])
+        )])
+        .is_ok());
+    }
+
+    fn convert_events(
+        change_set: MoveChangeSet,
+        event_creators: &[AccountAddress],
+        event_key_sender: Option<AccountAddress>,
+    ) -> Result<ChangeSetExt, VMStatus> {
+        convert_events_with_groups(
+            change_set,
+            MoveChangeSet::new(),
+            event_creators,
+            event_key_sender.map(|sender| BTreeSet::from([sender])),
+        )
+    }
+
+    fn convert_events_with_groups(
+        change_set: MoveChangeSet,
+        resource_group_change_set: MoveChangeSet,
+        event_creators: &[AccountAddress],
+        event_key_owners: Option<BTreeSet<AccountAddress>>,
+    ) -> Result<ChangeSetExt, VMStatus> {
+        let events = event_creators
+            .iter()
+            .map(|creator| {
+                (
+                    bcs::to_bytes(&EventKey::new(0, *creator)).unwrap(),
+                    0,
+                    TypeTag::U64,
+                    vec![],
+                )
+            })
+            .collect();
+        TestSession::convert_change_set(
+            change_set,
+            resource_group_change_set,
+            events,
+            vec![],
+            TableChangeSet::default(),
+            AggregatorChangeSet {
+                changes: BTreeMap::new(),
+            },
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            &AllowAllWrites,
+            false,
+            event_key_owners,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn event_sizes_match_the_serialized_events() {
+        let sender = AccountAddress::random();
+        let coin_store = |inner: TypeTag| {
+            let mut tag = struct_tag("coin", "CoinStore");
+            tag.type_params = vec![inner];
+            TypeTag::Struct(Box::new(tag))
+        };
+        let nested = coin_store(TypeTag::Vector(Box::new(coin_store(TypeTag::Struct(
+            Box::new(struct_tag("aptos_coin", "AptosCoin")),
+        )))));
+        let events = [
+            (TypeTag::U64, vec![]),
+            (nested.clone(), vec![7; 3]),
+            (coin_store(nested), vec![1; 300]),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(seq_num, (type_tag, data))| {
+            (
+                bcs::to_bytes(&EventKey::new(0, sender)).unwrap(),
+                seq_num as u64,
+                type_tag,
+                data,
+            )
+        })
+        .collect();
+
+        let change_set_ext = TestSession::convert_change_set(
+            MoveChangeSet::new(),
+            MoveChangeSet::new(),
+            events,
+            vec![],
+            TableChangeSet::default(),
+            AggregatorChangeSet {
+                changes: BTreeMap::new(),
+            },
+            &mut (),
+            &DefaultStateKeyCodec,
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let events = change_set_ext.change_set().events();
+        assert_eq!(change_set_ext.event_sizes().len(), events.len());
+        for (event, size) in change_set_ext.events_with_sizes() {
+            // The serialized event also holds its version tag and the length of its data.
+            let data_len_bytes =
+                bcs::to_bytes(event.event_data()).unwrap().len() - event.event_data().len();
+            assert_eq!(
+                u64::from(size) as usize + 1 + data_len_bytes,
+                bcs::to_bytes(event).unwrap().len()
+            );
+        }
+    }
+
+    #[test]
+    fn event_keys_must_belong_to_written_accounts() {
+        let sender = AccountAddress::random();
+        let recipient = AccountAddress::random();
+        let stranger = AccountAddress::random();
+
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(
+                recipient,
+                struct_tag("coin", "CoinStore"),
+                MoveStorageOp::Modify(vec![0]),
+            )
+            .unwrap();
+
+        let allowed = [sender, recipient, AccountAddress::ONE];
+        assert!(convert_events(change_set.clone(), &allowed, Some(sender)).is_ok());
+
+        match convert_events(change_set.clone(), &[stranger], Some(sender)) {
+            Err(VMStatus::Error(StatusCode::EVENT_KEY_MISMATCH, Some(message))) => {
+                assert!(message.contains(&stranger.to_hex()), "{}", message);
+            },
+            _ => panic!("expected the event key to be rejected"),
+        }
+
+        // Non-transaction sessions, or sessions with the check turned off, are not validated.
+        assert!(convert_events(change_set, &[stranger], None).is_ok());
+    }
+
+    #[test]
+    fn event_keys_may_belong_to_accounts_with_deferred_group_writes() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let sender = AccountAddress::random();
+        let object = AccountAddress::random();
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(object, object_core_tag(), MoveStorageOp::New(vec![0]))
+            .unwrap();
+
+        for defer_group_reads in [false, true] {
+            let resolver = StorageAdapter::new(&state_view);
+            let split = TestSession::split_and_merge_resource_groups(
+                &resolver,
+                change_set.clone(),
+                &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+                &DefaultStateKeyCodec,
+                &AllowAllWrites,
+                &mut NoGroupGas,
+                false,
+                defer_group_reads,
+            )
+            .unwrap();
+            // Deferred, the write to the group of the object is only in its patch.
+            assert_eq!(split.group_patches.is_empty(), !defer_group_reads);
+
+            let owners = TestSession::event_key_owners(sender, &split.group_patches);
+            assert!(convert_events_with_groups(
+                split.change_set,
+                split.resource_group_change_set,
+                &[object],
+                Some(owners),
+            )
+            .is_ok());
+        }
+    }
+
+    #[test]
+    fn reserved_system_addresses() {
+        assert!(is_reserved_system_address(&AccountAddress::ZERO));
+        assert!(is_reserved_system_address(&AccountAddress::ONE));
+        assert!(is_reserved_system_address(
+            &AccountAddress::from_hex_literal("0xa").unwrap()
+        ));
+        assert!(!is_reserved_system_address(
+            &AccountAddress::from_hex_literal("0xb").unwrap()
+        ));
+        assert!(!is_reserved_system_address(
+            &AccountAddress::from_hex_literal("0x100").unwrap()
+        ));
+    }
+
+    #[test]
+    fn removing_last_group_member_deletes_the_group() {
+        // The group held a single member, which the transaction removed.
+        let emptied_group = ResourceGroupMap::new();
+        let configs =
+            ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION);
+
+        let op = TestSession::resource_group_op(&emptied_group, false, &configs).unwrap();
+        assert_eq!(op, MoveStorageOp::Delete);
+    }
+
+    /// All orders of `items`.
+    fn permutations<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+        if items.is_empty() {
+            return vec![vec![]];
+        }
+        (0..items.len())
+            .flat_map(|i| {
+                let mut rest = items.to_vec();
+                let first = rest.remove(i);
+                permutations(&rest).into_iter().map(move |mut order| {
+                    order.insert(0, first.clone());
+                    order
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn group_member_ops_apply_in_any_order() {
+        let configs =
+            ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION);
+        let ops = [
+            None,
+            Some(MoveStorageOp::New(vec![1])),
+            Some(MoveStorageOp::Modify(vec![2])),
+            Some(MoveStorageOp::Delete),
+        ];
+
+        for num_members in 1..=3u32 {
+            let members: Vec<_> = (0..num_members)
+                .map(|i| struct_tag("m", &format!("R{}", i)))
+                .collect();
+            // Every member is in the group or not, and gets any of the ops or none.
+            for existing in 0..(1usize << num_members) {
+                for choice in 0..ops.len().pow(num_members) {
+                    let mut base = ResourceGroupMap::new();
+                    let mut member_ops = BTreeMap::new();
+                    for (i, member) in members.iter().enumerate() {
+                        if existing & (1 << i) != 0 {
+                            base.insert_member(member.clone(), vec![0]);
+                        }
+                        if let Some(op) = &ops[choice / ops.len().pow(i as u32) % ops.len()] {
+                            member_ops.insert(member.clone(), op.clone());
+                        }
+                    }
+                    let writes_member = member_ops
+                        .values()
+                        .any(|op| !matches!(op, MoveStorageOp::Delete));
+
+                    let mut merged = base.clone();
+                    let expected = merged
+                        .apply_member_ops(member_ops.clone())
+                        .is_ok()
+                        .then_some(merged);
+                    for order in permutations(&member_ops.into_iter().collect::<Vec<_>>()) {
+                        let mut group = base.clone();
+                        let consistent = order
+                            .into_iter()
+                            .all(|(struct_tag, op)| group.apply_member_op(struct_tag, op));
+                        assert_eq!(consistent.then_some(group), expected);
+                    }
+
+                    if let Some(merged) = expected {
+                        let op = TestSession::resource_group_op(&merged, existing == 0, &configs)
+                            .unwrap();
+                        assert_eq!(op == MoveStorageOp::Delete, merged.is_empty());
+                        if writes_member {
+                            assert_ne!(op, MoveStorageOp::Delete);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn creations_are_only_written_as_modifications_for_legacy_versions() {
+        for (gas_feature_version, legacy) in [
+            (0, true),
+            (2, true),
+            (3, false),
+            (LATEST_GAS_FEATURE_VERSION, false),
+        ] {
+            let configs = ChangeSetConfigs::unlimited_at_gas_feature_version(gas_feature_version);
+            assert_eq!(configs.legacy_resource_creation_as_modification(), legacy);
+        }
+    }
+
+    #[test]
+    fn resource_groups_are_not_read_again() {
+        let group = struct_tag("object", "ObjectGroup");
+        let present = AccountAddress::random();
+        let absent = AccountAddress::random();
+        let group_key = |addr| {
+            StateKey::access_path(AccessPath::resource_group_access_path(addr, group.clone()))
+        };
+
+        let mut state_view = CountingStateView::default();
+        state_view.data.insert(
+            group_key(present),
+            StateValue::new_legacy(resource_group(1, 8).to_bytes().unwrap()),
+        );
+        let storage = StorageAdapter::new(&state_view);
+        let vm = MoveVM::new(vec![]).unwrap();
+        let resolver = MoveResolverWithVMMetadata::new(&storage, &vm);
+
+        for _ in 0..3 {
+            assert!(resolver
+                .get_resource_group_data(&present, &group)
+                .unwrap()
+                .is_some());
+            assert!(resolver
+                .get_resource_group_data(&absent, &group)
+                .unwrap()
+                .is_none());
+        }
+        assert_eq!(state_view.reads_of(&group_key(present)), 1);
+        assert_eq!(state_view.reads_of(&group_key(absent)), 1);
+    }
+
+    #[test]
+    fn reads_beyond_finish_budget_fail() {
+        let group = struct_tag("object", "ObjectGroup");
+        let addr = AccountAddress::random();
+        let state_view = CountingStateView::default();
+        let storage = StorageAdapter::new(&state_view);
+        let vm = MoveVM::new(vec![]).unwrap();
+        let resolver = MoveResolverWithVMMetadata::new(&storage, &vm);
+
+        // Reads before a budget is set are not charged to it.
+        resolver.get_resource_group_data(&addr, &group).unwrap();
+        resolver.set_finish_budget(Some(FinishBudget {
+            max_reads: 1,
+            max_bytes: u64::MAX,
+        }));
+        // Nor are reads served from the cache.
+        resolver.get_resource_group_data(&addr, &group).unwrap();
+        resolver
+            .get_standard_resource(&addr, &struct_tag("account", "Account"))
+            .unwrap();
+        let err = resolver
+            .get_standard_resource(&addr, &struct_tag("coin", "CoinStore"))
+            .unwrap_err();
+        assert_eq!(err.major_status(), StatusCode::FINISH_BUDGET_EXCEEDED);
+
+        let err = resolver.blame_finish_budget(
+            PartialVMError::new(StatusCode::STORAGE_ERROR).finish(Location::Undefined),
+        );
+        assert_eq!(err.major_status(), StatusCode::FINISH_BUDGET_EXCEEDED);
+    }
+
+    /// Publishes a stand-in `0x1::object` module whose metadata makes `ObjectCore` and `Tag`
+    /// members of `ObjectGroup`.
+    fn publish_object_module(state_view: &mut CountingStateView) {
+        let member = KnownAttribute::resource_group_member("0x1::object::ObjectGroup".to_string());
+        let metadata = RuntimeModuleMetadataV1 {
+            error_map: BTreeMap::new(),
+            struct_attributes: BTreeMap::from([
+                ("ObjectCore".to_string(), vec![member.clone()]),
+                ("Tag".to_string(), vec![member]),
+            ]),
+            fun_attributes: BTreeMap::new(),
+        };
+
+        let mut module = empty_module();
+        module.identifiers = vec![Identifier::new("object").unwrap()];
+        module.address_identifiers = vec![AccountAddress::ONE];
+        module.metadata.push(Metadata {
+            key: APTOS_METADATA_KEY_V1.clone(),
+            value: bcs::to_bytes(&metadata).unwrap(),
+        });
+        let mut bytes = vec![];
+        module.serialize(&mut bytes).unwrap();
+        state_view.data.insert(
+            StateKey::access_path(AccessPath::from(&module.self_id())),
+            StateValue::new_legacy(bytes),
+        );
+    }
+
+    fn split_tag_writes(
+        state_view: &CountingStateView,
+        change_set: MoveChangeSet,
+        validate_containers: bool,
+    ) -> VMResult<MoveChangeSet> {
+        split_tag_writes_with(
+            state_view,
+            change_set,
+            validate_containers,
+            &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+        )
+        .map(|(groups, _)| groups)
+    }
+
+    fn split_tag_writes_with(
+        state_view: &CountingStateView,
+        change_set: MoveChangeSet,
+        validate_containers: bool,
+        configs: &ChangeSetConfigs,
+    ) -> VMResult<(MoveChangeSet, NumBytes)> {
+        let resolver = StorageAdapter::new(state_view);
+        let mut gas_hook = CountingGasHook::with_budget(u64::MAX);
+        TestSession::split_and_merge_resource_groups(
+            &resolver,
+            change_set,
+            configs,
+            &DefaultStateKeyCodec,
+            &AllowAllWrites,
+            &mut gas_hook,
+            validate_containers,
+            false,
+        )
+        .map(|split| {
+            (
+                split.resource_group_change_set,
+                NumBytes::new(gas_hook.tag_bytes),
+            )
+        })
+    }
+
+    fn convert_with_group_reads(
+        state_view: &CountingStateView,
+        change_set: MoveChangeSet,
+        defer_group_reads: bool,
+    ) -> ChangeSetExt {
+        let resolver = StorageAdapter::new(state_view);
+        let configs = Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+            LATEST_GAS_FEATURE_VERSION,
+        ));
+        let split = TestSession::split_and_merge_resource_groups(
+            &resolver,
+            change_set,
+            &configs,
+            &DefaultStateKeyCodec,
+            &AllowAllWrites,
+            &mut NoGroupGas,
+            false,
+            defer_group_reads,
+        )
+        .unwrap();
+        TestSession::convert_change_set(
+            split.change_set,
+            split.resource_group_change_set,
+            vec![],
+            vec![],
+            TableChangeSet::default(),
+            AggregatorChangeSet {
+                changes: BTreeMap::new(),
+            },
+            &mut (),
+            &DefaultStateKeyCodec,
+            &configs,
+            &AllowAllWrites,
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+        .with_group_classifications(split.group_classifications)
+        .with_group_size_deltas(split.group_size_deltas)
+        .with_group_patches(split.group_patches)
+    }
+
+    #[test]
+    fn deferred_group_reads_produce_the_same_write_set() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let group_tag = ObjectGroupResource::struct_tag();
+        let group_key = |addr| {
+            StateKey::access_path(AccessPath::resource_group_access_path(
+                addr,
+                group_tag.clone(),
+            ))
+        };
+        let group_blob = |members: Vec<(StructTag, Vec<u8>)>| {
+            StateValue::new_legacy(
+                bcs::to_bytes(&members.into_iter().collect::<BTreeMap<_, _>>()).unwrap(),
+            )
+        };
+        let modified = AccountAddress::random();
+        let emptied = AccountAddress::random();
+        let created = AccountAddress::random();
+        state_view.data.insert(
+            group_key(modified),
+            group_blob(vec![(object_core_tag(), vec![0])]),
+        );
+        state_view.data.insert(
+            group_key(emptied),
+            group_blob(vec![(struct_tag("object", "Tag"), vec![0])]),
+        );
+
+        let change_set = || {
+            let mut change_set = MoveChangeSet::new();
+            for (addr, struct_tag, op) in [
+                (modified, object_core_tag(), MoveStorageOp::Modify(vec![1])),
+                (
+                    modified,
+                    struct_tag("object", "Tag"),
+                    MoveStorageOp::New(vec![2]),
+                ),
+                (emptied, struct_tag("object", "Tag"), MoveStorageOp::Delete),
+                (created, object_core_tag(), MoveStorageOp::New(vec![3])),
+            ] {
+                change_set.add_resource_op(addr, struct_tag, op).unwrap();
+            }
+            change_set
+        };
+
+        let deferred = convert_with_group_reads(&state_view, change_set(), true);
+        assert_eq!(deferred.group_patches().len(), 3);
+        assert!(deferred.has_resource_group_writes());
+        for addr in [modified, emptied, created] {
+            assert_eq!(state_view.reads_of(&group_key(addr)), 0);
+        }
+        let deferred = deferred.materialize_group_patches(&state_view).unwrap();
+        assert!(deferred.group_patches().is_empty());
+
+        let legacy = convert_with_group_reads(&state_view, change_set(), false);
+        assert!(legacy.group_patches().is_empty());
+        assert_eq!(deferred.write_set(), legacy.write_set());
+        assert_eq!(
+            deferred.serialized_size_estimate(),
+            legacy.serialized_size_estimate()
+        );
+        assert_eq!(
+            deferred.write_set().get(&group_key(emptied)),
+            Some(&WriteOp::Deletion)
+        );
+    }
+
+    /// Denies writes to one resource, or resource group, anywhere.
+    struct DenyResourceWrites(StructTag);
+
+    impl WritePolicy for DenyResourceWrites {
+        fn check_resource_write(&self, _addr: &AccountAddress, tag: &StructTag) -> WriteDecision {
+            if *tag == self.0 {
+                WriteDecision::Deny
+            } else {
+                WriteDecision::Allow
+            }
+        }
+    }
+
+    #[test]
+    fn write_policy_applies_to_group_members() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let resolver = StorageAdapter::new(&state_view);
+        let configs =
+            ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION);
+        let addr = AccountAddress::random();
+        let split = |write_policy: &dyn WritePolicy, defer_group_reads: bool| {
+            let mut change_set = MoveChangeSet::new();
+            change_set
+                .add_resource_op(addr, object_core_tag(), MoveStorageOp::New(vec![0]))
+                .unwrap();
+            TestSession::split_and_merge_resource_groups(
+                &resolver,
+                change_set,
+                &configs,
+                &DefaultStateKeyCodec,
+                write_policy,
+                &mut NoGroupGas,
+                false,
+                defer_group_reads,
+            )
+            .map(|_| ())
+        };
+
+        for defer_group_reads in [false, true] {
+            // Denying the member fails the session whether its group is merged or deferred...
+            let err = split(&DenyResourceWrites(object_core_tag()), defer_group_reads).unwrap_err();
+            assert_eq!(err.major_status(), StatusCode::RESOURCE_WRITE_DENIED);
+            // ...while the other members may still be written.
+            assert!(split(
+                &DenyResourceWrites(struct_tag("object", "Tag")),
+                defer_group_reads
+            )
+            .is_ok());
+        }
+
+        // The group of a deferred patch is checked too.
+        let err = split(&DenyResourceWrites(ObjectGroupResource::struct_tag()), true).unwrap_err();
+        assert_eq!(err.major_status(), StatusCode::RESOURCE_WRITE_DENIED);
+    }
+
+    #[test]
+    fn resources_written_both_standalone_and_in_a_group_are_rejected() {
+        let addr = AccountAddress::random();
+        let change_set = || {
+            let mut change_set = MoveChangeSet::new();
+            change_set
+                .add_resource_op(addr, object_core_tag(), MoveStorageOp::New(vec![0]))
+                .unwrap();
+            change_set
+        };
+
+        // Before the upgrade publishing its metadata, `ObjectCore` is a standalone resource.
+        let stale = CountingStateView::default();
+        let standalone = TestSession::split_and_merge_resource_groups(
+            &StorageAdapter::new(&stale),
+            change_set(),
+            &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+            &DefaultStateKeyCodec,
+            &AllowAllWrites,
+            &mut NoGroupGas,
+            false,
+            false,
+        )
+        .unwrap()
+        .change_set;
+        assert_eq!(standalone.resources().count(), 1);
+
+        // After it, the same write goes to the `ObjectGroup`.
+        let mut upgraded = CountingStateView::default();
+        publish_object_module(&mut upgraded);
+        let classifications = convert_with_group_reads(&upgraded, change_set(), false)
+            .group_classifications()
+            .clone();
+        let group_tag = classifications[&object_core_tag()].clone().unwrap();
+        let member_tags = |addr| {
+            BTreeMap::from([(
+                (addr, group_tag.clone()),
+                BTreeSet::from([object_core_tag()]),
+            )])
+        };
+
+        let err =
+            TestSession::check_group_member_conflicts(&standalone, &member_tags(addr)).unwrap_err();
+        assert_eq!(
+            err.major_status(),
+            StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR
+        );
+        assert!(err.message().unwrap().contains("0x1::object::ObjectCore"));
+        // The same resource at different addresses does not conflict.
+        assert!(TestSession::check_group_member_conflicts(
+            &standalone,
+            &member_tags(AccountAddress::random())
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn group_classifications_cover_every_resource_op() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let group_tag = ObjectGroupResource::struct_tag();
+        let (first, second) = (AccountAddress::random(), AccountAddress::random());
+        let mut change_set = MoveChangeSet::new();
+        for (addr, struct_tag, op) in [
+            (first, object_core_tag(), MoveStorageOp::New(vec![0])),
+            (
+                first,
+                struct_tag("object", "Tag"),
+                MoveStorageOp::New(vec![1]),
+            ),
+            (
+                first,
+                struct_tag("coin", "CoinStore"),
+                MoveStorageOp::New(vec![2]),
+            ),
+            (second, object_core_tag(), MoveStorageOp::New(vec![3])),
+            (
+                second,
+                struct_tag("account", "Account"),
+                MoveStorageOp::Delete,
+            ),
+        ] {
+            change_set.add_resource_op(addr, struct_tag, op).unwrap();
+        }
+        let resource_ops: BTreeSet<_> = change_set
+            .accounts()
+            .values()
+            .flat_map(|account| account.resources().keys().cloned())
+            .collect();
+
+        for defer_group_reads in [false, true] {
+            let change_set_ext =
+                convert_with_group_reads(&state_view, change_set.clone(), defer_group_reads);
+            let classifications = change_set_ext.group_classifications();
+            assert_eq!(
+                classifications.keys().cloned().collect::<BTreeSet<_>>(),
+                resource_ops
+            );
+            assert_eq!(classifications[&object_core_tag()], Some(group_tag.clone()));
+            assert_eq!(
+                classifications[&struct_tag("object", "Tag")],
+                Some(group_tag.clone())
+            );
+            assert_eq!(classifications[&struct_tag("coin", "CoinStore")], None);
+            assert_eq!(classifications[&struct_tag("account", "Account")], None);
+        }
+    }
+
+    #[test]
+    fn decoding_existing_groups_is_counted_and_charged() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let group_tag = ObjectGroupResource::struct_tag();
+        let (whale, fresh) = (AccountAddress::random(), AccountAddress::random());
+        let whale_key =
+            StateKey::access_path(AccessPath::resource_group_access_path(whale, group_tag));
+        let whale_blob =
+            bcs::to_bytes(&BTreeMap::from([(object_core_tag(), vec![0u8; 1 << 20])])).unwrap();
+        state_view.data.insert(
+            whale_key.clone(),
+            StateValue::new_legacy(whale_blob.clone()),
+        );
+
+        let change_set = |addr| {
+            let mut change_set = MoveChangeSet::new();
+            change_set
+                .add_resource_op(
+                    addr,
+                    struct_tag("object", "Tag"),
+                    MoveStorageOp::New(vec![1]),
+                )
+                .unwrap();
+            change_set
+        };
+        let bytes_deserialized = |addr, defer_group_reads| {
+            TestSession::split_and_merge_resource_groups(
+                &StorageAdapter::new(&state_view),
+                change_set(addr),
+                &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+                &DefaultStateKeyCodec,
+                &AllowAllWrites,
+                &mut NoGroupGas,
+                false,
+                defer_group_reads,
+            )
+            .unwrap()
+            .group_bytes_deserialized
+        };
+        let whale_bytes = NumBytes::new(whale_blob.len() as u64);
+        let touched = bytes_deserialized(whale, false);
+        assert_eq!(touched, whale_bytes);
+        // A group that does not exist yet has nothing to decode.
+        assert_eq!(bytes_deserialized(fresh, false), NumBytes::zero());
+        // Deferred groups are decoded by the executor instead.
+        assert_eq!(bytes_deserialized(whale, true), NumBytes::zero());
+
+        let gas_params = AptosGasParameters::initial();
+        let gas_meter = |balance: u64| {
+            StandardGasMeter::new(
+                LATEST_GAS_FEATURE_VERSION,
+                gas_params.clone(),
+                StorageGasParameters::new(LATEST_GAS_FEATURE_VERSION, Some(&gas_params), None)
+                    .unwrap(),
+                balance,
+            )
+        };
+        let mut funded = gas_meter(1_000);
+        let before = funded.balance_internal();
+        funded.charge_group_deserialization(touched).unwrap();
+        assert_eq!(
+            before - funded.balance_internal(),
+            gas_params.txn.per_byte_group_deserialize * whale_bytes
+        );
+        assert_eq!(
+            gas_meter(1)
+                .charge_group_deserialization(touched)
+                .unwrap_err()
+                .major_status(),
+            StatusCode::OUT_OF_GAS
+        );
+    }
+
+    /// Charges bytes against a budget, counting what it was charged for.
+    struct CountingGasHook {
+        budget: u64,
+        tag_bytes: u64,
+        group_bytes: u64,
+    }
+
+    impl CountingGasHook {
+        fn with_budget(budget: u64) -> Self {
+            Self {
+                budget,
+                tag_bytes: 0,
+                group_bytes: 0,
+            }
+        }
+
+        fn charge(&mut self, bytes: NumBytes) -> VMResult<()> {
+            self.budget = self.budget.checked_sub(u64::from(bytes)).ok_or_else(|| {
+                PartialVMError::new(StatusCode::OUT_OF_GAS).finish(Location::Undefined)
+            })?;
+            Ok(())
+        }
+    }
+
+    impl ResourceGroupGasHook for CountingGasHook {
+        fn charge_member_tag(&mut self, tag_bytes: NumBytes) -> VMResult<()> {
+            self.charge(tag_bytes)?;
+            self.tag_bytes += u64::from(tag_bytes);
+            Ok(())
+        }
+
+        fn charge_group_decoding(&mut self, group_bytes: NumBytes) -> VMResult<()> {
+            self.charge(group_bytes)?;
+            self.group_bytes += u64::from(group_bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn merging_groups_is_charged_before_decoding() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let group_key = |addr| {
+            StateKey::access_path(AccessPath::resource_group_access_path(
+                addr,
+                ObjectGroupResource::struct_tag(),
+            ))
+        };
+        let (whale, garbage) = (AccountAddress::random(), AccountAddress::random());
+        let whale_blob =
+            bcs::to_bytes(&BTreeMap::from([(object_core_tag(), vec![0u8; 1 << 20])])).unwrap();
+        state_view
+            .data
+            .insert(group_key(whale), StateValue::new_legacy(whale_blob.clone()));
+        // Not a group at all, decoding it fails.
+        state_view.data.insert(
+            group_key(garbage),
+            StateValue::new_legacy(vec![0xFF; 1 << 20]),
+        );
+
+        let resolver = StorageAdapter::new(&state_view);
+        let configs =
+            ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION);
+        let split = |addr, gas_hook: &mut CountingGasHook| {
+            let mut change_set = MoveChangeSet::new();
+            change_set
+                .add_resource_op(
+                    addr,
+                    struct_tag("object", "Tag"),
+                    MoveStorageOp::New(vec![1]),
+                )
+                .unwrap();
+            TestSession::split_and_merge_resource_groups(
+                &resolver,
+                change_set,
+                &configs,
+                &DefaultStateKeyCodec,
+                &AllowAllWrites,
+                gas_hook,
+                false,
+                false,
+            )
+        };
+
+        let mut funded = CountingGasHook::with_budget(u64::MAX);
+        let split_change_set = split(whale, &mut funded).unwrap();
+        assert_eq!(funded.group_bytes, whale_blob.len() as u64);
+        assert!(funded.tag_bytes > 0);
+        assert_eq!(
+            split_change_set.group_bytes_deserialized,
+            NumBytes::new(funded.group_bytes)
+        );
+
+        // Paying for the member but not for the group fails before the group is decoded...
+        let mut short = CountingGasHook::with_budget(funded.tag_bytes);
+        let err = split(garbage, &mut short).map(|_| ()).unwrap_err();
+        assert_eq!(err.major_status(), StatusCode::OUT_OF_GAS);
+        assert_eq!(short.group_bytes, 0);
+        // ...while paying for it gets the group decoded.
+        let err = split(garbage, &mut CountingGasHook::with_budget(u64::MAX))
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            err.major_status(),
+            StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR
+        );
+    }
+
+    #[test]
+    fn group_size_deltas_count_growth_of_merged_groups() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let group_tag = ObjectGroupResource::struct_tag();
+        let (existing, fresh) = (AccountAddress::random(), AccountAddress::random());
+        let group_key = |addr| {
+            StateKey::access_path(AccessPath::resource_group_access_path(
+                addr,
+                group_tag.clone(),
+            ))
+        };
+        let old_blob =
+            bcs::to_bytes(&BTreeMap::from([(object_core_tag(), vec![0u8; 10])])).unwrap();
+        state_view.data.insert(
+            group_key(existing),
+            StateValue::new_legacy(old_blob.clone()),
+        );
+        let old_len = old_blob.len() as i64;
+
+        let convert = |addr, ops: Vec<(StructTag, MoveStorageOp<Vec<u8>>)>| {
+            let mut change_set = MoveChangeSet::new();
+            for (struct_tag, op) in ops {
+                change_set.add_resource_op(addr, struct_tag, op).unwrap();
+            }
+            convert_with_group_reads(&state_view, change_set, false)
+        };
+        let blob_len = |members: Vec<(StructTag, Vec<u8>)>| {
+            bcs::to_bytes(&members.into_iter().collect::<BTreeMap<_, _>>())
+                .unwrap()
+                .len() as i64
+        };
+
+        let grown = convert(existing, vec![(
+            struct_tag("object", "Tag"),
+            MoveStorageOp::New(vec![1; 5]),
+        )]);
+        let grown_len = blob_len(vec![
+            (object_core_tag(), vec![0; 10]),
+            (struct_tag("object", "Tag"), vec![1; 5]),
+        ]);
+        assert_eq!(grown.group_size_deltas(), &[(
+            group_key(existing),
+            grown_len - old_len
+        )]);
+
+        let shrunk = convert(existing, vec![(
+            object_core_tag(),
+            MoveStorageOp::Modify(vec![0; 2]),
+        )]);
+        assert_eq!(shrunk.group_size_deltas(), &[(group_key(existing), -8)]);
+
+        let replaced = convert(existing, vec![(
+            object_core_tag(),
+            MoveStorageOp::Modify(vec![1; 10]),
+        )]);
+        assert_eq!(replaced.group_size_deltas(), &[(group_key(existing), 0)]);
+
+        // Creating a group counts its full size, deleting one credits the size it had.
+        let created = convert(fresh, vec![(
+            struct_tag("object", "Tag"),
+            MoveStorageOp::New(vec![1]),
+        )]);
+        assert_eq!(created.group_size_deltas(), &[(
+            group_key(fresh),
+            blob_len(vec![(struct_tag("object", "Tag"), vec![1])])
+        )]);
+        let deleted = convert(existing, vec![(object_core_tag(), MoveStorageOp::Delete)]);
+        assert_eq!(deleted.group_size_deltas(), &[(
+            group_key(existing),
+            -old_len
+        )]);
+
+        // Deferred groups are only counted once merged.
+        let mut deferred_change_set = MoveChangeSet::new();
+        deferred_change_set
+            .add_resource_op(
+                existing,
+                object_core_tag(),
+                MoveStorageOp::Modify(vec![0; 2]),
+            )
+            .unwrap();
+        let deferred = convert_with_group_reads(&state_view, deferred_change_set, true);
+        assert!(deferred.group_size_deltas().is_empty());
+        let materialized = deferred.materialize_group_patches(&state_view).unwrap();
+        assert_eq!(materialized.group_size_deltas(), &[(
+            group_key(existing),
+            -8
+        )]);
+
+        // Squashing adds up the deltas of the same group, e.g. of a session shrinking the group
+        // another one grew before it.
+        let mut after_growth = CountingStateView::default();
+        publish_object_module(&mut after_growth);
+        after_growth.data.insert(
+            group_key(existing),
+            StateValue::new_legacy(
+                grown
+                    .write_set()
+                    .get(&group_key(existing))
+                    .unwrap()
+                    .bytes()
+                    .unwrap()
+                    .to_vec(),
+            ),
+        );
+        let mut shrink_change_set = MoveChangeSet::new();
+        shrink_change_set
+            .add_resource_op(
+                existing,
+                object_core_tag(),
+                MoveStorageOp::Modify(vec![0; 2]),
+            )
+            .unwrap();
+        let shrunk_after_growth = convert_with_group_reads(&after_growth, shrink_change_set, false);
+        let squashed = grown.squash(shrunk_after_growth).unwrap();
+        let final_len = blob_len(vec![
+            (object_core_tag(), vec![0; 2]),
+            (struct_tag("object", "Tag"), vec![1; 5]),
+        ]);
+        assert_eq!(squashed.group_size_deltas(), &[(
+            group_key(existing),
+            final_len - old_len
+        )]);
+    }
+
+    #[test]
+    fn unavailable_group_reads_are_not_invariant_violations() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let addr = AccountAddress::random();
+        let group_key = StateKey::access_path(AccessPath::resource_group_access_path(
+            addr,
+            ObjectGroupResource::struct_tag(),
+        ));
+        state_view
+            .unavailable_once
+            .borrow_mut()
+            .insert(group_key.clone());
+
+        let resolver = StorageAdapter::new(&state_view);
+        let configs =
+            ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION);
+        let split = || {
+            let mut change_set = MoveChangeSet::new();
+            change_set
+                .add_resource_op(
+                    addr,
+                    struct_tag("object", "Tag"),
+                    MoveStorageOp::New(vec![1]),
+                )
+                .unwrap();
+            TestSession::split_and_merge_resource_groups(
+                &resolver,
+                change_set,
+                &configs,
+                &DefaultStateKeyCodec,
+                &AllowAllWrites,
+                &mut NoGroupGas,
+                false,
+                false,
+            )
+        };
+
+        // The failed read is not mistaken for an invariant violation...
+        let err = split().map(|_| ()).unwrap_err();
+        assert_eq!(err.major_status(), StatusCode::STORAGE_UNAVAILABLE);
+        assert!(err.major_status().is_retriable());
+        // ...and executing again reads storage again.
+        assert!(split().is_ok());
+        assert_eq!(state_view.reads_of(&group_key), 2);
+    }
+
+    #[test]
+    fn writes_to_groups_with_pending_patches_cannot_be_squashed() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let addr = AccountAddress::random();
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_resource_op(addr, object_core_tag(), MoveStorageOp::New(vec![0]))
+            .unwrap();
+        let deferred = convert_with_group_reads(&state_view, change_set, true);
+
+        let group_key = StateKey::access_path(AccessPath::resource_group_access_path(
+            addr,
+            ObjectGroupResource::struct_tag(),
+        ));
+        let write_set = WriteSetMut::new(vec![(group_key, WriteOp::Creation(vec![0]))])
+            .freeze()
+            .unwrap();
+        let other = ChangeSet::new(write_set, vec![], &NoOpChangeSetChecker).unwrap();
+        assert!(deferred.squash_change_set(other).is_err());
+    }
+
+    #[test]
+    fn resource_group_creation_requires_a_container() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let account = AccountAddress::random();
+        state_view.data.insert(
+            StateKey::access_path(
+                AccessPath::resource_access_path(account, AccountResource::struct_tag()).unwrap(),
+            ),
+            StateValue::new_legacy(vec![0]),
+        );
+        let tag_write = |addr| {
+            let mut change_set = MoveChangeSet::new();
+            change_set
+                .add_resource_op(
+                    addr,
+                    struct_tag("object", "Tag"),
+                    MoveStorageOp::New(vec![1]),
+                )
+                .unwrap();
+            change_set
+        };
+
+        // An object created by the same change set provides the container.
+        let object = AccountAddress::random();
+        let mut change_set = tag_write(object);
+        change_set
+            .add_resource_op(object, object_core_tag(), MoveStorageOp::New(vec![0]))
+            .unwrap();
+        let groups = split_tag_writes(&state_view, change_set, true).unwrap();
+        assert_eq!(groups.resources().count(), 1);
+
+        // So does an existing account.
+        assert!(split_tag_writes(&state_view, tag_write(account), true).is_ok());
+
+        // A bare address only passes with validation turned off.
+        let bare = AccountAddress::random();
+        let err = split_tag_writes(&state_view, tag_write(bare), true).unwrap_err();
+        assert_eq!(
+            err.major_status(),
+            StatusCode::RESOURCE_GROUP_ADDRESS_NOT_INITIALIZED
+        );
+        assert!(split_tag_writes(&state_view, tag_write(bare), false).is_ok());
+    }
+
+    #[test]
+    fn empty_account_change_sets_are_pruned_before_reading() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+        let mut change_set = MoveChangeSet::new();
+        change_set
+            .add_account_changeset(AccountAddress::random(), AccountChangeSet::new())
+            .unwrap();
+
+        let groups = split_tag_writes(&state_view, change_set, true).unwrap();
+        assert!(groups.accounts().is_empty());
+        assert_eq!(state_view.total_reads(), 0);
+    }
+
+    #[test]
+    fn deeply_nested_group_member_tags_are_rejected() {
+        let mut state_view = CountingStateView::default();
+        publish_object_module(&mut state_view);
+
+        // 0x1::object::Tag<Wrapper<...<Wrapper<u64>>...>>, ten levels deep.
+        let mut type_arg = TypeTag::U64;
+        for _ in 0..9 {
+            let mut wrapper = struct_tag("wrapper", "Wrapper");
+            wrapper.type_params = vec![type_arg];
+            type_arg = TypeTag::Struct(Box::new(wrapper));
+        }
+        let mut member = struct_tag("object", "Tag");
+        member.type_params = vec![type_arg];
+        let member_bytes = bcs::serialized_size(&member).unwrap() as u64;
+        let change_set = || {
+            let mut change_set = MoveChangeSet::new();
+            change_set
+                .add_resource_op(
+                    AccountAddress::random(),
+                    member.clone(),
+                    MoveStorageOp::New(vec![1]),
+                )
+                .unwrap();
+            change_set
+        };
+        let split = |max_depth, max_bytes| {
+            split_tag_writes_with(
+                &state_view,
+                change_set(),
+                false,
+                &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION)
+                    .with_group_member_tag_limits(max_depth, max_bytes),
+            )
+        };
+
+        let (groups, tag_bytes) = split(10, member_bytes).unwrap();
+        assert_eq!(groups.resources().count(), 1);
+        assert_eq!(tag_bytes, NumBytes::new(member_bytes));
+
+        for err in [
+            split(9, member_bytes).unwrap_err(),
+            split(10, member_bytes - 1).unwrap_err(),
+        ] {
+            assert_eq!(
+                err.major_status(),
+                StatusCode::RESOURCE_GROUP_MEMBER_TAG_TOO_COMPLEX
+            );
+        }
+    }
+
+    /// Test-only native emitting an event the way `SafeNativeContext::emit_event` does.
+    fn native_emit_test_event(
+        context: &mut NativeContext,
+        _ty_args: Vec<Type>,
+        _args: VecDeque<Value>,
+    ) -> PartialVMResult<NativeResult> {
+        context
+            .extensions_mut()
+            .get_mut::<NativeEventContext>()
+            .emit(TypeTag::U64, bcs::to_bytes(&42u64).unwrap());
+        Ok(NativeResult::ok(InternalGas::zero(), smallvec![]))
+    }
+
+    #[test]
+    fn events_emitted_by_natives_end_up_in_the_change_set() {
+        let module_name = Identifier::new("native_events").unwrap();
+        let native_name = Identifier::new("emit").unwrap();
+        let function_name = Identifier::new("run").unwrap();
+
+        // module 0x1::native_events {
+        //     native fun emit();
+        //     public fun run() { emit() }
+        // }
+        let mut module = empty_module();
+        module.identifiers = vec![
+            module_name.clone(),
+            native_name.clone(),
+            function_name.clone(),
+        ];
+        module.address_identifiers = vec![AccountAddress::ONE];
+        for name in [IdentifierIndex(1), IdentifierIndex(2)] {
+            module.function_handles.push(FunctionHandle {
+                module: ModuleHandleIndex(0),
+                name,
+                parameters: SignatureIndex(0),
+                return_: SignatureIndex(0),
+                type_parameters: vec![],
+            });
+        }
+        module.function_defs = vec![
+            FunctionDefinition {
+                function: FunctionHandleIndex(0),
+                visibility: Visibility::Private,
+                is_entry: false,
+                acquires_global_resources: vec![],
+                code: None,
+            },
+            FunctionDefinition {
+                function: FunctionHandleIndex(1),
+                visibility: Visibility::Public,
+                is_entry: false,
+                acquires_global_resources: vec![],
+                code: Some(CodeUnit {
+                    locals: SignatureIndex(0),
+                    code: vec![Bytecode::Call(FunctionHandleIndex(0)), Bytecode::Ret],
+                }),
+            },
+        ];
+        let module_id = module.self_id();
+        let mut bytes = vec![];
+        module.serialize(&mut bytes).unwrap();
+
+        let mut state_view = CountingStateView::default();
+        state_view.data.insert(
+            StateKey::access_path(AccessPath::from(&module_id)),
+            StateValue::new_legacy(bytes),
+        );
+        let resolver = StorageAdapter::new(&state_view);
+        let native: NativeFunction = Arc::new(native_emit_test_event);
+        let vm = MoveVM::new(vec![(
+            AccountAddress::ONE,
+            module_name,
+            native_name,
+            native,
+        )])
+        .unwrap();
+
+        let session_id = SessionId::Txn {
+            sender: AccountAddress::random(),
+            sequence_number: 0,
+            script_hash: vec![],
+        };
+        let configs = Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+            LATEST_GAS_FEATURE_VERSION,
+        ));
+        let run = |session_id: &SessionId, previous: Option<&ChangeSetExt>| {
+            let mut extensions = NativeContextExtensions::default();
+            extensions.add(NativeTableContext::new([0; 32], &resolver));
+            extensions.add(NativeAggregatorContext::new([0; 32], &resolver));
+            extensions.add(NativeEventContext::new());
+            let mut session = SessionExt::new(
+                vm.new_session_with_extensions(&resolver, extensions),
+                &vm,
+                &resolver,
+                session_id,
+            );
+            if let Some(previous) = previous {
+                session.continue_native_events(previous);
+            }
+            for _ in 0..2 {
+                session
+                    .execute_function_bypass_visibility(
+                        &module_id,
+                        &function_name,
+                        vec![],
+                        Vec::<Vec<u8>>::new(),
+                        &mut UnmeteredGasMeter,
+                    )
+                    .unwrap();
+            }
+            session.finish(&mut (), &configs).unwrap()
+        };
+
+        // A later session of the same transaction, e.g. the epilogue, numbers its events after
+        // the ones it is squashed with.
+        let first = run(&session_id, None);
+        let second = run(&session_id, Some(&first));
+        let change_set = first.squash(second).unwrap();
+        let events = change_set.change_set().events();
+        assert_eq!(events.len(), 4);
+        for (seq_num, event) in events.iter().enumerate() {
+            assert_eq!(event.key(), &native_event_key(&session_id));
+            assert_eq!(event.sequence_number(), seq_num as u64);
+            assert_eq!(event.type_tag(), &TypeTag::U64);
+            assert_eq!(event.event_data(), bcs::to_bytes(&42u64).unwrap());
+        }
+
+        // Void sessions share an id, but not the key of their events.
+        let void_events = |change_set: ChangeSetExt| change_set.change_set().events().to_vec();
+        let (first, second) = (
+            void_events(run(&SessionId::void(), None)),
+            void_events(run(&SessionId::void(), None)),
+        );
+        assert_ne!(first[0].key(), second[0].key());
+    }
+
+    fn unique_session_value(session_id: &SessionId, counter: u64) -> [u8; 32] {
+        NativeTransactionContext::new(*session_id.as_uuid(), vec![], 4)
+            .unique_session_value(counter)
+    }
+
+    #[test]
+    fn unique_session_values_are_deterministic_per_transaction() {
+        let txn = |sequence_number| SessionId::Txn {
+            sender: AccountAddress::ONE,
+            sequence_number,
+            script_hash: vec![1, 2, 3],
+        };
+
+        assert_eq!(
+            unique_session_value(&txn(7), 0),
+            unique_session_value(&txn(7), 0)
+        );
+        assert_ne!(
+            unique_session_value(&txn(7), 0),
+            unique_session_value(&txn(8), 0)
+        );
+        assert_ne!(
+            unique_session_value(&txn(7), 0),
+            unique_session_value(&txn(7), 1)
+        );
+
+        // Sessions that do not execute a transaction have ids to derive values from too.
+        for session_id in [
+            SessionId::BlockMeta {
+                id: HashValue::zero(),
+            },
+            SessionId::genesis(HashValue::zero()),
+            SessionId::void(),
+        ] {
+            assert_eq!(
+                unique_session_value(&session_id, 0),
+                unique_session_value(&session_id, 0)
+            );
+        }
+    }
+
+    /// module 0x1::harness_test {
+    ///     struct R has key { v: u64 }
+    ///     public fun publish(s: &signer) { move_to(s, R { v: 1 }) }
+    /// }
+    fn harness_test_module() -> CompiledModule {
+        let mut module = empty_module();
+        module.identifiers = vec![
+            Identifier::new("harness_test").unwrap(),
+            Identifier::new("R").unwrap(),
+            Identifier::new("v").unwrap(),
+            Identifier::new("publish").unwrap(),
+        ];
+        module.address_identifiers = vec![AccountAddress::ONE];
+        module.struct_handles = vec![StructHandle {
+            module: ModuleHandleIndex(0),
+            name: IdentifierIndex(1),
+            abilities: AbilitySet::EMPTY | Ability::Key,
+            type_parameters: vec![],
+        }];
+        module.struct_defs = vec![StructDefinition {
+            struct_handle: StructHandleIndex(0),
+            field_information: StructFieldInformation::Declared(vec![FieldDefinition {
+                name: IdentifierIndex(2),
+                signature: TypeSignature(SignatureToken::U64),
+            }]),
+        }];
+        module
+            .signatures
+            .push(Signature(vec![SignatureToken::Reference(Box::new(
+                SignatureToken::Signer,
+            ))]));
+        module.function_handles = vec![FunctionHandle {
+            module: ModuleHandleIndex(0),
+            name: IdentifierIndex(3),
+            parameters: SignatureIndex(1),
+            return_: SignatureIndex(0),
+            type_parameters: vec![],
+        }];
+        module.function_defs = vec![FunctionDefinition {
+            function: FunctionHandleIndex(0),
+            visibility: Visibility::Public,
+            is_entry: false,
+            acquires_global_resources: vec![],
+            code: Some(CodeUnit {
+                locals: SignatureIndex(0),
+                code: vec![
+                    Bytecode::MoveLoc(0),
+                    Bytecode::LdU64(1),
+                    Bytecode::Pack(StructDefinitionIndex(0)),
+                    Bytecode::MoveTo(StructDefinitionIndex(0)),
+                    Bytecode::Ret,
+                ],
+            }),
+        }];
+        module
+    }
+
+    /// A stand-in for `0x1::table`, declaring just the natives it uses:
+    ///
+    /// ```ignore
+    /// module 0x1::table {
+    ///     struct Table<phantom K, phantom V> has drop, store { handle: address }
+    ///     struct Box<V> has drop, store { val: V }
+    ///
+    ///     public fun add(handle: address, key: u64, val: u64) {
+    ///         add_box<u64, u64, Box<u64>>(&mut Table { handle }, key, Box { val })
+    ///     }
+    ///     public fun bump(handle: address, key: u64) {
+    ///         let b = borrow_box_mut<u64, u64, Box<u64>>(&mut Table { handle }, key);
+    ///         b.val = b.val + 1
+    ///     }
+    /// }
+    /// ```
+    fn table_test_module() -> CompiledModule {
+        use SignatureToken::*;
+
+        let mut module = empty_module();
+        module.identifiers = [
+            "table",
+            "Table",
+            "Box",
+            "handle",
+            "val",
+            "add_box",
+            "borrow_box_mut",
+            "add",
+            "bump",
+        ]
+        .into_iter()
+        .map(|name| Identifier::new(name).unwrap())
+        .collect();
+        module.address_identifiers = vec![AccountAddress::ONE];
+
+        let drop_store = AbilitySet::EMPTY | Ability::Drop | Ability::Store;
+        let copy_drop = AbilitySet::EMPTY | Ability::Copy | Ability::Drop;
+        let phantom = StructTypeParameter {
+            constraints: AbilitySet::EMPTY,
+            is_phantom: true,
+        };
+        module.struct_handles = vec![
+            StructHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(1),
+                abilities: drop_store,
+                type_parameters: vec![phantom, phantom],
+            },
+            StructHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(2),
+                abilities: drop_store,
+                type_parameters: vec![StructTypeParameter {
+                    constraints: AbilitySet::EMPTY,
+                    is_phantom: false,
+                }],
+            },
+        ];
+        module.struct_defs = vec![
+            StructDefinition {
+                struct_handle: StructHandleIndex(0),
+                field_information: StructFieldInformation::Declared(vec![FieldDefinition {
+                    name: IdentifierIndex(3),
+                    signature: TypeSignature(Address),
+                }]),
+            },
+            StructDefinition {
+                struct_handle: StructHandleIndex(1),
+                field_information: StructFieldInformation::Declared(vec![FieldDefinition {
+                    name: IdentifierIndex(4),
+                    signature: TypeSignature(TypeParameter(0)),
+                }]),
+            },
+        ];
+
+        let generic_table = || {
+            MutableReference(Box::new(StructInstantiation(StructHandleIndex(0), vec![
+                TypeParameter(0),
+                TypeParameter(1),
+            ])))
+        };
+        let u64_table = StructInstantiation(StructHandleIndex(0), vec![U64, U64]);
+        let u64_box = StructInstantiation(StructHandleIndex(1), vec![U64]);
+        module.signatures.extend([
+            // 1: add_box parameters
+            Signature(vec![
+                generic_table(),
+                TypeParameter(0),
+                StructInstantiation(StructHandleIndex(1), vec![TypeParameter(1)]),
+            ]),
+            // 2: borrow_box_mut parameters
+            Signature(vec![generic_table(), TypeParameter(0)]),
+            // 3: borrow_box_mut return
+            Signature(vec![MutableReference(Box::new(StructInstantiation(
+                StructHandleIndex(1),
+                vec![TypeParameter(1)],
+            )))]),
+            // 4: add parameters
+            Signature(vec![Address, U64, U64]),
+            // 5: bump parameters
+            Signature(vec![Address, U64]),
+            // 6: Table<u64, u64>
+            Signature(vec![U64, U64]),
+            // 7: Box<u64>
+            Signature(vec![U64]),
+            // 8: <u64, u64, Box<u64>>
+            Signature(vec![U64, U64, u64_box.clone()]),
+            // 9: add locals
+            Signature(vec![u64_table.clone()]),
+            // 10: bump locals
+            Signature(vec![u64_table, MutableReference(Box::new(u64_box))]),
+        ]);
+
+        let native_type_parameters = vec![copy_drop, AbilitySet::EMPTY, AbilitySet::EMPTY];
+        module.function_handles = vec![
+            FunctionHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(5),
+                parameters: SignatureIndex(1),
+                return_: SignatureIndex(0),
+                type_parameters: native_type_parameters.clone(),
+            },
+            FunctionHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(6),
+                parameters: SignatureIndex(2),
+                return_: SignatureIndex(3),
+                type_parameters: native_type_parameters,
+            },
+            FunctionHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(7),
+                parameters: SignatureIndex(4),
+                return_: SignatureIndex(0),
+                type_parameters: vec![],
+            },
+            FunctionHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(8),
+                parameters: SignatureIndex(5),
+                return_: SignatureIndex(0),
+                type_parameters: vec![],
+            },
+        ];
+        module.field_handles = vec![FieldHandle {
+            owner: StructDefinitionIndex(1),
+            field: 0,
+        }];
+        module.struct_def_instantiations = vec![
+            StructDefInstantiation {
+                def: StructDefinitionIndex(0),
+                type_parameters: SignatureIndex(6),
+            },
+            StructDefInstantiation {
+                def: StructDefinitionIndex(1),
+                type_parameters: SignatureIndex(7),
+            },
+        ];
+        module.function_instantiations = vec![
+            FunctionInstantiation {
+                handle: FunctionHandleIndex(0),
+                type_parameters: SignatureIndex(8),
+            },
+            FunctionInstantiation {
+                handle: FunctionHandleIndex(1),
+                type_parameters: SignatureIndex(8),
+            },
+        ];
+        module.field_instantiations = vec![FieldInstantiation {
+            handle: FieldHandleIndex(0),
+            type_parameters: SignatureIndex(7),
+        }];
+
+        let function =
+            |handle: u16, visibility: Visibility, code: Option<CodeUnit>| FunctionDefinition {
+                function: FunctionHandleIndex(handle),
+                visibility,
+                is_entry: false,
+                acquires_global_resources: vec![],
+                code,
+            };
+        module.function_defs = vec![
+            function(0, Visibility::Private, None),
+            function(1, Visibility::Private, None),
+            function(
+                2,
+                Visibility::Public,
+                Some(CodeUnit {
+                    locals: SignatureIndex(9),
+                    code: vec![
+                        Bytecode::MoveLoc(0),
+                        Bytecode::PackGeneric(StructDefInstantiationIndex(0)),
+                        Bytecode::StLoc(3),
+                        Bytecode::MutBorrowLoc(3),
+                        Bytecode::MoveLoc(1),
+                        Bytecode::MoveLoc(2),
+                        Bytecode::PackGeneric(StructDefInstantiationIndex(1)),
+                        Bytecode::CallGeneric(FunctionInstantiationIndex(0)),
+                        Bytecode::Ret,
+                    ],
+                }),
+            ),
+            function(
+                3,
+                Visibility::Public,
+                Some(CodeUnit {
+                    locals: SignatureIndex(10),
+                    code: vec![
+                        Bytecode::MoveLoc(0),
+                        Bytecode::PackGeneric(StructDefInstantiationIndex(0)),
+                        Bytecode::StLoc(2),
+                        Bytecode::MutBorrowLoc(2),
+                        Bytecode::MoveLoc(1),
+                        Bytecode::CallGeneric(FunctionInstantiationIndex(1)),
+                        Bytecode::StLoc(3),
+                        Bytecode::CopyLoc(3),
+                        Bytecode::ImmBorrowFieldGeneric(FieldInstantiationIndex(0)),
+                        Bytecode::ReadRef,
+                        Bytecode::LdU64(1),
+                        Bytecode::Add,
+                        Bytecode::MoveLoc(3),
+                        Bytecode::MutBorrowFieldGeneric(FieldInstantiationIndex(0)),
+                        Bytecode::WriteRef,
+                        Bytecode::Ret,
+                    ],
+                }),
+            ),
+        ];
+        module
+    }
+
+    #[test]
+    fn session_chain_composes_table_writes_across_phases() {
+        let module = table_test_module();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+        let handle = AccountAddress::from_hex_literal("0xcafe").unwrap();
+        let key = bcs::to_bytes(&7u64).unwrap();
+        let args = |extra: &[u64]| {
+            let mut args = vec![bcs::to_bytes(&handle).unwrap(), key.clone()];
+            args.extend(extra.iter().map(|arg| bcs::to_bytes(arg).unwrap()));
+            args
+        };
+        fn run(
+            module_id: ModuleId,
+            name: &'static str,
+            args: Vec<Vec<u8>>,
+        ) -> impl FnOnce(&mut SessionExt<'_, '_, ChainedResolver<'_, InMemoryStateView>>) -> VMResult<()>
+        {
+            move |session| {
+                session
+                    .execute_function_bypass_visibility(
+                        &module_id,
+                        IdentStr::new(name).unwrap(),
+                        vec![],
+                        args,
+                        &mut UnmeteredGasMeter,
+                    )
+                    .map(|_| ())
+            }
+        }
+
+        // Separate sessions do not see each other's table items.
+        let mut session = env.new_session();
+        session
+            .execute_function(&module.self_id(), ident_str!("add"), vec![], args(&[10]))
+            .unwrap();
+        drop(session);
+        let mut session = env.new_session();
+        assert!(session
+            .execute_function(&module.self_id(), ident_str!("bump"), vec![], args(&[]))
+            .is_err());
+        drop(session);
+
+        let mut chain = env.session_chain();
+        chain
+            .run_phase(SessionId::void(), run(module.self_id(), "add", args(&[10])))
+            .unwrap();
+        chain
+            .run_phase(SessionId::void(), run(module.self_id(), "bump", args(&[])))
+            .unwrap();
+        let change_set = chain.finish().unwrap();
+
+        assert_eq!(change_set.write_set().iter().count(), 1);
+        assert_eq!(
+            change_set
+                .write_set()
+                .get(&StateKey::table_item(TableHandle(handle), key)),
+            Some(&WriteOp::Creation(bcs::to_bytes(&11u64).unwrap()))
+        );
+    }
+
+    /// A stand-in for `0x1::aggregator`, declaring just the native it uses:
+    ///
+    /// ```ignore
+    /// module 0x1::aggregator {
+    ///     struct Aggregator has drop, store { handle: address, key: address, limit: u128 }
+    ///     native fun add(aggregator: &mut Aggregator, value: u128);
+    ///     public fun bump(handle: address, key: address, value: u128) {
+    ///         add(&mut Aggregator { handle, key, limit: MAX_U128 }, value)
+    ///     }
+    /// }
+    /// ```
+    fn aggregator_test_module() -> CompiledModule {
+        use SignatureToken::*;
+
+        let mut module = empty_module();
+        module.identifiers = [
+            "aggregator",
+            "Aggregator",
+            "handle",
+            "key",
+            "limit",
+            "add",
+            "bump",
+        ]
+        .into_iter()
+        .map(|name| Identifier::new(name).unwrap())
+        .collect();
+        module.address_identifiers = vec![AccountAddress::ONE];
+        module.struct_handles = vec![StructHandle {
+            module: ModuleHandleIndex(0),
+            name: IdentifierIndex(1),
+            abilities: AbilitySet::EMPTY | Ability::Drop | Ability::Store,
+            type_parameters: vec![],
+        }];
+        let field = |name: u16, signature: SignatureToken| FieldDefinition {
+            name: IdentifierIndex(name),
+            signature: TypeSignature(signature),
+        };
+        module.struct_defs = vec![StructDefinition {
+            struct_handle: StructHandleIndex(0),
+            field_information: StructFieldInformation::Declared(vec![
+                field(2, Address),
+                field(3, Address),
+                field(4, U128),
+            ]),
+        }];
+        module.signatures.extend([
+            // 1: add parameters
+            Signature(vec![
+                MutableReference(Box::new(Struct(StructHandleIndex(0)))),
+                U128,
+            ]),
+            // 2: bump parameters
+            Signature(vec![Address, Address, U128]),
+            // 3: bump locals
+            Signature(vec![Struct(StructHandleIndex(0))]),
+        ]);
+        module.function_handles = vec![
+            FunctionHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(5),
+                parameters: SignatureIndex(1),
+                return_: SignatureIndex(0),
+                type_parameters: vec![],
+            },
+            FunctionHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(6),
+                parameters: SignatureIndex(2),
+                return_: SignatureIndex(0),
+                type_parameters: vec![],
+            },
+        ];
+        module.function_defs = vec![
+            FunctionDefinition {
+                function: FunctionHandleIndex(0),
+                visibility: Visibility::Private,
+                is_entry: false,
+                acquires_global_resources: vec![],
+                code: None,
+            },
+            FunctionDefinition {
+                function: FunctionHandleIndex(1),
+                visibility: Visibility::Public,
+                is_entry: false,
+                acquires_global_resources: vec![],
+                code: Some(CodeUnit {
+                    locals: SignatureIndex(3),
+                    code: vec![
+                        Bytecode::MoveLoc(0),
+                        Bytecode::MoveLoc(1),
+                        Bytecode::LdU128(u128::MAX),
+                        Bytecode::Pack(StructDefinitionIndex(0)),
+                        Bytecode::StLoc(3),
+                        Bytecode::MutBorrowLoc(3),
+                        Bytecode::MoveLoc(2),
+                        Bytecode::Call(FunctionHandleIndex(0)),
+                        Bytecode::Ret,
+                    ],
+                }),
+            },
+        ];
+        module
+    }
+
+    #[test]
+    fn view_sessions_resolve_aggregator_deltas_without_writing() {
+        let module = aggregator_test_module();
+        let handle = AccountAddress::from_hex_literal("0xcafe").unwrap();
+        let key = AccountAddress::from_hex_literal("0xbeef").unwrap();
+        let state_key = StateKey::table_item(TableHandle(handle), key.to_vec());
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .with_table_item(TableHandle(handle), key.to_vec(), serialize(&100))
+            .build()
+            .unwrap();
+        let bump = |value: u128| {
+            vec![
+                bcs::to_bytes(&handle).unwrap(),
+                bcs::to_bytes(&key).unwrap(),
+                bcs::to_bytes(&value).unwrap(),
+            ]
+        };
+
+        let mut session = env.new_session();
+        for value in [5, 7] {
+            session
+                .execute_function(&module.self_id(), ident_str!("bump"), vec![], bump(value))
+                .unwrap();
+        }
+        let (events, values) = session.finish_view().unwrap();
+        assert!(events.is_empty());
+        assert_eq!(values, vec![(state_key.clone(), 112)]);
+
+        // The deltas are resolved against the state, so one that cannot be applied to it fails.
+        let mut session = env.new_session();
+        session
+            .execute_function(
+                &module.self_id(),
+                ident_str!("bump"),
+                vec![],
+                bump(u128::MAX),
+            )
+            .unwrap();
+        assert!(session.finish_view().is_err());
+
+        // A session without aggregator deltas resolves to nothing.
+        let (events, values) = env.new_session().finish_view().unwrap();
+        assert!(events.is_empty());
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn view_sessions_reject_writes() {
+        let module = harness_test_module();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+
+        let mut session = env.new_session();
+        session
+            .execute_function(&module.self_id(), ident_str!("publish"), vec![], vec![
+                MoveValue::Signer(AccountAddress::ONE)
+                    .simple_serialize()
+                    .unwrap(),
+            ])
+            .unwrap();
+        assert_eq!(
+            session.finish_view().unwrap_err().major_status(),
+            StatusCode::RESOURCE_WRITE_DENIED
+        );
+
+        // Only sessions of view functions can be finished as views.
+        let state_view = InMemoryStateView::default();
+        let resolver = StorageAdapter::new(&state_view);
+        let session = env
+            .vm()
+            .new_session(&resolver, SessionId::genesis(HashValue::zero()));
+        assert_eq!(
+            session.finish_view(&state_view).unwrap_err().major_status(),
+            StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR
+        );
+    }
+
+    fn extension_limited_configs(
+        max_table_change_entries: u64,
+        max_aggregator_changes: u64,
+    ) -> ChangeSetConfigs {
+        ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION)
+            .with_extension_change_limits(max_table_change_entries, max_aggregator_changes)
+    }
+
+    #[test]
+    fn table_changes_over_the_limit_are_rejected() {
+        let module = table_test_module();
+        let handle = AccountAddress::from_hex_literal("0xcafe").unwrap();
+        let add_items = |env: &SessionTestEnv, num_items: u64| {
+            let mut session = env.new_session();
+            for key in 0..num_items {
+                session
+                    .execute_function(&module.self_id(), ident_str!("add"), vec![], vec![
+                        bcs::to_bytes(&handle).unwrap(),
+                        bcs::to_bytes(&key).unwrap(),
+                        bcs::to_bytes(&key).unwrap(),
+                    ])
+                    .unwrap();
+            }
+            session.finish_and_inspect()
+        };
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .with_change_set_configs(extension_limited_configs(3, u64::MAX))
+            .build()
+            .unwrap();
+
+        assert_eq!(add_items(&env, 3).unwrap().num_writes(), 3);
+        assert_eq!(
+            add_items(&env, 4).unwrap_err().major_status(),
+            StatusCode::TOO_MANY_WRITE_OPS
+        );
+    }
+
+    #[test]
+    fn aggregator_changes_over_the_limit_are_rejected() {
+        let module = aggregator_test_module();
+        let handle = AccountAddress::from_hex_literal("0xcafe").unwrap();
+        let bump_aggregators = |env: &SessionTestEnv, num_aggregators: u8| {
+            let mut session = env.new_session();
+            for i in 0..num_aggregators {
+                let key = AccountAddress::new([i; AccountAddress::LENGTH]);
+                session
+                    .execute_function(&module.self_id(), ident_str!("bump"), vec![], vec![
+                        bcs::to_bytes(&handle).unwrap(),
+                        bcs::to_bytes(&key).unwrap(),
+                        bcs::to_bytes(&1u128).unwrap(),
+                    ])
+                    .unwrap();
+            }
+            session.finish_and_inspect()
+        };
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .with_change_set_configs(extension_limited_configs(u64::MAX, 2))
+            .build()
+            .unwrap();
+
+        let change_set = bump_aggregators(&env, 2).unwrap();
+        assert_eq!(change_set.change_set_ext().delta_change_set().len(), 2);
+        assert_eq!(
+            bump_aggregators(&env, 3).unwrap_err().major_status(),
+            StatusCode::TOO_MANY_WRITE_OPS
+        );
+    }
+
+    /// A stand-in for `0x1::event`, declaring both of its natives:
+    ///
+    /// ```ignore
+    /// module 0x1::event {
+    ///     struct E has drop, store { v: u64 }
+    ///     native fun write_to_event_store<T: drop + store>(guid: vector<u8>, count: u64, msg: T);
+    ///     native fun write_module_event_to_store<T: drop + store>(msg: T);
+    ///     public fun emit_both(guid: vector<u8>, v: u64) {
+    ///         write_to_event_store<E>(guid, 0, E { v });
+    ///         write_module_event_to_store<E>(E { v });
+    ///         write_to_event_store<E>(guid, 1, E { v })
+    ///     }
+    /// }
+    /// ```
+    fn event_test_module() -> CompiledModule {
+        use SignatureToken::*;
+
+        let mut module = empty_module();
+        module.identifiers = [
+            "event",
+            "E",
+            "v",
+            "write_to_event_store",
+            "write_module_event_to_store",
+            "emit_both",
+        ]
+        .into_iter()
+        .map(|name| Identifier::new(name).unwrap())
+        .collect();
+        module.address_identifiers = vec![AccountAddress::ONE];
+
+        let drop_store = AbilitySet::EMPTY | Ability::Drop | Ability::Store;
+        module.struct_handles = vec![StructHandle {
+            module: ModuleHandleIndex(0),
+            name: IdentifierIndex(1),
+            abilities: drop_store,
+            type_parameters: vec![],
+        }];
+        module.struct_defs = vec![StructDefinition {
+            struct_handle: StructHandleIndex(0),
+            field_information: StructFieldInformation::Declared(vec![FieldDefinition {
+                name: IdentifierIndex(2),
+                signature: TypeSignature(U64),
+            }]),
+        }];
+        module.signatures.extend([
+            // 1: write_to_event_store parameters
+            Signature(vec![Vector(Box::new(U8)), U64, TypeParameter(0)]),
+            // 2: write_module_event_to_store parameters
+            Signature(vec![TypeParameter(0)]),
+            // 3: emit_both parameters
+            Signature(vec![Vector(Box::new(U8)), U64]),
+            // 4: <E>
+            Signature(vec![Struct(StructHandleIndex(0))]),
+        ]);
+
+        let function_handle =
+            |name: u16, parameters: u16, type_parameters: Vec<AbilitySet>| FunctionHandle {
+                module: ModuleHandleIndex(0),
+                name: IdentifierIndex(name),
+                parameters: SignatureIndex(parameters),
+                return_: SignatureIndex(0),
+                type_parameters,
+            };
+        module.function_handles = vec![
+            function_handle(3, 1, vec![drop_store]),
+            function_handle(4, 2, vec![drop_store]),
+            function_handle(5, 3, vec![]),
+        ];
+        module.function_instantiations = vec![
+            FunctionInstantiation {
+                handle: FunctionHandleIndex(0),
+                type_parameters: SignatureIndex(4),
+            },
+            FunctionInstantiation {
+                handle: FunctionHandleIndex(1),
+                type_parameters: SignatureIndex(4),
+            },
+        ];
+
+        let function =
+            |handle: u16, visibility: Visibility, code: Option<CodeUnit>| FunctionDefinition {
+                function: FunctionHandleIndex(handle),
+                visibility,
+                is_entry: false,
+                acquires_global_resources: vec![],
+                code,
+            };
+        module.function_defs = vec![
+            function(0, Visibility::Private, None),
+            function(1, Visibility::Private, None),
+            function(
+                2,
+                Visibility::Public,
+                Some(CodeUnit {
+                    locals: SignatureIndex(0),
+                    code: vec![
+                        Bytecode::CopyLoc(0),
+                        Bytecode::LdU64(0),
+                        Bytecode::CopyLoc(1),
+                        Bytecode::Pack(StructDefinitionIndex(0)),
+                        Bytecode::CallGeneric(FunctionInstantiationIndex(0)),
+                        Bytecode::CopyLoc(1),
+                        Bytecode::Pack(StructDefinitionIndex(0)),
+                        Bytecode::CallGeneric(FunctionInstantiationIndex(1)),
+                        Bytecode::MoveLoc(0),
+                        Bytecode::LdU64(1),
+                        Bytecode::MoveLoc(1),
+                        Bytecode::Pack(StructDefinitionIndex(0)),
+                        Bytecode::CallGeneric(FunctionInstantiationIndex(0)),
+                        Bytecode::Ret,
+                    ],
+                }),
+            ),
+        ];
+        module
+    }
+
+    #[test]
+    fn module_events_are_emitted_along_with_legacy_events() {
+        let module = event_test_module();
+        let key = EventKey::new(0, AccountAddress::ONE);
+        let emit_both = |env: &SessionTestEnv| {
+            let mut session = env.new_session();
+            session.execute_function(&module.self_id(), ident_str!("emit_both"), vec![], vec![
+                bcs::to_bytes(&key.to_bytes()).unwrap(),
+                bcs::to_bytes(&7u64).unwrap(),
+            ])?;
+            session.finish_and_inspect()
+        };
+        let event_tag = TypeTag::Struct(Box::new(struct_tag("event", "E")));
+        let data = bcs::to_bytes(&7u64).unwrap();
+
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .with_feature(FeatureFlag::MODULE_EVENT)
+            .build()
+            .unwrap();
+        // Events are output in the order they were emitted, whatever their kind.
+        let change_set = emit_both(&env).unwrap();
+        assert_eq!(change_set.events(), &[
+            ContractEvent::new(key, 0, event_tag.clone(), data.clone()),
+            ContractEvent::new_module_event(event_tag.clone(), data.clone()),
+            ContractEvent::new(key, 1, event_tag, data),
+        ]);
+        assert!(!change_set.events()[0].is_module_event());
+        assert!(change_set.events()[1].is_module_event());
+
+        // Module events count towards the limits on events like the others: here the legacy
+        // event alone fits, but not along with the module event.
+        let mut gas_params = AptosGasParameters::initial();
+        gas_params.txn.max_bytes_all_events_per_transaction = 12.into();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .with_feature(FeatureFlag::MODULE_EVENT)
+            .with_change_set_configs(ChangeSetConfigs::new(
+                LATEST_GAS_FEATURE_VERSION,
+                &gas_params,
+            ))
+            .build()
+            .unwrap();
+        assert_eq!(
+            emit_both(&env).unwrap_err().major_status(),
+            StatusCode::STORAGE_WRITE_LIMIT_REACHED
+        );
+
+        // Without the feature, emitting a module event aborts the session.
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+        let err = emit_both(&env).unwrap_err();
+        assert_eq!(err.major_status(), StatusCode::ABORTED);
+        assert_eq!(err.sub_status(), Some(0x03_0001));
+    }
+
+    /// module 0x1::group_churn {
+    ///     #[resource_group_member(group = 0x1::group_churn::Group)]
+    ///     struct Member has key { v: u64 }
+    ///     public fun churn(s: &signer, addr: address) acquires Member {
+    ///         move_to(s, Member { v: 1 });
+    ///         let Member { v: _ } = move_from<Member>(addr);
+    ///     }
+    /// }
+    fn group_churn_module() -> CompiledModule {
+        let member = KnownAttribute::resource_group_member("0x1::group_churn::Group".to_string());
+        let metadata = RuntimeModuleMetadataV1 {
+            error_map: BTreeMap::new(),
+            struct_attributes: BTreeMap::from([("Member".to_string(), vec![member])]),
+            fun_attributes: BTreeMap::new(),
+        };
+
+        let mut module = empty_module();
+        module.identifiers = vec![
+            Identifier::new("group_churn").unwrap(),
+            Identifier::new("Member").unwrap(),
+            Identifier::new("v").unwrap(),
+            Identifier::new("churn").unwrap(),
+        ];
+        module.address_identifiers = vec![AccountAddress::ONE];
+        module.metadata.push(Metadata {
+            key: APTOS_METADATA_KEY_V1.clone(),
+            value: bcs::to_bytes(&metadata).unwrap(),
+        });
+        module.struct_handles = vec![StructHandle {
+            module: ModuleHandleIndex(0),
+            name: IdentifierIndex(1),
+            abilities: AbilitySet::EMPTY | Ability::Key,
+            type_parameters: vec![],
+        }];
+        module.struct_defs = vec![StructDefinition {
+            struct_handle: StructHandleIndex(0),
+            field_information: StructFieldInformation::Declared(vec![FieldDefinition {
+                name: IdentifierIndex(2),
+                signature: TypeSignature(SignatureToken::U64),
+            }]),
+        }];
+        module.signatures.push(Signature(vec![
+            SignatureToken::Reference(Box::new(SignatureToken::Signer)),
+            SignatureToken::Address,
+        ]));
+        module.function_handles = vec![FunctionHandle {
+            module: ModuleHandleIndex(0),
+            name: IdentifierIndex(3),
+            parameters: SignatureIndex(1),
+            return_: SignatureIndex(0),
+            type_parameters: vec![],
+        }];
+        module.function_defs = vec![FunctionDefinition {
+            function: FunctionHandleIndex(0),
+            visibility: Visibility::Public,
+            is_entry: false,
+            acquires_global_resources: vec![StructDefinitionIndex(0)],
+            code: Some(CodeUnit {
+                locals: SignatureIndex(0),
+                code: vec![
+                    Bytecode::MoveLoc(0),
+                    Bytecode::LdU64(1),
+                    Bytecode::Pack(StructDefinitionIndex(0)),
+                    Bytecode::MoveTo(StructDefinitionIndex(0)),
+                    Bytecode::MoveLoc(1),
+                    Bytecode::MoveFrom(StructDefinitionIndex(0)),
+                    Bytecode::Unpack(StructDefinitionIndex(0)),
+                    Bytecode::Pop,
+                    Bytecode::Ret,
+                ],
+            }),
+        }];
+        module
+    }
+
+    #[test]
+    fn group_member_added_and_removed_is_not_merged() {
+        let module = group_churn_module();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+        let addr = AccountAddress::random();
+
+        let mut session = env.new_session();
+        session
+            .execute_function(&module.self_id(), ident_str!("churn"), vec![], vec![
+                MoveValue::Signer(addr).simple_serialize().unwrap(),
+                MoveValue::Address(addr).simple_serialize().unwrap(),
+            ])
+            .unwrap();
+        let (change_set, read_stats) = session.finish_with_read_stats().unwrap();
+
+        assert_eq!(change_set.num_writes(), 0);
+        assert_eq!(read_stats.group_merge.group_data_reads, 0);
+    }
+
+    #[test]
+    fn finish_without_table_extension_has_no_table_writes() {
+        let state_view = CountingStateView::default();
+        let resolver = StorageAdapter::new(&state_view);
+        let vm = MoveVM::new(vec![]).unwrap();
+        let mut extensions = NativeContextExtensions::default();
+        extensions.add(NativeAggregatorContext::new([0; 32], &resolver));
+        extensions.add(NativeEventContext::new());
+        let session = SessionExt::new(
+            vm.new_session_with_extensions(&resolver, extensions),
+            &vm,
+            &resolver,
+            &SessionId::void(),
+        );
+
+        let change_set = session
+            .finish(
+                &mut (),
+                &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                    LATEST_GAS_FEATURE_VERSION,
+                )),
+            )
+            .unwrap();
+        assert_eq!(change_set.write_set().iter().count(), 0);
+        assert!(change_set.delta_change_set().is_empty());
+    }
+
+    #[test]
+    fn finish_without_event_extension_has_no_native_events() {
+        let state_view = CountingStateView::default();
+        let resolver = StorageAdapter::new(&state_view);
+        let vm = MoveVM::new(vec![]).unwrap();
+        let mut extensions = NativeContextExtensions::default();
+        extensions.add(NativeTableContext::new([0; 32], &resolver));
+        extensions.add(NativeAggregatorContext::new([0; 32], &resolver));
+        let session = SessionExt::new(
+            vm.new_session_with_extensions(&resolver, extensions),
+            &vm,
+            &resolver,
+            &SessionId::void(),
+        );
+
+        let change_set = session
+            .finish(
+                &mut (),
+                &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                    LATEST_GAS_FEATURE_VERSION,
+                )),
+            )
+            .unwrap();
+        assert!(change_set.change_set().events().is_empty());
+    }
+
+    #[test]
+    fn finish_with_table_extension_keeps_table_writes() {
+        let module = table_test_module();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+        let handle = AccountAddress::from_hex_literal("0xcafe").unwrap();
+        let key = bcs::to_bytes(&7u64).unwrap();
+
+        let mut session = env.new_session();
+        session
+            .execute_function(&module.self_id(), ident_str!("add"), vec![], vec![
+                bcs::to_bytes(&handle).unwrap(),
+                key.clone(),
+                bcs::to_bytes(&10u64).unwrap(),
+            ])
+            .unwrap();
+        let change_set = session.finish_and_inspect().unwrap();
+
+        assert_eq!(change_set.num_writes(), 1);
+        assert_eq!(
+            change_set.table_item(TableHandle(handle), key),
+            Some(&WriteOp::Creation(bcs::to_bytes(&10u64).unwrap()))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "feature-usage")]
+    fn new_sessions_have_read_no_feature_flags() {
+        let env = SessionTestHarness::new().build().unwrap();
+        let mut session = env.new_session();
+        assert!(session.session_mut().feature_flags_read().is_empty());
+    }
+
+    #[test]
+    fn harness_session_writes_created_resources() {
+        let module = harness_test_module();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+        let addr = AccountAddress::random();
+
+        let mut session = env.new_session();
+        session
+            .execute_function(&module.self_id(), ident_str!("publish"), vec![], vec![
+                MoveValue::Signer(addr).simple_serialize().unwrap(),
+            ])
+            .unwrap();
+        let change_set = session.finish_and_inspect().unwrap();
+
+        assert_eq!(change_set.num_writes(), 1);
+        assert_eq!(
+            change_set.resource(addr, struct_tag("harness_test", "R")),
+            Some(&WriteOp::Creation(bcs::to_bytes(&1u64).unwrap()))
+        );
+        assert!(change_set.events().is_empty());
+    }
+
+    #[test]
+    fn execution_markers_are_not_committed() {
+        let module = harness_test_module();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+        let addr = AccountAddress::random();
+
+        let finish = |incarnation| {
+            let mut session = env.new_session();
+            session
+                .execute_function(&module.self_id(), ident_str!("publish"), vec![], vec![
+                    MoveValue::Signer(addr).simple_serialize().unwrap(),
+                ])
+                .unwrap();
+            session
+                .finish_and_inspect()
+                .unwrap()
+                .into_inner()
+                .with_marker(ExecutionMarker {
+                    epoch: 1,
+                    incarnation,
+                })
+        };
+        let first = finish(0);
+        let second = finish(1);
+
+        assert_eq!(first.marker().unwrap().incarnation, 0);
+        assert_eq!(second.marker().unwrap().incarnation, 1);
+        assert!(format!("{:?}", second).contains("incarnation: 1"));
+
+        let (_, first) = first.into_inner();
+        let (_, second) = second.into_inner();
+        assert_eq!(
+            bcs::to_bytes(&first).unwrap(),
+            bcs::to_bytes(&second).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-metrics"))]
+    fn finish_counts_reads_by_phase() {
+        let module = harness_test_module();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+
+        let mut session = env.new_session();
+        session
+            .execute_function(&module.self_id(), ident_str!("publish"), vec![], vec![
+                MoveValue::Signer(AccountAddress::random())
+                    .simple_serialize()
+                    .unwrap(),
+            ])
+            .unwrap();
+        let (_, read_stats) = session.finish_with_read_stats().unwrap();
+
+        // Classifying the written resource looks up the metadata of its module; nothing is a
+        // resource group, so no group is read, and with nothing to strip conversion reads nothing.
+        assert_eq!(read_stats.group_merge, ResolverReads {
+            group_metadata_lookups: 1,
+            ..ResolverReads::default()
+        });
+        assert_eq!(read_stats.write_op_conversion, ResolverReads::default());
+    }
+
+    #[test]
+    fn finish_fails_beyond_budget() {
+        let module = harness_test_module();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .build()
+            .unwrap();
+        let publish = |budget| {
+            let mut session = env.new_session();
+            session
+                .execute_function(&module.self_id(), ident_str!("publish"), vec![], vec![
+                    MoveValue::Signer(AccountAddress::random())
+                        .simple_serialize()
+                        .unwrap(),
+                ])
+                .unwrap();
+            session.session_mut().set_finish_budget(budget);
+            session.finish_and_inspect()
+        };
+
+        // Writing a single small resource is well within a modest budget.
+        assert!(publish(FinishBudget {
+            max_reads: 10,
+            max_bytes: 64 * 1024,
+        })
+        .is_ok());
+        let err = publish(FinishBudget {
+            max_reads: u64::MAX,
+            max_bytes: 16,
+        })
+        .unwrap_err();
+        assert_eq!(err.major_status(), StatusCode::FINISH_BUDGET_EXCEEDED);
+    }
+
+    #[test]
+    fn harness_session_reads_seeded_resources() {
+        let module = harness_test_module();
+        let addr = AccountAddress::random();
+        let env = SessionTestHarness::new()
+            .with_module(&module)
+            .with_resource(
+                addr,
+                struct_tag("harness_test", "R"),
+                bcs::to_bytes(&7u64).unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        let mut session = env.new_session();
+        let err = session
+            .execute_function(&module.self_id(), ident_str!("publish"), vec![], vec![
+                MoveValue::Signer(addr).simple_serialize().unwrap(),
+            ])
+            .unwrap_err();
+        assert_eq!(err.major_status(), StatusCode::RESOURCE_ALREADY_EXISTS);
+    }
+
+    /// module 0x1::<name> { public fun noop() {} }
+    fn noop_module(name: &str) -> CompiledModule {
+        let mut module = empty_module();
+        module.identifiers = vec![
+            Identifier::new(name).unwrap(),
+            Identifier::new("noop").unwrap(),
+        ];
+        module.address_identifiers = vec![AccountAddress::ONE];
+        module.function_handles = vec![FunctionHandle {
+            module: ModuleHandleIndex(0),
+            name: IdentifierIndex(1),
+            parameters: SignatureIndex(0),
+            return_: SignatureIndex(0),
+            type_parameters: vec![],
+        }];
+        module.function_defs = vec![FunctionDefinition {
+            function: FunctionHandleIndex(0),
+            visibility: Visibility::Public,
+            is_entry: false,
+            acquires_global_resources: vec![],
+            code: Some(CodeUnit {
+                locals: SignatureIndex(0),
+                code: vec![Bytecode::Ret],
+            }),
+        }];
+        module
+    }
+
+    #[test]
+    fn published_modules_are_recorded_in_the_change_set() {
+        let existing = noop_module("existing");
+        let env = SessionTestHarness::new()
+            .with_module(&existing)
+            .build()
+            .unwrap();
+
+        let mut session = env.new_session();
+        session
+            .execute_function(&existing.self_id(), ident_str!("noop"), vec![], vec![])
+            .unwrap();
+        let mut bytes = vec![];
+        noop_module("published").serialize(&mut bytes).unwrap();
+        session
+            .session_mut()
+            .publish_module_bundle(vec![bytes], AccountAddress::ONE, &mut UnmeteredGasMeter)
+            .unwrap();
+        let change_set = session.finish_and_inspect().unwrap();
+        assert_eq!(change_set.change_set_ext().modules_changed(), &[
+            noop_module("published").self_id()
+        ]);
+    }
+}
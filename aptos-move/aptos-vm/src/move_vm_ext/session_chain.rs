@@ -0,0 +1,168 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    data_cache::{IntoMoveResolver, StorageAdapterOwned},
+    move_vm_ext::{MoveVmExt, SessionExt, SessionId},
+};
+use anyhow::Result;
+use aptos_aggregator::{delta_change_set::DeltaChangeSet, transaction::ChangeSetExt};
+use aptos_gas::ChangeSetConfigs;
+use aptos_state_view::{StateView, StateViewId, TStateView};
+use aptos_types::{
+    access_path::AccessPath,
+    state_store::{
+        state_key::StateKey, state_storage_usage::StateStorageUsage, state_value::StateValue,
+    },
+    transaction::ChangeSet,
+    write_set::WriteSet,
+};
+use move_binary_format::errors::{Location, PartialVMError, VMError, VMResult};
+use move_core_types::{
+    account_address::AccountAddress,
+    language_storage::StructTag,
+    vm_status::{StatusCode, VMStatus},
+};
+use std::sync::Arc;
+
+/// A state view showing the effects of a change set on top of a base state view, e.g. so that a
+/// session respawned after another one sees its writes. Deltas are applied to the base value when
+/// read; if that fails, the error is the `VMStatus` of the failure, e.g. the aggregator's
+/// overflow abort, and can be downcast to it.
+pub struct EffectiveStateView<'a, S> {
+    base: &'a S,
+    change_set: Option<&'a ChangeSetExt>,
+}
+
+impl<'a, S> EffectiveStateView<'a, S> {
+    pub fn new(base: &'a S, change_set: Option<&'a ChangeSetExt>) -> Self {
+        Self { base, change_set }
+    }
+}
+
+impl<'a, S: StateView> EffectiveStateView<'a, S> {
+    /// The blob of the resource group at `address`, with the change set applied.
+    pub fn get_resource_group_data(
+        &self,
+        address: &AccountAddress,
+        resource_group: &StructTag,
+    ) -> Result<Option<Vec<u8>>> {
+        let access_path = AccessPath::resource_group_access_path(*address, resource_group.clone());
+        self.get_state_value_bytes(&StateKey::access_path(access_path))
+    }
+}
+
+impl<'a, S: StateView> TStateView for EffectiveStateView<'a, S> {
+    type Key = StateKey;
+
+    fn id(&self) -> StateViewId {
+        self.base.id()
+    }
+
+    fn get_state_value(&self, state_key: &StateKey) -> Result<Option<StateValue>> {
+        let change_set = match self.change_set {
+            Some(change_set) => change_set,
+            None => return self.base.get_state_value(state_key),
+        };
+        if let Some(write_op) = change_set.write_set().get(state_key) {
+            return Ok(write_op.as_state_value());
+        }
+        match change_set.delta_change_set().get(state_key) {
+            Some(delta_op) => {
+                let write_op = delta_op
+                    .try_into_write_op(self.base, state_key)
+                    .map_err(anyhow::Error::new)?;
+                Ok(write_op.as_state_value())
+            },
+            None => self.base.get_state_value(state_key),
+        }
+    }
+
+    fn is_genesis(&self) -> bool {
+        self.base.is_genesis()
+    }
+
+    fn get_usage(&self) -> Result<StateStorageUsage> {
+        self.base.get_usage()
+    }
+}
+
+/// The resolver the sessions of a `SessionChain` run against.
+pub type ChainedResolver<'a, S> = StorageAdapterOwned<EffectiveStateView<'a, S>>;
+
+/// Runs the phases of a transaction, e.g. prologue, payload and epilogue, as a chain of sessions
+/// producing a single `ChangeSetExt`.
+///
+/// Every phase reads the state left by the phases before it, tables and aggregators included,
+/// without the caller having to materialize anything in between. The effects of a phase are
+/// squashed into the ones before it, so an item created in one phase and modified in the next
+/// ends up as a single creation.
+pub struct SessionChain<'a, S> {
+    vm: &'a MoveVmExt,
+    base: &'a S,
+    configs: Arc<ChangeSetConfigs>,
+    change_set: Option<ChangeSetExt>,
+}
+
+impl<'a, S: StateView> SessionChain<'a, S> {
+    pub fn new(vm: &'a MoveVmExt, base: &'a S, configs: Arc<ChangeSetConfigs>) -> Self {
+        Self {
+            vm,
+            base,
+            configs,
+            change_set: None,
+        }
+    }
+
+    /// Runs `phase` in a new session and adds its effects to the chain. If the phase or
+    /// finishing its session fails, the chain is left as it was.
+    pub fn run_phase<T, F>(&mut self, session_id: SessionId, phase: F) -> VMResult<T>
+    where
+        F: FnOnce(&mut SessionExt<'_, '_, ChainedResolver<'_, S>>) -> VMResult<T>,
+    {
+        let resolver =
+            EffectiveStateView::new(self.base, self.change_set.as_ref()).into_move_resolver();
+        let mut session = self.vm.new_session(&resolver, session_id);
+        if let Some(change_set) = &self.change_set {
+            session.continue_native_events(change_set);
+        }
+        let result = phase(&mut session)?;
+        let phase_change_set = session.finish(&mut (), &self.configs)?;
+        drop(resolver);
+
+        self.change_set = Some(match self.change_set.take() {
+            Some(change_set) => change_set
+                .squash(phase_change_set)
+                .map_err(|err| invariant_violation(err.to_string()))?,
+            None => phase_change_set,
+        });
+        Ok(result)
+    }
+
+    /// The effects of all phases run so far.
+    pub fn change_set(&self) -> Option<&ChangeSetExt> {
+        self.change_set.as_ref()
+    }
+
+    /// Returns the effects of all phases, or an empty change set if none was run.
+    pub fn finish(self) -> VMResult<ChangeSetExt> {
+        match self.change_set {
+            Some(change_set) => Ok(change_set),
+            None => {
+                let change_set = ChangeSet::new(WriteSet::default(), vec![], self.configs.as_ref())
+                    .map_err(|status: VMStatus| invariant_violation(status.to_string()))?;
+                Ok(ChangeSetExt::new(
+                    DeltaChangeSet::empty(),
+                    change_set,
+                    self.configs,
+                ))
+            },
+        }
+    }
+}
+
+fn invariant_violation(message: String) -> VMError {
+    PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+        .with_message(message)
+        .finish(Location::Undefined)
+}
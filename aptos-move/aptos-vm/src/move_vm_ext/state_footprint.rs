@@ -0,0 +1,50 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::move_vm_ext::session::is_reserved_system_address;
+use aptos_types::state_store::state_key::{StateKey, StateKeyInner};
+use move_core_types::account_address::AccountAddress;
+use std::collections::BTreeSet;
+
+/// The state a session may touch, consulted for every written state key (deltas included) when a
+/// session with an enforced footprint is finished. Writes outside of it fail the session with
+/// `BLOCK_META_WRITE_OUTSIDE_FOOTPRINT`, listing every offending key.
+pub trait StateFootprint: Send + Sync {
+    fn allows(&self, state_key: &StateKey) -> bool;
+}
+
+/// The footprint expected of block prologues: state under the reserved system addresses, the
+/// block proposer's account for fee distribution, and any address added on top.
+///
+/// Table items cannot be attributed to an account from their key alone, so they are allowed; the
+/// framework keeps e.g. the coin supply aggregator in one.
+pub struct BlockMetaFootprint {
+    addresses: BTreeSet<AccountAddress>,
+}
+
+impl BlockMetaFootprint {
+    pub fn new(proposer: AccountAddress) -> Self {
+        Self {
+            addresses: BTreeSet::from([proposer]),
+        }
+    }
+
+    /// Also allows writes under `addr`, e.g. to the account of a validator paid by the prologue.
+    pub fn with_address(mut self, addr: AccountAddress) -> Self {
+        self.addresses.insert(addr);
+        self
+    }
+}
+
+impl StateFootprint for BlockMetaFootprint {
+    fn allows(&self, state_key: &StateKey) -> bool {
+        match state_key.inner() {
+            StateKeyInner::AccessPath(access_path) => {
+                is_reserved_system_address(&access_path.address)
+                    || self.addresses.contains(&access_path.address)
+            },
+            StateKeyInner::TableItem { .. } => true,
+            StateKeyInner::Raw(_) => false,
+        }
+    }
+}
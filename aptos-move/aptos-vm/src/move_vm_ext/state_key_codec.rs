@@ -0,0 +1,37 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_types::{
+    access_path::AccessPath,
+    state_store::{state_key::StateKey, table::TableHandle},
+};
+
+/// Builds the state keys a session's writes are stored under when its change set is converted,
+/// and the keys existing resource groups are read from when merging into them, so that reads and
+/// writes agree. Storage laying out its keys differently, e.g. prefixing them with a shard derived
+/// from the address, plugs in its own codec with `SessionExt::set_state_key_codec`.
+pub trait StateKeyCodec: Send + Sync {
+    fn access_path_key(&self, access_path: AccessPath) -> StateKey {
+        StateKey::access_path(access_path)
+    }
+
+    fn table_item_key(&self, handle: TableHandle, key: Vec<u8>) -> StateKey {
+        StateKey::table_item(handle, key)
+    }
+
+    /// Whether keys are built exactly as `StateKey::access_path` and `StateKey::table_item` build
+    /// them. Only then are keys cached by an `AccessPathCache`, and group reads served by the
+    /// resolver itself, used as they are.
+    fn is_default(&self) -> bool {
+        false
+    }
+}
+
+/// The codec used unless another one is set, building keys as the rest of the system does.
+pub struct DefaultStateKeyCodec;
+
+impl StateKeyCodec for DefaultStateKeyCodec {
+    fn is_default(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,304 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Scaffolding for tests that run a `SessionExt` over an in-memory state, without a genesis or
+//! the framework.
+
+use crate::{
+    data_cache::{FinishReadStats, IntoMoveResolver, StorageAdapterOwned},
+    move_vm_ext::{MoveVmExt, NoGroupGas, SessionChain, SessionExt, SessionId},
+};
+use aptos_aggregator::transaction::ChangeSetExt;
+use aptos_gas::{
+    AbstractValueSizeGasParameters, ChangeSetConfigs, NativeGasParameters,
+    LATEST_GAS_FEATURE_VERSION,
+};
+use aptos_state_view::TStateView;
+use aptos_types::{
+    access_path::AccessPath,
+    contract_event::ContractEvent,
+    on_chain_config::{FeatureFlag, Features, TimedFeatures},
+    state_store::{
+        state_key::StateKey, state_storage_usage::StateStorageUsage, state_value::StateValue,
+        table::TableHandle,
+    },
+    write_set::WriteOp,
+};
+use move_binary_format::{errors::VMResult, CompiledModule};
+use move_core_types::{
+    account_address::AccountAddress,
+    identifier::IdentStr,
+    language_storage::{ModuleId, StructTag, TypeTag},
+};
+use move_vm_runtime::session::SerializedReturnValues;
+use move_vm_types::gas::UnmeteredGasMeter;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+/// The state sessions of a `SessionTestHarness` read from.
+#[derive(Default)]
+pub struct InMemoryStateView {
+    data: HashMap<StateKey, StateValue>,
+}
+
+impl InMemoryStateView {
+    pub fn insert(&mut self, state_key: StateKey, bytes: Vec<u8>) {
+        self.data.insert(state_key, StateValue::new_legacy(bytes));
+    }
+}
+
+impl TStateView for InMemoryStateView {
+    type Key = StateKey;
+
+    fn get_state_value(&self, state_key: &StateKey) -> anyhow::Result<Option<StateValue>> {
+        Ok(self.data.get(state_key).cloned())
+    }
+
+    fn is_genesis(&self) -> bool {
+        false
+    }
+
+    fn get_usage(&self) -> anyhow::Result<StateStorageUsage> {
+        Ok(StateStorageUsage::new_untracked())
+    }
+}
+
+/// Builds the state and the VM configuration sessions are tested against, e.g.
+///
+/// ```ignore
+/// let env = SessionTestHarness::new()
+///     .with_module(&module)
+///     .with_resource(addr, struct_tag, bytes)
+///     .build()?;
+/// let mut session = env.new_session();
+/// session.execute_function(&module.self_id(), ident_str!("run"), vec![], vec![])?;
+/// let change_set = session.finish_and_inspect()?;
+/// ```
+pub struct SessionTestHarness {
+    state_view: InMemoryStateView,
+    features: Features,
+    gas_feature_version: u64,
+    change_set_configs: Option<ChangeSetConfigs>,
+}
+
+impl Default for SessionTestHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionTestHarness {
+    pub fn new() -> Self {
+        Self {
+            state_view: InMemoryStateView::default(),
+            features: Features::default(),
+            gas_feature_version: LATEST_GAS_FEATURE_VERSION,
+            change_set_configs: None,
+        }
+    }
+
+    fn with_value(mut self, state_key: StateKey, bytes: Vec<u8>) -> Self {
+        self.state_view.insert(state_key, bytes);
+        self
+    }
+
+    pub fn with_resource(
+        self,
+        addr: AccountAddress,
+        struct_tag: StructTag,
+        bytes: Vec<u8>,
+    ) -> Self {
+        let access_path = AccessPath::resource_access_path(addr, struct_tag)
+            .expect("struct tag should be serializable");
+        self.with_value(StateKey::access_path(access_path), bytes)
+    }
+
+    pub fn with_resource_group(
+        self,
+        addr: AccountAddress,
+        group_tag: StructTag,
+        members: BTreeMap<StructTag, Vec<u8>>,
+    ) -> Self {
+        let bytes = bcs::to_bytes(&members).expect("resource group should be serializable");
+        self.with_value(
+            StateKey::access_path(AccessPath::resource_group_access_path(addr, group_tag)),
+            bytes,
+        )
+    }
+
+    pub fn with_table_item(self, handle: TableHandle, key: Vec<u8>, value: Vec<u8>) -> Self {
+        self.with_value(StateKey::table_item(handle, key), value)
+    }
+
+    pub fn with_module(self, module: &CompiledModule) -> Self {
+        let mut bytes = vec![];
+        module
+            .serialize(&mut bytes)
+            .expect("module should be serializable");
+        self.with_value(
+            StateKey::access_path(AccessPath::from(&module.self_id())),
+            bytes,
+        )
+    }
+
+    pub fn with_feature(mut self, flag: FeatureFlag) -> Self {
+        let val = flag as u64;
+        let byte_index = (val / 8) as usize;
+        if self.features.features.len() <= byte_index {
+            self.features.features.resize(byte_index + 1, 0);
+        }
+        self.features.features[byte_index] |= 1 << (val % 8);
+        self
+    }
+
+    pub fn with_gas_feature_version(mut self, gas_feature_version: u64) -> Self {
+        self.gas_feature_version = gas_feature_version;
+        self
+    }
+
+    /// Finishes sessions with `configs` instead of configs without limits.
+    pub fn with_change_set_configs(mut self, configs: ChangeSetConfigs) -> Self {
+        self.change_set_configs = Some(configs);
+        self
+    }
+
+    pub fn build(self) -> VMResult<SessionTestEnv> {
+        let vm = MoveVmExt::new(
+            NativeGasParameters::zeros(),
+            AbstractValueSizeGasParameters::zeros(),
+            self.gas_feature_version,
+            0,
+            self.features,
+            TimedFeatures::enable_all(),
+        )?;
+        Ok(SessionTestEnv {
+            vm,
+            resolver: self.state_view.into_move_resolver(),
+            configs: Arc::new(self.change_set_configs.unwrap_or_else(|| {
+                ChangeSetConfigs::unlimited_at_gas_feature_version(self.gas_feature_version)
+            })),
+        })
+    }
+}
+
+/// The VM and the state built by a `SessionTestHarness`.
+pub struct SessionTestEnv {
+    vm: MoveVmExt,
+    resolver: StorageAdapterOwned<InMemoryStateView>,
+    configs: Arc<ChangeSetConfigs>,
+}
+
+impl SessionTestEnv {
+    pub fn vm(&self) -> &MoveVmExt {
+        &self.vm
+    }
+
+    /// A chain of sessions over the state, see `SessionChain`.
+    pub fn session_chain(&self) -> SessionChain<'_, InMemoryStateView> {
+        SessionChain::new(&self.vm, &*self.resolver, self.configs.clone())
+    }
+
+    pub fn new_session(&self) -> HarnessSession<'_> {
+        HarnessSession {
+            session: self.vm.new_session(&self.resolver, SessionId::void()),
+            state_view: &self.resolver,
+            configs: &self.configs,
+        }
+    }
+}
+
+pub struct HarnessSession<'a> {
+    session: SessionExt<'a, 'a, StorageAdapterOwned<InMemoryStateView>>,
+    state_view: &'a InMemoryStateView,
+    configs: &'a Arc<ChangeSetConfigs>,
+}
+
+impl<'a> HarnessSession<'a> {
+    pub fn session_mut(
+        &mut self,
+    ) -> &mut SessionExt<'a, 'a, StorageAdapterOwned<InMemoryStateView>> {
+        &mut self.session
+    }
+
+    /// Runs the function regardless of its visibility, without metering gas.
+    pub fn execute_function(
+        &mut self,
+        module_id: &ModuleId,
+        function_name: &IdentStr,
+        ty_args: Vec<TypeTag>,
+        args: Vec<Vec<u8>>,
+    ) -> VMResult<SerializedReturnValues> {
+        self.session.execute_function_bypass_visibility(
+            module_id,
+            function_name,
+            ty_args,
+            args,
+            &mut UnmeteredGasMeter,
+        )
+    }
+
+    /// Finishes the session as a view function's, see `SessionExt::finish_view`.
+    pub fn finish_view(self) -> VMResult<(Vec<ContractEvent>, Vec<(StateKey, u128)>)> {
+        self.session.finish_view(self.state_view)
+    }
+
+    pub fn finish_and_inspect(self) -> VMResult<InspectedChangeSet> {
+        self.session
+            .finish(&mut (), self.configs)
+            .map(InspectedChangeSet)
+    }
+
+    pub fn finish_with_read_stats(self) -> VMResult<(InspectedChangeSet, FinishReadStats)> {
+        self.session
+            .finish_with_read_stats(&mut (), self.configs, &mut NoGroupGas)
+            .map(|(change_set, read_stats)| (InspectedChangeSet(change_set), read_stats))
+    }
+}
+
+/// A finished change set, with lookups by what was written rather than by state key.
+pub struct InspectedChangeSet(ChangeSetExt);
+
+impl InspectedChangeSet {
+    pub fn change_set_ext(&self) -> &ChangeSetExt {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> ChangeSetExt {
+        self.0
+    }
+
+    pub fn num_writes(&self) -> usize {
+        self.0.write_set().iter().count()
+    }
+
+    pub fn resource(&self, addr: AccountAddress, struct_tag: StructTag) -> Option<&WriteOp> {
+        let access_path = AccessPath::resource_access_path(addr, struct_tag).ok()?;
+        self.0.write_set().get(&StateKey::access_path(access_path))
+    }
+
+    pub fn resource_group(&self, addr: AccountAddress, group_tag: StructTag) -> Option<&WriteOp> {
+        self.0.write_set().get(&StateKey::access_path(
+            AccessPath::resource_group_access_path(addr, group_tag),
+        ))
+    }
+
+    /// The members of the written resource group, if it was written and not deleted.
+    pub fn resource_group_members(
+        &self,
+        addr: AccountAddress,
+        group_tag: StructTag,
+    ) -> Option<BTreeMap<StructTag, Vec<u8>>> {
+        let bytes = self.resource_group(addr, group_tag)?.bytes()?;
+        bcs::from_bytes(bytes).ok()
+    }
+
+    pub fn table_item(&self, handle: TableHandle, key: Vec<u8>) -> Option<&WriteOp> {
+        self.0.write_set().get(&StateKey::table_item(handle, key))
+    }
+
+    pub fn events(&self) -> &[ContractEvent] {
+        self.0.change_set().events()
+    }
+}
@@ -5,14 +5,20 @@ use crate::{
     move_vm_ext::{MoveResolverExt, SessionExt, SessionId},
     natives::aptos_natives,
 };
+#[cfg(feature = "feature-usage")]
+use aptos_framework::natives::feature_usage::NativeFeatureUsageContext;
 use aptos_framework::natives::{
     aggregator_natives::NativeAggregatorContext,
     code::NativeCodeContext,
     cryptography::{algebra::AlgebraContext, ristretto255_point::NativeRistrettoPointContext},
+    event::NativeEventContext,
+    object::NativeObjectContext,
     state_storage::NativeStateStorageContext,
-    transaction_context::NativeTransactionContext,
+    transaction_context::{NativeTransactionContext, NativeWriteBudgetContext},
 };
 use aptos_gas::{AbstractValueSizeGasParameters, NativeGasParameters};
+#[cfg(feature = "feature-usage")]
+use aptos_types::on_chain_config::FeatureUsageRecorder;
 use aptos_types::on_chain_config::{FeatureFlag, Features, TimedFeatureFlag, TimedFeatures};
 use move_binary_format::errors::VMResult;
 use move_bytecode_verifier::VerifierConfig;
@@ -25,6 +31,8 @@ use std::{ops::Deref, sync::Arc};
 pub struct MoveVmExt {
     inner: MoveVM,
     chain_id: u8,
+    features: Arc<Features>,
+    strip_noop_modifications: bool,
 }
 
 impl MoveVmExt {
@@ -47,6 +55,11 @@ impl MoveVmExt {
             };
 
         let treat_friend_as_private = features.is_enabled(FeatureFlag::TREAT_FRIEND_AS_PRIVATE);
+        // Note: dropping no-op modifications changes the write sets transactions produce, so it
+        //       has to wait for the gas schedule that introduced it, like the binary format above.
+        let strip_noop_modifications =
+            features.is_enabled(FeatureFlag::STRIP_NOOP_MODIFICATIONS) && gas_feature_version >= 9;
+        let features = Arc::new(features);
 
         Ok(Self {
             inner: MoveVM::new_with_config(
@@ -55,7 +68,7 @@ impl MoveVmExt {
                     abs_val_size_gas_params,
                     gas_feature_version,
                     timed_features.clone(),
-                    Arc::new(features),
+                    features.clone(),
                 ),
                 VMConfig {
                     verifier: verifier_config(treat_friend_as_private, &timed_features),
@@ -64,6 +77,8 @@ impl MoveVmExt {
                 },
             )?,
             chain_id,
+            features,
+            strip_noop_modifications,
         })
     }
 
@@ -84,28 +99,54 @@ impl MoveVmExt {
         extensions.add(AlgebraContext::new());
         extensions.add(NativeAggregatorContext::new(txn_hash, remote));
 
-        let script_hash = match session_id {
+        let script_hash = match &session_id {
             SessionId::Txn {
                 sender: _,
                 sequence_number: _,
                 script_hash,
-            } => script_hash,
+            } => script_hash.clone(),
             _ => vec![],
         };
 
-        extensions.add(NativeTransactionContext::new(script_hash, self.chain_id));
+        extensions.add(NativeTransactionContext::new(
+            txn_hash,
+            script_hash,
+            self.chain_id,
+        ));
         extensions.add(NativeCodeContext::default());
         extensions.add(NativeStateStorageContext::new(remote));
+        extensions.add(NativeObjectContext::new(remote));
+        extensions.add(NativeEventContext::new());
+        extensions.add(NativeWriteBudgetContext::new());
+
+        let validate_resource_group_containers = self
+            .features
+            .is_enabled(FeatureFlag::RESOURCE_GROUP_CONTAINER_VALIDATION);
+        let validate_aggregator_handles = self
+            .features
+            .is_enabled(FeatureFlag::AGGREGATOR_HANDLE_VALIDATION);
+        let validate_event_keys = self.features.is_enabled(FeatureFlag::EVENT_KEY_VALIDATION);
+        // Natives record the flags they check, see `SessionExt::feature_flags_read`.
+        #[cfg(feature = "feature-usage")]
+        extensions.add(NativeFeatureUsageContext::new(FeatureUsageRecorder::new(
+            self.features.clone(),
+        )));
 
         // The VM code loader has bugs around module upgrade. After a module upgrade, the internal
         // cache needs to be flushed to work around those bugs.
         self.inner.flush_loader_cache_if_invalidated();
 
-        SessionExt::new(
+        let mut session = SessionExt::new(
             self.inner.new_session_with_extensions(remote, extensions),
             self,
             remote,
-        )
+            &session_id,
+        );
+        session.set_validate_resource_group_containers(validate_resource_group_containers);
+        session.set_strip_noop_modifications(self.strip_noop_modifications);
+        session.set_validate_aggregator_handles(validate_aggregator_handles);
+        session.set_validate_event_keys(validate_event_keys);
+        session
     }
 }
 
@@ -0,0 +1,34 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use move_core_types::{account_address::AccountAddress, language_storage::StructTag};
+
+/// Outcome of consulting a [`WritePolicy`] about a single resource write.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WriteDecision {
+    Allow,
+    Deny,
+}
+
+/// Decides whether a session is allowed to write a resource. This is consulted for every resource
+/// op when a session is finished, for the members of resource groups as well as for the groups
+/// themselves, and a single denial fails the whole session with `RESOURCE_WRITE_DENIED`.
+///
+/// Meant as defense-in-depth when a session runs code on behalf of someone else, e.g. to keep
+/// delegated code away from certain resources of the sender.
+pub trait WritePolicy: Send + Sync {
+    fn check_resource_write(&self, addr: &AccountAddress, struct_tag: &StructTag) -> WriteDecision;
+}
+
+/// The default policy, allowing every write.
+pub struct AllowAllWrites;
+
+impl WritePolicy for AllowAllWrites {
+    fn check_resource_write(
+        &self,
+        _addr: &AccountAddress,
+        _struct_tag: &StructTag,
+    ) -> WriteDecision {
+        WriteDecision::Allow
+    }
+}
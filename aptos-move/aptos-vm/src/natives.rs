@@ -15,11 +15,18 @@ use move_vm_runtime::native_functions::NativeFunctionTable;
 use std::sync::Arc;
 #[cfg(feature = "testing")]
 use {
-    aptos_framework::natives::{
-        aggregator_natives::NativeAggregatorContext, code::NativeCodeContext,
-        cryptography::ristretto255_point::NativeRistrettoPointContext,
-        transaction_context::NativeTransactionContext,
+    aptos_framework::{
+        natives::{
+            aggregator_natives::NativeAggregatorContext,
+            code::NativeCodeContext,
+            cryptography::ristretto255_point::NativeRistrettoPointContext,
+            event::NativeEventContext,
+            object::{ModuleMetadataResolver, NativeObjectContext},
+            transaction_context::{NativeTransactionContext, NativeWriteBudgetContext},
+        },
+        RuntimeModuleMetadataV1,
     },
+    move_core_types::language_storage::ModuleId,
     move_vm_runtime::native_extensions::NativeContextExtensions,
     move_vm_test_utils::BlankStorage,
     once_cell::sync::Lazy,
@@ -28,6 +35,18 @@ use {
 #[cfg(feature = "testing")]
 static DUMMY_RESOLVER: Lazy<BlankStorage> = Lazy::new(|| BlankStorage);
 
+/// Move unit tests run without Aptos module metadata, so no type is a resource group (member)
+/// there.
+#[cfg(feature = "testing")]
+struct NoModuleMetadata;
+
+#[cfg(feature = "testing")]
+impl ModuleMetadataResolver for NoModuleMetadata {
+    fn resolve_module_metadata(&self, _module_id: &ModuleId) -> Option<RuntimeModuleMetadataV1> {
+        None
+    }
+}
+
 pub fn aptos_natives(
     gas_params: NativeGasParameters,
     abs_val_size_gas_params: AbstractValueSizeGasParameters,
@@ -91,8 +110,15 @@ pub fn configure_for_unit_test() {
 #[cfg(feature = "testing")]
 fn unit_test_extensions_hook(exts: &mut NativeContextExtensions) {
     exts.add(NativeCodeContext::default());
-    exts.add(NativeTransactionContext::new(vec![1], ChainId::test().id())); // We use the testing environment chain ID here
+    exts.add(NativeTransactionContext::new(
+        [0; 32],
+        vec![1],
+        ChainId::test().id(),
+    )); // We use the testing environment chain ID here
     exts.add(NativeAggregatorContext::new([0; 32], &*DUMMY_RESOLVER));
     exts.add(NativeRistrettoPointContext::new());
     exts.add(AlgebraContext::new());
+    exts.add(NativeEventContext::new());
+    exts.add(NativeWriteBudgetContext::new());
+    exts.add(NativeObjectContext::new(&NoModuleMetadata));
 }
@@ -0,0 +1,376 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Golden snapshots of the change sets canonical session outputs are converted into, under every
+//! combination of the settings conversion branches on. Each snapshot holds the `ChangeSetExt` as
+//! exported by `to_bytes`, after a readable summary of it, so that any change to conversion shows
+//! up as a reviewed diff of `tests/goldens` rather than as a surprise when replaying history.
+//!
+//! Combinations converting to the same change set share a snapshot. After an intended change to
+//! conversion, regenerate the goldens with
+//!
+//! ```text
+//! UPDATE_GOLDENFILES=1 cargo test -p aptos-vm --features fuzzing --test change_set_goldens
+//! ```
+
+use aptos_aggregator::{
+    delta_change_set::delta_add,
+    human_readable::{DecodingAnnotator, HumanReadableKey, HumanReadableWriteKind},
+    transaction::ChangeSetExt,
+};
+use aptos_framework::natives::aggregator_natives::AggregatorChange;
+use aptos_gas::{ChangeSetConfigs, LATEST_GAS_FEATURE_VERSION};
+use aptos_types::{
+    access_path::AccessPath,
+    account_config::AccountResource,
+    event::EventKey,
+    state_store::{state_key::StateKey, table::TableHandle},
+};
+use aptos_vm::fuzzing::{
+    convert_parts, struct_tag, BaseState, ChangeSetParts, GROUP_MEMBERS, PLAIN_RESOURCE,
+};
+use goldenfile::Mint;
+use move_core_types::{
+    account_address::AccountAddress,
+    effects::Op,
+    identifier::Identifier,
+    language_storage::{StructTag, TypeTag},
+    move_resource::MoveStructType,
+    vm_status::VMStatus,
+};
+use std::{collections::BTreeMap, fmt::Write as _, io::Write, path::PathBuf, sync::Arc};
+
+/// The gas feature versions snapshotted: the last one with the legacy resource creation and
+/// resource group semantics, and the latest.
+const GAS_FEATURE_VERSIONS: [(u64, &str); 2] = [(2, "2"), (LATEST_GAS_FEATURE_VERSION, "latest")];
+
+/// The settings a session's output is converted under.
+#[derive(Clone, Copy)]
+struct Settings {
+    gas_feature_version: (u64, &'static str),
+    validate_resource_group_containers: bool,
+    defer_resource_group_reads: bool,
+    strip_noop_modifications: bool,
+}
+
+impl Settings {
+    fn matrix() -> Vec<Self> {
+        let mut matrix = vec![];
+        for gas_feature_version in GAS_FEATURE_VERSIONS {
+            for validate_resource_group_containers in [false, true] {
+                for defer_resource_group_reads in [false, true] {
+                    for strip_noop_modifications in [false, true] {
+                        matrix.push(Self {
+                            gas_feature_version,
+                            validate_resource_group_containers,
+                            defer_resource_group_reads,
+                            strip_noop_modifications,
+                        });
+                    }
+                }
+            }
+        }
+        matrix
+    }
+
+    fn label(&self) -> String {
+        format!(
+            "gas_feature_version={} validate_resource_group_containers={} \
+             defer_resource_group_reads={} strip_noop_modifications={}",
+            self.gas_feature_version.1,
+            self.validate_resource_group_containers,
+            self.defer_resource_group_reads,
+            self.strip_noop_modifications,
+        )
+    }
+}
+
+/// A canonical session output, along with the state it is converted against.
+struct Scenario {
+    name: &'static str,
+    parts: ChangeSetParts,
+    base_state: BaseState,
+}
+
+fn address(i: u8) -> AccountAddress {
+    let mut bytes = [0u8; AccountAddress::LENGTH];
+    bytes[AccountAddress::LENGTH - 1] = i;
+    AccountAddress::new(bytes)
+}
+
+fn resource_key(addr: AccountAddress, struct_tag: StructTag) -> StateKey {
+    StateKey::access_path(AccessPath::resource_access_path(addr, struct_tag).unwrap())
+}
+
+fn members(members: &[(&str, Vec<u8>)]) -> BTreeMap<StructTag, Vec<u8>> {
+    members
+        .iter()
+        .map(|(name, data)| (struct_tag(name), data.clone()))
+        .collect()
+}
+
+fn empty_parts() -> ChangeSetParts {
+    ChangeSetParts {
+        resources: vec![],
+        modules: vec![],
+        table_items: vec![],
+        aggregators: vec![],
+        events: vec![],
+        validate_resource_group_containers: false,
+        defer_resource_group_reads: false,
+        strip_noop_modifications: false,
+    }
+}
+
+fn empty_base_state() -> BaseState {
+    BaseState {
+        groups: vec![],
+        values: vec![],
+    }
+}
+
+fn scenarios() -> Vec<Scenario> {
+    let plain = || struct_tag(PLAIN_RESOURCE);
+    let member = GROUP_MEMBERS[0];
+    let other_member = GROUP_MEMBERS[1];
+    let table = address(7);
+    let aggregators = address(8);
+
+    vec![
+        // Created, modified, deleted, and modified to what is already stored.
+        Scenario {
+            name: "resources",
+            parts: ChangeSetParts {
+                resources: vec![
+                    (address(2), plain(), Op::New(vec![1])),
+                    (address(3), plain(), Op::Modify(vec![2])),
+                    (address(4), plain(), Op::Delete),
+                    (address(5), plain(), Op::Modify(vec![5])),
+                ],
+                ..empty_parts()
+            },
+            base_state: BaseState {
+                values: vec![
+                    (resource_key(address(3), plain()), vec![1]),
+                    (resource_key(address(4), plain()), vec![1]),
+                    (resource_key(address(5), plain()), vec![5]),
+                ],
+                ..empty_base_state()
+            },
+        },
+        // A member added to an existing group, a group created under an account, a group left
+        // empty, and a member modified to what is already stored.
+        Scenario {
+            name: "resource_groups",
+            parts: ChangeSetParts {
+                resources: vec![
+                    (address(2), struct_tag(member), Op::New(vec![1])),
+                    (address(3), struct_tag(member), Op::New(vec![3])),
+                    (address(4), struct_tag(member), Op::Delete),
+                    (address(5), struct_tag(member), Op::Modify(vec![5])),
+                ],
+                ..empty_parts()
+            },
+            base_state: BaseState {
+                groups: vec![
+                    (address(2), members(&[(other_member, vec![2])])),
+                    (address(4), members(&[(member, vec![4])])),
+                    (address(5), members(&[(member, vec![5])])),
+                ],
+                values: vec![(
+                    resource_key(address(3), AccountResource::struct_tag()),
+                    vec![0],
+                )],
+            },
+        },
+        // A group created at an address holding neither an account nor an object.
+        Scenario {
+            name: "resource_group_without_container",
+            parts: ChangeSetParts {
+                resources: vec![(address(6), struct_tag(member), Op::New(vec![6]))],
+                ..empty_parts()
+            },
+            base_state: empty_base_state(),
+        },
+        Scenario {
+            name: "table_items",
+            parts: ChangeSetParts {
+                table_items: vec![
+                    (table, vec![1], Op::New(vec![1])),
+                    (table, vec![2], Op::Modify(vec![2])),
+                    (table, vec![3], Op::Delete),
+                    (table, vec![4], Op::Modify(vec![4])),
+                ],
+                ..empty_parts()
+            },
+            base_state: BaseState {
+                values: vec![
+                    (StateKey::table_item(TableHandle(table), vec![2]), vec![1]),
+                    (StateKey::table_item(TableHandle(table), vec![3]), vec![1]),
+                    (StateKey::table_item(TableHandle(table), vec![4]), vec![4]),
+                ],
+                ..empty_base_state()
+            },
+        },
+        Scenario {
+            name: "aggregators",
+            parts: ChangeSetParts {
+                aggregators: vec![
+                    (aggregators, address(1), AggregatorChange::Write(5)),
+                    (
+                        aggregators,
+                        address(2),
+                        AggregatorChange::Merge(delta_add(3, 100)),
+                    ),
+                    (aggregators, address(3), AggregatorChange::Delete),
+                    (aggregators, address(4), AggregatorChange::Write(7)),
+                ],
+                ..empty_parts()
+            },
+            base_state: BaseState {
+                values: vec![
+                    (
+                        StateKey::table_item(TableHandle(aggregators), address(2).to_vec()),
+                        bcs::to_bytes(&10u128).unwrap(),
+                    ),
+                    (
+                        StateKey::table_item(TableHandle(aggregators), address(3).to_vec()),
+                        bcs::to_bytes(&10u128).unwrap(),
+                    ),
+                    (
+                        StateKey::table_item(TableHandle(aggregators), address(4).to_vec()),
+                        bcs::to_bytes(&7u128).unwrap(),
+                    ),
+                ],
+                ..empty_base_state()
+            },
+        },
+        // Conversion does not look into module bytes, so these need not be valid modules.
+        Scenario {
+            name: "modules",
+            parts: ChangeSetParts {
+                modules: vec![
+                    (
+                        address(9),
+                        Identifier::new("published").unwrap(),
+                        Op::New(vec![0xA1, 0x1C, 0xEB, 0x0B]),
+                    ),
+                    (
+                        address(9),
+                        Identifier::new("upgraded").unwrap(),
+                        Op::Modify(vec![0xA1, 0x1C, 0xEB, 0x0B]),
+                    ),
+                ],
+                ..empty_parts()
+            },
+            base_state: empty_base_state(),
+        },
+        Scenario {
+            name: "events",
+            parts: ChangeSetParts {
+                events: vec![
+                    (
+                        bcs::to_bytes(&EventKey::new(0, address(2))).unwrap(),
+                        0,
+                        TypeTag::U64,
+                        bcs::to_bytes(&1u64).unwrap(),
+                    ),
+                    (
+                        bcs::to_bytes(&EventKey::new(1, address(3))).unwrap(),
+                        5,
+                        TypeTag::U64,
+                        bcs::to_bytes(&2u64).unwrap(),
+                    ),
+                ],
+                ..empty_parts()
+            },
+            base_state: empty_base_state(),
+        },
+    ]
+}
+
+fn render_key(key: &HumanReadableKey) -> String {
+    match key {
+        HumanReadableKey::Module { address, name } => format!("module {}::{}", address, name),
+        HumanReadableKey::Resource { address, resource } => {
+            format!("resource {} at {}", resource, address)
+        },
+        HumanReadableKey::ResourceGroup { address, group } => {
+            format!("group {} at {}", group, address)
+        },
+        HumanReadableKey::TableItem { handle, key } => format!("table item {} in {}", key, handle),
+        HumanReadableKey::Raw { key } => format!("raw {}", key),
+    }
+}
+
+fn render(result: Result<ChangeSetExt, VMStatus>) -> String {
+    let change_set = match result {
+        Ok(change_set) => change_set,
+        Err(status) => return format!("error {:?}\n", status.status_code()),
+    };
+
+    let mut out = String::new();
+    let human_readable = change_set.to_human_readable(&DecodingAnnotator);
+    for write in human_readable.writes {
+        let kind = match write.kind {
+            HumanReadableWriteKind::Creation => "creation",
+            HumanReadableWriteKind::Modification => "modification",
+            HumanReadableWriteKind::Deletion => "deletion",
+        };
+        write!(out, "{} {}", kind, render_key(&write.key)).unwrap();
+        if let Some(data) = write.data {
+            write!(out, ": {}", data).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    for delta in human_readable.deltas {
+        writeln!(out, "delta {}: {}", render_key(&delta.key), delta.delta).unwrap();
+    }
+    for event in change_set.change_set().events() {
+        writeln!(
+            out,
+            "event {}: {}",
+            event.type_tag(),
+            hex::encode(event.event_data())
+        )
+        .unwrap();
+    }
+    writeln!(out, "bcs {}", hex::encode(change_set.to_bytes().unwrap())).unwrap();
+    out
+}
+
+#[test]
+fn change_sets_match_goldens() {
+    let mut mint = Mint::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/goldens"));
+    for scenario in scenarios() {
+        // The distinct conversions, in the order they were first seen, with the settings
+        // producing each.
+        let mut snapshots: Vec<(String, Vec<String>)> = vec![];
+        for settings in Settings::matrix() {
+            let parts = ChangeSetParts {
+                validate_resource_group_containers: settings.validate_resource_group_containers,
+                defer_resource_group_reads: settings.defer_resource_group_reads,
+                strip_noop_modifications: settings.strip_noop_modifications,
+                ..scenario.parts.clone()
+            };
+            let configs = Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                settings.gas_feature_version.0,
+            ));
+            let snapshot = render(convert_parts(parts, scenario.base_state.clone(), configs));
+            match snapshots.iter_mut().find(|(seen, _)| *seen == snapshot) {
+                Some((_, labels)) => labels.push(settings.label()),
+                None => snapshots.push((snapshot, vec![settings.label()])),
+            }
+        }
+
+        let mut file = mint
+            .new_goldenfile(format!("{}.exp", scenario.name))
+            .unwrap();
+        for (snapshot, labels) in snapshots {
+            for label in labels {
+                writeln!(file, "## {}", label).unwrap();
+            }
+            writeln!(file, "{}", snapshot).unwrap();
+        }
+    }
+}
@@ -42,6 +42,10 @@ pub static RAYON_EXEC_POOL: Lazy<rayon::ThreadPool> = Lazy::new(|| {
         .unwrap()
 });
 
+/// How many times a transaction is executed when its execution keeps returning
+/// `ExecutionStatus::Retry`, before the error is propagated to the caller.
+const MAX_EXECUTION_ATTEMPTS: usize = 3;
+
 pub struct BlockExecutor<T, E, S> {
     // number of active concurrent tasks, corresponding to the maximum number of rayon
     // threads that may be concurrently participating in parallel execution.
@@ -83,15 +87,22 @@ where
         let (idx_to_execute, incarnation) = version;
         let txn = &signature_verified_block[idx_to_execute as usize];
 
-        let speculative_view = MVHashMapView::new(versioned_cache, scheduler);
-
-        // VM execution.
-        let execute_result = executor.execute_transaction(
-            &LatestView::<T, S>::new_mv_view(base_view, &speculative_view, idx_to_execute),
-            txn,
-            idx_to_execute,
-            false,
-        );
+        // VM execution. An execution that is retried starts over with a fresh view, so that
+        // the reads of the failed attempt are not recorded.
+        let mut attempt = 1;
+        let (speculative_view, execute_result) = loop {
+            let speculative_view = MVHashMapView::new(versioned_cache, scheduler);
+            let execute_result = executor.execute_transaction(
+                &LatestView::<T, S>::new_mv_view(base_view, &speculative_view, idx_to_execute),
+                txn,
+                idx_to_execute,
+                false,
+            );
+            match execute_result {
+                ExecutionStatus::Retry(_) if attempt < MAX_EXECUTION_ATTEMPTS => attempt += 1,
+                execute_result => break (speculative_view, execute_result),
+            }
+        };
         let mut prev_modified_keys = last_input_output.modified_keys(idx_to_execute);
 
         // For tracking whether the recent execution wrote outside of the previous write/delta set.
@@ -130,8 +141,8 @@ where
                 apply_updates(&output);
                 ExecutionStatus::SkipRest(output)
             },
-            ExecutionStatus::Abort(err) => {
-                // Record the status indicating abort.
+            ExecutionStatus::Abort(err) | ExecutionStatus::Retry(err) => {
+                // Record the status indicating abort, also if retrying did not help.
                 ExecutionStatus::Abort(Error::UserError(err))
             },
         };
@@ -316,7 +327,7 @@ where
                         final_results.push(t);
                         break;
                     },
-                    ExecutionStatus::Abort(err) => {
+                    ExecutionStatus::Abort(err) | ExecutionStatus::Retry(err) => {
                         ret = Some(err);
                         break;
                     },
@@ -359,12 +370,19 @@ where
 
         let mut ret = Vec::with_capacity(num_txns);
         for (idx, txn) in signature_verified_block.iter().enumerate() {
-            let res = executor.execute_transaction(
-                &LatestView::<T, S>::new_btree_view(base_view, &data_map, idx as TxnIndex),
-                txn,
-                idx as TxnIndex,
-                true,
-            );
+            let mut attempt = 1;
+            let res = loop {
+                let res = executor.execute_transaction(
+                    &LatestView::<T, S>::new_btree_view(base_view, &data_map, idx as TxnIndex),
+                    txn,
+                    idx as TxnIndex,
+                    true,
+                );
+                match res {
+                    ExecutionStatus::Retry(_) if attempt < MAX_EXECUTION_ATTEMPTS => attempt += 1,
+                    res => break res,
+                }
+            };
 
             let must_skip = matches!(res, ExecutionStatus::SkipRest(_));
 
@@ -381,8 +399,8 @@ where
                     }
                     ret.push(output);
                 },
-                ExecutionStatus::Abort(err) => {
-                    // Record the status indicating abort.
+                ExecutionStatus::Abort(err) | ExecutionStatus::Retry(err) => {
+                    // Record the status indicating abort, also if retrying did not help.
                     return Err(Error::UserError(err));
                 },
             }
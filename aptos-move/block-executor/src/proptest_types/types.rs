@@ -70,6 +70,47 @@ where
     }
 }
 
+/// Fails to serve the first `failures` reads, as storage of a node might, and afterwards serves
+/// reads like `DeltaDataView`.
+pub(crate) struct FlakyDataView<K, V> {
+    pub(crate) failures: AtomicUsize,
+    pub(crate) phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> TStateView for FlakyDataView<K, V>
+where
+    K: PartialOrd + Ord + Send + Sync + Clone + Hash + Eq + ModulePath + 'static,
+    V: Debug + Send + Sync + Debug + Clone + TransactionWrite + 'static,
+{
+    type Key = K;
+
+    /// Gets the state value for a given state key.
+    fn get_state_value(&self, _: &K) -> anyhow::Result<Option<StateValue>> {
+        if self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+        {
+            anyhow::bail!("Storage unavailable");
+        }
+        Ok(Some(StateValue::new_legacy(serialize(
+            &STORAGE_AGGREGATOR_VALUE,
+        ))))
+    }
+
+    fn id(&self) -> StateViewId {
+        StateViewId::Miscellaneous
+    }
+
+    fn is_genesis(&self) -> bool {
+        unreachable!();
+    }
+
+    fn get_usage(&self) -> anyhow::Result<StateStorageUsage> {
+        unreachable!();
+    }
+}
+
 pub(crate) struct EmptyDataView<K, V> {
     pub(crate) phantom: PhantomData<(K, V)>,
 }
@@ -448,8 +489,11 @@ where
                 // Reads
                 let mut reads_result = vec![];
                 for k in reads[read_idx].iter() {
-                    // TODO: later test errors as well? (by fixing state_view behavior).
-                    reads_result.push(view.get_state_value_bytes(k).unwrap());
+                    match view.get_state_value_bytes(k) {
+                        Ok(value) => reads_result.push(value),
+                        // Failing to read is not caused by the transaction, execute it again.
+                        Err(_) => return ExecutionStatus::Retry(txn_idx as usize),
+                    }
                 }
                 ExecutionStatus::Success(Output(
                     writes_and_deltas[write_idx].0.clone(),
@@ -19,6 +19,10 @@ pub enum ExecutionStatus<T, E> {
     /// Transaction was executed successfully, but will skip the execution of the trailing
     /// transactions in the list
     SkipRest(T),
+    /// Transaction could not be executed because of a transient failure of the node, e.g.
+    /// storage failing to serve a read. The transaction is executed again, and the error is
+    /// propagated back to the caller only if it persists.
+    Retry(E),
 }
 
 /// Trait that defines a transaction that could be parallel executed by the scheduler. Each
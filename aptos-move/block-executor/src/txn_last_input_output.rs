@@ -189,7 +189,7 @@ impl<K: ModulePath, T: TransactionOutput, E: Send + Clone> TxnLastInputOutput<K,
                 .into_iter()
                 .filter_map(|(k, _)| k.module_path())
                 .collect(),
-            ExecutionStatus::Abort(_) => Vec::new(),
+            ExecutionStatus::Abort(_) | ExecutionStatus::Retry(_) => Vec::new(),
         };
 
         if !self.module_read_write_intersection.load(Ordering::Relaxed) {
@@ -226,7 +226,7 @@ impl<K: ModulePath, T: TransactionOutput, E: Send + Clone> TxnLastInputOutput<K,
                     .map(|(k, _)| k)
                     .chain(t.get_deltas().into_iter().map(|(k, _)| k))
                     .collect(),
-                ExecutionStatus::Abort(_) => HashSet::new(),
+                ExecutionStatus::Abort(_) | ExecutionStatus::Retry(_) => HashSet::new(),
             },
         }
     }
@@ -3,8 +3,11 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    errors::Error,
     executor::BlockExecutor,
-    proptest_types::types::{DeltaDataView, ExpectedOutput, KeyType, Task, Transaction, ValueType},
+    proptest_types::types::{
+        DeltaDataView, ExpectedOutput, FlakyDataView, KeyType, Task, Transaction, ValueType,
+    },
     scheduler::{Scheduler, SchedulerTask},
 };
 use aptos_aggregator::delta_change_set::{delta_add, delta_sub, DeltaOp, DeltaUpdate};
@@ -249,6 +252,50 @@ fn early_skips() {
     run_and_assert(transactions)
 }
 
+#[test]
+fn retry_on_storage_failure() {
+    let key = KeyType(random::<[u8; 32]>(), false);
+    let transactions = vec![Transaction::Write {
+        incarnation: Arc::new(AtomicUsize::new(0)),
+        reads: vec![vec![key]],
+        writes_and_deltas: vec![(vec![], vec![])],
+    }];
+    let executor = BlockExecutor::<
+        Transaction<KeyType<[u8; 32]>, ValueType<[u8; 32]>>,
+        Task<KeyType<[u8; 32]>, ValueType<[u8; 32]>>,
+        FlakyDataView<KeyType<[u8; 32]>, ValueType<[u8; 32]>>,
+    >::new(num_cpus::get());
+
+    // The first read fails, the transaction is executed again and succeeds.
+    let data_view = FlakyDataView {
+        failures: AtomicUsize::new(1),
+        phantom: PhantomData,
+    };
+    let output = executor
+        .execute_transactions_parallel((), &transactions, &data_view)
+        .map(|zipped| zipped.into_iter().map(|(res, _)| res).collect());
+    ExpectedOutput::generate_baseline(&transactions, None).assert_output(&output);
+
+    let data_view = FlakyDataView {
+        failures: AtomicUsize::new(1),
+        phantom: PhantomData,
+    };
+    let output = executor
+        .execute_transactions_sequential((), &transactions, &data_view)
+        .map(|zipped| zipped.into_iter().map(|(res, _)| res).collect());
+    ExpectedOutput::generate_baseline(&transactions, None).assert_output(&output);
+
+    // Storage keeps failing, the error is propagated once the attempts are exhausted.
+    let data_view = FlakyDataView {
+        failures: AtomicUsize::new(usize::MAX),
+        phantom: PhantomData,
+    };
+    assert_matches!(
+        executor.execute_transactions_sequential((), &transactions, &data_view),
+        Err(Error::UserError(0))
+    );
+}
+
 #[test]
 fn scheduler_tasks() {
     let s = Scheduler::new(5);
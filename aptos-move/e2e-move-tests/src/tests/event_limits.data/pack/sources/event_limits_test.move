@@ -0,0 +1,27 @@
+module 0xcafe::event_limits_test {
+    use aptos_framework::account;
+    use aptos_framework::event::{Self, EventHandle};
+    use std::signer;
+
+    /// Serializes to 8 bytes.
+    struct Ping has drop, store {
+        seq: u64,
+    }
+
+    struct Pings has key {
+        handle: EventHandle<Ping>,
+    }
+
+    fun init_module(account: &signer) {
+        move_to(account, Pings { handle: account::new_event_handle<Ping>(account) });
+    }
+
+    public entry fun emit(account: &signer, count: u64) acquires Pings {
+        let handle = &mut borrow_global_mut<Pings>(signer::address_of(account)).handle;
+        let i = 0;
+        while (i < count) {
+            event::emit_event(handle, Ping { seq: i });
+            i = i + 1;
+        }
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{assert_success, tests::common, MoveHarness};
+use aptos_types::{
+    account_address::AccountAddress,
+    transaction::{ExecutionStatus, TransactionStatus},
+};
+use move_core_types::{gas_algebra::NumBytes, vm_status::AbortLocation};
+
+#[test]
+fn event_limit_aborts_on_the_event_crossing_it() {
+    let mut h = MoveHarness::new();
+
+    let acc = h.new_account_at(AccountAddress::from_hex_literal("0xcafe").unwrap());
+    assert_success!(h.publish_package(&acc, &common::test_dir_path("event_limits.data/pack")));
+
+    // Room for three 8-byte events per transaction.
+    h.modify_gas_schedule(|gas_params| {
+        gas_params.txn.max_bytes_all_events_per_transaction = NumBytes::new(3 * 8);
+    });
+
+    let emit = |h: &mut MoveHarness, count: u64| {
+        let txn = h.create_entry_function(
+            &acc,
+            str::parse("0xcafe::event_limits_test::emit").unwrap(),
+            vec![],
+            vec![bcs::to_bytes(&count).unwrap()],
+        );
+        let max_gas_amount = txn.max_gas_amount();
+        let output = h.run_raw(txn);
+        (
+            output.status().to_owned(),
+            output.gas_used(),
+            max_gas_amount,
+        )
+    };
+    let assert_failed_in_event_native = |status: TransactionStatus| match status {
+        TransactionStatus::Keep(ExecutionStatus::ExecutionFailure { location, .. }) => {
+            assert_eq!(
+                location,
+                AbortLocation::Module(str::parse("0x1::event").unwrap())
+            );
+        },
+        status => panic!("unexpected status {:?}", status),
+    };
+
+    let (status, gas_used_at_three, _) = emit(&mut h, 3);
+    assert_success!(status);
+
+    // The fourth event fails in the event native, not when the transaction is finished...
+    let (status, gas_used_at_four, max_gas_amount) = emit(&mut h, 4);
+    assert_failed_in_event_native(status);
+    assert!(gas_used_at_four > gas_used_at_three);
+    assert!(
+        gas_used_at_four < max_gas_amount,
+        "used {} of {} gas",
+        gas_used_at_four,
+        max_gas_amount
+    );
+
+    // ...so asking for many more events stops at the same point, with the same gas remaining.
+    let (status, gas_used_at_hundred, _) = emit(&mut h, 100);
+    assert_failed_in_event_native(status);
+    assert_eq!(gas_used_at_hundred, gas_used_at_four);
+}
@@ -9,6 +9,7 @@ mod code_publishing;
 mod common;
 mod constructor_args;
 mod error_map;
+mod event_limits;
 mod framework_compatibility;
 mod gas;
 mod generate_upgrade_script;
@@ -28,6 +29,7 @@ mod per_category_gas_limits;
 mod resource_groups;
 mod rotate_auth_key;
 mod scripts;
+mod session_counter;
 mod simple_defi;
 mod smart_data_structures;
 mod stake;
@@ -37,3 +39,4 @@ mod token_objects;
 mod transaction_fee;
 mod type_too_large;
 mod vector_numeric_address;
+mod write_budget;
@@ -341,3 +341,48 @@ fn verify_resource_group_upgrades() {
     let result = h.publish_package(&account, path.path());
     assert_vm_status!(result, StatusCode::CONSTRAINT_NOT_SATISFIED);
 }
+
+#[test]
+fn resource_group_attributes_are_visible_to_move() {
+    let mut h = MoveHarness::new_with_features(vec![FeatureFlag::RESOURCE_GROUPS], vec![]);
+    let account = h.new_account_at(AccountAddress::from_hex_literal("0xf00d").unwrap());
+
+    let source = r#"
+        module 0xf00d::M {
+            use aptos_framework::object::{Self, ObjectGroup};
+
+            #[resource_group_member(group = 0xf00d::M::ResourceGroup)]
+            struct ResourceGroupMember has key { }
+
+            #[resource_group(scope = address)]
+            struct ResourceGroup { }
+
+            struct Resource has key { }
+
+            public entry fun check() {
+                assert!(object::is_resource_group<ResourceGroup>(), 1);
+                assert!(!object::is_resource_group_member<ResourceGroup>(), 2);
+                assert!(object::is_resource_group_member<ResourceGroupMember>(), 3);
+                assert!(!object::is_resource_group<ResourceGroupMember>(), 4);
+                assert!(!object::is_resource_group<Resource>(), 5);
+                assert!(!object::is_resource_group_member<Resource>(), 6);
+                assert!(object::is_resource_group<ObjectGroup>(), 7);
+            }
+        }
+        "#;
+    let mut builder = PackageBuilder::new("Package");
+    builder.add_source("m.move", source);
+    builder.add_local_dep(
+        "AptosFramework",
+        &common::framework_dir_path("aptos-framework").to_string_lossy(),
+    );
+    let path = builder.write_to_temp().unwrap();
+    assert_success!(h.publish_package(&account, path.path()));
+
+    assert_success!(h.run_entry_function(
+        &account,
+        str::parse("0xf00d::M::check").unwrap(),
+        vec![],
+        vec![],
+    ));
+}
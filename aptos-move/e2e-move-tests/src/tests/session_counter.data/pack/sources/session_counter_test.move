@@ -0,0 +1,25 @@
+module 0xcafe::session_counter_test {
+    use aptos_framework::session_counter;
+    use std::signer;
+
+    /// Since tests in e2e-move-tests/ can only call entry functions which don't have return values, the counter
+    /// values observed by the last call are stored in this resource.
+    struct Observed has key {
+        first: u64,
+        second: u64,
+    }
+
+    public entry fun observe(account: &signer) acquires Observed {
+        let first = session_counter::next();
+        let second = session_counter::next();
+
+        let addr = signer::address_of(account);
+        if (exists<Observed>(addr)) {
+            let observed = borrow_global_mut<Observed>(addr);
+            observed.first = first;
+            observed.second = second;
+        } else {
+            move_to(account, Observed { first, second });
+        }
+    }
+}
@@ -0,0 +1,42 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{assert_success, tests::common, MoveHarness};
+use aptos_types::account_address::AccountAddress;
+use move_core_types::parser::parse_struct_tag;
+use serde::{Deserialize, Serialize};
+
+/// Mimics `0xcafe::session_counter_test::Observed`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct Observed {
+    first: u64,
+    second: u64,
+}
+
+#[test]
+fn session_counter_restarts_in_every_transaction() {
+    let mut h = MoveHarness::new();
+
+    let acc = h.new_account_at(AccountAddress::from_hex_literal("0xcafe").unwrap());
+    assert_success!(h.publish_package(&acc, &common::test_dir_path("session_counter.data/pack")));
+
+    let observed_tag = parse_struct_tag("0xcafe::session_counter_test::Observed").unwrap();
+    // Each transaction runs in its own session (with its own session id), so the counter starts
+    // at 0 every time and nothing about it is persisted.
+    for _ in 0..2 {
+        assert_success!(h.run_entry_function(
+            &acc,
+            str::parse("0xcafe::session_counter_test::observe").unwrap(),
+            vec![],
+            vec![],
+        ));
+        assert_eq!(
+            h.read_resource::<Observed>(acc.address(), observed_tag.clone())
+                .unwrap(),
+            Observed {
+                first: 0,
+                second: 1
+            }
+        );
+    }
+}
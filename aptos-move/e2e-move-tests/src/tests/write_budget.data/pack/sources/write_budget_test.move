@@ -0,0 +1,44 @@
+module 0xcafe::write_budget_test {
+    use aptos_framework::transaction_context;
+    use std::signer;
+    use std::vector;
+
+    const BLOB_SIZE: u64 = 1024;
+
+    struct Blobs has key {
+        blobs: vector<vector<u8>>,
+    }
+
+    fun init_module(account: &signer) {
+        move_to(account, Blobs { blobs: vector::empty() });
+    }
+
+    /// Adds `count` blobs, however many bytes that takes.
+    public entry fun add(account: &signer, count: u64) acquires Blobs {
+        let blobs = &mut borrow_global_mut<Blobs>(signer::address_of(account)).blobs;
+        let i = 0;
+        while (i < count) {
+            vector::push_back(blobs, blob());
+            i = i + 1;
+        }
+    }
+
+    /// Adds blobs for as long as the transaction can still write one more, with room to spare for
+    /// the rest of the transaction.
+    public entry fun fill(account: &signer) acquires Blobs {
+        let blobs = &mut borrow_global_mut<Blobs>(signer::address_of(account)).blobs;
+        while (transaction_context::remaining_write_budget() >= 4 * BLOB_SIZE) {
+            vector::push_back(blobs, blob());
+        }
+    }
+
+    fun blob(): vector<u8> {
+        let blob = vector::empty();
+        let i = 0;
+        while (i < BLOB_SIZE) {
+            vector::push_back(&mut blob, 0xAB);
+            i = i + 1;
+        };
+        blob
+    }
+}
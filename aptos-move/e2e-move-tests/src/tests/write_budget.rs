@@ -0,0 +1,64 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{assert_success, tests::common, MoveHarness};
+use aptos_types::{
+    account_address::AccountAddress,
+    transaction::{ExecutionStatus, TransactionStatus},
+};
+use move_core_types::{
+    gas_algebra::NumBytes, language_storage::StructTag, vm_status::AbortLocation,
+};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Blobs {
+    blobs: Vec<Vec<u8>>,
+}
+
+#[test]
+fn filling_up_to_the_write_budget_stays_within_the_limit() {
+    let mut h = MoveHarness::new();
+
+    let acc = h.new_account_at(AccountAddress::from_hex_literal("0xcafe").unwrap());
+    assert_success!(h.publish_package(&acc, &common::test_dir_path("write_budget.data/pack")));
+
+    // Room for fewer than sixteen 1KiB blobs per transaction.
+    h.modify_gas_schedule(|gas_params| {
+        gas_params.txn.max_bytes_all_write_ops_per_transaction = NumBytes::new(16 * 1024);
+    });
+
+    let blobs_tag: StructTag = str::parse("0xcafe::write_budget_test::Blobs").unwrap();
+    let num_blobs = |h: &MoveHarness| {
+        h.read_resource::<Blobs>(acc.address(), blobs_tag.clone())
+            .unwrap()
+            .blobs
+            .len()
+    };
+
+    // Adding blobs regardless of the budget fails when the transaction is finished...
+    let status = h.run_entry_function(
+        &acc,
+        str::parse("0xcafe::write_budget_test::add").unwrap(),
+        vec![],
+        vec![bcs::to_bytes(&16u64).unwrap()],
+    );
+    assert!(matches!(
+        status,
+        TransactionStatus::Keep(ExecutionStatus::ExecutionFailure {
+            location: AbortLocation::Script,
+            ..
+        })
+    ));
+    assert_eq!(num_blobs(&h), 0);
+
+    // ...while stopping once the budget runs low does not, and still gets some of them written.
+    assert_success!(h.run_entry_function(
+        &acc,
+        str::parse("0xcafe::write_budget_test::fill").unwrap(),
+        vec![],
+        vec![],
+    ));
+    let filled = num_blobs(&h);
+    assert!(filled > 0 && filled < 16, "filled {} blobs", filled);
+}
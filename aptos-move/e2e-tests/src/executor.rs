@@ -61,6 +61,7 @@ use std::{
     fs::{self, OpenOptions},
     io::Write,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 static RNG_SEED: [u8; 32] = [9u8; 32];
@@ -612,7 +613,9 @@ impl FakeExecutor {
             let change_set_ext = session
                 .finish(
                     &mut (),
-                    &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+                    &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                        LATEST_GAS_FEATURE_VERSION,
+                    )),
                 )
                 .expect("Failed to generate txn effects");
             let (_delta_change_set, change_set) = change_set_ext.into_inner();
@@ -655,7 +658,9 @@ impl FakeExecutor {
         let change_set_ext = session
             .finish(
                 &mut (),
-                &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+                &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                    LATEST_GAS_FEATURE_VERSION,
+                )),
             )
             .expect("Failed to generate txn effects");
         // TODO: Support deltas in fake executor.
@@ -4,12 +4,19 @@
 /// events emitted to a handle and emit events to the event store.
 module aptos_framework::event {
     use std::bcs;
+    use std::error;
+    use std::features;
 
     use aptos_framework::guid::GUID;
 
     friend aptos_framework::account;
     friend aptos_framework::object;
 
+    /// Module events are not enabled.
+    const EMODULE_EVENTS_NOT_ENABLED: u64 = 1;
+    /// The payload of a module event is not a struct.
+    const EMODULE_EVENT_NOT_STRUCT: u64 = 2;
+
     /// A handle for an event such that:
     /// 1. Other modules can emit events to this handle.
     /// 2. Storage can use this handle to prove the total number of events that happened in the past.
@@ -50,6 +57,17 @@ module aptos_framework::event {
     /// Log `msg` as the `count`th event associated with the event stream identified by `guid`
     native fun write_to_event_store<T: drop + store>(guid: vector<u8>, count: u64, msg: T);
 
+    /// Emit a module event with payload `msg`. Unlike events emitted to an `EventHandle`, module events
+    /// have no GUID or sequence number: they are identified by the struct type of `msg` alone.
+    public fun emit<T: drop + store>(msg: T) {
+        assert!(features::module_event_enabled(), error::invalid_state(EMODULE_EVENTS_NOT_ENABLED));
+        write_module_event_to_store<T>(msg);
+    }
+
+    /// Log `msg` as a module event. Aborts with `EMODULE_EVENTS_NOT_ENABLED` unless the `MODULE_EVENT`
+    /// feature is enabled, and with `EMODULE_EVENT_NOT_STRUCT` if `T` is not a struct.
+    native fun write_module_event_to_store<T: drop + store>(msg: T);
+
     /// Destroy a unique handle.
     public fun destroy_handle<T: drop + store>(handle: EventHandle<T>) {
         EventHandle<T> { counter: _, guid: _ } = handle;
@@ -13,4 +13,14 @@ spec aptos_framework::event {
     spec write_to_event_store<T: drop + store>(guid: vector<u8>, count: u64, msg: T) {
         pragma opaque;
     }
+
+    spec emit {
+        pragma opaque;
+        aborts_if [abstract] !features::spec_module_event_enabled();
+    }
+
+    /// Native function use opaque.
+    spec write_module_event_to_store<T: drop + store>(msg: T) {
+        pragma opaque;
+    }
 }
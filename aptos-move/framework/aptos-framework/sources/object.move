@@ -185,6 +185,20 @@ module aptos_framework::object {
 
     native fun exists_at<T: key>(object: address): bool;
 
+    /// Returns true if `T` is declared as a resource group.
+    public fun is_resource_group<T>(): bool {
+        is_resource_group_internal<T>()
+    }
+
+    /// Returns true if `T` is declared as a member of a resource group.
+    public fun is_resource_group_member<T>(): bool {
+        is_resource_group_member_internal<T>()
+    }
+
+    native fun is_resource_group_internal<T>(): bool;
+
+    native fun is_resource_group_member_internal<T>(): bool;
+
     /// Returns the address of within an ObjectId.
     public fun object_address<T: key>(object: &Object<T>): address {
         object.inner
@@ -2,4 +2,30 @@ spec aptos_framework::object {
     spec exists_at<T>(object: address): bool {
         pragma intrinsic;
     }
+
+    spec is_resource_group<T>(): bool {
+        aborts_if false;
+        ensures result == spec_is_resource_group<T>();
+    }
+
+    spec is_resource_group_member<T>(): bool {
+        aborts_if false;
+        ensures result == spec_is_resource_group_member<T>();
+    }
+
+    spec is_resource_group_internal<T>(): bool {
+        pragma opaque;
+        aborts_if false;
+        ensures result == spec_is_resource_group<T>();
+    }
+
+    spec is_resource_group_member_internal<T>(): bool {
+        pragma opaque;
+        aborts_if false;
+        ensures result == spec_is_resource_group_member<T>();
+    }
+
+    spec fun spec_is_resource_group<T>(): bool;
+
+    spec fun spec_is_resource_group_member<T>(): bool;
 }
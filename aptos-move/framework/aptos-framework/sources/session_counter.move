@@ -0,0 +1,18 @@
+/// A counter that starts at 0 in every session (i.e., every transaction) and is never persisted.
+/// Useful for deriving several distinct values, e.g. object seeds, within a single transaction
+/// without storing a resource.
+module aptos_framework::session_counter {
+    /// Returns the current value of the session counter and increments it.
+    public fun next(): u64 {
+        session_counter_next_internal()
+    }
+
+    native fun session_counter_next_internal(): u64;
+
+    #[test]
+    fun test_next_increments() {
+        let first = next();
+        assert!(next() == first + 1, 0);
+        assert!(next() == first + 2, 0);
+    }
+}
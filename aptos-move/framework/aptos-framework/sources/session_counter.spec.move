@@ -0,0 +1,6 @@
+spec aptos_framework::session_counter {
+    spec session_counter_next_internal(): u64 {
+        pragma opaque;
+        aborts_if false;
+    }
+}
@@ -1,4 +1,45 @@
 module aptos_framework::transaction_context {
     /// Return the script hash of the current entry function.
     public native fun get_script_hash(): vector<u8>;
+
+    /// Return 32 bytes derived from the current session, i.e. transaction, and `counter`. The same
+    /// transaction always gets the same bytes, but they cannot be known before the transaction is, e.g.
+    /// to order commitments in a commit-reveal scheme.
+    public fun unique_session_value(counter: u64): vector<u8> {
+        unique_session_value_internal(counter)
+    }
+
+    native fun unique_session_value_internal(counter: u64): vector<u8>;
+
+    /// Return how many more bytes the current transaction may write before it fails with
+    /// `STORAGE_WRITE_LIMIT_REACHED`, e.g. to process a batch in as many steps as fit into one
+    /// transaction. This is the room left under the limit on the bytes of all write ops of a transaction.
+    /// Events are limited separately and not counted here.
+    ///
+    /// Write ops are counted as if the transaction ended now, with their keys and values as stored,
+    /// except that a resource in a resource group counts as if stored on its own rather than as part of
+    /// the group, and that items written to tables are not counted. Writing more than the returned
+    /// number of bytes thus fails the transaction, but writing less only does if it also writes to tables
+    /// or grows resource groups by more than their members do. If the limits are not enforced while the
+    /// transaction executes, `MAX_U64` is returned.
+    ///
+    /// Its cost grows with the bytes written so far.
+    public fun remaining_write_budget(): u64 {
+        remaining_write_budget_internal()
+    }
+
+    native fun remaining_write_budget_internal(): u64;
+
+    #[test]
+    fun test_unique_session_value() {
+        let value = unique_session_value(0);
+        assert!(std::vector::length(&value) == 32, 0);
+        assert!(unique_session_value(0) == value, 1);
+        assert!(unique_session_value(1) != value, 2);
+    }
+
+    #[test]
+    fun test_remaining_write_budget_unlimited() {
+        assert!(remaining_write_budget() == 18446744073709551615, 0);
+    }
 }
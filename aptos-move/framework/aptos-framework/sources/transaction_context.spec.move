@@ -6,4 +6,15 @@ spec aptos_framework::transaction_context {
     }
 
     spec fun spec_get_script_hash(): vector<u8>;
+
+    spec unique_session_value_internal(counter: u64): vector<u8> {
+        pragma opaque;
+        aborts_if false;
+        ensures len(result) == 32;
+    }
+
+    spec remaining_write_budget_internal(): u64 {
+        pragma opaque;
+        aborts_if false;
+    }
 }
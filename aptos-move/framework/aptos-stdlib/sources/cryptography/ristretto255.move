@@ -62,6 +62,9 @@ module aptos_std::ristretto255 {
     /// The maximum size in bytes of a canonically-encoded Ristretto255 point is 32 bytes.
     const MAX_POINT_NUM_BYTES: u64 = 32u64;
 
+    /// The maximum total number of bytes (DST included) that can be hashed into a single Fiat-Shamir challenge.
+    const MAX_FIAT_SHAMIR_INPUT_NUM_BYTES: u64 = 65536;
+
     /// The basepoint (generator) of the Ristretto255 group
     const BASE_POINT: vector<u8> = x"e2f2ae0a6abc4e71a884a961c500515f58e30b6aa582dd8db6a65945e08d2d76";
 
@@ -75,6 +78,13 @@ module aptos_std::ristretto255 {
     const E_ZERO_POINTS: u64 = 2;
     /// Expected more than zero scalars as input.
     const E_ZERO_SCALARS: u64 = 3;
+    /// The total size of the inputs to a Fiat-Shamir challenge exceeds `MAX_FIAT_SHAMIR_INPUT_NUM_BYTES`.
+    const E_FIAT_SHAMIR_INPUT_TOO_LARGE: u64 = 4;
+    /// The commitments, values and blindings of a batch of Pedersen openings differ in length.
+    const E_PEDERSEN_OPEN_CHECK_LENGTH_MISMATCH: u64 = 5;
+    /// A Pedersen commitment is not a canonically-encoded point, or a value or blinding is not a canonically-encoded
+    /// scalar.
+    const E_PEDERSEN_OPEN_CHECK_MALFORMED_INPUT: u64 = 6;
 
     //
     // Scalar and point structs
@@ -282,6 +292,32 @@ module aptos_std::ristretto255 {
         }
     }
 
+    /// Returns true if, for every i, the commitment `comms[i]` opens to the value `values[i]` with the blinding
+    /// `blindings[i]`, i.e., if `comms[i] = values[i] * BASE_POINT + blindings[i] * H`, where H is the default
+    /// Pedersen blinding base of the bulletproofs library. All inputs are canonical 32-byte encodings.
+    ///
+    /// All openings are checked at once, with a single multi-scalar multiplication over a random linear combination
+    /// of them. Its coefficients are derived from a merlin transcript over all the inputs and randomness drawn from the
+    /// session, so every validator checks the same combination, and it only holds for a wrong opening with negligible
+    /// probability.
+    ///
+    /// Aborts with `E_PEDERSEN_OPEN_CHECK_LENGTH_MISMATCH` if the three vectors differ in length, and with
+    /// `E_PEDERSEN_OPEN_CHECK_MALFORMED_INPUT` if any of the inputs is not canonically-encoded.
+    public fun pedersen_open_check_batch(
+        comms: vector<vector<u8>>,
+        values: vector<vector<u8>>,
+        blindings: vector<vector<u8>>
+    ): bool {
+        pedersen_open_check_batch_internal(comms, values, blindings)
+    }
+
+    /// Returns true if `a` and `b` are equal, in time that only depends on the length of the longer one. Unlike `==`
+    /// on vectors, this does not reveal how many leading bytes the two share, and so is the way to compare secret
+    /// dependent bytes, e.g., commitments or tags. Vectors of different lengths are unequal.
+    public fun ct_eq(a: vector<u8>, b: vector<u8>): bool {
+        ct_eq_internal(a, b)
+    }
+
     //
     // Functions for arithmetic on Scalars
     //
@@ -305,6 +341,17 @@ module aptos_std::ristretto255 {
         }
     }
 
+    /// Derives a Fiat-Shamir challenge Scalar by absorbing the domain-separation tag `dst`, followed by each of the
+    /// `elements` (e.g., serialized commitments), into a merlin transcript.
+    ///
+    /// Aborts with `E_FIAT_SHAMIR_INPUT_TOO_LARGE` if `dst` and `elements` together exceed
+    /// `MAX_FIAT_SHAMIR_INPUT_NUM_BYTES` bytes.
+    public fun new_scalar_from_fiat_shamir_challenge(dst: vector<u8>, elements: vector<vector<u8>>): Scalar {
+        Scalar {
+            data: fiat_shamir_challenge_internal(dst, elements)
+        }
+    }
+
     /// Creates a Scalar from an u8.
     public fun new_scalar_from_u8(byte: u8): Scalar {
         let s = scalar_zero();
@@ -487,6 +534,14 @@ module aptos_std::ristretto255 {
     /// WARNING: This function can only be called with P = RistrettoPoint and S = Scalar.
     native fun multi_scalar_mul_internal<P, S>(points: &vector<P>, scalars: &vector<S>): u64;
 
+    native fun pedersen_open_check_batch_internal(
+        comms: vector<vector<u8>>,
+        values: vector<vector<u8>>,
+        blindings: vector<vector<u8>>
+    ): bool;
+
+    native fun ct_eq_internal(a: vector<u8>, b: vector<u8>): bool;
+
     //
     // Only used internally for implementing Scalar.
     //
@@ -505,6 +560,8 @@ module aptos_std::ristretto255 {
 
     native fun scalar_from_sha512_internal(sha512_input: vector<u8>): vector<u8>;
 
+    native fun fiat_shamir_challenge_internal(dst: vector<u8>, elements: vector<vector<u8>>): vector<u8>;
+
     native fun scalar_mul_internal(a_bytes: vector<u8>, b_bytes: vector<u8>): vector<u8>;
 
     native fun scalar_add_internal(a_bytes: vector<u8>, b_bytes: vector<u8>): vector<u8>;
@@ -575,6 +632,10 @@ module aptos_std::ristretto255 {
     const B_POINT: vector<u8> = x"fa0b3624b081c62f364d0b2839dcc76d7c3ab0e27e31beb2b9ed766575f28e76";
     const A_PLUS_B_POINT: vector<u8> = x"70cf3753475b9ff33e2f84413ed6b5052073bccc0a0a81789d3e5675dc258056";
 
+    /// The default Pedersen blinding base of the bulletproofs library, i.e., the SHA3-512 hash-to-point of the
+    /// compressed `BASE_POINT`.
+    const PEDERSEN_RAND_BASE: vector<u8> = x"8c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f34048871134";
+
     //    const NON_CANONICAL_LARGEST_ED25519_S: vector<u8> = x"f8ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f";
     //    const CANONICAL_LARGEST_ED25519_S_PLUS_ONE: vector<u8> = x"7e344775474a7f9723b63a8be92ae76dffffffffffffffffffffffffffffff0f";
     //    const CANONICAL_LARGEST_ED25519_S_MINUS_ONE: vector<u8> = x"7c344775474a7f9723b63a8be92ae76dffffffffffffffffffffffffffffff0f";
@@ -999,6 +1060,40 @@ module aptos_std::ristretto255 {
         assert!(scalar_is_one(&scalar_mul(&inv, &s)), 1);
     }
 
+    #[test]
+    fun test_scalar_from_fiat_shamir_challenge() {
+        let comm = point_to_bytes(&point_compress(&basepoint_mul(&new_scalar_from_sha512(b"comm"))));
+
+        let c1 = new_scalar_from_fiat_shamir_challenge(b"AptosTest/1", vector[comm, b"extra"]);
+        let c2 = new_scalar_from_fiat_shamir_challenge(b"AptosTest/1", vector[comm, b"extra"]);
+        let c3 = new_scalar_from_fiat_shamir_challenge(b"AptosTest/2", vector[comm, b"extra"]);
+
+        assert!(scalar_is_canonical_internal(c1.data), 1);
+        assert!(scalar_equals(&c1, &c2), 1);
+        assert!(!scalar_equals(&c1, &c3), 1);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 0x010004, location = Self)]
+    fun test_scalar_from_fiat_shamir_challenge_input_too_large() {
+        let chunk = vector[];
+        let i = 0;
+        while (i < 1024) {
+            std::vector::push_back(&mut chunk, 0);
+            i = i + 1;
+        };
+
+        // 64 chunks of 1 KiB fill the transcript exactly, so the one-byte DST pushes it over the limit.
+        let elements = vector[];
+        let j = 0;
+        while (j < MAX_FIAT_SHAMIR_INPUT_NUM_BYTES / 1024) {
+            std::vector::push_back(&mut elements, chunk);
+            j = j + 1;
+        };
+
+        new_scalar_from_fiat_shamir_challenge(b"x", elements);
+    }
+
     #[test]
     fun test_scalar_from_sha512() {
         // Test a specific message hashes correctly to the field
@@ -1168,4 +1263,97 @@ module aptos_std::ristretto255 {
         assert!(scalar_is_canonical_internal(non_can.data), 1);
         assert!(scalar_equals(&non_can, &scalar_zero()), 1);
     }
+
+    /// Returns `num` valid Pedersen openings, as (commitments, values, blindings).
+    #[test_only]
+    fun pedersen_openings(num: u64): (vector<vector<u8>>, vector<vector<u8>>, vector<vector<u8>>) {
+        let rand_base = std::option::extract(&mut new_point_from_bytes(PEDERSEN_RAND_BASE));
+
+        let comms = vector[];
+        let values = vector[];
+        let blindings = vector[];
+        let i = 0;
+        while (i < num) {
+            let v = new_scalar_from_u64(i * 1000);
+            let r = new_scalar_from_sha512(std::bcs::to_bytes(&i));
+            let comm = basepoint_double_mul(&r, &rand_base, &v);
+
+            std::vector::push_back(&mut comms, point_to_bytes(&point_compress(&comm)));
+            std::vector::push_back(&mut values, scalar_to_bytes(&v));
+            std::vector::push_back(&mut blindings, scalar_to_bytes(&r));
+            i = i + 1;
+        };
+
+        (comms, values, blindings)
+    }
+
+    #[test]
+    fun test_pedersen_open_check_batch() {
+        let (comms, values, blindings) = pedersen_openings(16);
+        assert!(pedersen_open_check_batch(comms, values, blindings), 1);
+
+        // An empty batch holds trivially.
+        assert!(pedersen_open_check_batch(vector[], vector[], vector[]), 1);
+    }
+
+    #[test]
+    fun test_pedersen_open_check_batch_one_bad_opening() {
+        let (comms, values, blindings) = pedersen_openings(16);
+
+        // Claim the 11th commitment opens to a value off by one.
+        let bad_value = scalar_add(&new_scalar_from_u64(10 * 1000), &scalar_one());
+        *std::vector::borrow_mut(&mut values, 10) = scalar_to_bytes(&bad_value);
+        assert!(!pedersen_open_check_batch(comms, values, blindings), 1);
+
+        // Likewise for a wrong blinding.
+        let (comms, values, blindings) = pedersen_openings(16);
+        *std::vector::borrow_mut(&mut blindings, 3) = scalar_to_bytes(&scalar_one());
+        assert!(!pedersen_open_check_batch(comms, values, blindings), 1);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 0x010005, location = Self)]
+    fun test_pedersen_open_check_batch_length_mismatch() {
+        let (comms, values, blindings) = pedersen_openings(4);
+        std::vector::pop_back(&mut blindings);
+
+        pedersen_open_check_batch(comms, values, blindings);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 0x010006, location = Self)]
+    fun test_pedersen_open_check_batch_malformed_commitment() {
+        let (comms, values, blindings) = pedersen_openings(4);
+        *std::vector::borrow_mut(&mut comms, 2) = NON_CANONICAL_ALL_ONES;
+
+        pedersen_open_check_batch(comms, values, blindings);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 0x010006, location = Self)]
+    fun test_pedersen_open_check_batch_malformed_value() {
+        let (comms, values, blindings) = pedersen_openings(4);
+        *std::vector::borrow_mut(&mut values, 0) = L_PLUS_ONE;
+
+        pedersen_open_check_batch(comms, values, blindings);
+    }
+
+    #[test]
+    fun test_ct_eq() {
+        let (comms, _, _) = pedersen_openings(2);
+        let comm = *std::vector::borrow(&comms, 0);
+        assert!(ct_eq(comm, comm), 1);
+        assert!(ct_eq(vector[], vector[]), 1);
+
+        // Same length, but different bytes.
+        assert!(!ct_eq(comm, *std::vector::borrow(&comms, 1)), 1);
+        assert!(!ct_eq(A_SCALAR, B_SCALAR), 1);
+
+        // Different lengths, even if one is a prefix of the other.
+        let longer = comm;
+        std::vector::push_back(&mut longer, 0);
+        assert!(!ct_eq(comm, longer), 1);
+        assert!(!ct_eq(longer, comm), 1);
+        assert!(!ct_eq(vector[], vector[0]), 1);
+    }
 }
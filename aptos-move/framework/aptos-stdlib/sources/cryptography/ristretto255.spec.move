@@ -69,6 +69,16 @@ spec aptos_std::ristretto255 {
         pragma opaque;
     }
 
+    spec pedersen_open_check_batch_internal {
+        // TODO: temporary mockup.
+        pragma opaque;
+    }
+
+    spec ct_eq_internal {
+        // TODO: temporary mockup.
+        pragma opaque;
+    }
+
     spec scalar_is_canonical_internal {
         // TODO: temporary mockup.
         pragma opaque;
@@ -104,6 +114,11 @@ spec aptos_std::ristretto255 {
         pragma opaque;
     }
 
+    spec fiat_shamir_challenge_internal {
+        // TODO: temporary mockup.
+        pragma opaque;
+    }
+
     spec scalar_mul_internal {
         // TODO: temporary mockup.
         pragma opaque;
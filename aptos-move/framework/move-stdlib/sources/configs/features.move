@@ -183,6 +183,57 @@ module std::features {
         is_enabled(PARTIAL_GOVERNANCE_VOTING)
     }
 
+    /// Whether creating a resource group at an address requires an account or an object to exist there.
+    /// Lifetime: transient
+    const RESOURCE_GROUP_CONTAINER_VALIDATION: u64 = 18;
+    public fun get_resource_group_container_validation_feature(): u64 { RESOURCE_GROUP_CONTAINER_VALIDATION }
+    public fun resource_group_container_validation_enabled(): bool acquires Features {
+        is_enabled(RESOURCE_GROUP_CONTAINER_VALIDATION)
+    }
+
+    /// Whether modifications writing back the value already in storage are dropped from the write set.
+    /// Lifetime: transient
+    const STRIP_NOOP_MODIFICATIONS: u64 = 19;
+    public fun get_strip_noop_modifications_feature(): u64 { STRIP_NOOP_MODIFICATIONS }
+    public fun strip_noop_modifications_enabled(): bool acquires Features {
+        is_enabled(STRIP_NOOP_MODIFICATIONS)
+    }
+
+    /// Whether aggregator changes are checked to belong to the table of the aggregator factory.
+    /// Lifetime: transient
+    const AGGREGATOR_HANDLE_VALIDATION: u64 = 20;
+    public fun get_aggregator_handle_validation_feature(): u64 { AGGREGATOR_HANDLE_VALIDATION }
+    public fun aggregator_handle_validation_enabled(): bool acquires Features {
+        is_enabled(AGGREGATOR_HANDLE_VALIDATION)
+    }
+
+    /// Whether transactions growing the state under an account beyond its storage quota are rejected.
+    /// Reserved: the VM does not enforce storage quotas yet, so this flag has no effect.
+    /// Lifetime: transient
+    const STORAGE_QUOTAS: u64 = 21;
+    public fun get_storage_quotas_feature(): u64 { STORAGE_QUOTAS }
+    public fun storage_quotas_enabled(): bool acquires Features {
+        is_enabled(STORAGE_QUOTAS)
+    }
+
+    /// Whether Move code can emit module events, which are identified by their type rather than an
+    /// event handle.
+    /// Lifetime: transient
+    const MODULE_EVENT: u64 = 22;
+    public fun get_module_event_feature(): u64 { MODULE_EVENT }
+    public fun module_event_enabled(): bool acquires Features {
+        is_enabled(MODULE_EVENT)
+    }
+
+    /// Whether the events a transaction emits must be keyed to its sender, an account it writes or a
+    /// reserved address.
+    /// Lifetime: transient
+    const EVENT_KEY_VALIDATION: u64 = 23;
+    public fun get_event_key_validation_feature(): u64 { EVENT_KEY_VALIDATION }
+    public fun event_key_validation_enabled(): bool acquires Features {
+        is_enabled(EVENT_KEY_VALIDATION)
+    }
+
     // ============================================================================================
     // Feature Flag Implementation
 
@@ -56,4 +56,14 @@ spec std::features {
         aborts_if [abstract] false;
         ensures [abstract] result == spec_partial_governance_voting_enabled();
     }
+
+    spec fun spec_module_event_enabled(): bool {
+        spec_is_enabled(MODULE_EVENT)
+    }
+
+    spec module_event_enabled {
+        pragma opaque;
+        aborts_if [abstract] false;
+        ensures [abstract] result == spec_module_event_enabled();
+    }
 }
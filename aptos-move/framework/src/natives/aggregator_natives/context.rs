@@ -40,6 +40,7 @@ pub struct NativeAggregatorContext<'a> {
     txn_hash: [u8; 32],
     pub(crate) resolver: &'a dyn TableResolver,
     pub(crate) aggregator_data: RefCell<AggregatorData>,
+    session_counter: u64,
 }
 
 impl<'a> NativeAggregatorContext<'a> {
@@ -50,6 +51,7 @@ impl<'a> NativeAggregatorContext<'a> {
             txn_hash,
             resolver,
             aggregator_data: Default::default(),
+            session_counter: 0,
         }
     }
 
@@ -58,6 +60,15 @@ impl<'a> NativeAggregatorContext<'a> {
         self.txn_hash
     }
 
+    /// Returns the next value of the session counter, or none once it is exhausted. The counter
+    /// starts at 0 in every context, whatever its transaction hash, and is dropped with it, so it
+    /// never produces changes.
+    pub fn next_session_counter(&mut self) -> Option<u64> {
+        let value = self.session_counter;
+        self.session_counter = value.checked_add(1)?;
+        Some(value)
+    }
+
     /// Returns all changes made within this context (i.e. by a single
     /// transaction).
     pub fn into_change_set(self) -> AggregatorChangeSet {
@@ -213,4 +224,16 @@ mod test {
         assert!(changes.contains_key(&aggregator_id_for_test(700)));
         assert_matches!(changes.get(&aggregator_id_for_test(800)).unwrap(), Delete);
     }
+
+    #[test]
+    fn test_session_counter() {
+        let mut context = NativeAggregatorContext::new([0; 32], &EmptyStorage);
+        assert_eq!(context.next_session_counter(), Some(0));
+        assert_eq!(context.next_session_counter(), Some(1));
+        assert!(context.into_change_set().changes.is_empty());
+
+        // The counter of another session starts over.
+        let mut context = NativeAggregatorContext::new([1; 32], &EmptyStorage);
+        assert_eq!(context.next_session_counter(), Some(0));
+    }
 }
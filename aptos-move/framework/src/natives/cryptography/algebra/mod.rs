@@ -212,7 +212,7 @@ macro_rules! abort_unless_arithmetics_enabled_for_structure {
 macro_rules! abort_unless_feature_flag_enabled {
     ($context:ident, $flag_opt:expr) => {
         match $flag_opt {
-            Some(flag) if $context.get_feature_flags().is_enabled(flag) => {
+            Some(flag) if $context.is_feature_enabled(flag) => {
                 // Continue.
             },
             _ => {
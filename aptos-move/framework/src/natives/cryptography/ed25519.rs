@@ -60,8 +60,7 @@ fn native_public_key_validate(
         Ok(slice) => slice,
         Err(_) => {
             if context
-                .get_feature_flags()
-                .is_enabled(FeatureFlag::ED25519_PUBKEY_VALIDATE_RETURN_FALSE_WRONG_LENGTH)
+                .is_feature_enabled(FeatureFlag::ED25519_PUBKEY_VALIDATE_RETURN_FALSE_WRONG_LENGTH)
             {
                 return Ok(smallvec![Value::bool(false)]);
             } else {
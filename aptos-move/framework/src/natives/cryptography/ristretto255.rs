@@ -3,7 +3,7 @@
 
 use crate::{
     natives::{
-        cryptography::{ristretto255_point, ristretto255_scalar},
+        cryptography::{ristretto255_pedersen, ristretto255_point, ristretto255_scalar},
         helpers::{make_safe_native, SafeNativeError, SafeNativeResult},
     },
     safely_assert_eq, safely_pop_arg,
@@ -25,6 +25,18 @@ pub(crate) const SCALAR_NUM_BYTES: usize = 32;
 /// The size of a serialized compressed Ristretto point, in bytes.
 pub(crate) const COMPRESSED_POINT_NUM_BYTES: usize = 32;
 
+/// The maximum number of bytes (domain-separation tag included) that can be absorbed into a single
+/// Fiat-Shamir transcript.
+pub(crate) const MAX_FIAT_SHAMIR_INPUT_NUM_BYTES: usize = 64 * 1024;
+
+/// Abort codes for the Ristretto255 natives (0x01 == INVALID_ARGUMENT)
+/// NOTE: This must match the code in the Move implementation
+pub mod abort_codes {
+    pub const NFE_FIAT_SHAMIR_INPUT_TOO_LARGE: u64 = 0x01_0004;
+    pub const NFE_PEDERSEN_OPEN_CHECK_LENGTH_MISMATCH: u64 = 0x01_0005;
+    pub const NFE_PEDERSEN_OPEN_CHECK_MALFORMED_INPUT: u64 = 0x01_0006;
+}
+
 #[derive(Debug, Clone)]
 pub struct GasParameters {
     pub basepoint_mul: InternalGasPerArg,
@@ -55,6 +67,15 @@ pub struct GasParameters {
     pub scalar_neg: InternalGasPerArg,
     pub scalar_sub: InternalGasPerArg,
     pub scalar_parse_arg: InternalGasPerArg,
+
+    pub fiat_shamir_base: InternalGasPerArg,
+    pub fiat_shamir_per_byte: InternalGasPerByte,
+
+    pub pedersen_open_check_base: InternalGasPerArg,
+    pub pedersen_open_check_per_commitment: InternalGasPerArg,
+
+    pub ct_eq_base: InternalGasPerArg,
+    pub ct_eq_per_byte: InternalGasPerByte,
 }
 
 pub fn make_all(
@@ -205,6 +226,24 @@ pub fn make_all(
                 )
             },
         ),
+        (
+            "pedersen_open_check_batch_internal",
+            make_safe_native(
+                gas_params.clone(),
+                timed_features.clone(),
+                features.clone(),
+                ristretto255_pedersen::native_pedersen_open_check_batch,
+            ),
+        ),
+        (
+            "ct_eq_internal",
+            make_safe_native(
+                gas_params.clone(),
+                timed_features.clone(),
+                features.clone(),
+                ristretto255_pedersen::native_ct_eq,
+            ),
+        ),
         (
             "scalar_is_canonical_internal",
             make_safe_native(
@@ -297,11 +336,20 @@ pub fn make_all(
         ),
         (
             "scalar_uniform_from_64_bytes_internal",
+            make_safe_native(
+                gas_params.clone(),
+                timed_features.clone(),
+                features.clone(),
+                ristretto255_scalar::native_scalar_uniform_from_64_bytes,
+            ),
+        ),
+        (
+            "fiat_shamir_challenge_internal",
             make_safe_native(
                 gas_params,
                 timed_features,
                 features,
-                ristretto255_scalar::native_scalar_uniform_from_64_bytes,
+                ristretto255_scalar::native_scalar_from_fiat_shamir_challenge,
             ),
         ),
     ];
@@ -0,0 +1,290 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! Natives checking Pedersen commitments over Ristretto255, as produced by the bulletproofs library,
+//! and comparing them without leaking where they differ.
+
+use crate::{
+    natives::{
+        cryptography::ristretto255::{
+            abort_codes, GasParameters, COMPRESSED_POINT_NUM_BYTES, SCALAR_NUM_BYTES,
+        },
+        helpers::{SafeNativeContext, SafeNativeError, SafeNativeResult},
+    },
+    safely_assert_eq, safely_pop_arg, safely_pop_vec_arg,
+};
+use curve25519_dalek::{
+    constants::{RISTRETTO_BASEPOINT_COMPRESSED, RISTRETTO_BASEPOINT_POINT},
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::{IsIdentity, VartimeMultiscalarMul},
+};
+use merlin::Transcript;
+use move_core_types::gas_algebra::{NumArgs, NumBytes};
+use move_vm_types::{loaded_data::runtime_types::Type, values::Value};
+use once_cell::sync::Lazy;
+use rand_core::RngCore;
+use sha3::Sha3_512;
+use smallvec::{smallvec, SmallVec};
+use std::{collections::VecDeque, convert::TryFrom};
+use subtle::ConstantTimeEq;
+
+/// The label of the merlin transcript the coefficients of `pedersen_open_check_batch_internal`'s
+/// random linear combination are derived from.
+const PEDERSEN_OPEN_CHECK_TRANSCRIPT_LABEL: &[u8] = b"AptosRistretto255PedersenOpenCheckBatch";
+
+/// The default Pedersen blinding base of the bulletproofs library, i.e., the SHA3-512 hash of the
+/// compressed basepoint to a point. Its compression is `PEDERSEN_RAND_BASE` in the Move module.
+static PEDERSEN_RAND_BASE: Lazy<RistrettoPoint> = Lazy::new(|| {
+    RistrettoPoint::hash_from_bytes::<Sha3_512>(RISTRETTO_BASEPOINT_COMPRESSED.as_bytes())
+});
+
+pub(crate) fn native_pedersen_open_check_batch(
+    gas_params: &GasParameters,
+    context: &mut SafeNativeContext,
+    _ty_args: Vec<Type>,
+    mut arguments: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    safely_assert_eq!(_ty_args.len(), 0);
+    safely_assert_eq!(arguments.len(), 3);
+
+    let blindings = safely_pop_vec_arg!(arguments, Vec<u8>);
+    let values = safely_pop_vec_arg!(arguments, Vec<u8>);
+    let comms = safely_pop_vec_arg!(arguments, Vec<u8>);
+
+    context.charge(
+        gas_params.pedersen_open_check_base * NumArgs::one()
+            + gas_params.pedersen_open_check_per_commitment * NumArgs::new(comms.len() as u64),
+    )?;
+
+    if comms.len() != values.len() || comms.len() != blindings.len() {
+        return Err(SafeNativeError::Abort {
+            abort_code: abort_codes::NFE_PEDERSEN_OPEN_CHECK_LENGTH_MISMATCH,
+        });
+    }
+
+    let mut session_seed = [0u8; 32];
+    context.session_rng().fill_bytes(&mut session_seed);
+    let valid = pedersen_open_check_batch(&comms, &values, &blindings, &session_seed).ok_or(
+        SafeNativeError::Abort {
+            abort_code: abort_codes::NFE_PEDERSEN_OPEN_CHECK_MALFORMED_INPUT,
+        },
+    )?;
+
+    Ok(smallvec![Value::bool(valid)])
+}
+
+pub(crate) fn native_ct_eq(
+    gas_params: &GasParameters,
+    context: &mut SafeNativeContext,
+    _ty_args: Vec<Type>,
+    mut arguments: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    safely_assert_eq!(_ty_args.len(), 0);
+    safely_assert_eq!(arguments.len(), 2);
+
+    let b = safely_pop_arg!(arguments, Vec<u8>);
+    let a = safely_pop_arg!(arguments, Vec<u8>);
+
+    context.charge(
+        gas_params.ct_eq_base * NumArgs::one()
+            + gas_params.ct_eq_per_byte * NumBytes::new(a.len().max(b.len()) as u64),
+    )?;
+
+    Ok(smallvec![Value::bool(ct_eq(&a, &b))])
+}
+
+/// Whether `a` and `b` are equal, taking time that only depends on the length of the longer one.
+/// Inputs of different lengths are unequal, but are compared all the same, as if the shorter one
+/// was padded with zeros, so that the time taken does not tell how much of a prefix they share.
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    let len = a.len().max(b.len());
+    let mut a_padded = a.to_vec();
+    a_padded.resize(len, 0);
+    let mut b_padded = b.to_vec();
+    b_padded.resize(len, 0);
+
+    let same_len = (a.len() as u64).ct_eq(&(b.len() as u64));
+    (a_padded.as_slice().ct_eq(b_padded.as_slice()) & same_len).into()
+}
+
+/// Checks that every commitment `comms[i]` opens to `values[i]` with the blinding `blindings[i]`,
+/// or returns `None` if any of them is not a canonical encoding. The three slices are of the same
+/// length.
+///
+/// Rather than checking each `comms[i] == values[i] * B + blindings[i] * H` separately, this picks
+/// coefficients `z_i` and checks in a single multi-scalar multiplication that
+///
+///   (sum z_i values[i]) * B + (sum z_i blindings[i]) * H - sum z_i comms[i] == 0,
+///
+/// which, if any opening is wrong, only holds with negligible probability over the `z_i`. These are
+/// squeezed out of a merlin transcript which `session_seed`, drawn from the `SessionRng`, and all
+/// inputs were absorbed into first, so that they are the same on every validator and cannot be
+/// chosen around by whoever picked the inputs. The seed alone would not do, as the sender knows it
+/// ahead of time, e.g. before another transaction writes the openings this one checks.
+pub(crate) fn pedersen_open_check_batch(
+    comms: &[Vec<u8>],
+    values: &[Vec<u8>],
+    blindings: &[Vec<u8>],
+    session_seed: &[u8; 32],
+) -> Option<bool> {
+    let mut transcript = Transcript::new(PEDERSEN_OPEN_CHECK_TRANSCRIPT_LABEL);
+    transcript.append_message(b"session", session_seed);
+    transcript.append_u64(b"n", comms.len() as u64);
+
+    let mut points = Vec::with_capacity(comms.len());
+    let mut value_scalars = Vec::with_capacity(values.len());
+    let mut blinding_scalars = Vec::with_capacity(blindings.len());
+    for ((comm, value), blinding) in comms.iter().zip(values).zip(blindings) {
+        points.push(decompress_canonical_point(comm)?);
+        value_scalars.push(canonical_scalar(value)?);
+        blinding_scalars.push(canonical_scalar(blinding)?);
+
+        transcript.append_message(b"comm", comm);
+        transcript.append_message(b"value", value);
+        transcript.append_message(b"blinding", blinding);
+    }
+
+    let mut value_sum = Scalar::zero();
+    let mut blinding_sum = Scalar::zero();
+    let mut scalars = Vec::with_capacity(comms.len() + 2);
+    for (value, blinding) in value_scalars.iter().zip(&blinding_scalars) {
+        let mut buf = [0u8; 64];
+        transcript.challenge_bytes(b"z", &mut buf);
+        let z = Scalar::from_bytes_mod_order_wide(&buf);
+
+        value_sum += z * value;
+        blinding_sum += z * blinding;
+        scalars.push(-z);
+    }
+    scalars.push(value_sum);
+    scalars.push(blinding_sum);
+    points.push(RISTRETTO_BASEPOINT_POINT);
+    points.push(*PEDERSEN_RAND_BASE);
+
+    Some(RistrettoPoint::vartime_multiscalar_mul(scalars.iter(), points.iter()).is_identity())
+}
+
+fn canonical_scalar(bytes: &[u8]) -> Option<Scalar> {
+    let slice = <[u8; SCALAR_NUM_BYTES]>::try_from(bytes).ok()?;
+
+    Scalar::from_canonical_bytes(slice)
+}
+
+fn decompress_canonical_point(bytes: &[u8]) -> Option<RistrettoPoint> {
+    let slice = <[u8; COMPRESSED_POINT_NUM_BYTES]>::try_from(bytes).ok()?;
+
+    CompressedRistretto(slice).decompress()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: [u8; 32] = [7; 32];
+
+    /// `num` valid openings, as (commitments, values, blindings).
+    fn openings(num: u64) -> (Vec<Vec<u8>>, Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let mut comms = vec![];
+        let mut values = vec![];
+        let mut blindings = vec![];
+        for i in 0..num {
+            let value = Scalar::from(i * 1000);
+            let blinding = Scalar::hash_from_bytes::<sha2::Sha512>(&i.to_le_bytes());
+            let comm = value * RISTRETTO_BASEPOINT_POINT + blinding * *PEDERSEN_RAND_BASE;
+
+            comms.push(comm.compress().to_bytes().to_vec());
+            values.push(value.to_bytes().to_vec());
+            blindings.push(blinding.to_bytes().to_vec());
+        }
+        (comms, values, blindings)
+    }
+
+    #[test]
+    fn rand_base_matches_move_constant() {
+        assert_eq!(
+            hex::encode(PEDERSEN_RAND_BASE.compress().as_bytes()),
+            "8c9240b456a9e6dc65c377a1048d745f94a08cdb7f44cbcd7b46f34048871134"
+        );
+    }
+
+    #[test]
+    fn valid_openings_pass() {
+        let (comms, values, blindings) = openings(64);
+        assert_eq!(
+            pedersen_open_check_batch(&comms, &values, &blindings, &SEED),
+            Some(true)
+        );
+        assert_eq!(pedersen_open_check_batch(&[], &[], &[], &SEED), Some(true));
+        // Whatever the session draws.
+        assert_eq!(
+            pedersen_open_check_batch(&comms, &values, &blindings, &[0; 32]),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn one_bad_opening_among_many_fails() {
+        let (comms, mut values, blindings) = openings(64);
+        values[37] = (Scalar::from(37_000u64) + Scalar::one())
+            .to_bytes()
+            .to_vec();
+        assert_eq!(
+            pedersen_open_check_batch(&comms, &values, &blindings, &SEED),
+            Some(false)
+        );
+
+        // Openings which are each wrong, but whose errors cancel out in the plain sum.
+        let (comms, mut values, blindings) = openings(64);
+        values[3] = (Scalar::from(3_000u64) + Scalar::one()).to_bytes().to_vec();
+        values[4] = (Scalar::from(4_000u64) - Scalar::one()).to_bytes().to_vec();
+        assert_eq!(
+            pedersen_open_check_batch(&comms, &values, &blindings, &SEED),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn malformed_inputs_are_rejected() {
+        let (mut comms, values, mut blindings) = openings(4);
+        blindings[1] = vec![0xFF; SCALAR_NUM_BYTES];
+        assert_eq!(
+            pedersen_open_check_batch(&comms, &values, &blindings, &SEED),
+            None
+        );
+
+        let (_, _, blindings) = openings(4);
+        comms[2] = vec![0xFF; COMPRESSED_POINT_NUM_BYTES];
+        assert_eq!(
+            pedersen_open_check_batch(&comms, &values, &blindings, &SEED),
+            None
+        );
+
+        comms[2] = vec![0; 31];
+        assert_eq!(
+            pedersen_open_check_batch(&comms, &values, &blindings, &SEED),
+            None
+        );
+    }
+
+    #[test]
+    fn ct_eq_compares_bytes() {
+        let (comms, _, _) = openings(2);
+        assert!(ct_eq(&comms[0], &comms[0].clone()));
+        assert!(ct_eq(&[], &[]));
+
+        // Same length, differing in the last byte only.
+        let mut other = comms[0].clone();
+        *other.last_mut().unwrap() ^= 1;
+        assert!(!ct_eq(&comms[0], &other));
+        assert!(!ct_eq(&comms[0], &comms[1]));
+
+        // A strict prefix, and the same bytes padded with the zeros the comparison pads with.
+        assert!(!ct_eq(&comms[0], &comms[0][..31]));
+        let mut padded = comms[0].clone();
+        padded.push(0);
+        assert!(!ct_eq(&comms[0], &padded));
+        assert!(!ct_eq(&padded, &comms[0]));
+        assert!(!ct_eq(&[], &[0]));
+    }
+}
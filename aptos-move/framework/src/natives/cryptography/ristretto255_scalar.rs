@@ -4,14 +4,15 @@
 use crate::{
     natives::{
         cryptography::ristretto255::{
-            pop_32_byte_slice, pop_64_byte_slice, pop_scalar_from_bytes, GasParameters,
-            SCALAR_NUM_BYTES,
+            abort_codes, pop_32_byte_slice, pop_64_byte_slice, pop_scalar_from_bytes,
+            GasParameters, MAX_FIAT_SHAMIR_INPUT_NUM_BYTES, SCALAR_NUM_BYTES,
         },
-        helpers::{SafeNativeContext, SafeNativeResult},
+        helpers::{SafeNativeContext, SafeNativeError, SafeNativeResult},
     },
-    safely_assert_eq, safely_pop_arg,
+    safely_assert_eq, safely_pop_arg, safely_pop_vec_arg,
 };
 use curve25519_dalek::scalar::Scalar;
+use merlin::Transcript;
 use move_core_types::gas_algebra::{NumArgs, NumBytes};
 use move_vm_types::{loaded_data::runtime_types::Type, values::Value};
 use sha2::Sha512;
@@ -22,6 +23,10 @@ use std::{
     ops::{Add, Mul, Neg, Sub},
 };
 
+/// The label of the merlin transcript used by `fiat_shamir_challenge_internal`. The caller-supplied
+/// domain-separation tag is absorbed into this transcript right after it is created.
+const FIAT_SHAMIR_TRANSCRIPT_LABEL: &[u8] = b"AptosRistretto255FiatShamir";
+
 pub(crate) fn native_scalar_is_canonical(
     gas_params: &GasParameters,
     context: &mut SafeNativeContext,
@@ -231,3 +236,71 @@ pub(crate) fn native_scalar_uniform_from_64_bytes(
 
     Ok(smallvec![Value::vector_u8(s.to_bytes().to_vec())])
 }
+
+pub(crate) fn native_scalar_from_fiat_shamir_challenge(
+    gas_params: &GasParameters,
+    context: &mut SafeNativeContext,
+    _ty_args: Vec<Type>,
+    mut arguments: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    safely_assert_eq!(_ty_args.len(), 0);
+    safely_assert_eq!(arguments.len(), 2);
+
+    let elements = safely_pop_vec_arg!(arguments, Vec<u8>);
+    let dst = safely_pop_arg!(arguments, Vec<u8>);
+
+    let num_bytes = dst.len() + elements.iter().map(|e| e.len()).sum::<usize>();
+
+    context.charge(
+        gas_params.fiat_shamir_base * NumArgs::one()
+            + gas_params.fiat_shamir_per_byte * NumBytes::new(num_bytes as u64),
+    )?;
+
+    if num_bytes > MAX_FIAT_SHAMIR_INPUT_NUM_BYTES {
+        return Err(SafeNativeError::Abort {
+            abort_code: abort_codes::NFE_FIAT_SHAMIR_INPUT_TOO_LARGE,
+        });
+    }
+
+    let s = fiat_shamir_challenge(&dst, &elements);
+
+    Ok(smallvec![Value::vector_u8(s.to_bytes().to_vec())])
+}
+
+/// Absorbs the domain-separation tag `dst` and then each of the `elements` into a merlin transcript,
+/// and squeezes a uniformly-distributed challenge scalar out of it.
+///
+/// The DST is appended as a `dom-sep` message, the same way the bulletproofs library consumes it.
+pub(crate) fn fiat_shamir_challenge(dst: &[u8], elements: &[Vec<u8>]) -> Scalar {
+    let mut transcript = Transcript::new(FIAT_SHAMIR_TRANSCRIPT_LABEL);
+    transcript.append_message(b"dom-sep", dst);
+    for element in elements {
+        transcript.append_message(b"element", element);
+    }
+
+    let mut buf = [0u8; 64];
+    transcript.challenge_bytes(b"challenge", &mut buf);
+
+    Scalar::from_bytes_mod_order_wide(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fiat_shamir_challenge_is_canonical_and_domain_separated() {
+        let elements = vec![vec![1u8; 32], vec![2u8; 32]];
+
+        let a = fiat_shamir_challenge(b"AptosTest/A", &elements);
+        let b = fiat_shamir_challenge(b"AptosTest/B", &elements);
+        assert!(a.is_canonical());
+        assert_ne!(a, b);
+
+        // Concatenating elements must not collide with absorbing them separately.
+        let c = fiat_shamir_challenge(b"AptosTest/A", &[[vec![1u8; 32], vec![2u8; 32]].concat()]);
+        assert_ne!(a, c);
+
+        assert_eq!(a, fiat_shamir_challenge(b"AptosTest/A", &elements));
+    }
+}
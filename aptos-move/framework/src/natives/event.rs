@@ -8,13 +8,106 @@ use crate::{
     safely_pop_arg,
 };
 use aptos_gas_algebra_ext::{AbstractValueSize, InternalGasPerAbstractValueUnit};
-use aptos_types::on_chain_config::{Features, TimedFeatures};
-use move_core_types::gas_algebra::InternalGas;
+use aptos_types::on_chain_config::{FeatureFlag, Features, TimedFeatures};
+use better_any::{Tid, TidAble};
+use move_binary_format::errors::PartialVMError;
+use move_core_types::{gas_algebra::InternalGas, language_storage::TypeTag, vm_status::StatusCode};
 use move_vm_runtime::native_functions::NativeFunction;
 use move_vm_types::{loaded_data::runtime_types::Type, values::Value};
 use smallvec::{smallvec, SmallVec};
 use std::{collections::VecDeque, sync::Arc};
 
+/// All events emitted in a session, in emission order, so that the session can output them in
+/// that order when it is finished.
+///
+/// Events emitted by natives through `SafeNativeContext::emit_event` are not tied to an event
+/// handle: the session keys them itself. Module events, emitted from Move through
+/// `0x1::event::emit`, have no key at all and are identified by their type. Events emitted through
+/// event handles are kept by the Move VM, so only their place among the others is recorded here.
+///
+/// The context also keeps the running size of all events emitted in the session, Move events
+/// included, so that the limits on event sizes can be enforced while the session executes rather
+/// than only when it is finished.
+#[derive(Default, Tid)]
+pub struct NativeEventContext {
+    events: Vec<EmittedEvent>,
+    limits: Option<EventLimits>,
+    total_bytes: u64,
+}
+
+/// An event recorded by `NativeEventContext`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EmittedEvent {
+    /// The next of the events emitted through an event handle, which the Move VM keeps in the
+    /// same order.
+    Handle,
+    /// An event emitted by a native.
+    Native(TypeTag, Vec<u8>),
+    /// A module event.
+    Module(TypeTag, Vec<u8>),
+}
+
+/// Limits on the size of the events emitted by a session, in bytes of their payloads.
+#[derive(Clone, Copy, Debug)]
+pub struct EventLimits {
+    pub max_bytes_per_event: u64,
+    pub max_bytes_all_events: u64,
+}
+
+impl NativeEventContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes emitting an event fail with `STORAGE_WRITE_LIMIT_REACHED` as soon as it crosses one
+    /// of `limits`. Without limits events are only checked when the session is finished.
+    pub fn set_limits(&mut self, limits: EventLimits) {
+        self.limits = Some(limits);
+    }
+
+    pub fn limits(&self) -> Option<EventLimits> {
+        self.limits
+    }
+
+    /// Adds an event of `size` bytes to the running total, failing if this crosses a limit.
+    pub fn record_event_size(&mut self, size: u64) -> SafeNativeResult<()> {
+        self.total_bytes = self.total_bytes.saturating_add(size);
+        match self.limits {
+            Some(limits)
+                if size > limits.max_bytes_per_event
+                    || self.total_bytes > limits.max_bytes_all_events =>
+            {
+                Err(SafeNativeError::LimitExceeded(
+                    PartialVMError::new(StatusCode::STORAGE_WRITE_LIMIT_REACHED).with_message(
+                        format!(
+                            "event of {} bytes brings the events of the transaction to {} bytes",
+                            size, self.total_bytes
+                        ),
+                    ),
+                ))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn emit(&mut self, type_tag: TypeTag, data: Vec<u8>) {
+        self.events.push(EmittedEvent::Native(type_tag, data));
+    }
+
+    pub fn emit_module_event(&mut self, type_tag: TypeTag, data: Vec<u8>) {
+        self.events.push(EmittedEvent::Module(type_tag, data));
+    }
+
+    /// Records that an event was emitted through an event handle.
+    pub fn record_handle_event(&mut self) {
+        self.events.push(EmittedEvent::Handle);
+    }
+
+    pub fn into_events(self) -> Vec<EmittedEvent> {
+        self.events
+    }
+}
+
 /***************************************************************************************************
  * native fun write_to_event_store
  *
@@ -48,9 +141,34 @@ fn native_write_to_event_store(
         gas_params.base + gas_params.per_abstract_value_unit * calc_abstract_val_size(&msg),
     )?;
 
+    // Sessions without the event extension, e.g. the ones of tools that only run Move code,
+    // neither limit nor record the events emitted through handles.
+    let limits_enforced = context
+        .extensions()
+        .try_get::<NativeEventContext>()
+        .map_or(false, |event_context| event_context.limits().is_some());
+    if limits_enforced {
+        // Same size as the one checked when the session is finished: the BCS of the message.
+        let size = match context.type_to_type_layout(&ty)? {
+            Some(layout) => msg
+                .simple_serialize(&layout)
+                .map(|bytes| bytes.len() as u64),
+            None => None,
+        };
+        if let Some(size) = size {
+            context
+                .extensions_mut()
+                .get_mut::<NativeEventContext>()
+                .record_event_size(size)?;
+        }
+    }
+
     if !context.save_event(guid, seq_num, ty, msg)? {
         return Err(SafeNativeError::Abort { abort_code: 0 });
     }
+    if let Some(event_context) = context.extensions_mut().try_get_mut::<NativeEventContext>() {
+        event_context.record_handle_event();
+    }
 
     Ok(smallvec![])
 }
@@ -68,6 +186,88 @@ pub fn make_native_write_to_event_store(
     }
 }
 
+/***************************************************************************************************
+ * native fun write_module_event_to_store
+ *
+ *   gas cost: base_cost + per_abstract_value_unit * size of the message
+ *
+ **************************************************************************************************/
+/// `error::invalid_state(EMODULE_EVENTS_NOT_ENABLED)` in `0x1::event`.
+const EMODULE_EVENTS_NOT_ENABLED: u64 = 0x03_0001;
+/// `error::invalid_argument(EMODULE_EVENT_NOT_STRUCT)` in `0x1::event`.
+const EMODULE_EVENT_NOT_STRUCT: u64 = 0x01_0002;
+
+#[derive(Debug, Clone)]
+pub struct WriteModuleEventToStoreGasParameters {
+    pub base: InternalGas,
+    pub per_abstract_value_unit: InternalGasPerAbstractValueUnit,
+}
+
+#[inline]
+fn native_write_module_event_to_store(
+    gas_params: &WriteModuleEventToStoreGasParameters,
+    calc_abstract_val_size: impl FnOnce(&Value) -> AbstractValueSize,
+    context: &mut SafeNativeContext,
+    mut ty_args: Vec<Type>,
+    mut arguments: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    debug_assert!(ty_args.len() == 1);
+    debug_assert!(arguments.len() == 1);
+
+    let ty = ty_args.pop().unwrap();
+    let msg = arguments.pop_back().unwrap();
+
+    context.charge(
+        gas_params.base + gas_params.per_abstract_value_unit * calc_abstract_val_size(&msg),
+    )?;
+
+    if !context.is_feature_enabled(FeatureFlag::MODULE_EVENT) {
+        return Err(SafeNativeError::Abort {
+            abort_code: EMODULE_EVENTS_NOT_ENABLED,
+        });
+    }
+    let type_tag = context.type_to_type_tag(&ty)?;
+    if !matches!(type_tag, TypeTag::Struct(_)) {
+        return Err(SafeNativeError::Abort {
+            abort_code: EMODULE_EVENT_NOT_STRUCT,
+        });
+    }
+    let data = context
+        .type_to_type_layout(&ty)?
+        .and_then(|layout| msg.simple_serialize(&layout))
+        .ok_or_else(|| {
+            SafeNativeError::InvariantViolation(
+                PartialVMError::new(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+                    .with_message(format!("failed to serialize module event {}", type_tag)),
+            )
+        })?;
+
+    let event_context = context.extensions_mut().get_mut::<NativeEventContext>();
+    event_context.record_event_size(data.len() as u64)?;
+    event_context.emit_module_event(type_tag, data);
+
+    Ok(smallvec![])
+}
+
+pub fn make_native_write_module_event_to_store(
+    calc_abstract_val_size: impl Fn(&Value) -> AbstractValueSize + Send + Sync + 'static,
+) -> impl Fn(
+    &WriteModuleEventToStoreGasParameters,
+    &mut SafeNativeContext,
+    Vec<Type>,
+    VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    move |gas_params, context, ty_args, args| -> SafeNativeResult<SmallVec<[Value; 1]>> {
+        native_write_module_event_to_store(
+            gas_params,
+            &calc_abstract_val_size,
+            context,
+            ty_args,
+            args,
+        )
+    }
+}
+
 /***************************************************************************************************
  * module
  *
@@ -75,6 +275,7 @@ pub fn make_native_write_to_event_store(
 #[derive(Debug, Clone)]
 pub struct GasParameters {
     pub write_to_event_store: WriteToEventStoreGasParameters,
+    pub write_module_event_to_store: WriteModuleEventToStoreGasParameters,
 }
 
 pub fn make_all(
@@ -83,15 +284,31 @@ pub fn make_all(
     timed_features: TimedFeatures,
     features: Arc<Features>,
 ) -> impl Iterator<Item = (String, NativeFunction)> {
-    let natives = [(
-        "write_to_event_store",
-        make_safe_native(
-            gas_params.write_to_event_store,
-            timed_features,
-            features,
-            make_native_write_to_event_store(calc_abstract_val_size),
+    let calc_abstract_val_size = Arc::new(calc_abstract_val_size);
+    let calc_module_event_size = {
+        let calc_abstract_val_size = calc_abstract_val_size.clone();
+        move |val: &Value| calc_abstract_val_size(val)
+    };
+    let natives = [
+        (
+            "write_to_event_store",
+            make_safe_native(
+                gas_params.write_to_event_store,
+                timed_features.clone(),
+                features.clone(),
+                make_native_write_to_event_store(move |val: &Value| calc_abstract_val_size(val)),
+            ),
+        ),
+        (
+            "write_module_event_to_store",
+            make_safe_native(
+                gas_params.write_module_event_to_store,
+                timed_features,
+                features,
+                make_native_write_module_event_to_store(calc_module_event_size),
+            ),
         ),
-    )];
+    ];
 
     make_module_natives(natives)
 }
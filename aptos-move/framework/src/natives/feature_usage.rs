@@ -0,0 +1,28 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_types::on_chain_config::{FeatureFlag, FeatureUsageRecorder};
+use better_any::{Tid, TidAble};
+
+/// The native feature usage extension. If a session has one, natives checking feature flags
+/// through `SafeNativeContext::is_feature_enabled` record the flags they checked in it, so the
+/// session can tell which flags its execution depended on. It never produces writes.
+#[derive(Tid)]
+pub struct NativeFeatureUsageContext {
+    recorder: FeatureUsageRecorder,
+}
+
+impl NativeFeatureUsageContext {
+    pub fn new(recorder: FeatureUsageRecorder) -> Self {
+        Self { recorder }
+    }
+
+    pub fn is_enabled(&self, flag: FeatureFlag) -> bool {
+        self.recorder.is_enabled(flag)
+    }
+
+    /// The flags checked so far, in ascending order.
+    pub fn used_flags(&self) -> Vec<u64> {
+        self.recorder.used_flags()
+    }
+}
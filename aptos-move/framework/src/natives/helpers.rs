@@ -1,13 +1,21 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use aptos_types::on_chain_config::{Features, TimedFeatureFlag, TimedFeatures};
+#[cfg(feature = "feature-usage")]
+use crate::natives::feature_usage::NativeFeatureUsageContext;
+use crate::natives::{event::NativeEventContext, transaction_context::NativeTransactionContext};
+use aptos_types::on_chain_config::{FeatureFlag, Features, TimedFeatureFlag, TimedFeatures};
 use move_binary_format::errors::{PartialVMError, PartialVMResult};
-use move_core_types::gas_algebra::InternalGas;
+use move_core_types::{
+    gas_algebra::{InternalGas, InternalGasPerByte, NumBytes},
+    language_storage::TypeTag,
+};
 use move_vm_runtime::native_functions::{NativeContext, NativeFunction};
 use move_vm_types::{
     loaded_data::runtime_types::Type, natives::function::NativeResult, values::Value,
 };
+use rand_core::RngCore;
+use sha3::{Digest, Sha3_256};
 use smallvec::SmallVec;
 use std::{
     collections::VecDeque,
@@ -217,13 +225,206 @@ impl<'a, 'b, 'c> SafeNativeContext<'a, 'b, 'c> {
     pub fn get_feature_flags(&self) -> &Features {
         self.features.deref()
     }
+
+    /// Whether `flag` is enabled, recording the check if the session keeps track of the flags
+    /// it reads, see `NativeFeatureUsageContext`.
+    pub fn is_feature_enabled(&self, flag: FeatureFlag) -> bool {
+        #[cfg(feature = "feature-usage")]
+        if let Some(feature_usage) = self.extensions().try_get::<NativeFeatureUsageContext>() {
+            return feature_usage.is_enabled(flag);
+        }
+        self.features.is_enabled(flag)
+    }
+
+    /// Queues an event of the given type, charging `gas_per_byte` for its payload first. The
+    /// event is emitted when the session is finished, see `NativeEventContext`.
+    pub fn emit_event(
+        &mut self,
+        type_tag: TypeTag,
+        data: Vec<u8>,
+        gas_per_byte: InternalGasPerByte,
+    ) -> SafeNativeResult<()> {
+        self.charge(gas_per_byte * NumBytes::new(data.len() as u64))?;
+
+        let event_context = self.extensions_mut().get_mut::<NativeEventContext>();
+        event_context.record_event_size(data.len() as u64)?;
+        event_context.emit(type_tag, data);
+        Ok(())
+    }
+
+    /// A random number generator that is the same on every validator, see `SessionRng`.
+    pub fn session_rng(&mut self) -> SessionRng {
+        self.extensions_mut()
+            .get_mut::<NativeTransactionContext>()
+            .session_rng()
+    }
+}
+
+/// A random number generator for natives which is deterministic across validators: its output
+/// only depends on the session and on how many RNGs the session handed out before, see
+/// `NativeTransactionContext::session_rng`.
+///
+/// Whoever sends the transaction can compute the output ahead of time. It is meant for picking
+/// e.g. the coefficients of a random linear combination, which only need to be independent of
+/// inputs fixed before them, and must be combined with a hash of the inputs wherever these could
+/// be chosen after the transaction. It must never be used for anything that has to stay secret,
+/// which is why it is not a `CryptoRng` and cannot generate keys or blindings, not even in
+/// test-only natives.
+pub struct SessionRng {
+    seed: [u8; 32],
+    block: [u8; 32],
+    blocks: u64,
+    pos: usize,
+}
+
+impl SessionRng {
+    /// Domain separator of the output blocks.
+    const BLOCK_DST: &'static [u8] = b"APTOS::SessionRng::Block";
+
+    pub fn new(seed: [u8; 32]) -> Self {
+        Self {
+            seed,
+            block: [0; 32],
+            blocks: 0,
+            // Exhausted, so that the first block is computed on first use.
+            pos: 32,
+        }
+    }
+
+    fn next_block(&mut self) {
+        let mut hasher = Sha3_256::new();
+        hasher.update(Self::BLOCK_DST);
+        hasher.update(self.seed);
+        hasher.update(self.blocks.to_le_bytes());
+        self.block = hasher.finalize().into();
+        self.blocks += 1;
+        self.pos = 0;
+    }
+}
+
+impl RngCore for SessionRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut filled = 0;
+        while filled < dest.len() {
+            if self.pos == self.block.len() {
+                self.next_block();
+            }
+            let len = (dest.len() - filled).min(self.block.len() - self.pos);
+            dest[filled..filled + len].copy_from_slice(&self.block[self.pos..self.pos + len]);
+            filled += len;
+            self.pos += len;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Per-byte pricing that changes with the size of the input, for natives whose cost grows
+/// sub-linearly (or super-linearly) with it.
+///
+/// Each tier is the byte offset it starts at and the price of every byte from there on until the
+/// next tier starts, so bytes are charged like income in tax brackets. Tiers must be sorted by
+/// their offsets; bytes before the first tier are free.
+#[derive(Clone, Debug)]
+pub struct TieredGasParameter {
+    pub tiers: Vec<(NumBytes, InternalGasPerByte)>,
+}
+
+impl TieredGasParameter {
+    /// The cost of an input of `len` bytes.
+    pub fn cost(&self, len: NumBytes) -> InternalGas {
+        let len = u64::from(len);
+        let mut cost = InternalGas::from(0);
+        for (i, (start, per_byte)) in self.tiers.iter().enumerate() {
+            let start = u64::from(*start);
+            if len <= start {
+                break;
+            }
+            let end = match self.tiers.get(i + 1) {
+                Some((next_start, _)) => len.min(u64::from(*next_start)),
+                None => len,
+            };
+            cost += *per_byte * NumBytes::new(end - start);
+        }
+        cost
+    }
+
+    /// Charges the cost of an input of `len` bytes, see `SafeNativeContext::charge`.
+    #[must_use = "must always propagate the error returned by this function to the native function that called it using the ? operator"]
+    pub fn charge_tiered(
+        &self,
+        context: &mut SafeNativeContext,
+        len: NumBytes,
+    ) -> SafeNativeResult<()> {
+        context.charge(self.cost(len))
+    }
+}
+
+#[test]
+fn test_tiered_gas_parameter_cost() {
+    let param = TieredGasParameter {
+        tiers: vec![
+            (NumBytes::new(0), InternalGasPerByte::new(1)),
+            (NumBytes::new(700), InternalGasPerByte::new(3)),
+            (NumBytes::new(1000), InternalGasPerByte::new(10)),
+        ],
+    };
+    let cost = |len| u64::from(param.cost(NumBytes::new(len)));
+
+    assert_eq!(cost(0), 0);
+    assert_eq!(cost(1), 1);
+    assert_eq!(cost(699), 699);
+    assert_eq!(cost(700), 700);
+    assert_eq!(cost(701), 703);
+    assert_eq!(cost(999), 700 + 299 * 3);
+    assert_eq!(cost(1000), 700 + 300 * 3);
+    assert_eq!(cost(1001), 700 + 300 * 3 + 10);
+
+    let flat = TieredGasParameter {
+        tiers: vec![(NumBytes::new(0), InternalGasPerByte::new(5))],
+    };
+    assert_eq!(u64::from(flat.cost(NumBytes::new(123))), 615);
+    assert_eq!(
+        u64::from(TieredGasParameter { tiers: vec![] }.cost(NumBytes::new(123))),
+        0
+    );
+}
+
+#[test]
+fn test_tiered_gas_parameter_free_prefix() {
+    let param = TieredGasParameter {
+        tiers: vec![(NumBytes::new(64), InternalGasPerByte::new(2))],
+    };
+    assert_eq!(u64::from(param.cost(NumBytes::new(64))), 0);
+    assert_eq!(u64::from(param.cost(NumBytes::new(65))), 2);
 }
 
 #[allow(unused)]
 pub enum SafeNativeError {
-    Abort { abort_code: u64 },
+    Abort {
+        abort_code: u64,
+    },
     OutOfGas,
     InvariantViolation(PartialVMError),
+    /// A limit on what a transaction may do, e.g. on the size of its events, was crossed. Unlike an
+    /// invariant violation this is caused by the transaction itself, and it fails the transaction
+    /// with the status code of the error.
+    LimitExceeded(PartialVMError),
 }
 
 /// Allows us to keep using the `?` operator on function calls that return `PartialVMResult` inside safe natives.
@@ -273,7 +474,7 @@ where
             Err(err) => match err {
                 Abort { abort_code } => Ok(NativeResult::err(context.gas_used, abort_code)),
                 OutOfGas => Ok(NativeResult::out_of_gas(context.gas_used)),
-                InvariantViolation(err) => Err(err),
+                InvariantViolation(err) | LimitExceeded(err) => Err(err),
             },
         }
     };
@@ -347,3 +548,42 @@ fn test_log2_ceil() {
     assert_eq!(log2_ceil(16), Some(4));
     assert_eq!(log2_ceil(usize::MAX), Some(usize::BITS as usize));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_rng_is_deterministic() {
+        let outputs = |txn_hash: [u8; 32]| {
+            let mut context = NativeTransactionContext::new(txn_hash, vec![], 4);
+            (0..3)
+                .map(|_| {
+                    let mut rng = context.session_rng();
+                    let mut bytes = vec![0; 45];
+                    rng.fill_bytes(&mut bytes);
+                    (bytes, rng.next_u64())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Two identical sessions draw the same values...
+        let first = outputs([1; 32]);
+        assert_eq!(first, outputs([1; 32]));
+        // ...while every RNG of a session, and every session, draws different ones.
+        assert_ne!(first[0], first[1]);
+        assert_ne!(first[1], first[2]);
+        assert_ne!(first, outputs([2; 32]));
+
+        // Reading in chunks draws the same bytes as reading at once.
+        let mut rng = SessionRng::new([3; 32]);
+        let mut at_once = [0; 70];
+        rng.fill_bytes(&mut at_once);
+        let mut rng = SessionRng::new([3; 32]);
+        let mut in_chunks = [0; 70];
+        for chunk in in_chunks.chunks_mut(9) {
+            rng.fill_bytes(chunk);
+        }
+        assert_eq!(at_once, in_chunks);
+    }
+}
@@ -10,9 +10,12 @@ pub mod create_signer;
 pub mod cryptography;
 pub mod debug;
 pub mod event;
+#[cfg(feature = "feature-usage")]
+pub mod feature_usage;
 pub mod hash;
 mod helpers;
 pub mod object;
+pub mod session_counter;
 pub mod state_storage;
 pub mod string_utils;
 pub mod transaction_context;
@@ -52,6 +55,7 @@ pub struct GasParameters {
     pub code: code::GasParameters,
     pub event: event::GasParameters,
     pub state_storage: state_storage::GasParameters,
+    pub session_counter: session_counter::GasParameters,
     pub aggregator: aggregator::GasParameters,
     pub aggregator_factory: aggregator_factory::GasParameters,
     pub object: object::GasParameters,
@@ -181,6 +185,12 @@ impl GasParameters {
                 scalar_sub: 0.into(),
                 point_parse_arg: 0.into(),
                 scalar_parse_arg: 0.into(),
+                fiat_shamir_base: 0.into(),
+                fiat_shamir_per_byte: 0.into(),
+                pedersen_open_check_base: 0.into(),
+                pedersen_open_check_per_commitment: 0.into(),
+                ct_eq_base: 0.into(),
+                ct_eq_per_byte: 0.into(),
             },
             hash: hash::GasParameters {
                 sip_hash: hash::SipHashGasParameters {
@@ -227,6 +237,13 @@ impl GasParameters {
             },
             transaction_context: transaction_context::GasParameters {
                 get_script_hash: transaction_context::GetScriptHashGasParameters { base: 0.into() },
+                unique_session_value: transaction_context::UniqueSessionValueGasParameters {
+                    base: 0.into(),
+                },
+                remaining_write_budget: transaction_context::RemainingWriteBudgetGasParameters {
+                    base: 0.into(),
+                    per_byte: 0.into(),
+                },
             },
             code: code::GasParameters {
                 request_publish: code::RequestPublishGasParameters {
@@ -239,12 +256,19 @@ impl GasParameters {
                     base: 0.into(),
                     per_abstract_value_unit: 0.into(),
                 },
+                write_module_event_to_store: event::WriteModuleEventToStoreGasParameters {
+                    base: 0.into(),
+                    per_abstract_value_unit: 0.into(),
+                },
             },
             state_storage: state_storage::GasParameters {
                 get_usage: state_storage::GetUsageGasParameters {
                     base_cost: 0.into(),
                 },
             },
+            session_counter: session_counter::GasParameters {
+                next: session_counter::NextGasParameters { base: 0.into() },
+            },
             aggregator: aggregator::GasParameters {
                 add: aggregator::AddGasParameters { base: 0.into() },
                 read: aggregator::ReadGasParameters { base: 0.into() },
@@ -260,6 +284,10 @@ impl GasParameters {
                     per_byte_loaded: 0.into(),
                     per_item_loaded: 0.into(),
                 },
+                is_resource_group: object::IsResourceGroupGasParameters {
+                    base: 0.into(),
+                    per_module_metadata_loaded: 0.into(),
+                },
             },
             string_utils: string_utils::GasParameters {
                 base: 0.into(),
@@ -409,6 +437,14 @@ pub fn all_natives(
             features.clone()
         )
     );
+    add_natives_from_module!(
+        "session_counter",
+        session_counter::make_all(
+            gas_params.session_counter,
+            timed_features.clone(),
+            features.clone()
+        )
+    );
     add_natives_from_module!(
         "aggregator",
         aggregator::make_all(
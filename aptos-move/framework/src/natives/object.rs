@@ -3,12 +3,14 @@
 
 use crate::{
     natives::helpers::{make_safe_native, SafeNativeContext, SafeNativeResult},
-    safely_assert_eq, safely_pop_arg,
+    safely_assert_eq, safely_pop_arg, RuntimeModuleMetadataV1,
 };
 use aptos_types::on_chain_config::{Features, TimedFeatures};
+use better_any::{Tid, TidAble};
 use move_core_types::{
     account_address::AccountAddress,
     gas_algebra::{InternalGas, InternalGasPerByte},
+    language_storage::{ModuleId, TypeTag},
     vm_status::StatusCode,
 };
 use move_vm_runtime::native_functions::NativeFunction;
@@ -16,7 +18,44 @@ use move_vm_types::{
     loaded_data::runtime_types::Type, natives::function::PartialVMError, values::Value,
 };
 use smallvec::{smallvec, SmallVec};
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+
+/// Ability to look up the Aptos metadata of a module, e.g. its resource group attributes.
+pub trait ModuleMetadataResolver {
+    fn resolve_module_metadata(&self, module_id: &ModuleId) -> Option<RuntimeModuleMetadataV1>;
+}
+
+/// Exposes module metadata to the object natives, loading the metadata of each module at most
+/// once per session.
+#[derive(Tid)]
+pub struct NativeObjectContext<'a> {
+    resolver: &'a dyn ModuleMetadataResolver,
+    metadata: BTreeMap<ModuleId, Option<RuntimeModuleMetadataV1>>,
+}
+
+impl<'a> NativeObjectContext<'a> {
+    pub fn new(resolver: &'a dyn ModuleMetadataResolver) -> Self {
+        Self {
+            resolver,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    fn is_loaded(&self, module_id: &ModuleId) -> bool {
+        self.metadata.contains_key(module_id)
+    }
+
+    fn module_metadata(&mut self, module_id: &ModuleId) -> Option<&RuntimeModuleMetadataV1> {
+        let resolver = self.resolver;
+        self.metadata
+            .entry(module_id.clone())
+            .or_insert_with(|| resolver.resolve_module_metadata(module_id))
+            .as_ref()
+    }
+}
 
 /***************************************************************************************************
  * native exists_at<T>
@@ -67,6 +106,87 @@ fn native_exists_at(
     Ok(smallvec![Value::bool(exists)])
 }
 
+/***************************************************************************************************
+ * native is_resource_group_internal<T>
+ * native is_resource_group_member_internal<T>
+ *
+ *   gas cost: base + per_module_metadata_loaded, the latter only for the first lookup of T's
+ *             module in the session
+ *
+ **************************************************************************************************/
+#[derive(Clone, Debug)]
+pub struct IsResourceGroupGasParameters {
+    pub base: InternalGas,
+    pub per_module_metadata_loaded: InternalGas,
+}
+
+/// Whether `ty` is a struct carrying the resource group (`member == false`) or resource group
+/// member (`member == true`) attribute.
+fn has_resource_group_attribute(
+    gas_params: &IsResourceGroupGasParameters,
+    context: &mut SafeNativeContext,
+    ty: &Type,
+    member: bool,
+) -> SafeNativeResult<bool> {
+    context.charge(gas_params.base)?;
+
+    let struct_tag = match context.type_to_type_tag(ty)? {
+        TypeTag::Struct(struct_tag) => struct_tag,
+        _ => return Ok(false),
+    };
+    let module_id = struct_tag.module_id();
+
+    if !context
+        .extensions()
+        .get::<NativeObjectContext>()
+        .is_loaded(&module_id)
+    {
+        context.charge(gas_params.per_module_metadata_loaded)?;
+    }
+
+    let has_attribute = context
+        .extensions_mut()
+        .get_mut::<NativeObjectContext>()
+        .module_metadata(&module_id)
+        .and_then(|metadata| metadata.struct_attributes.get(struct_tag.name.as_str()))
+        .map_or(false, |attributes| {
+            attributes.iter().any(|attribute| {
+                if member {
+                    attribute.is_resource_group_member()
+                } else {
+                    attribute.is_resource_group()
+                }
+            })
+        });
+    Ok(has_attribute)
+}
+
+fn native_is_resource_group(
+    gas_params: &IsResourceGroupGasParameters,
+    context: &mut SafeNativeContext,
+    ty_args: Vec<Type>,
+    args: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    safely_assert_eq!(ty_args.len(), 1);
+    safely_assert_eq!(args.len(), 0);
+
+    let is_group = has_resource_group_attribute(gas_params, context, &ty_args[0], false)?;
+    Ok(smallvec![Value::bool(is_group)])
+}
+
+fn native_is_resource_group_member(
+    gas_params: &IsResourceGroupGasParameters,
+    context: &mut SafeNativeContext,
+    ty_args: Vec<Type>,
+    args: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    safely_assert_eq!(ty_args.len(), 1);
+    safely_assert_eq!(args.len(), 0);
+
+    let is_member = has_resource_group_attribute(gas_params, context, &ty_args[0], true)?;
+    Ok(smallvec![Value::bool(is_member)])
+}
+
 /***************************************************************************************************
  * module
  *
@@ -74,6 +194,7 @@ fn native_exists_at(
 #[derive(Debug, Clone)]
 pub struct GasParameters {
     pub exists_at: ExistsAtGasParameters,
+    pub is_resource_group: IsResourceGroupGasParameters,
 }
 
 pub fn make_all(
@@ -81,15 +202,35 @@ pub fn make_all(
     timed_features: TimedFeatures,
     features: Arc<Features>,
 ) -> impl Iterator<Item = (String, NativeFunction)> {
-    let natives = [(
-        "exists_at",
-        make_safe_native(
-            gas_params.exists_at,
-            timed_features,
-            features,
-            native_exists_at,
+    let natives = [
+        (
+            "exists_at",
+            make_safe_native(
+                gas_params.exists_at,
+                timed_features.clone(),
+                features.clone(),
+                native_exists_at,
+            ),
+        ),
+        (
+            "is_resource_group_internal",
+            make_safe_native(
+                gas_params.is_resource_group.clone(),
+                timed_features.clone(),
+                features.clone(),
+                native_is_resource_group,
+            ),
+        ),
+        (
+            "is_resource_group_member_internal",
+            make_safe_native(
+                gas_params.is_resource_group,
+                timed_features,
+                features,
+                native_is_resource_group_member,
+            ),
         ),
-    )];
+    ];
 
     crate::natives::helpers::make_module_natives(natives)
 }
@@ -0,0 +1,74 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::natives::{
+    aggregator_natives::NativeAggregatorContext,
+    helpers::{make_safe_native, SafeNativeContext, SafeNativeError, SafeNativeResult},
+};
+use aptos_types::{
+    on_chain_config::{Features, TimedFeatures},
+    vm_status::StatusCode,
+};
+use move_binary_format::errors::PartialVMError;
+use move_core_types::gas_algebra::InternalGas;
+use move_vm_runtime::native_functions::NativeFunction;
+use move_vm_types::{loaded_data::runtime_types::Type, values::Value};
+use smallvec::{smallvec, SmallVec};
+use std::{collections::VecDeque, sync::Arc};
+
+/***************************************************************************************************
+ * native fun session_counter_next_internal
+ *
+ *   gas cost: base_cost
+ *
+ **************************************************************************************************/
+#[derive(Clone, Debug)]
+pub struct NextGasParameters {
+    pub base: InternalGas,
+}
+
+fn native_next(
+    gas_params: &NextGasParameters,
+    context: &mut SafeNativeContext,
+    _ty_args: Vec<Type>,
+    _args: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    debug_assert!(_ty_args.is_empty());
+    debug_assert!(_args.is_empty());
+
+    context.charge(gas_params.base)?;
+
+    // The counter lives in the aggregator context, which every session already gets, seeded with
+    // the session's UUID.
+    let value = context
+        .extensions_mut()
+        .get_mut::<NativeAggregatorContext>()
+        .next_session_counter()
+        .ok_or_else(|| {
+            SafeNativeError::InvariantViolation(PartialVMError::new(StatusCode::ARITHMETIC_ERROR))
+        })?;
+
+    Ok(smallvec![Value::u64(value)])
+}
+
+/***************************************************************************************************
+ * module
+ *
+ **************************************************************************************************/
+#[derive(Debug, Clone)]
+pub struct GasParameters {
+    pub next: NextGasParameters,
+}
+
+pub fn make_all(
+    gas_params: GasParameters,
+    timed_features: TimedFeatures,
+    features: Arc<Features>,
+) -> impl Iterator<Item = (String, NativeFunction)> {
+    let natives = [(
+        "session_counter_next_internal",
+        make_safe_native(gas_params.next, timed_features, features, native_next),
+    )];
+
+    crate::natives::helpers::make_module_natives(natives)
+}
@@ -1,37 +1,91 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::natives::helpers::{make_safe_native, SafeNativeContext, SafeNativeResult};
+use crate::{
+    natives::helpers::{make_safe_native, SafeNativeContext, SafeNativeResult, SessionRng},
+    safely_pop_arg,
+};
 use aptos_types::on_chain_config::{Features, TimedFeatures};
 use better_any::{Tid, TidAble};
-use move_core_types::gas_algebra::InternalGas;
+use move_core_types::gas_algebra::{InternalGas, InternalGasPerByte, NumBytes};
 use move_vm_runtime::native_functions::NativeFunction;
 use move_vm_types::{loaded_data::runtime_types::Type, values::Value};
+use sha3::{Digest, Sha3_256};
 use smallvec::{smallvec, SmallVec};
 use std::{collections::VecDeque, fmt::Debug, sync::Arc};
 
+/// Domain separator of the values returned by `unique_session_value`, so that they never collide
+/// with other hashes of the session id.
+const UNIQUE_SESSION_VALUE_DST: &[u8] = b"APTOS::TransactionContext::UniqueSessionValue";
+
+/// Domain separator of the seeds of `SessionRng`s.
+const SESSION_RNG_DST: &[u8] = b"APTOS::TransactionContext::SessionRng";
+
 /// The native transaction context extension. This needs to be attached to the
 /// NativeContextExtensions value which is passed into session functions, so its accessible from
 /// natives of this extension.
 #[derive(Tid)]
 pub struct NativeTransactionContext {
+    txn_hash: [u8; 32],
     script_hash: Vec<u8>,
     chain_id: u8,
+    session_rngs: u64,
 }
 
 impl NativeTransactionContext {
     /// Create a new instance of a native transaction context. This must be passed in via an
     /// extension into VM session functions.
-    pub fn new(script_hash: Vec<u8>, chain_id: u8) -> Self {
+    pub fn new(txn_hash: [u8; 32], script_hash: Vec<u8>, chain_id: u8) -> Self {
         Self {
+            txn_hash,
             script_hash,
             chain_id,
+            session_rngs: 0,
         }
     }
 
     pub fn chain_id(&self) -> u8 {
         self.chain_id
     }
+
+    /// A value derived from the hash of the session id and `counter`: the same whenever the same
+    /// transaction is executed, but not known before the transaction is.
+    pub fn unique_session_value(&self, counter: u64) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        hasher.update(UNIQUE_SESSION_VALUE_DST);
+        hasher.update(self.txn_hash);
+        hasher.update(counter.to_le_bytes());
+        hasher.finalize().into()
+    }
+
+    /// A new `SessionRng`, seeded from the hash of the session id and the number of RNGs handed
+    /// out before it, so that every call gets a different one and the calls of the same session
+    /// on any validator get the same ones.
+    pub fn session_rng(&mut self) -> SessionRng {
+        let mut hasher = Sha3_256::new();
+        hasher.update(SESSION_RNG_DST);
+        hasher.update(self.txn_hash);
+        hasher.update(self.session_rngs.to_le_bytes());
+        self.session_rngs += 1;
+        SessionRng::new(hasher.finalize().into())
+    }
+}
+
+/// The limit on the bytes of all write ops of a transaction, as checked when its session is
+/// finished, for `remaining_write_budget` to count down from. Without it the budget is unlimited.
+#[derive(Default, Tid)]
+pub struct NativeWriteBudgetContext {
+    max_bytes_all_write_ops: Option<u64>,
+}
+
+impl NativeWriteBudgetContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_max_bytes_all_write_ops(&mut self, max_bytes: u64) {
+        self.max_bytes_all_write_ops = Some(max_bytes);
+    }
 }
 
 /***************************************************************************************************
@@ -60,6 +114,82 @@ fn native_get_script_hash(
     )])
 }
 
+/***************************************************************************************************
+ * native fun unique_session_value_internal
+ *
+ *   gas cost: base_cost
+ *
+ **************************************************************************************************/
+#[derive(Clone, Debug)]
+pub struct UniqueSessionValueGasParameters {
+    pub base: InternalGas,
+}
+
+fn native_unique_session_value(
+    gas_params: &UniqueSessionValueGasParameters,
+    context: &mut SafeNativeContext,
+    _ty_args: Vec<Type>,
+    mut args: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    debug_assert!(_ty_args.is_empty());
+    debug_assert!(args.len() == 1);
+
+    context.charge(gas_params.base)?;
+
+    let counter = safely_pop_arg!(args, u64);
+    let transaction_context = context.extensions().get::<NativeTransactionContext>();
+
+    Ok(smallvec![Value::vector_u8(
+        transaction_context.unique_session_value(counter)
+    )])
+}
+
+/***************************************************************************************************
+ * native fun remaining_write_budget_internal
+ *
+ *   gas cost: base_cost + per_byte * bytes written so far
+ *
+ **************************************************************************************************/
+#[derive(Clone, Debug)]
+pub struct RemainingWriteBudgetGasParameters {
+    pub base: InternalGas,
+    pub per_byte: InternalGasPerByte,
+}
+
+/// How many more bytes the transaction may write before its session fails to finish: the room
+/// left under the limit on all write ops, with what the resources and modules changed so far
+/// would take up if written now. Events are limited separately and not counted.
+///
+/// Write ops are sized as when the session is finished, except for resource group members, which
+/// are sized on their own rather than as part of the group written in their place.
+fn native_remaining_write_budget(
+    gas_params: &RemainingWriteBudgetGasParameters,
+    context: &mut SafeNativeContext,
+    _ty_args: Vec<Type>,
+    _args: VecDeque<Value>,
+) -> SafeNativeResult<SmallVec<[Value; 1]>> {
+    debug_assert!(_ty_args.is_empty());
+    debug_assert!(_args.is_empty());
+
+    context.charge(gas_params.base)?;
+
+    let remaining = match context
+        .extensions()
+        .get::<NativeWriteBudgetContext>()
+        .max_bytes_all_write_ops
+    {
+        Some(max_bytes) => {
+            // Sizing the changes takes serializing them, which is charged for by the byte.
+            let written = context.written_bytes()?;
+            context.charge(gas_params.per_byte * NumBytes::new(written))?;
+            max_bytes.saturating_sub(written)
+        },
+        None => u64::MAX,
+    };
+
+    Ok(smallvec![Value::u64(remaining)])
+}
+
 /***************************************************************************************************
  * module
  *
@@ -67,6 +197,8 @@ fn native_get_script_hash(
 #[derive(Debug, Clone)]
 pub struct GasParameters {
     pub get_script_hash: GetScriptHashGasParameters,
+    pub unique_session_value: UniqueSessionValueGasParameters,
+    pub remaining_write_budget: RemainingWriteBudgetGasParameters,
 }
 
 pub fn make_all(
@@ -74,15 +206,35 @@ pub fn make_all(
     timed_features: TimedFeatures,
     features: Arc<Features>,
 ) -> impl Iterator<Item = (String, NativeFunction)> {
-    let natives = [(
-        "get_script_hash",
-        make_safe_native(
-            gas_params.get_script_hash,
-            timed_features,
-            features,
-            native_get_script_hash,
+    let natives = [
+        (
+            "get_script_hash",
+            make_safe_native(
+                gas_params.get_script_hash,
+                timed_features.clone(),
+                features.clone(),
+                native_get_script_hash,
+            ),
+        ),
+        (
+            "unique_session_value_internal",
+            make_safe_native(
+                gas_params.unique_session_value,
+                timed_features.clone(),
+                features.clone(),
+                native_unique_session_value,
+            ),
+        ),
+        (
+            "remaining_write_budget_internal",
+            make_safe_native(
+                gas_params.remaining_write_budget,
+                timed_features,
+                features,
+                native_remaining_write_budget,
+            ),
         ),
-    )];
+    ];
 
     crate::natives::helpers::make_module_natives(natives)
 }
@@ -42,6 +42,7 @@ use move_vm_types::gas::UnmeteredGasMeter;
 use once_cell::sync::Lazy;
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 // The seed is arbitrarily picked to produce a consistent key. XXX make this more formal?
 const GENESIS_SEED: [u8; 32] = [42; 32];
@@ -140,7 +141,9 @@ pub fn encode_aptos_mainnet_genesis_transaction(
     let cs1 = session
         .finish(
             &mut (),
-            &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
         )
         .unwrap();
 
@@ -156,7 +159,9 @@ pub fn encode_aptos_mainnet_genesis_transaction(
     let cs2 = session
         .finish(
             &mut (),
-            &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
         )
         .unwrap();
     let change_set_ext = cs1.squash(cs2).unwrap();
@@ -255,7 +260,9 @@ pub fn encode_genesis_change_set(
     let cs1 = session
         .finish(
             &mut (),
-            &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
         )
         .unwrap();
 
@@ -271,7 +278,9 @@ pub fn encode_genesis_change_set(
     let cs2 = session
         .finish(
             &mut (),
-            &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+            &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                LATEST_GAS_FEATURE_VERSION,
+            )),
         )
         .unwrap();
 
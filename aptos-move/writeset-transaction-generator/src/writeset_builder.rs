@@ -27,6 +27,7 @@ use move_core_types::{
 };
 use move_vm_runtime::session::SerializedReturnValues;
 use move_vm_types::gas::UnmeteredGasMeter;
+use std::sync::Arc;
 
 pub struct GenesisSession<'r, 'l, S>(SessionExt<'r, 'l, S>);
 
@@ -134,7 +135,9 @@ where
             .0
             .finish(
                 &mut (),
-                &ChangeSetConfigs::unlimited_at_gas_feature_version(LATEST_GAS_FEATURE_VERSION),
+                &Arc::new(ChangeSetConfigs::unlimited_at_gas_feature_version(
+                    LATEST_GAS_FEATURE_VERSION,
+                )),
             )
             .map_err(|err| format_err!("Unexpected VM Error: {:?}", err))
             .unwrap()
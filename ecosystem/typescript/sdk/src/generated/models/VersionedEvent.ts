@@ -18,5 +18,10 @@ export type VersionedEvent = {
      * The JSON representation of the event
      */
     data: any;
+    /**
+     * Set for module events, which are identified by their type rather than emitted to an event
+     * handle: their `guid` and `sequence_number` are all zeros and identify nothing
+     */
+    is_module_event?: boolean;
 };
 
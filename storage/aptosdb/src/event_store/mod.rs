@@ -309,12 +309,15 @@ impl EventStore {
         events: &[ContractEvent],
         batch: &SchemaBatch,
     ) -> Result<HashValue> {
-        // Event table and indices updates
+        // Event table and indices updates. Module events have no key to be indexed by.
         events
             .iter()
             .enumerate()
             .try_for_each::<_, Result<_>>(|(idx, event)| {
                 batch.put::<EventSchema>(&(version, idx as u64), event)?;
+                if event.is_module_event() {
+                    return Ok(());
+                }
                 batch.put::<EventByKeySchema>(
                     &(*event.key(), event.sequence_number()),
                     &(version, idx as u64),
@@ -463,12 +466,15 @@ impl EventStore {
         let mut current_version = start;
         for events in self.get_events_by_version_iter(start, (end - start) as usize)? {
             for (current_index, event) in (events?).into_iter().enumerate() {
-                db_batch.delete::<EventByVersionSchema>(&(
-                    *event.key(),
-                    current_version,
-                    event.sequence_number(),
-                ))?;
-                db_batch.delete::<EventByKeySchema>(&(*event.key(), event.sequence_number()))?;
+                if !event.is_module_event() {
+                    db_batch.delete::<EventByVersionSchema>(&(
+                        *event.key(),
+                        current_version,
+                        event.sequence_number(),
+                    ))?;
+                    db_batch
+                        .delete::<EventByKeySchema>(&(*event.key(), event.sequence_number()))?;
+                }
                 db_batch.delete::<EventSchema>(&(current_version, current_index as u64))?;
             }
             current_version += 1;
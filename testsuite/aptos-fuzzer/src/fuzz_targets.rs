@@ -88,6 +88,7 @@ static ALL_TARGETS: Lazy<BTreeMap<&'static str, Box<dyn FuzzTargetImpl>>> = Lazy
         Box::<transaction::TwoSignedTransactions>::default(),
         // VM
         Box::<vm::CompiledModuleTarget>::default(),
+        Box::<vm::ConvertChangeSet>::default(),
     ];
     targets
         .into_iter()
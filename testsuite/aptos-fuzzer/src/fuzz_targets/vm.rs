@@ -2,8 +2,9 @@
 // Parts of the project are originally copyright © Meta Platforms, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::FuzzTargetImpl;
+use crate::{corpus_from_strategy, fuzz_data_to_value, FuzzTargetImpl};
 use aptos_proptest_helpers::ValueGenerator;
+use aptos_vm::fuzzing::{arb_base_state, arb_change_set_parts, fuzz_convert};
 use move_binary_format::file_format::CompiledModule;
 use proptest::prelude::*;
 
@@ -30,3 +31,25 @@ impl FuzzTargetImpl for CompiledModuleTarget {
         let _ = CompiledModule::deserialize(data);
     }
 }
+
+#[derive(Clone, Debug, Default)]
+pub struct ConvertChangeSet;
+
+impl FuzzTargetImpl for ConvertChangeSet {
+    fn description(&self) -> &'static str {
+        "VM SessionExt change set conversion"
+    }
+
+    fn generate(&self, _idx: usize, _gen: &mut ValueGenerator) -> Option<Vec<u8>> {
+        Some(corpus_from_strategy((
+            arb_change_set_parts(),
+            arb_base_state(),
+        )))
+    }
+
+    fn fuzz(&self, data: &[u8]) {
+        let (parts, base_state) =
+            fuzz_data_to_value(data, (arb_change_set_parts(), arb_base_state()));
+        fuzz_convert(parts, base_state);
+    }
+}
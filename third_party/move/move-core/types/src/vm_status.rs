@@ -681,11 +681,16 @@ pub enum StatusCode {
     TYPE_RESOLUTION_FAILURE = 2021,
     DUPLICATE_NATIVE_FUNCTION = 2022,
     // Reserved error code for future use
-    RESERVED_INVARIANT_VIOLATION_ERROR_1 = 2023,
-    RESERVED_INVARIANT_VIOLATION_ERROR_2 = 2024,
-    RESERVED_INVARIANT_VIOLATION_ERROR_3 = 2025,
-    RESERVED_INVARIANT_VIOLATION_ERROR_4 = 2026,
-    RESERVED_INVARIANT_VIOLATION_ERROR_5 = 2027,
+    // An aggregator delta targets a state key the same change set deletes.
+    DELTA_ON_DELETED_STATE_KEY = 2023,
+    // A change set writes the same state key more than once.
+    DUPLICATE_WRITE_SET_KEY = 2024,
+    // A block metadata session writes state outside of its allowed footprint.
+    BLOCK_META_WRITE_OUTSIDE_FOOTPRINT = 2025,
+    // An aggregator delta of a change set cannot be applied to the value in storage.
+    DELTA_MATERIALIZATION_FAILURE = 2026,
+    // An aggregator change targets a table that does not belong to the aggregator factory.
+    UNKNOWN_AGGREGATOR_HANDLE = 2027,
 
     // Errors that can arise from binary decoding (deserialization)
     // Deserializtion Errors: 3000-3999
@@ -744,12 +749,27 @@ pub enum StatusCode {
     EXECUTION_LIMIT_REACHED = 4030,
     IO_LIMIT_REACHED = 4031,
     STORAGE_LIMIT_REACHED = 4032,
-    // Reserved error code for future use
-    RESERVED_RUNTIME_ERROR_1 = 4033,
-    RESERVED_RUNTIME_ERROR_2 = 4034,
-    RESERVED_RUNTIME_ERROR_3 = 4035,
-    RESERVED_RUNTIME_ERROR_4 = 4036,
-    RESERVED_RUNTIME_ERROR_5 = 4037,
+    // A write to a resource was rejected by the session's write policy.
+    RESOURCE_WRITE_DENIED = 4033,
+    // A resource group was created at an address that holds neither an account nor an object.
+    RESOURCE_GROUP_ADDRESS_NOT_INITIALIZED = 4034,
+    // The struct tag of a resource group member is nested too deeply or is too large.
+    RESOURCE_GROUP_MEMBER_TAG_TOO_COMPLEX = 4035,
+    // A transaction wrote more state items than allowed per transaction.
+    TOO_MANY_WRITE_OPS = 4036,
+    // A transaction grew the state under an account beyond the account's storage quota.
+    STORAGE_QUOTA_EXCEEDED = 4037,
+    // A session not allowed to delete resources at framework reserved addresses deleted one.
+    RESERVED_RESOURCE_DELETION_DENIED = 4038,
+    // Finishing a session read or produced more than the budget derived from the gas limit.
+    FINISH_BUDGET_EXCEEDED = 4039,
+
+    // Errors caused by the node executing the transaction rather than by the transaction. They
+    // are never committed, the transaction is executed again instead.
+    // Node Errors: 5000-5999
+    // Storage failed to serve a read, e.g. because of an IO error of the node, rather than the
+    // read being invalid. Executing the transaction again may succeed.
+    STORAGE_UNAVAILABLE = 5000,
 
     // A reserved status to represent an unknown vm status.
     // this is std::u64::MAX, but we can't pattern match on that, so put the hardcoded value in
@@ -793,6 +813,12 @@ impl StatusCode {
 
         StatusType::Unknown
     }
+
+    /// Whether the status is caused by the node rather than the transaction, so that executing
+    /// the transaction again may end differently.
+    pub fn is_retriable(self) -> bool {
+        matches!(self, StatusCode::STORAGE_UNAVAILABLE)
+    }
 }
 
 // TODO(#1307)
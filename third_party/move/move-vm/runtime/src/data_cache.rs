@@ -270,6 +270,29 @@ impl<'r, 'l, S: MoveResolver> DataStore for TransactionDataCache<'r, 'l, S> {
         Ok(())
     }
 
+    fn written_bytes(&self) -> PartialVMResult<u64> {
+        let mut bytes = 0;
+        for (addr, account_cache) in &self.account_map {
+            for (module_name, (blob, _is_republishing)) in &account_cache.module_map {
+                let module_id = ModuleId::new(*addr, module_name.clone());
+                bytes +=
+                    (AccountAddress::LENGTH + module_id.access_vector().len() + blob.len()) as u64;
+            }
+            for (ty, (layout, gv)) in &account_cache.data_map {
+                let size = match gv.written_size(layout)? {
+                    Some(size) => size,
+                    None => continue,
+                };
+                let struct_tag = match self.loader.type_to_type_tag(ty)? {
+                    TypeTag::Struct(struct_tag) => struct_tag,
+                    _ => return Err(PartialVMError::new(StatusCode::INTERNAL_TYPE_ERROR)),
+                };
+                bytes += (AccountAddress::LENGTH + struct_tag.access_vector().len()) as u64 + size;
+            }
+        }
+        Ok(bytes)
+    }
+
     fn exists_module(&self, module_id: &ModuleId) -> VMResult<bool> {
         if let Some(account_cache) = self.account_map.get(module_id.address()) {
             if account_cache.module_map.contains_key(module_id.name()) {
@@ -35,6 +35,12 @@ impl<'a> NativeContextExtensions<'a> {
             .unwrap()
     }
 
+    /// Like `get`, but returns `None` if no extension of type `T` was added.
+    pub fn try_get<T: TidAble<'a>>(&self) -> Option<&T> {
+        let ext = self.map.get(&T::id())?;
+        Some(ext.as_ref().downcast_ref::<T>().unwrap())
+    }
+
     pub fn get_mut<T: TidAble<'a>>(&mut self) -> &mut T {
         self.map
             .get_mut(&T::id())
@@ -44,6 +50,12 @@ impl<'a> NativeContextExtensions<'a> {
             .unwrap()
     }
 
+    /// Like `get_mut`, but returns `None` if no extension of type `T` was added.
+    pub fn try_get_mut<T: TidAble<'a>>(&mut self) -> Option<&mut T> {
+        let ext = self.map.get_mut(&T::id())?;
+        Some(ext.as_mut().downcast_mut::<T>().unwrap())
+    }
+
     pub fn remove<T: TidAble<'a>>(&mut self) -> T {
         // can't use expect below because it requires `T: Debug`.
         match self
@@ -56,6 +68,15 @@ impl<'a> NativeContextExtensions<'a> {
             Err(_) => panic!("downcast error"),
         }
     }
+
+    /// Like `remove`, but returns `None` if no extension of type `T` was added.
+    pub fn try_remove<T: TidAble<'a>>(&mut self) -> Option<T> {
+        let ext = self.map.remove(&T::id())?;
+        match ext.downcast_box::<T>() {
+            Ok(val) => Some(*val),
+            Err(_) => panic!("downcast error"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -80,4 +101,18 @@ mod tests {
         let e1 = exts.remove::<Ext>();
         assert_eq!(*e1.a, 25)
     }
+
+    #[test]
+    fn try_remove_unknown_ext() {
+        let mut v: u64 = 23;
+        let mut exts = NativeContextExtensions::default();
+        assert!(exts.try_remove::<Ext>().is_none());
+        assert!(exts.try_get::<Ext>().is_none());
+        assert!(exts.try_get_mut::<Ext>().is_none());
+        exts.add(Ext { a: &mut v });
+        assert_eq!(*exts.try_get::<Ext>().unwrap().a, 23);
+        *exts.try_get_mut::<Ext>().unwrap().a += 1;
+        assert_eq!(*exts.try_remove::<Ext>().unwrap().a, 24);
+        assert!(exts.try_remove::<Ext>().is_none());
+    }
 }
@@ -157,6 +157,12 @@ impl<'a, 'b> NativeContext<'a, 'b> {
         self.data_store.events()
     }
 
+    /// The bytes the resources and modules changed so far would take up if written now, see
+    /// `DataStore::written_bytes`.
+    pub fn written_bytes(&self) -> PartialVMResult<u64> {
+        self.data_store.written_bytes()
+    }
+
     pub fn type_to_type_tag(&self, ty: &Type) -> PartialVMResult<TypeTag> {
         self.resolver.loader().type_to_type_tag(ty)
     }
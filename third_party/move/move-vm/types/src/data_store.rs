@@ -45,6 +45,11 @@ pub trait DataStore {
     /// Check if this module exists.
     fn exists_module(&self, module_id: &ModuleId) -> VMResult<bool>;
 
+    /// The bytes the resources and modules changed so far would take up if written now: every
+    /// resource created or changed, serialized, and every module published, each along with the
+    /// address and access path it is stored under. Deleted resources take up nothing.
+    fn written_bytes(&self) -> PartialVMResult<u64>;
+
     // ---
     // EventStore operations
     // ---
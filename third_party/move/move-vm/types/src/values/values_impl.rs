@@ -2544,6 +2544,22 @@ impl GlobalValueImpl {
             },
         }
     }
+
+    fn written_size(&self, layout: &MoveTypeLayout) -> PartialVMResult<Option<u64>> {
+        let fields = match self {
+            Self::Fresh { fields } => fields,
+            Self::Cached { fields, status }
+                if matches!(&*status.borrow(), GlobalDataStatus::Dirty) =>
+            {
+                fields
+            },
+            Self::None | Self::Deleted | Self::Cached { .. } => return Ok(None),
+        };
+        let val = ValueImpl::Container(Container::Struct(Rc::clone(fields)));
+        bcs::serialized_size(&AnnotatedValue { layout, val: &val })
+            .map(|size| Some(size as u64))
+            .map_err(|_| PartialVMError::new(StatusCode::INTERNAL_TYPE_ERROR))
+    }
 }
 
 impl GlobalValue {
@@ -2582,6 +2598,12 @@ impl GlobalValue {
     pub fn is_mutated(&self) -> bool {
         self.0.is_mutated()
     }
+
+    /// The size of the resource as it would be written, i.e., serialized with `layout`, or `None`
+    /// if nothing would be written as the resource is unchanged, absent or deleted.
+    pub fn written_size(&self, layout: &MoveTypeLayout) -> PartialVMResult<Option<u64>> {
+        self.0.written_size(layout)
+    }
 }
 
 /***************************************************************************************
@@ -7,6 +7,7 @@ pub mod coin_info;
 pub mod coin_store;
 pub mod core_account;
 pub mod object;
+pub mod storage_quota;
 pub mod transaction_validation;
 
 pub use chain_id::*;
@@ -14,4 +15,5 @@ pub use coin_info::*;
 pub use coin_store::*;
 pub use core_account::*;
 pub use object::*;
+pub use storage_quota::*;
 pub use transaction_validation::*;
@@ -0,0 +1,47 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use move_core_types::{
+    ident_str,
+    identifier::IdentStr,
+    move_resource::{MoveResource, MoveStructType},
+};
+use serde::{Deserialize, Serialize};
+
+/// A Rust representation of StorageQuota, capping the bytes the state items under an account
+/// may occupy.
+///
+/// There is no Move module declaring this resource yet, so it only exists where it is written
+/// directly into storage, e.g. in tests. Nothing maintains `used_bytes` either, so quotas are only
+/// enforced in tests, where whoever writes the resource keeps it in sync with the account's usage.
+/// The VM reads it when checking a transaction's growth against `limit_bytes` but never updates
+/// it.
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StorageQuotaResource {
+    limit_bytes: u64,
+    used_bytes: u64,
+}
+
+impl StorageQuotaResource {
+    pub fn new(limit_bytes: u64, used_bytes: u64) -> Self {
+        Self {
+            limit_bytes,
+            used_bytes,
+        }
+    }
+
+    pub fn limit_bytes(&self) -> u64 {
+        self.limit_bytes
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.used_bytes
+    }
+}
+
+impl MoveStructType for StorageQuotaResource {
+    const MODULE_NAME: &'static IdentStr = ident_str!("storage_quota");
+    const STRUCT_NAME: &'static IdentStr = ident_str!("StorageQuota");
+}
+
+impl MoveResource for StorageQuotaResource {}
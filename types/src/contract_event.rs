@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    account_address::AccountAddress,
     account_config::{DepositEvent, NewBlockEvent, NewEpochEvent, WithdrawEvent},
     event::EventKey,
     transaction::Version,
@@ -13,12 +14,17 @@ use move_core_types::{language_storage::TypeTag, move_resource::MoveStructType};
 #[cfg(any(test, feature = "fuzzing"))]
 use proptest_derive::Arbitrary;
 use serde::{Deserialize, Serialize};
-use std::{convert::TryFrom, ops::Deref};
+use std::convert::TryFrom;
+
+/// The key all module events report, see `ContractEvent::key`. No event handle can have it: it
+/// would have to be created by an account at the reserved address 0x0.
+static MODULE_EVENT_KEY: EventKey = EventKey::new(0, AccountAddress::ZERO);
 
 /// Support versioning of the data structure.
 #[derive(Hash, Clone, Eq, PartialEq, Serialize, Deserialize, CryptoHasher, BCSCryptoHash)]
 pub enum ContractEvent {
     V0(ContractEventV0),
+    V1(ContractEventV1),
 }
 
 impl ContractEvent {
@@ -35,16 +41,51 @@ impl ContractEvent {
             event_data,
         ))
     }
-}
 
-// Temporary hack to avoid massive changes, it won't work when new variant comes and needs proper
-// dispatch at that time.
-impl Deref for ContractEvent {
-    type Target = ContractEventV0;
+    /// A module event, identified by its type alone.
+    pub fn new_module_event(type_tag: TypeTag, event_data: Vec<u8>) -> Self {
+        ContractEvent::V1(ContractEventV1::new(type_tag, event_data))
+    }
+
+    pub fn is_module_event(&self) -> bool {
+        matches!(self, ContractEvent::V1(_))
+    }
+
+    /// The key of the event handle the event was emitted to. Module events are not emitted to a
+    /// handle, and all report the same key, which no handle has.
+    pub fn key(&self) -> &EventKey {
+        match self {
+            ContractEvent::V0(event) => event.key(),
+            ContractEvent::V1(_) => &MODULE_EVENT_KEY,
+        }
+    }
+
+    /// The number of events emitted to the event handle before this one, or 0 for module events.
+    pub fn sequence_number(&self) -> u64 {
+        match self {
+            ContractEvent::V0(event) => event.sequence_number(),
+            ContractEvent::V1(_) => 0,
+        }
+    }
+
+    pub fn event_data(&self) -> &[u8] {
+        match self {
+            ContractEvent::V0(event) => event.event_data(),
+            ContractEvent::V1(event) => event.event_data(),
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
+    pub fn type_tag(&self) -> &TypeTag {
         match self {
-            ContractEvent::V0(event) => event,
+            ContractEvent::V0(event) => event.type_tag(),
+            ContractEvent::V1(event) => event.type_tag(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            ContractEvent::V0(event) => event.size(),
+            ContractEvent::V1(event) => event.size(),
         }
     }
 }
@@ -99,14 +140,46 @@ impl ContractEventV0 {
     }
 }
 
+/// Entry produced via a call to the `emit` builtin: a module event, which is not emitted to an
+/// event handle and is identified by its type alone.
+#[derive(Hash, Clone, Eq, PartialEq, Serialize, Deserialize, CryptoHasher)]
+pub struct ContractEventV1 {
+    /// The type of the data
+    type_tag: TypeTag,
+    /// The data payload of the event
+    #[serde(with = "serde_bytes")]
+    event_data: Vec<u8>,
+}
+
+impl ContractEventV1 {
+    pub fn new(type_tag: TypeTag, event_data: Vec<u8>) -> Self {
+        Self {
+            type_tag,
+            event_data,
+        }
+    }
+
+    pub fn event_data(&self) -> &[u8] {
+        &self.event_data
+    }
+
+    pub fn type_tag(&self) -> &TypeTag {
+        &self.type_tag
+    }
+
+    pub fn size(&self) -> usize {
+        bcs::to_bytes(&self.type_tag).unwrap().len() + self.event_data.len()
+    }
+}
+
 impl TryFrom<&ContractEvent> for NewBlockEvent {
     type Error = Error;
 
     fn try_from(event: &ContractEvent) -> Result<Self> {
-        if event.type_tag != TypeTag::Struct(Box::new(Self::struct_tag())) {
+        if event.type_tag() != &TypeTag::Struct(Box::new(Self::struct_tag())) {
             anyhow::bail!("Expected NewBlockEvent")
         }
-        Self::try_from_bytes(&event.event_data)
+        Self::try_from_bytes(event.event_data())
     }
 }
 
@@ -114,10 +187,10 @@ impl TryFrom<&ContractEvent> for NewEpochEvent {
     type Error = Error;
 
     fn try_from(event: &ContractEvent) -> Result<Self> {
-        if event.type_tag != TypeTag::Struct(Box::new(Self::struct_tag())) {
+        if event.type_tag() != &TypeTag::Struct(Box::new(Self::struct_tag())) {
             anyhow::bail!("Expected NewEpochEvent")
         }
-        Self::try_from_bytes(&event.event_data)
+        Self::try_from_bytes(event.event_data())
     }
 }
 
@@ -125,10 +198,10 @@ impl TryFrom<&ContractEvent> for WithdrawEvent {
     type Error = Error;
 
     fn try_from(event: &ContractEvent) -> Result<Self> {
-        if event.type_tag != TypeTag::Struct(Box::new(WithdrawEvent::struct_tag())) {
+        if event.type_tag() != &TypeTag::Struct(Box::new(WithdrawEvent::struct_tag())) {
             anyhow::bail!("Expected Sent Payment")
         }
-        Self::try_from_bytes(&event.event_data)
+        Self::try_from_bytes(event.event_data())
     }
 }
 
@@ -136,23 +209,31 @@ impl TryFrom<&ContractEvent> for DepositEvent {
     type Error = Error;
 
     fn try_from(event: &ContractEvent) -> Result<Self> {
-        if event.type_tag != TypeTag::Struct(Box::new(DepositEvent::struct_tag())) {
+        if event.type_tag() != &TypeTag::Struct(Box::new(DepositEvent::struct_tag())) {
             anyhow::bail!("Expected Received Payment")
         }
-        Self::try_from_bytes(&event.event_data)
+        Self::try_from_bytes(event.event_data())
     }
 }
 
 impl std::fmt::Debug for ContractEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "ContractEvent {{ key: {:?}, index: {:?}, type: {:?}, event_data: {:?} }}",
-            self.key,
-            self.sequence_number,
-            self.type_tag,
-            hex::encode(&self.event_data)
-        )
+        match self {
+            ContractEvent::V0(event) => write!(
+                f,
+                "ContractEvent {{ key: {:?}, index: {:?}, type: {:?}, event_data: {:?} }}",
+                event.key,
+                event.sequence_number,
+                event.type_tag,
+                hex::encode(&event.event_data)
+            ),
+            ContractEvent::V1(event) => write!(
+                f,
+                "ModuleEvent {{ type: {:?}, event_data: {:?} }}",
+                event.type_tag,
+                hex::encode(&event.event_data)
+            ),
+        }
     }
 }
 
@@ -162,13 +243,19 @@ impl std::fmt::Display for ContractEvent {
             write!(
                 f,
                 "ContractEvent {{ key: {}, index: {:?}, type: {:?}, event_data: {:?} }}",
-                self.key, self.sequence_number, self.type_tag, payload,
+                self.key(),
+                self.sequence_number(),
+                self.type_tag(),
+                payload,
             )
         } else if let Ok(payload) = DepositEvent::try_from(self) {
             write!(
                 f,
                 "ContractEvent {{ key: {}, index: {:?}, type: {:?}, event_data: {:?} }}",
-                self.key, self.sequence_number, self.type_tag, payload,
+                self.key(),
+                self.sequence_number(),
+                self.type_tag(),
+                payload,
             )
         } else {
             write!(f, "{:?}", self)
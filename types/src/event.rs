@@ -17,7 +17,7 @@ pub struct EventKey {
 }
 
 impl EventKey {
-    pub fn new(creation_number: u64, account_address: AccountAddress) -> Self {
+    pub const fn new(creation_number: u64, account_address: AccountAddress) -> Self {
         Self {
             creation_number,
             account_address,
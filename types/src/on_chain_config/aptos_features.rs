@@ -3,6 +3,8 @@
 
 use crate::on_chain_config::OnChainConfig;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "feature-usage")]
+use std::{cell::RefCell, sync::Arc};
 
 /// The feature flags define in the Move source. This must stay aligned with the constants there.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -25,6 +27,12 @@ pub enum FeatureFlag {
     STRUCT_CONSTRUCTORS = 15,
     PERIODICAL_REWARD_RATE_DECREASE = 16,
     PARTIAL_GOVERNANCE_VOTING = 17,
+    RESOURCE_GROUP_CONTAINER_VALIDATION = 18,
+    STRIP_NOOP_MODIFICATIONS = 19,
+    AGGREGATOR_HANDLE_VALIDATION = 20,
+    STORAGE_QUOTAS = 21,
+    MODULE_EVENT = 22,
+    EVENT_KEY_VALIDATION = 23,
 }
 
 /// Representation of features on chain as a bitset.
@@ -62,3 +70,72 @@ impl Features {
 
 // --------------------------------------------------------------------------------------------
 // Code Publishing
+
+// --------------------------------------------------------------------------------------------
+// Feature Usage
+
+/// Wraps `Features`, remembering which flags were checked through it, e.g. so that replaying a
+/// transaction can tell whether a different features snapshot could have changed its outcome.
+/// Only compiled with the `feature-usage` feature.
+#[cfg(feature = "feature-usage")]
+#[derive(Debug)]
+pub struct FeatureUsageRecorder {
+    features: Arc<Features>,
+    // One bit per flag, set when the flag is first checked.
+    used: RefCell<Vec<u64>>,
+}
+
+#[cfg(feature = "feature-usage")]
+impl FeatureUsageRecorder {
+    pub fn new(features: Arc<Features>) -> Self {
+        Self {
+            features,
+            used: RefCell::new(vec![]),
+        }
+    }
+
+    /// Like `Features::is_enabled`, recording that `flag` was checked.
+    pub fn is_enabled(&self, flag: FeatureFlag) -> bool {
+        let val = flag as u64;
+        let word_index = (val / 64) as usize;
+        let mut used = self.used.borrow_mut();
+        if used.len() <= word_index {
+            used.resize(word_index + 1, 0);
+        }
+        used[word_index] |= 1 << (val % 64);
+        self.features.is_enabled(flag)
+    }
+
+    /// The flags checked so far, in ascending order.
+    pub fn used_flags(&self) -> Vec<u64> {
+        self.used
+            .borrow()
+            .iter()
+            .enumerate()
+            .flat_map(|(word_index, &word)| {
+                (0..64u64)
+                    .filter(move |bit| word & (1 << bit) != 0)
+                    .map(move |bit| word_index as u64 * 64 + bit)
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "feature-usage"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feature_usage_recorder_records_checked_flags() {
+        let recorder = FeatureUsageRecorder::new(Arc::new(Features::default()));
+        assert!(recorder.used_flags().is_empty());
+
+        assert!(recorder.is_enabled(FeatureFlag::VM_BINARY_FORMAT_V6));
+        assert!(!recorder.is_enabled(FeatureFlag::AGGREGATOR_HANDLE_VALIDATION));
+        assert!(recorder.is_enabled(FeatureFlag::VM_BINARY_FORMAT_V6));
+        assert_eq!(recorder.used_flags(), vec![
+            FeatureFlag::VM_BINARY_FORMAT_V6 as u64,
+            FeatureFlag::AGGREGATOR_HANDLE_VALIDATION as u64,
+        ]);
+    }
+}
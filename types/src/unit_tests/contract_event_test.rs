@@ -23,3 +23,23 @@ fn test_event_json_serialize() {
     let contract_event2: ContractEvent = serde_json::from_str(contract_json.as_str()).unwrap();
     assert_eq!(contract_event, contract_event2)
 }
+
+proptest! {
+    #[test]
+    fn module_event_bcs_roundtrip(type_tag in any::<TypeTag>(), event_data in any::<Vec<u8>>()) {
+        assert_canonical_encode_decode(ContractEvent::new_module_event(type_tag, event_data));
+    }
+}
+
+#[test]
+fn module_events_have_no_handle() {
+    let event = ContractEvent::new_module_event(TypeTag::U64, vec![1]);
+    let legacy = ContractEvent::new(EventKey::random(), 3, TypeTag::U64, vec![1]);
+    assert!(event.is_module_event());
+    assert!(!legacy.is_module_event());
+    assert_eq!(event.sequence_number(), 0);
+    assert_ne!(event.key(), legacy.key());
+    assert_eq!(event.event_data(), legacy.event_data());
+    // Module events do not pay for a key and sequence number.
+    assert_eq!(event.size() + legacy.key().size() + 8, legacy.size());
+}
@@ -6,7 +6,7 @@
 //! path it updates. For each access path, the VM can either give its new value or delete it.
 
 use crate::state_store::{
-    state_key::StateKey,
+    state_key::{StateKey, StateKeyInner},
     state_value::{StateValue, StateValueMetadata},
 };
 use anyhow::{bail, Result};
@@ -14,6 +14,7 @@ use aptos_crypto_derive::{BCSCryptoHash, CryptoHasher};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{btree_map, BTreeMap},
+    fmt,
     ops::Deref,
 };
 
@@ -301,6 +302,20 @@ impl WriteSetMut {
         self.write_set.insert(item.0, item.1);
     }
 
+    /// Like `insert`, but fails instead of replacing the op already written to the same key.
+    pub fn try_insert(
+        &mut self,
+        item: (StateKey, WriteOp),
+    ) -> std::result::Result<(), DuplicateStateKey> {
+        match self.write_set.entry(item.0) {
+            btree_map::Entry::Occupied(entry) => Err(DuplicateStateKey(entry.key().clone())),
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(item.1);
+                Ok(())
+            },
+        }
+    }
+
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.write_set.is_empty()
@@ -343,6 +358,28 @@ impl WriteSetMut {
     }
 }
 
+/// A state key written more than once while building a `WriteSetMut`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DuplicateStateKey(pub StateKey);
+
+impl fmt::Display for DuplicateStateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "state key written more than once: ")?;
+        match self.0.inner() {
+            StateKeyInner::AccessPath(access_path) => write!(f, "{}", access_path),
+            StateKeyInner::TableItem { handle, key } => write!(
+                f,
+                "table item {{ handle: {}, key: {} }}",
+                handle.0.to_hex_literal(),
+                hex::encode(key)
+            ),
+            StateKeyInner::Raw(key) => write!(f, "raw {}", hex::encode(key)),
+        }
+    }
+}
+
+impl std::error::Error for DuplicateStateKey {}
+
 impl ::std::iter::FromIterator<(StateKey, WriteOp)> for WriteSetMut {
     fn from_iter<I: IntoIterator<Item = (StateKey, WriteOp)>>(iter: I) -> Self {
         let mut ws = WriteSetMut::default();